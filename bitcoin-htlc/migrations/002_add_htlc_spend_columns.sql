@@ -0,0 +1,3 @@
+-- Record the transactions that spent the HTLC
+ALTER TABLE orders ADD COLUMN htlc_claim_tx TEXT;
+ALTER TABLE orders ADD COLUMN htlc_refund_tx TEXT;
@@ -0,0 +1,34 @@
+-- Create htlcs table
+CREATE TABLE IF NOT EXISTS htlcs (
+    id TEXT PRIMARY KEY NOT NULL,
+    order_id TEXT,
+    status TEXT NOT NULL,
+    
+    -- HTLC parameters
+    network TEXT NOT NULL,
+    output_type TEXT NOT NULL,
+    payment_hash TEXT NOT NULL,
+    sender_public_key TEXT NOT NULL,
+    recipient_public_key TEXT NOT NULL,
+    timeout_height INTEGER NOT NULL,
+    redeem_script TEXT NOT NULL,
+    address TEXT NOT NULL,
+    min_confirmations INTEGER NOT NULL,
+    
+    -- Funding and spends
+    amount INTEGER,
+    funding_txid TEXT,
+    funding_vout INTEGER,
+    claim_txid TEXT,
+    refund_txid TEXT,
+    preimage TEXT,
+    
+    -- Timestamps
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX idx_htlcs_order_id ON htlcs(order_id);
+CREATE INDEX idx_htlcs_status ON htlcs(status);
+CREATE INDEX idx_htlcs_address ON htlcs(address);
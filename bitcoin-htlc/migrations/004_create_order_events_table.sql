@@ -0,0 +1,12 @@
+-- Create order_events table, an append-only log of order status transitions
+CREATE TABLE IF NOT EXISTS order_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    order_id TEXT NOT NULL,
+    from_status TEXT,
+    to_status TEXT NOT NULL,
+    cause TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX idx_order_events_order_id ON order_events(order_id);
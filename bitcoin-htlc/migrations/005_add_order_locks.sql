@@ -0,0 +1,3 @@
+-- Lease columns so only one orchestrator instance acts on an order at a time
+ALTER TABLE orders ADD COLUMN locked_by TEXT;
+ALTER TABLE orders ADD COLUMN locked_until INTEGER;
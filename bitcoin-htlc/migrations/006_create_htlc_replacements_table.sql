@@ -0,0 +1,15 @@
+-- Create htlc_replacements table, an append-only log of fee bumps of HTLC claims and refunds
+CREATE TABLE IF NOT EXISTS htlc_replacements (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    htlc_id TEXT NOT NULL,
+    spend_path TEXT NOT NULL,
+    replaced_txid TEXT NOT NULL,
+    txid TEXT NOT NULL,
+    fee INTEGER NOT NULL,
+    fee_rate INTEGER NOT NULL,
+    block_height INTEGER NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX idx_htlc_replacements_htlc_id ON htlc_replacements(htlc_id);
@@ -0,0 +1,13 @@
+-- Create tx_confirmations table, the block each watched HTLC transaction confirmed in
+-- so a reorg that drops it can be noticed
+CREATE TABLE IF NOT EXISTS tx_confirmations (
+    txid TEXT PRIMARY KEY NOT NULL,
+    htlc_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    block_height INTEGER NOT NULL,
+    block_hash TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX idx_tx_confirmations_htlc_id ON tx_confirmations(htlc_id);
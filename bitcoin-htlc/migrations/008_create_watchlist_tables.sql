@@ -0,0 +1,38 @@
+-- Create watched_scripts table, scriptPubKeys followed block by block until they are funded and spent
+CREATE TABLE IF NOT EXISTS watched_scripts (
+    id TEXT PRIMARY KEY NOT NULL,
+    script_pubkey TEXT NOT NULL,
+    htlc_id TEXT,
+    min_confirmations INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    funding_txid TEXT,
+    funding_vout INTEGER,
+    funding_amount INTEGER,
+    funding_height INTEGER,
+    spend_txid TEXT,
+    spend_height INTEGER,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Create watch_events table, an append-only log of what happened to watched scripts
+CREATE TABLE IF NOT EXISTS watch_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    watch_id TEXT NOT NULL,
+    htlc_id TEXT,
+    kind TEXT NOT NULL,
+    txid TEXT NOT NULL,
+    block_height INTEGER NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Create scanned_blocks table, the recently scanned blocks; the highest one is the scan cursor
+-- and the hashes below it find the fork point after a reorg
+CREATE TABLE IF NOT EXISTS scanned_blocks (
+    height INTEGER PRIMARY KEY NOT NULL,
+    block_hash TEXT NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX idx_watched_scripts_status ON watched_scripts(status);
+CREATE INDEX idx_watch_events_watch_id ON watch_events(watch_id);
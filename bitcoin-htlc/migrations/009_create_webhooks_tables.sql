@@ -0,0 +1,31 @@
+-- Create webhooks table, endpoints registered for order and watchlist notifications
+CREATE TABLE IF NOT EXISTS webhooks (
+    id TEXT PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Create webhook_subscriptions table, the events each webhook is notified of
+CREATE TABLE IF NOT EXISTS webhook_subscriptions (
+    webhook_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    PRIMARY KEY (webhook_id, event)
+);
+
+-- Create webhook_deliveries table, an outbox of notifications written in the same
+-- transaction as the change they announce and posted by the orchestrator
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL,
+    delivered_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX idx_webhook_subscriptions_event ON webhook_subscriptions(event);
+CREATE INDEX idx_webhook_deliveries_pending ON webhook_deliveries(delivered_at, next_attempt_at);
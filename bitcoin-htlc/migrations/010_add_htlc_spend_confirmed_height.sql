@@ -0,0 +1,2 @@
+-- Height the recorded claim or refund confirmed at; spends without one are still watched for fee bumps
+ALTER TABLE htlcs ADD COLUMN spend_confirmed_height INTEGER;
@@ -0,0 +1,2 @@
+-- Funding the service signed for the HTLC, taken before its broadcast so the HTLC is funded once
+ALTER TABLE htlcs ADD COLUMN funding_broadcast_txid TEXT;
@@ -5,20 +5,16 @@ use uuid::Uuid;
 
 /// Claim HTLC with preimage
 pub async fn claim_htlc(
-    _state: web::Data<AppState>,
-    _htlc_id: web::Path<Uuid>,
+    state: web::Data<AppState>,
+    htlc_id: web::Path<Uuid>,
     request: web::Json<ClaimRequest>,
 ) -> Result<HttpResponse, ApiError> {
     request.0.validate()?;
     
-    // TODO: Implement HTLC claiming logic
-    // For now, return a mock response
-    let response = ClaimResponse {
-        transaction_id: "1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef".to_string(),
-        status: "broadcast".to_string(),
-        claim_address: "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7".to_string(),
-        claimed_amount: 95000,
-    };
+    let response = state
+        .order_service
+        .claim_htlc(htlc_id.into_inner(), request.into_inner())
+        .await?;
     
     Ok(HttpResponse::Ok().json(response))
 }
@@ -0,0 +1,20 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::*, AppState};
+use validator::Validate;
+use uuid::Uuid;
+
+/// Speed up an unconfirmed refund with a child paying for it
+pub async fn cpfp_htlc_refund(
+    state: web::Data<AppState>,
+    htlc_id: web::Path<Uuid>,
+    request: web::Json<CpfpRequest>,
+) -> Result<HttpResponse, ApiError> {
+    request.0.validate()?;
+
+    let response = state
+        .order_service
+        .cpfp_htlc_refund(htlc_id.into_inner(), request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
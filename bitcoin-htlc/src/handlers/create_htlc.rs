@@ -52,8 +52,8 @@ pub async fn create_htlc(
         user_pubkey
     };
     
-    // Get current block height from database or bitcoin service
-    let current_block_height = get_current_block_height(&state).await?;
+    // Timeout is relative to the current chain tip
+    let current_block_height = state.order_service.bitcoin_client().get_block_height().await?;
     let timeout_height = current_block_height + request.timeout_blocks;
     
     // Build HTLC parameters
@@ -62,11 +62,17 @@ pub async fn create_htlc(
         sender_pubkey: resolver_pubkey,
         payment_hash,
         timeout: timeout_height,
+        output_type: request.output_type,
+        network: state.order_service.network(),
     };
     
     // Build the HTLC script
     let htlc_script = build_htlc_script(&htlc_params)?;
     
+    // Persist the HTLC so claim, refund and verify can resolve it by id
+    let htlc = HtlcRecord::new(htlc_params, &htlc_script);
+    state.htlc_repository.insert(&htlc).await?;
+    
     // Calculate estimated timeout timestamp (assuming ~10 minutes per block)
     let estimated_timeout_timestamp = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -75,9 +81,11 @@ pub async fn create_htlc(
         + (request.timeout_blocks as u64 * 600);
     
     let response = CreateHtlcResponse {
+        htlc_id: htlc.id,
         htlc_script: hex::encode(&htlc_script.redeem_script),
-        htlc_address: htlc_script.p2sh_address,
-        script_hash: hex::encode(&htlc_script.script_hash),
+        htlc_address: htlc_script.address,
+        script_hash: hex::encode(htlc_script.script_hash),
+        output_type: htlc_script.output_type,
         timeout_blocks: request.timeout_blocks,
         estimated_timeout_timestamp,
     };
@@ -85,30 +93,11 @@ pub async fn create_htlc(
     Ok(HttpResponse::Ok().json(response))
 }
 
-/// Get current block height from database or bitcoin service
-async fn get_current_block_height(state: &web::Data<AppState>) -> Result<u32, ApiError> {
-    // Try to get from database first - using bitcoin_timeout_blocks as a proxy
-    let result = sqlx::query!("SELECT MAX(bitcoin_timeout_blocks) as max_timeout FROM orders")
-        .fetch_one(&state.pool)
-        .await;
-    
-    if let Ok(record) = result {
-        if let Some(_timeout) = record.max_timeout {
-            // If we have orders, use a reasonable current height
-            // In production, this should call a Bitcoin RPC service
-            return Ok(2_500_000);
-        }
-    }
-    
-    // Default to a reasonable testnet height if no data available
-    // In production, you would call a Bitcoin RPC or API service
-    Ok(2_500_000)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sqlx::sqlite::SqlitePoolOptions;
+    use crate::services::bitcoin::{BitcoinClient, MockBackend};
+    use crate::test_support::test_pool;
 
     fn create_mock_create_request() -> CreateHtlcRequest {
         CreateHtlcRequest {
@@ -116,6 +105,7 @@ mod tests {
             user_public_key: "02789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd".to_string(),
             timeout_blocks: 144,
             resolver_public_key: Some("03789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd".to_string()),
+            output_type: HtlcOutputType::P2wsh,
         }
     }
 
@@ -151,31 +141,31 @@ mod tests {
 
     #[actix_rt::test]
     async fn test_create_htlc_handler() {
-        // Create a test database pool
-        let pool = SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect(":memory:")
-            .await
-            .expect("Failed to create test database");
+        let pool = test_pool().await;
 
-        // Run migrations
-        sqlx::migrate!("./migrations")
-            .run(&pool)
-            .await
-            .expect("Failed to run migrations");
+        // In-memory chain at a fixed tip
+        let bitcoin_client = BitcoinClient::from_backend(MockBackend::new(2_500_000));
 
         // Create app state
-        let app_state = web::Data::new(AppState::new(pool));
+        let app_state = web::Data::new(AppState::with_bitcoin_client(pool, bitcoin_client));
         
         // Create request
         let request = web::Json(create_mock_create_request());
         
         // Call handler
-        let result = create_htlc(app_state, request).await;
+        let result = create_htlc(app_state.clone(), request).await;
         assert!(result.is_ok());
         
         // Check response
         let response = result.unwrap();
         assert_eq!(response.status(), 200);
+
+        // The HTLC is stored with a timeout relative to the reported tip
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let response: CreateHtlcResponse = serde_json::from_slice(&body).unwrap();
+        let stored = app_state.htlc_repository.find(response.htlc_id).await.unwrap();
+        assert_eq!(stored.params.timeout, 2_500_144);
+        assert_eq!(stored.address, response.htlc_address);
+        assert_eq!(stored.status, HtlcStatus::Created);
     }
 }
\ No newline at end of file
@@ -0,0 +1,38 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::*, AppState};
+use validator::Validate;
+use uuid::Uuid;
+
+/// Export an unsigned PSBT funding the HTLC from the caller's wallet
+pub async fn export_funding_psbt(
+    state: web::Data<AppState>,
+    htlc_id: web::Path<Uuid>,
+    request: web::Json<FundingPsbtRequest>,
+) -> Result<HttpResponse, ApiError> {
+    request.0.validate()?;
+
+    let response = state
+        .order_service
+        .export_funding_psbt(htlc_id.into_inner(), request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_funding_psbt_request_requires_amount() {
+        let mut request = FundingPsbtRequest {
+            wallet_address: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
+            amount: 100_000,
+            fee_rate: None,
+        };
+        assert!(request.validate().is_ok());
+
+        request.amount = 0;
+        assert!(request.validate().is_err());
+    }
+}
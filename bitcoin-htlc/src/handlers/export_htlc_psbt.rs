@@ -0,0 +1,64 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::*, AppState};
+use validator::Validate;
+use uuid::Uuid;
+
+/// Export an unsigned claim or refund PSBT for an external signer
+pub async fn export_htlc_psbt(
+    state: web::Data<AppState>,
+    path: web::Path<(Uuid, HtlcSpendPath)>,
+    request: web::Json<SpendPsbtRequest>,
+) -> Result<HttpResponse, ApiError> {
+    request.0.validate()?;
+    let (htlc_id, spend_path) = path.into_inner();
+
+    let response = state
+        .order_service
+        .export_htlc_psbt(htlc_id, spend_path, request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::bitcoin::{BitcoinClient, MockBackend};
+    use crate::test_support::test_pool;
+    use actix_web::{http::StatusCode, test, App};
+
+    #[actix_rt::test]
+    async fn test_routes_claim_and_refund_paths() {
+        let pool = test_pool().await;
+        let state = AppState::with_bitcoin_client(pool, BitcoinClient::from_backend(MockBackend::new(2_500_000)));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/htlc/{htlc_id}/{spend_path}/psbt", web::post().to(export_htlc_psbt)),
+        )
+        .await;
+
+        let body = serde_json::json!({ "destination_address": "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx" });
+        for spend_path in ["claim", "refund"] {
+            let request = test::TestRequest::post()
+                .uri(&format!("/htlc/{}/{}/psbt", Uuid::new_v4(), spend_path))
+                .set_json(&body)
+                .to_request();
+            let response: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+            assert_eq!(response["code"], "HTLC_NOT_FOUND", "{}", spend_path);
+        }
+
+        // Anything but a spend path does not match the route
+        let request = test::TestRequest::post()
+            .uri(&format!("/htlc/{}/sweep/psbt", Uuid::new_v4()))
+            .set_json(&body)
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::NOT_FOUND);
+
+        let request = test::TestRequest::post()
+            .uri(&format!("/htlc/{}/claim/psbt", Uuid::new_v4()))
+            .set_json(serde_json::json!({ "destination_address": "x", "fee_rate": 0 }))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,5 +1,8 @@
 use actix_web::{web, HttpResponse};
 use crate::{models::*, AppState};
+use crate::services::build_htlc_script;
+use crate::services::transaction::{funding_vsize, htlc_claim_vsize};
+use bitcoin::{key::Secp256k1, secp256k1::SecretKey, Address, Amount, Network, PublicKey, TxOut};
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
@@ -11,6 +14,8 @@ pub struct FeeEstimateQuery {
     #[serde(rename = "toToken")]
     pub to_token: Option<String>,
     pub urgent: Option<bool>,
+    #[serde(rename = "outputType")]
+    pub output_type: Option<HtlcOutputType>,
 }
 
 #[derive(Serialize)]
@@ -34,6 +39,8 @@ pub struct BitcoinNetworkFee {
     pub satoshis: String,
     pub satoshis_per_vbyte: u32,
     pub estimated_vsize: u32,
+    /// The rate is a fallback because live estimates were unavailable
+    pub stale: bool,
 }
 
 #[derive(Serialize)]
@@ -90,15 +97,18 @@ pub async fn estimate_fees(
         fee_estimates.half_hour
     };
 
+    let (funding_vsize, claim_vsize) = swap_vsizes(query.output_type.unwrap_or_default())?;
+
     // Calculate fees based on direction
-    let (bitcoin_fee, ethereum_fee, warnings) = match query.direction {
+    let (bitcoin_fee, ethereum_fee, mut warnings) = match query.direction {
         SwapDirection::EthToBtc => {
             // HTLC creation + claim transactions
-            let estimated_vsize = 250; // Approximate HTLC vsize
+            let estimated_vsize = funding_vsize + claim_vsize;
             let bitcoin_fee = BitcoinNetworkFee {
                 satoshis: (sats_per_vbyte * estimated_vsize).to_string(),
                 satoshis_per_vbyte: sats_per_vbyte,
                 estimated_vsize,
+                stale: fee_estimates.stale,
             };
             
             // Ethereum gas for Fusion+ order
@@ -117,11 +127,12 @@ pub async fn estimate_fees(
         }
         SwapDirection::BtcToEth => {
             // User creates HTLC
-            let estimated_vsize = 300;
+            let estimated_vsize = funding_vsize;
             let bitcoin_fee = BitcoinNetworkFee {
                 satoshis: (sats_per_vbyte * estimated_vsize).to_string(),
                 satoshis_per_vbyte: sats_per_vbyte,
                 estimated_vsize,
+                stale: fee_estimates.stale,
             };
             
             // Ethereum gas for Fusion+ fill
@@ -136,6 +147,10 @@ pub async fn estimate_fees(
         }
     };
 
+    if fee_estimates.stale {
+        warnings.push("Bitcoin fee rates are unavailable - network fee uses a fallback rate".to_string());
+    }
+
     // Resolver fee (0.5%)
     let resolver_fee_amount = (amount as f64 * 0.005) as u64;
     let resolver_fee = ResolverFee {
@@ -177,8 +192,36 @@ pub async fn estimate_fees(
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Signed vsizes of the swap's funding and claim transactions for `output_type`
+///
+/// Sized on a representative HTLC, funded from one P2WPKH coin with change and claimed
+/// with a 32 byte preimage to a P2WPKH address.
+fn swap_vsizes(output_type: HtlcOutputType) -> Result<(u32, u32), ApiError> {
+    let secp = Secp256k1::new();
+    let key = |seed: u8| PublicKey::new(SecretKey::from_slice(&[seed; 32]).expect("valid key").public_key(&secp));
+    let htlc_script = build_htlc_script(&HtlcParams {
+        recipient_pubkey: key(1),
+        sender_pubkey: key(2),
+        payment_hash: [0u8; 32],
+        timeout: 2_500_000,
+        output_type,
+        network: Network::Testnet,
+    })?;
+    let wallet_script = Address::p2wpkh(&key(3), Network::Testnet)
+        .expect("compressed key")
+        .script_pubkey();
+
+    let outputs = [htlc_script.script_pubkey.clone(), wallet_script.clone()]
+        .map(|script_pubkey| TxOut { value: Amount::ZERO, script_pubkey });
+    let funding = funding_vsize(1, &wallet_script, &outputs)?;
+    let claim = htlc_claim_vsize(&htlc_script, 32, &wallet_script)?;
+
+    Ok((funding as u32, claim as u32))
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
 
     #[test]
     fn test_fee_calculation() {
@@ -186,4 +229,21 @@ mod tests {
         let resolver_fee = (amount as f64 * 0.005) as u64;
         assert_eq!(resolver_fee, 5000); // 0.5% of 0.01 BTC
     }
+
+    #[test]
+    fn test_swap_vsizes_follow_output_type() {
+        let (p2wsh_funding, p2wsh_claim) = swap_vsizes(HtlcOutputType::P2wsh).unwrap();
+        // One P2WPKH input, P2WSH and P2WPKH outputs
+        assert_eq!(p2wsh_funding, 153);
+        assert!(p2wsh_claim > 100 && p2wsh_claim < 200, "{}", p2wsh_claim);
+
+        let (p2sh_funding, p2sh_claim) = swap_vsizes(HtlcOutputType::P2sh).unwrap();
+        assert!(p2sh_funding < p2wsh_funding);
+        // Without a witness discount the redeem script costs full weight
+        assert!(p2sh_claim > p2wsh_claim + 100);
+
+        let (p2tr_funding, p2tr_claim) = swap_vsizes(HtlcOutputType::P2tr).unwrap();
+        assert_eq!(p2tr_funding, p2wsh_funding);
+        assert!(p2tr_claim > 100 && p2tr_claim < 200, "{}", p2tr_claim);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,37 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::*, AppState};
+use validator::Validate;
+use uuid::Uuid;
+
+/// Fund an HTLC the service is the sender of from the resolver wallet
+pub async fn fund_htlc(
+    state: web::Data<AppState>,
+    htlc_id: web::Path<Uuid>,
+    request: web::Json<FundHtlcRequest>,
+) -> Result<HttpResponse, ApiError> {
+    request.0.validate()?;
+
+    let response = state
+        .order_service
+        .fund_htlc(htlc_id.into_inner(), request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_fund_request_requires_amount() {
+        let mut request = FundHtlcRequest {
+            amount: 100_000,
+            fee_rate: None,
+        };
+        assert!(request.validate().is_ok());
+
+        request.amount = 0;
+        assert!(request.validate().is_err());
+    }
+}
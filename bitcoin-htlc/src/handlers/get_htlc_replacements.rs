@@ -0,0 +1,12 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::ApiError, AppState};
+use uuid::Uuid;
+
+/// Get the fee bumps of an HTLC's claim or refund
+pub async fn get_htlc_replacements(
+    state: web::Data<AppState>,
+    htlc_id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let response = state.order_service.get_htlc_replacements(htlc_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
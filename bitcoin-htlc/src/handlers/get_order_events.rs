@@ -0,0 +1,12 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::ApiError, AppState};
+use uuid::Uuid;
+
+/// Get the status history of an order
+pub async fn get_order_events(
+    state: web::Data<AppState>,
+    order_id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let response = state.order_service.get_order_events(order_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
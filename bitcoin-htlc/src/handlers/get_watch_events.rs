@@ -0,0 +1,12 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::ApiError, AppState};
+use uuid::Uuid;
+
+/// Get the fundings, confirmations, spends and reorgs of a watched script
+pub async fn get_watch_events(
+    state: web::Data<AppState>,
+    watch_id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let response = state.order_service.get_watch_events(watch_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,12 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::ApiError, AppState};
+use uuid::Uuid;
+
+/// Get a watched script and what the scan has seen of it
+pub async fn get_watched_script(
+    state: web::Data<AppState>,
+    watch_id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let response = state.order_service.get_watched_script(watch_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -1,24 +1,44 @@
 pub mod health_check;
 pub mod create_order;
 pub mod get_order;
+pub mod get_order_events;
 pub mod submit_fusion_proof;
 pub mod create_htlc;
 pub mod verify_htlc;
 pub mod claim_htlc;
 pub mod refund_htlc;
+pub mod cpfp_htlc_refund;
+pub mod fund_htlc;
+pub mod get_htlc_replacements;
+pub mod export_htlc_psbt;
+pub mod export_funding_psbt;
+pub mod submit_htlc_psbt;
 pub mod transaction_status;
 pub mod webhooks;
 pub mod fee_estimate;
+pub mod watch_script;
+pub mod get_watched_script;
+pub mod get_watch_events;
 
 // Re-export handlers for easy access
 pub use health_check::health_check;
 pub use create_order::create_order;
 pub use get_order::get_order;
+pub use get_order_events::get_order_events;
 pub use submit_fusion_proof::submit_fusion_proof;
 pub use create_htlc::create_htlc;
 pub use verify_htlc::verify_htlc;
 pub use claim_htlc::claim_htlc;
 pub use refund_htlc::refund_htlc;
+pub use cpfp_htlc_refund::cpfp_htlc_refund;
+pub use fund_htlc::fund_htlc;
+pub use get_htlc_replacements::get_htlc_replacements;
+pub use export_htlc_psbt::export_htlc_psbt;
+pub use export_funding_psbt::export_funding_psbt;
+pub use submit_htlc_psbt::submit_htlc_psbt;
 pub use transaction_status::get_transaction_status;
 pub use webhooks::register_webhook;
-pub use fee_estimate::estimate_fees;
\ No newline at end of file
+pub use fee_estimate::estimate_fees;
+pub use watch_script::watch_script;
+pub use get_watched_script::get_watched_script;
+pub use get_watch_events::get_watch_events;
\ No newline at end of file
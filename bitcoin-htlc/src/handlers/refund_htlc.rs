@@ -4,17 +4,10 @@ use uuid::Uuid;
 
 /// Refund HTLC after timeout
 pub async fn refund_htlc(
-    _state: web::Data<AppState>,
-    _htlc_id: web::Path<Uuid>,
+    state: web::Data<AppState>,
+    htlc_id: web::Path<Uuid>,
 ) -> Result<HttpResponse, ApiError> {
-    // TODO: Implement HTLC refund logic
-    // For now, return a mock response
-    let response = RefundResponse {
-        transaction_id: "abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890".to_string(),
-        status: "broadcast".to_string(),
-        refund_address: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-        refunded_amount: 94000,
-    };
+    let response = state.order_service.refund_htlc(htlc_id.into_inner()).await?;
     
     Ok(HttpResponse::Ok().json(response))
 }
@@ -0,0 +1,37 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::*, AppState};
+use validator::Validate;
+use uuid::Uuid;
+
+/// Finalize and broadcast a signed claim or refund PSBT
+pub async fn submit_htlc_psbt(
+    state: web::Data<AppState>,
+    htlc_id: web::Path<Uuid>,
+    request: web::Json<SubmitPsbtRequest>,
+) -> Result<HttpResponse, ApiError> {
+    request.0.validate()?;
+
+    let response = state
+        .order_service
+        .submit_htlc_psbt(htlc_id.into_inner(), request.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_submit_psbt_validates_preimage() {
+        let mut request = SubmitPsbtRequest {
+            psbt: "cHNidP8BAA==".to_string(),
+            preimage: None,
+        };
+        assert!(request.validate().is_ok());
+
+        request.preimage = Some("short".to_string());
+        assert!(request.validate().is_err());
+    }
+}
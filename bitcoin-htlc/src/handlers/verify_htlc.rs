@@ -1,30 +1,205 @@
 use actix_web::{web, HttpResponse};
-use crate::{models::*, AppState};
+use bitcoin::{consensus::deserialize, OutPoint, PublicKey, Transaction};
+use std::str::FromStr;
+use crate::{
+    models::*,
+    repository::WebhookRepository,
+    services::htlc::{parse_htlc_script, verify_htlc_output},
+    AppState,
+};
 use validator::Validate;
 
 /// Verify HTLC parameters
 pub async fn verify_htlc(
-    _state: web::Data<AppState>,
+    state: web::Data<AppState>,
     request: web::Json<VerifyHtlcRequest>,
 ) -> Result<HttpResponse, ApiError> {
     request.0.validate()?;
     
-    // TODO: Implement HTLC verification logic
-    // For now, return a mock response
+    // Decode the funding transaction
+    let transaction_bytes = hex::decode(&request.transaction_hex)
+        .map_err(|_| ApiError::BadRequest {
+            code: "INVALID_TRANSACTION".to_string(),
+            message: "Transaction hex is not valid hex".to_string(),
+            details: None,
+        })?;
+    let transaction: Transaction = deserialize(&transaction_bytes)
+        .map_err(|e| ApiError::BadRequest {
+            code: "INVALID_TRANSACTION".to_string(),
+            message: format!("Failed to decode transaction: {}", e),
+            details: None,
+        })?;
+    
+    // Rebuild the expected HTLC parameters from the request
+    let mut payment_hash = [0u8; 32];
+    hex::decode_to_slice(&request.expected_payment_hash, &mut payment_hash)
+        .map_err(|_| ApiError::BadRequest {
+            code: "INVALID_PAYMENT_HASH".to_string(),
+            message: "Payment hash must be exactly 32 bytes".to_string(),
+            details: None,
+        })?;
+    
+    let recipient_pubkey = PublicKey::from_str(&request.expected_recipient_pubkey)
+        .map_err(|_| ApiError::BadRequest {
+            code: "INVALID_RECIPIENT_PUBKEY".to_string(),
+            message: "Invalid recipient public key format".to_string(),
+            details: None,
+        })?;
+    
+    let sender_pubkey = PublicKey::from_str(&request.expected_sender_pubkey)
+        .map_err(|_| ApiError::BadRequest {
+            code: "INVALID_SENDER_PUBKEY".to_string(),
+            message: "Invalid sender public key format".to_string(),
+            details: None,
+        })?;
+    
+    let service_network = state.order_service.network();
+    let htlc_params = HtlcParams {
+        sender_pubkey,
+        recipient_pubkey,
+        payment_hash,
+        timeout: request.expected_timeout_height,
+        output_type: request.output_type,
+        network: request.network.unwrap_or(service_network),
+    };
+    
+    let mut verification = verify_htlc_output(
+        &transaction,
+        request.output_index,
+        &htlc_params,
+        request.expected_amount,
+        service_network,
+    )?;
+    
+    // A counterparty's redeem script is audited against what the caller expects
+    if let Some(redeem_script) = &request.redeem_script {
+        let script = hex::decode(redeem_script).map_err(|_| ApiError::BadRequest {
+            code: "INVALID_HTLC_SCRIPT".to_string(),
+            message: "Redeem script is not valid hex".to_string(),
+            details: None,
+        })?;
+        let parsed = parse_htlc_script(&script, htlc_params.network)?;
+        verification
+            .validation_errors
+            .extend(parameter_mismatches(&parsed, &htlc_params));
+    }
+    
+    // A registered HTLC must match what the caller expects, and records its funding once
+    // the chain confirms it
+    if let Some(htlc_id) = request.htlc_id {
+        let htlc = state.htlc_repository.find(htlc_id).await?;
+        if htlc.address != verification.htlc_script.address {
+            verification.validation_errors.push(format!(
+                "Expected parameters do not match HTLC {} at {}",
+                htlc_id, htlc.address
+            ));
+        }
+        
+        if verification.is_valid() {
+            let outpoint = OutPoint::new(transaction.txid(), request.output_index);
+            match funding_problem_on_chain(&state, &htlc, outpoint, verification.actual_amount).await? {
+                Some(problem) => verification.validation_errors.push(problem),
+                None => {
+                    state
+                        .order_service
+                        .record_htlc_funding(&htlc, outpoint, verification.actual_amount)
+                        .await?;
+                    if let Some(order_id) = htlc.order_id {
+                        let data = serde_json::json!({
+                            "orderId": order_id,
+                            "htlcId": htlc.id,
+                            "txid": outpoint.txid.to_string(),
+                            "vout": outpoint.vout,
+                            "amount": verification.actual_amount,
+                        });
+                        WebhookRepository::new(state.pool.clone())
+                            .enqueue(WebhookEvent::OrderBitcoinHtlcVerified, data)
+                            .await?;
+                    }
+                }
+            }
+        }
+    }
+    
     let response = VerifyHtlcResponse {
-        valid: true,
-        htlc_address: "2N8hwP1WmJrFF5QWABn38y63uYLhnJYJYTF".to_string(),
-        actual_amount: request.expected_amount,
-        timeout_height: 500000,
-        validation_errors: vec![],
+        valid: verification.is_valid(),
+        htlc_address: verification.htlc_script.address,
+        actual_amount: verification.actual_amount,
+        timeout_height: htlc_params.timeout,
+        validation_errors: verification.validation_errors,
     };
     
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Why `outpoint` cannot be recorded as the funding of `htlc`, `None` once it is a
+/// confirmed unspent output of `amount` at the HTLC address
+///
+/// The posted transaction is only the caller's claim; the chain has the final word.
+async fn funding_problem_on_chain(
+    state: &AppState,
+    htlc: &HtlcRecord,
+    outpoint: OutPoint,
+    amount: u64,
+) -> Result<Option<String>, ApiError> {
+    let utxos = state.order_service.bitcoin_client().get_utxos(&htlc.address).await?;
+    let utxo = utxos
+        .iter()
+        .find(|utxo| utxo.txid == outpoint.txid.to_string() && utxo.vout == outpoint.vout);
+
+    Ok(match utxo {
+        None => Some(format!(
+            "Funding output {} is not an unspent output at {} on chain",
+            outpoint, htlc.address
+        )),
+        Some(utxo) if !utxo.status.confirmed => Some(format!(
+            "Funding transaction {} is not confirmed yet",
+            outpoint.txid
+        )),
+        Some(utxo) if utxo.value != amount => Some(format!(
+            "Funding output {} holds {} sat on chain, not {}",
+            outpoint, utxo.value, amount
+        )),
+        Some(_) => None,
+    })
+}
+
+/// Describe every field in which a parsed redeem script differs from the expected parameters
+fn parameter_mismatches(parsed: &HtlcParams, expected: &HtlcParams) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    if parsed.payment_hash != expected.payment_hash {
+        mismatches.push(format!(
+            "Redeem script payment hash {} does not match expected {}",
+            hex::encode(parsed.payment_hash),
+            hex::encode(expected.payment_hash)
+        ));
+    }
+    if parsed.recipient_pubkey != expected.recipient_pubkey {
+        mismatches.push(format!(
+            "Redeem script recipient key {} does not match expected {}",
+            parsed.recipient_pubkey, expected.recipient_pubkey
+        ));
+    }
+    if parsed.sender_pubkey != expected.sender_pubkey {
+        mismatches.push(format!(
+            "Redeem script sender key {} does not match expected {}",
+            parsed.sender_pubkey, expected.sender_pubkey
+        ));
+    }
+    if parsed.timeout != expected.timeout {
+        mismatches.push(format!(
+            "Redeem script timeout {} does not match expected {}",
+            parsed.timeout, expected.timeout
+        ));
+    }
+    mismatches
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::bitcoin::SimulatedChain;
+    use crate::test_support::test_pool;
 
     fn create_mock_verify_request() -> VerifyHtlcRequest {
         VerifyHtlcRequest {
@@ -34,6 +209,11 @@ mod tests {
             expected_payment_hash: "1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef".to_string(),
             expected_recipient_pubkey: "02789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd".to_string(),
             expected_sender_pubkey: "03789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd".to_string(),
+            expected_timeout_height: 2_500_144,
+            output_type: HtlcOutputType::P2wsh,
+            network: None,
+            htlc_id: None,
+            redeem_script: None,
         }
     }
 
@@ -64,4 +244,177 @@ mod tests {
         let request = create_mock_verify_request();
         assert!(request.validate().is_ok());
     }
-}
\ No newline at end of file
+
+    async fn app_state() -> web::Data<AppState> {
+        let pool = test_pool().await;
+        web::Data::new(AppState::new(pool))
+    }
+
+    async fn app_state_on(chain: std::sync::Arc<SimulatedChain>) -> web::Data<AppState> {
+        let state = app_state().await;
+        web::Data::new(AppState::with_bitcoin_client(
+            state.pool.clone(),
+            crate::services::bitcoin::BitcoinClient::from_arc(chain),
+        ))
+    }
+
+    fn expected_params(request: &VerifyHtlcRequest) -> HtlcParams {
+        let mut payment_hash = [0u8; 32];
+        hex::decode_to_slice(&request.expected_payment_hash, &mut payment_hash).unwrap();
+        HtlcParams {
+            sender_pubkey: PublicKey::from_str(&request.expected_sender_pubkey).unwrap(),
+            recipient_pubkey: PublicKey::from_str(&request.expected_recipient_pubkey).unwrap(),
+            payment_hash,
+            timeout: request.expected_timeout_height,
+            output_type: request.output_type,
+            network: bitcoin::Network::Testnet,
+        }
+    }
+
+    fn funding_hex(request: &VerifyHtlcRequest, amount: u64) -> String {
+        let htlc = crate::services::build_htlc_script(&expected_params(request)).unwrap();
+
+        let transaction = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(amount),
+                script_pubkey: htlc.script_pubkey,
+            }],
+        };
+        bitcoin::consensus::encode::serialize_hex(&transaction)
+    }
+
+    async fn verify(request: VerifyHtlcRequest) -> VerifyHtlcResponse {
+        verify_with(app_state().await, request).await
+    }
+
+    async fn verify_with(state: web::Data<AppState>, request: VerifyHtlcRequest) -> VerifyHtlcResponse {
+        let response = verify_htlc(state, web::Json(request)).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_verify_htlc_accepts_matching_funding_output() {
+        let mut request = create_mock_verify_request();
+        request.transaction_hex = funding_hex(&request, request.expected_amount);
+
+        let response = verify(request).await;
+        assert!(response.valid, "{:?}", response.validation_errors);
+        assert_eq!(response.actual_amount, 100000);
+        assert_eq!(response.timeout_height, 2_500_144);
+    }
+
+    #[actix_rt::test]
+    async fn test_verify_htlc_reports_amount_and_timeout_mismatch() {
+        let mut request = create_mock_verify_request();
+        request.transaction_hex = funding_hex(&request, 99_000);
+        request.expected_timeout_height += 1;
+
+        let response = verify(request).await;
+        assert!(!response.valid);
+        assert_eq!(response.actual_amount, 99_000);
+        assert_eq!(response.validation_errors.len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_verify_htlc_rejects_undecodable_transaction() {
+        let result = verify_htlc(app_state().await, web::Json(create_mock_verify_request())).await;
+        match result {
+            Err(ApiError::BadRequest { code, .. }) => assert_eq!(code, "INVALID_TRANSACTION"),
+            other => panic!("Expected BadRequest, got {:?}", other.map(|r| r.status())),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_verify_htlc_records_funding_of_registered_htlc() {
+        let chain = std::sync::Arc::new(SimulatedChain::new(bitcoin::Network::Testnet, 2_500_000));
+        let state = app_state_on(chain.clone()).await;
+        let mut request = create_mock_verify_request();
+
+        let params = expected_params(&request);
+        let htlc = HtlcRecord::new(params.clone(), &crate::services::build_htlc_script(&params).unwrap());
+        state.htlc_repository.insert(&htlc).await.unwrap();
+        request.htlc_id = Some(htlc.id);
+
+        let address = bitcoin::Address::from_str(&htlc.address).unwrap().assume_checked();
+        let funding = chain.fund(&address, bitcoin::Amount::from_sat(request.expected_amount));
+        request.transaction_hex = bitcoin::consensus::encode::serialize_hex(&chain.transaction(&funding.txid).unwrap());
+
+        // Not recorded while the funding is only in the mempool
+        let response = verify_with(state.clone(), request.clone()).await;
+        assert!(!response.valid);
+        assert!(response.validation_errors[0].contains("not confirmed"), "{:?}", response.validation_errors);
+        assert_eq!(state.htlc_repository.find(htlc.id).await.unwrap().status, HtlcStatus::Created);
+
+        chain.mine(1);
+        let response = verify_with(state.clone(), request.clone()).await;
+        assert!(response.valid, "{:?}", response.validation_errors);
+
+        let stored = state.htlc_repository.find(htlc.id).await.unwrap();
+        assert_eq!(stored.status, HtlcStatus::Funded);
+        assert_eq!(stored.amount, Some(100000));
+        assert_eq!(stored.funding_outpoint.unwrap().vout, 0);
+
+        // Parameters of another HTLC do not verify against the registered one
+        let mut other = request.clone();
+        other.expected_timeout_height += 1;
+        other.transaction_hex = funding_hex(&other, other.expected_amount);
+        let response = verify_with(state, other).await;
+        assert!(!response.valid);
+        assert_eq!(response.validation_errors.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_verify_htlc_does_not_record_funding_missing_from_chain() {
+        let chain = std::sync::Arc::new(SimulatedChain::new(bitcoin::Network::Testnet, 2_500_000));
+        let state = app_state_on(chain).await;
+        let mut request = create_mock_verify_request();
+        request.transaction_hex = funding_hex(&request, request.expected_amount);
+
+        let params = expected_params(&request);
+        let htlc = HtlcRecord::new(params.clone(), &crate::services::build_htlc_script(&params).unwrap());
+        state.htlc_repository.insert(&htlc).await.unwrap();
+        request.htlc_id = Some(htlc.id);
+
+        // A well-formed transaction the chain has never seen
+        let response = verify_with(state.clone(), request).await;
+        assert!(!response.valid);
+        assert!(response.validation_errors[0].contains("not an unspent output"));
+        let stored = state.htlc_repository.find(htlc.id).await.unwrap();
+        assert_eq!(stored.status, HtlcStatus::Created);
+        assert_eq!(stored.funding_outpoint, None);
+    }
+
+    #[actix_rt::test]
+    async fn test_verify_htlc_audits_counterparty_redeem_script() {
+        let mut request = create_mock_verify_request();
+        request.transaction_hex = funding_hex(&request, request.expected_amount);
+        let script = crate::services::build_htlc_script(&expected_params(&request)).unwrap();
+        request.redeem_script = Some(hex::encode(&script.redeem_script));
+
+        let response = verify(request.clone()).await;
+        assert!(response.valid, "{:?}", response.validation_errors);
+
+        // A script locking to another hash and timeout is reported field by field
+        let mut other = request.clone();
+        other.expected_payment_hash = "ab".repeat(32);
+        other.expected_timeout_height += 1;
+        let other_script = crate::services::build_htlc_script(&expected_params(&other)).unwrap();
+        request.redeem_script = Some(hex::encode(&other_script.redeem_script));
+
+        let response = verify(request.clone()).await;
+        assert!(!response.valid);
+        assert_eq!(response.validation_errors.len(), 2);
+        assert!(response.validation_errors[0].contains("payment hash"));
+        assert!(response.validation_errors[1].contains("timeout"));
+
+        request.redeem_script = Some("00".to_string());
+        match verify_htlc(app_state().await, web::Json(request)).await {
+            Err(ApiError::BadRequest { code, .. }) => assert_eq!(code, "INVALID_HTLC_SCRIPT"),
+            other => panic!("Expected BadRequest, got {:?}", other.map(|r| r.status())),
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use actix_web::{web, HttpResponse};
+use crate::{models::*, AppState};
+use validator::Validate;
+
+/// Watch an address or scriptPubKey until it is funded and spent
+pub async fn watch_script(
+    state: web::Data<AppState>,
+    request: web::Json<WatchScriptRequest>,
+) -> Result<HttpResponse, ApiError> {
+    request.0.validate()?;
+
+    let response = state.order_service.watch_script(request.into_inner()).await?;
+    Ok(HttpResponse::Created().json(response))
+}
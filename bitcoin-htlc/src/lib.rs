@@ -5,6 +5,10 @@ pub mod services;
 pub mod utils;
 pub mod swagger;
 pub mod middleware;
+pub mod repository;
+
+#[cfg(test)]
+mod test_support;
 
 use actix_web::web;
 use sqlx::SqlitePool;
@@ -13,16 +17,23 @@ use sqlx::SqlitePool;
 pub struct AppState {
     pub pool: SqlitePool,
     pub order_service: services::order::OrderService,
+    pub htlc_repository: repository::HtlcRepository,
 }
 
 impl AppState {
     pub fn new(pool: SqlitePool) -> Self {
-        let bitcoin_client = services::bitcoin::BitcoinClient::new();
+        Self::with_bitcoin_client(pool, services::bitcoin::BitcoinClient::new())
+    }
+
+    /// App state talking to a specific chain backend instead of the one from the environment
+    pub fn with_bitcoin_client(pool: SqlitePool, bitcoin_client: services::bitcoin::BitcoinClient) -> Self {
         let order_service = services::order::OrderService::new(pool.clone(), bitcoin_client);
+        let htlc_repository = repository::HtlcRepository::new(pool.clone());
         
         Self {
             pool,
             order_service,
+            htlc_repository,
         }
     }
 }
@@ -5,7 +5,14 @@ use env_logger::Env;
 use log::info;
 use sqlx::sqlite::SqlitePoolOptions;
 use std::env;
-use thunder_portal::{AppState, configure_app, middleware::ApiKeyAuth};
+use thunder_portal::{
+    AppState, configure_app, middleware::ApiKeyAuth,
+    services::{
+        bitcoin::{BitcoinClient, ElectrumClient, ZmqSubscriber},
+        Orchestrator,
+    },
+};
+use tokio::sync::broadcast;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -34,8 +41,34 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to run migrations");
 
+    // Chain backends that push notifications wake the orchestrator through this channel
+    let (chain_events, _) = broadcast::channel(1024);
+    let mut pushes_chain_events = false;
+
     // Create application state
-    let app_state = AppState::new(pool);
+    let bitcoin_client = match ElectrumClient::from_env() {
+        Some(electrum) => {
+            info!("Using Electrum server at {}", electrum.url);
+            pushes_chain_events = true;
+            BitcoinClient::from_backend(electrum.with_chain_events(chain_events.clone()))
+        }
+        None => BitcoinClient::new(),
+    };
+    let app_state = AppState::with_bitcoin_client(pool, bitcoin_client);
+
+    if let Some(subscriber) = ZmqSubscriber::from_env() {
+        info!("Listening for bitcoind notifications at {}", subscriber.endpoint());
+        subscriber.spawn(chain_events.clone());
+        pushes_chain_events = true;
+    }
+
+    // Drive active orders in the background
+    let mut orchestrator = Orchestrator::new(app_state.order_service.clone());
+    if pushes_chain_events {
+        orchestrator = orchestrator.with_chain_events(chain_events);
+    }
+    info!("Starting swap orchestrator {}", orchestrator.instance_id());
+    orchestrator.spawn();
 
     info!("Starting HTTP server on {}:{}", host, port);
 
@@ -11,13 +11,17 @@ pub struct CreateHtlcRequest {
     pub timeout_blocks: u32,
     #[validate(regex(path = "crate::utils::PUBKEY_REGEX"))]
     pub resolver_public_key: Option<String>,
+    #[serde(default)]
+    pub output_type: HtlcOutputType,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateHtlcResponse {
+    pub htlc_id: uuid::Uuid,
     pub htlc_script: String,
     pub htlc_address: String,
     pub script_hash: String,
+    pub output_type: HtlcOutputType,
     pub timeout_blocks: u32,
     pub estimated_timeout_timestamp: u64,
 }
@@ -33,6 +37,21 @@ pub struct VerifyHtlcRequest {
     pub expected_recipient_pubkey: String,
     #[validate(regex(path = "crate::utils::PUBKEY_REGEX"))]
     pub expected_sender_pubkey: String,
+    #[validate(range(min = 1))]
+    pub expected_timeout_height: u32,
+    #[serde(default)]
+    pub output_type: HtlcOutputType,
+    /// Network the caller expects the HTLC on, defaults to the service network
+    #[serde(default)]
+    pub network: Option<bitcoin::Network>,
+    /// Registered HTLC the funding is for; its stored parameters must match and the
+    /// funding outpoint is recorded when verification succeeds
+    #[serde(default)]
+    pub htlc_id: Option<uuid::Uuid>,
+    /// Redeem script (hex) built by the counterparty; decoded and compared with the
+    /// expected parameters field by field
+    #[serde(default)]
+    pub redeem_script: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -67,12 +86,267 @@ pub struct RefundResponse {
     pub refunded_amount: u64,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct CpfpRequest {
+    /// Address the child pays to, defaults to the wallet receiving the refund
+    pub destination_address: Option<String>,
+    /// sat/vB the refund and its child pay together, defaults to the fastest estimate
+    #[validate(range(min = 1, max = 10000))]
+    pub fee_rate: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpfpResponse {
+    pub transaction_id: String,
+    /// The refund the child pays for
+    pub parent_transaction_id: String,
+    pub destination_address: String,
+    pub amount: u64,
+    /// Paid by the child alone
+    pub fee: u64,
+    /// sat/vB of refund and child together
+    pub package_fee_rate: u64,
+}
+
+/// Branch of the HTLC script a spend takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HtlcSpendPath {
+    /// Recipient signature and preimage
+    Claim,
+    /// Sender signature after the timeout height
+    Refund,
+}
+
+impl HtlcSpendPath {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HtlcSpendPath::Claim => "claim",
+            HtlcSpendPath::Refund => "refund",
+        }
+    }
+}
+
+impl std::str::FromStr for HtlcSpendPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "claim" => Ok(HtlcSpendPath::Claim),
+            "refund" => Ok(HtlcSpendPath::Refund),
+            other => Err(format!("Unknown HTLC spend path {}", other)),
+        }
+    }
+}
+
+/// Fee bump of a claim or refund, as recorded in the `htlc_replacements` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HtlcReplacement {
+    pub htlc_id: uuid::Uuid,
+    pub spend_path: HtlcSpendPath,
+    /// Spend the replacement evicts from mempools
+    pub replaced_txid: String,
+    pub txid: String,
+    pub fee: u64,
+    /// sat/vB
+    pub fee_rate: u64,
+    /// Chain tip when the replacement was broadcast
+    pub block_height: u32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Role of a transaction watched for reorgs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HtlcTransactionKind {
+    Funding,
+    Claim,
+    Refund,
+}
+
+impl HtlcTransactionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HtlcTransactionKind::Funding => "funding",
+            HtlcTransactionKind::Claim => "claim",
+            HtlcTransactionKind::Refund => "refund",
+        }
+    }
+}
+
+impl std::str::FromStr for HtlcTransactionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "funding" => Ok(HtlcTransactionKind::Funding),
+            "claim" => Ok(HtlcTransactionKind::Claim),
+            "refund" => Ok(HtlcTransactionKind::Refund),
+            other => Err(format!("Unknown HTLC transaction kind {}", other)),
+        }
+    }
+}
+
+/// Block a funding, claim or refund was last seen confirmed in, as recorded in the
+/// `tx_confirmations` table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxConfirmation {
+    pub txid: String,
+    pub htlc_id: uuid::Uuid,
+    pub kind: HtlcTransactionKind,
+    pub block_height: u32,
+    pub block_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SpendPsbtRequest {
+    /// Address the claim or refund pays to
+    pub destination_address: String,
+    /// Added to claim PSBTs so the finalizer has it; may also be given on submission
+    #[validate(regex(path = "crate::utils::HASH_REGEX"))]
+    pub preimage: Option<String>,
+    /// sat/vB, defaults to the half hour estimate
+    #[validate(range(min = 1, max = 10000))]
+    pub fee_rate: Option<u64>,
+    /// `[fingerprint/path]` origin of the signing key, for signers that look keys up by
+    /// derivation rather than by public key
+    pub key_origin: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FundHtlcRequest {
+    #[validate(range(min = 1))]
+    pub amount: u64,
+    /// sat/vB, defaults to the half hour estimate
+    #[validate(range(min = 1, max = 10000))]
+    pub fee_rate: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundHtlcResponse {
+    pub transaction_id: String,
+    pub htlc_address: String,
+    pub amount: u64,
+    pub fee: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FundingPsbtRequest {
+    /// Wallet address whose coins fund the HTLC and which receives the change
+    pub wallet_address: String,
+    #[validate(range(min = 1))]
+    pub amount: u64,
+    /// sat/vB, defaults to the half hour estimate
+    #[validate(range(min = 1, max = 10000))]
+    pub fee_rate: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PsbtResponse {
+    /// Unsigned BIP-174 PSBT, base64 encoded
+    pub psbt: String,
+    pub fee: u64,
+    pub fee_rate: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SubmitPsbtRequest {
+    /// Signed claim or refund PSBT, base64 encoded
+    pub psbt: String,
+    /// Preimage for claim PSBTs that do not carry it
+    #[validate(regex(path = "crate::utils::HASH_REGEX"))]
+    pub preimage: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitPsbtResponse {
+    pub transaction_id: String,
+    pub status: String,
+    pub spend_path: HtlcSpendPath,
+    pub destination_address: String,
+    pub amount: u64,
+}
+
+/// How the HTLC redeem script is committed to in the funding output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HtlcOutputType {
+    /// Legacy P2SH - redeem script and signature are carried in the scriptSig
+    #[default]
+    P2sh,
+    /// P2WSH nested in P2SH - scriptSig only pushes the witness program
+    P2shP2wsh,
+    /// Native segwit v0 P2WSH
+    P2wsh,
+    /// Taproot - hashlock and timelock tap leaves under a MuSig2 user/resolver internal key
+    P2tr,
+}
+
+impl HtlcOutputType {
+    /// Whether spends of this output carry their signatures in the witness
+    pub fn is_segwit(&self) -> bool {
+        !matches!(self, HtlcOutputType::P2sh)
+    }
+
+    /// Whether spends of this output are ECDSA signatures over the redeem script
+    pub fn uses_redeem_script(&self) -> bool {
+        !matches!(self, HtlcOutputType::P2tr)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HtlcOutputType::P2sh => "p2sh",
+            HtlcOutputType::P2shP2wsh => "p2sh_p2wsh",
+            HtlcOutputType::P2wsh => "p2wsh",
+            HtlcOutputType::P2tr => "p2tr",
+        }
+    }
+}
+
+impl std::str::FromStr for HtlcOutputType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p2sh" => Ok(HtlcOutputType::P2sh),
+            "p2sh_p2wsh" => Ok(HtlcOutputType::P2shP2wsh),
+            "p2wsh" => Ok(HtlcOutputType::P2wsh),
+            "p2tr" => Ok(HtlcOutputType::P2tr),
+            other => Err(format!("Unknown HTLC output type {}", other)),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HtlcScript {
+    /// IF/ELSE redeem script; for P2TR it is only a canonical encoding of the
+    /// parameters, the output commits to the tap leaves instead
     pub redeem_script: Vec<u8>,
-    #[allow(dead_code)]
-    pub script_hash: [u8; 32],
-    pub p2sh_address: String,
+    /// What the output commits to: HASH160 of the redeem script for P2SH, its SHA256
+    /// (the P2WSH witness program) for the segwit types, the tap tree merkle root for P2TR
+    pub script_hash: Vec<u8>,
+    pub address: String,
+    pub script_pubkey: bitcoin::ScriptBuf,
+    pub output_type: HtlcOutputType,
+    pub taproot: Option<TaprootHtlc>,
+}
+
+/// Tap tree of a P2TR HTLC
+///
+/// Both leaves sit at depth one, so each control block proves one sibling.
+#[derive(Debug, Clone)]
+pub struct TaprootHtlc {
+    /// MuSig2 aggregate of the recipient and sender keys (key path)
+    pub internal_key: bitcoin::key::XOnlyPublicKey,
+    /// Internal key tweaked with the merkle root, as found in the scriptPubKey
+    pub output_key: bitcoin::key::TweakedPublicKey,
+    pub merkle_root: bitcoin::taproot::TapNodeHash,
+    /// `OP_SHA256 <payment_hash> OP_EQUALVERIFY <recipient> OP_CHECKSIG`
+    pub claim_leaf: bitcoin::ScriptBuf,
+    /// `<timeout> OP_CLTV OP_DROP <sender> OP_CHECKSIG`
+    pub refund_leaf: bitcoin::ScriptBuf,
+    pub claim_control_block: bitcoin::taproot::ControlBlock,
+    pub refund_control_block: bitcoin::taproot::ControlBlock,
 }
 
 #[derive(Debug, Clone)]
@@ -81,4 +355,85 @@ pub struct HtlcParams {
     pub sender_pubkey: bitcoin::PublicKey,
     pub payment_hash: [u8; 32],
     pub timeout: u32,
-}
\ No newline at end of file
+    pub output_type: HtlcOutputType,
+    pub network: bitcoin::Network,
+}
+
+/// Lifecycle of a persisted HTLC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HtlcStatus {
+    Created,
+    Funded,
+    Claimed,
+    Refunded,
+}
+
+impl HtlcStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HtlcStatus::Created => "created",
+            HtlcStatus::Funded => "funded",
+            HtlcStatus::Claimed => "claimed",
+            HtlcStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl std::str::FromStr for HtlcStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(HtlcStatus::Created),
+            "funded" => Ok(HtlcStatus::Funded),
+            "claimed" => Ok(HtlcStatus::Claimed),
+            "refunded" => Ok(HtlcStatus::Refunded),
+            other => Err(format!("Unknown HTLC status {}", other)),
+        }
+    }
+}
+
+/// HTLC as stored in the `htlcs` table
+#[derive(Debug, Clone)]
+pub struct HtlcRecord {
+    pub id: uuid::Uuid,
+    /// Order the HTLC was created for, `None` for standalone `/htlc/create` calls
+    pub order_id: Option<uuid::Uuid>,
+    pub status: HtlcStatus,
+    pub params: HtlcParams,
+    pub redeem_script: Vec<u8>,
+    pub address: String,
+    /// Confirmations the funding needs before the HTLC may be claimed
+    pub min_confirmations: u32,
+    pub amount: Option<u64>,
+    pub funding_outpoint: Option<bitcoin::OutPoint>,
+    pub claim_txid: Option<String>,
+    pub refund_txid: Option<String>,
+    pub preimage: Option<Vec<u8>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl HtlcRecord {
+    /// New unfunded HTLC for the given parameters and their built script
+    pub fn new(params: HtlcParams, htlc_script: &HtlcScript) -> Self {
+        let now = chrono::Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4(),
+            order_id: None,
+            status: HtlcStatus::Created,
+            params,
+            redeem_script: htlc_script.redeem_script.clone(),
+            address: htlc_script.address.clone(),
+            min_confirmations: 1,
+            amount: None,
+            funding_outpoint: None,
+            claim_txid: None,
+            refund_txid: None,
+            preimage: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
@@ -1,7 +1,11 @@
 pub mod order;
 pub mod htlc;
 pub mod error;
+pub mod watchlist;
+pub mod webhook;
 
 pub use order::*;
 pub use htlc::*;
-pub use error::*;
\ No newline at end of file
+pub use error::*;
+pub use watchlist::*;
+pub use webhook::*;
\ No newline at end of file
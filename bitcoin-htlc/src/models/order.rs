@@ -12,7 +12,7 @@ pub enum SwapDirection {
     BtcToEth,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum OrderStatus {
     Created,
@@ -31,6 +31,119 @@ pub enum OrderStatus {
     Failed,
 }
 
+impl SwapDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwapDirection::EthToBtc => "ETH_TO_BTC",
+            SwapDirection::BtcToEth => "BTC_TO_ETH",
+        }
+    }
+}
+
+impl std::str::FromStr for SwapDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ETH_TO_BTC" => Ok(SwapDirection::EthToBtc),
+            "BTC_TO_ETH" => Ok(SwapDirection::BtcToEth),
+            other => Err(format!("Unknown swap direction {}", other)),
+        }
+    }
+}
+
+impl OrderStatus {
+    /// Value stored in the `orders.status` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Created => "created",
+            OrderStatus::AwaitingFusionProof => "awaiting_fusion_proof",
+            OrderStatus::FusionProofVerified => "fusion_proof_verified",
+            OrderStatus::BitcoinHtlcCreated => "bitcoin_htlc_created",
+            OrderStatus::BitcoinHtlcFunded => "bitcoin_htlc_funded",
+            OrderStatus::BitcoinHtlcConfirmed => "bitcoin_htlc_confirmed",
+            OrderStatus::FusionOrderFillable => "fusion_order_fillable",
+            OrderStatus::FusionOrderFilling => "fusion_order_filling",
+            OrderStatus::FusionOrderFilled => "fusion_order_filled",
+            OrderStatus::PreimageRevealed => "preimage_revealed",
+            OrderStatus::BitcoinHtlcClaimed => "bitcoin_htlc_claimed",
+            OrderStatus::Completed => "completed",
+            OrderStatus::Expired => "expired",
+            OrderStatus::Failed => "failed",
+        }
+    }
+
+    /// Completed, expired and failed orders never change again
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, OrderStatus::Completed | OrderStatus::Expired | OrderStatus::Failed)
+    }
+
+    /// Transition table of the swap lifecycle
+    ///
+    /// Any live order may fail. It may expire until the preimage is out, after which
+    /// the claim has to go through.
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        use OrderStatus::*;
+
+        if self.is_terminal() {
+            return false;
+        }
+        match next {
+            Failed => return true,
+            Expired => return !matches!(self, PreimageRevealed | BitcoinHtlcClaimed),
+            _ => {}
+        }
+
+        matches!(
+            (self, next),
+            (Created, AwaitingFusionProof | FusionProofVerified | BitcoinHtlcCreated)
+                | (AwaitingFusionProof, FusionProofVerified | BitcoinHtlcCreated)
+                | (FusionProofVerified, BitcoinHtlcCreated)
+                | (BitcoinHtlcCreated, BitcoinHtlcFunded)
+                | (BitcoinHtlcFunded, BitcoinHtlcConfirmed | BitcoinHtlcClaimed)
+                | (BitcoinHtlcConfirmed, FusionOrderFillable | PreimageRevealed | BitcoinHtlcClaimed)
+                | (FusionOrderFillable, FusionOrderFilling)
+                | (FusionOrderFilling, FusionOrderFilled)
+                | (FusionOrderFilled, PreimageRevealed | BitcoinHtlcClaimed)
+                | (PreimageRevealed, BitcoinHtlcClaimed | Completed)
+                | (BitcoinHtlcClaimed, Completed)
+        )
+    }
+
+    /// Steps a reorg may undo
+    ///
+    /// Only statuses that rest on a confirmation alone go back. Once the Fusion order
+    /// is live or the preimage is out, other chains have acted on it and the order
+    /// stays where it is.
+    pub fn can_roll_back_to(&self, previous: OrderStatus) -> bool {
+        matches!((self, previous), (OrderStatus::BitcoinHtlcConfirmed, OrderStatus::BitcoinHtlcFunded))
+    }
+}
+
+impl std::str::FromStr for OrderStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(OrderStatus::Created),
+            "awaiting_fusion_proof" => Ok(OrderStatus::AwaitingFusionProof),
+            "fusion_proof_verified" => Ok(OrderStatus::FusionProofVerified),
+            "bitcoin_htlc_created" => Ok(OrderStatus::BitcoinHtlcCreated),
+            "bitcoin_htlc_funded" => Ok(OrderStatus::BitcoinHtlcFunded),
+            "bitcoin_htlc_confirmed" => Ok(OrderStatus::BitcoinHtlcConfirmed),
+            "fusion_order_fillable" => Ok(OrderStatus::FusionOrderFillable),
+            "fusion_order_filling" => Ok(OrderStatus::FusionOrderFilling),
+            "fusion_order_filled" => Ok(OrderStatus::FusionOrderFilled),
+            "preimage_revealed" => Ok(OrderStatus::PreimageRevealed),
+            "bitcoin_htlc_claimed" => Ok(OrderStatus::BitcoinHtlcClaimed),
+            "completed" => Ok(OrderStatus::Completed),
+            "expired" => Ok(OrderStatus::Expired),
+            "failed" => Ok(OrderStatus::Failed),
+            other => Err(format!("Unknown order status {}", other)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenInfo {
     pub symbol: String,
@@ -228,11 +341,24 @@ pub struct Order {
     pub htlc_address: Option<String>,
     pub htlc_redeem_script: Option<String>,
     pub htlc_funding_tx: Option<String>,
+    pub htlc_claim_tx: Option<String>,
+    pub htlc_refund_tx: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub expires_at: DateTime<Utc>,
 }
 
+/// One row of the `order_events` audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderEvent {
+    pub order_id: Uuid,
+    /// `None` for the event recording the order's creation
+    pub from_status: Option<OrderStatus>,
+    pub to_status: OrderStatus,
+    pub cause: String,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderDetails {
     pub order_id: Uuid,
@@ -288,4 +414,49 @@ pub struct OrderConfirmations {
     pub bitcoin_current: u32,
     pub ethereum_required: u32,
     pub ethereum_current: u32,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_order_status_round_trips_through_column_value() {
+        for status in [
+            OrderStatus::Created,
+            OrderStatus::BitcoinHtlcConfirmed,
+            OrderStatus::PreimageRevealed,
+            OrderStatus::Failed,
+        ] {
+            assert_eq!(OrderStatus::from_str(status.as_str()), Ok(status));
+            assert_eq!(serde_json::to_value(status).unwrap(), status.as_str());
+        }
+        assert!(OrderStatus::from_str("bogus").is_err());
+    }
+
+    #[test]
+    fn test_transition_table() {
+        assert!(OrderStatus::Created.can_transition_to(OrderStatus::BitcoinHtlcCreated));
+        assert!(OrderStatus::BitcoinHtlcConfirmed.can_transition_to(OrderStatus::BitcoinHtlcClaimed));
+        assert!(OrderStatus::BitcoinHtlcFunded.can_transition_to(OrderStatus::Expired));
+        assert!(OrderStatus::PreimageRevealed.can_transition_to(OrderStatus::Failed));
+
+        // No skipping ahead, no going back, no expiry once the preimage is public
+        assert!(!OrderStatus::Created.can_transition_to(OrderStatus::BitcoinHtlcClaimed));
+        assert!(!OrderStatus::BitcoinHtlcFunded.can_transition_to(OrderStatus::BitcoinHtlcCreated));
+        assert!(!OrderStatus::PreimageRevealed.can_transition_to(OrderStatus::Expired));
+        assert!(!OrderStatus::Created.can_transition_to(OrderStatus::Created));
+
+        for terminal in [OrderStatus::Completed, OrderStatus::Expired, OrderStatus::Failed] {
+            assert!(!terminal.can_transition_to(OrderStatus::Failed));
+        }
+    }
+
+    #[test]
+    fn test_reorgs_only_undo_confirmations() {
+        assert!(OrderStatus::BitcoinHtlcConfirmed.can_roll_back_to(OrderStatus::BitcoinHtlcFunded));
+        assert!(!OrderStatus::FusionOrderFillable.can_roll_back_to(OrderStatus::BitcoinHtlcFunded));
+        assert!(!OrderStatus::BitcoinHtlcFunded.can_roll_back_to(OrderStatus::BitcoinHtlcCreated));
+        assert!(!OrderStatus::Completed.can_roll_back_to(OrderStatus::BitcoinHtlcClaimed));
+    }
+}
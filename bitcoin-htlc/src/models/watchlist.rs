@@ -0,0 +1,147 @@
+use bitcoin::{OutPoint, ScriptBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct WatchScriptRequest {
+    /// Address to watch; give either this or `script_pubkey`
+    pub address: Option<String>,
+    /// Hex scriptPubKey to watch, for outputs without an address
+    pub script_pubkey: Option<String>,
+    /// Block depth at which the funding counts as confirmed, 1 by default
+    #[validate(range(min = 1, max = 100))]
+    pub min_confirmations: Option<u32>,
+}
+
+/// Where a watched script is in its life, as far as the scanned blocks tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchStatus {
+    /// No output paying the script has been seen in a block yet
+    Watching,
+    Funded,
+    /// The funding is at least `min_confirmations` deep
+    Confirmed,
+    Spent,
+}
+
+impl WatchStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchStatus::Watching => "watching",
+            WatchStatus::Funded => "funded",
+            WatchStatus::Confirmed => "confirmed",
+            WatchStatus::Spent => "spent",
+        }
+    }
+}
+
+impl std::str::FromStr for WatchStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "watching" => Ok(WatchStatus::Watching),
+            "funded" => Ok(WatchStatus::Funded),
+            "confirmed" => Ok(WatchStatus::Confirmed),
+            "spent" => Ok(WatchStatus::Spent),
+            other => Err(format!("Unknown watch status {}", other)),
+        }
+    }
+}
+
+/// Script watched for fundings and spends, as stored in the `watched_scripts` table
+///
+/// Only the first output paying the script is followed; later ones are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchedScript {
+    pub id: Uuid,
+    pub script_pubkey: ScriptBuf,
+    /// HTLC whose address this is, `None` for scripts watched through the API
+    pub htlc_id: Option<Uuid>,
+    pub min_confirmations: u32,
+    pub status: WatchStatus,
+    pub funding_outpoint: Option<OutPoint>,
+    pub funding_amount: Option<u64>,
+    pub funding_height: Option<u32>,
+    pub spend_txid: Option<String>,
+    pub spend_height: Option<u32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl WatchedScript {
+    pub fn new(script_pubkey: ScriptBuf, min_confirmations: u32, htlc_id: Option<Uuid>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            script_pubkey,
+            htlc_id,
+            min_confirmations,
+            status: WatchStatus::Watching,
+            funding_outpoint: None,
+            funding_amount: None,
+            funding_height: None,
+            spend_txid: None,
+            spend_height: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Whether the funding has `min_confirmations` once `height` is the scanned tip
+    pub fn is_confirmed_at(&self, height: u32) -> bool {
+        self.funding_height
+            .is_some_and(|funding_height| height + 1 >= funding_height + self.min_confirmations)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchEventKind {
+    Funded,
+    Confirmed,
+    Spent,
+    /// The block holding the funding or spend left the active chain
+    Reorged,
+}
+
+impl WatchEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchEventKind::Funded => "funded",
+            WatchEventKind::Confirmed => "confirmed",
+            WatchEventKind::Spent => "spent",
+            WatchEventKind::Reorged => "reorged",
+        }
+    }
+}
+
+impl std::str::FromStr for WatchEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "funded" => Ok(WatchEventKind::Funded),
+            "confirmed" => Ok(WatchEventKind::Confirmed),
+            "spent" => Ok(WatchEventKind::Spent),
+            "reorged" => Ok(WatchEventKind::Reorged),
+            other => Err(format!("Unknown watch event kind {}", other)),
+        }
+    }
+}
+
+/// Change to a watched script, as recorded in the `watch_events` table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchEvent {
+    pub watch_id: Uuid,
+    pub htlc_id: Option<Uuid>,
+    pub kind: WatchEventKind,
+    /// Funding or spend the event is about
+    pub txid: String,
+    /// Block the transaction is in, or was in for `reorged`
+    pub block_height: u32,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,130 @@
+use super::{OrderStatus, WatchEventKind};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookRegistration {
+    #[validate(url)]
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    #[validate(length(min = 32))]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    #[serde(rename = "order.created")]
+    OrderCreated,
+    #[serde(rename = "order.fusion_proof_submitted")]
+    OrderFusionProofSubmitted,
+    #[serde(rename = "order.bitcoin_htlc_created")]
+    OrderBitcoinHtlcCreated,
+    #[serde(rename = "order.bitcoin_htlc_funded")]
+    OrderBitcoinHtlcFunded,
+    #[serde(rename = "order.bitcoin_htlc_verified")]
+    OrderBitcoinHtlcVerified,
+    #[serde(rename = "order.bitcoin_htlc_confirmed")]
+    OrderBitcoinHtlcConfirmed,
+    #[serde(rename = "order.fusion_order_filled")]
+    OrderFusionOrderFilled,
+    #[serde(rename = "order.preimage_revealed")]
+    OrderPreimageRevealed,
+    #[serde(rename = "order.completed")]
+    OrderCompleted,
+    #[serde(rename = "order.failed")]
+    OrderFailed,
+    #[serde(rename = "order.expired")]
+    OrderExpired,
+    #[serde(rename = "order.refunded")]
+    OrderRefunded,
+    #[serde(rename = "watch.funded")]
+    WatchFunded,
+    #[serde(rename = "watch.confirmed")]
+    WatchConfirmed,
+    #[serde(rename = "watch.spent")]
+    WatchSpent,
+    #[serde(rename = "watch.reorged")]
+    WatchReorged,
+}
+
+impl WebhookEvent {
+    /// Name subscribers register for, stored in `webhook_subscriptions.event`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::OrderCreated => "order.created",
+            WebhookEvent::OrderFusionProofSubmitted => "order.fusion_proof_submitted",
+            WebhookEvent::OrderBitcoinHtlcCreated => "order.bitcoin_htlc_created",
+            WebhookEvent::OrderBitcoinHtlcFunded => "order.bitcoin_htlc_funded",
+            WebhookEvent::OrderBitcoinHtlcVerified => "order.bitcoin_htlc_verified",
+            WebhookEvent::OrderBitcoinHtlcConfirmed => "order.bitcoin_htlc_confirmed",
+            WebhookEvent::OrderFusionOrderFilled => "order.fusion_order_filled",
+            WebhookEvent::OrderPreimageRevealed => "order.preimage_revealed",
+            WebhookEvent::OrderCompleted => "order.completed",
+            WebhookEvent::OrderFailed => "order.failed",
+            WebhookEvent::OrderExpired => "order.expired",
+            WebhookEvent::OrderRefunded => "order.refunded",
+            WebhookEvent::WatchFunded => "watch.funded",
+            WebhookEvent::WatchConfirmed => "watch.confirmed",
+            WebhookEvent::WatchSpent => "watch.spent",
+            WebhookEvent::WatchReorged => "watch.reorged",
+        }
+    }
+
+    /// Event announcing that an order reached `status`, `None` for the intermediate
+    /// statuses subscribers are not told about
+    pub fn for_order_status(status: OrderStatus) -> Option<Self> {
+        match status {
+            OrderStatus::Created => Some(WebhookEvent::OrderCreated),
+            OrderStatus::FusionProofVerified => Some(WebhookEvent::OrderFusionProofSubmitted),
+            OrderStatus::BitcoinHtlcCreated => Some(WebhookEvent::OrderBitcoinHtlcCreated),
+            OrderStatus::BitcoinHtlcFunded => Some(WebhookEvent::OrderBitcoinHtlcFunded),
+            OrderStatus::BitcoinHtlcConfirmed => Some(WebhookEvent::OrderBitcoinHtlcConfirmed),
+            OrderStatus::FusionOrderFilled => Some(WebhookEvent::OrderFusionOrderFilled),
+            OrderStatus::PreimageRevealed => Some(WebhookEvent::OrderPreimageRevealed),
+            OrderStatus::Completed => Some(WebhookEvent::OrderCompleted),
+            OrderStatus::Failed => Some(WebhookEvent::OrderFailed),
+            OrderStatus::Expired => Some(WebhookEvent::OrderExpired),
+            OrderStatus::AwaitingFusionProof
+            | OrderStatus::FusionOrderFillable
+            | OrderStatus::FusionOrderFilling
+            | OrderStatus::BitcoinHtlcClaimed => None,
+        }
+    }
+}
+
+impl From<WatchEventKind> for WebhookEvent {
+    fn from(kind: WatchEventKind) -> Self {
+        match kind {
+            WatchEventKind::Funded => WebhookEvent::WatchFunded,
+            WatchEventKind::Confirmed => WebhookEvent::WatchConfirmed,
+            WatchEventKind::Spent => WebhookEvent::WatchSpent,
+            WatchEventKind::Reorged => WebhookEvent::WatchReorged,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookResponse {
+    pub webhook_id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub secret: Option<String>,
+}
+
+/// Notification waiting in the `webhook_deliveries` outbox, with where to post it
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub event: String,
+    /// JSON body, posted as stored so the signature covers exactly these bytes
+    pub payload: String,
+    /// Posts tried so far, the one in flight included
+    pub attempts: u32,
+}
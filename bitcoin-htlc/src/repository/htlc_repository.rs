@@ -0,0 +1,701 @@
+use crate::models::{
+    ApiError, HtlcOutputType, HtlcParams, HtlcRecord, HtlcReplacement, HtlcSpendPath, HtlcStatus, HtlcTransactionKind,
+    TxConfirmation,
+};
+use bitcoin::{Network, OutPoint, PublicKey, Txid};
+use chrono::Utc;
+use sqlx::{sqlite::SqliteRow, Row, SqliteConnection, SqlitePool};
+use std::str::FromStr;
+use uuid::Uuid;
+
+const HTLC_COLUMNS: &str = r#"
+    id, order_id, status, network, output_type, payment_hash, sender_public_key,
+    recipient_public_key, timeout_height, redeem_script, address, min_confirmations,
+    amount, funding_txid, funding_vout, claim_txid, refund_txid, preimage,
+    created_at, updated_at
+"#;
+
+/// Persistence for HTLCs in the `htlcs` table
+#[derive(Clone)]
+pub struct HtlcRepository {
+    pool: SqlitePool,
+}
+
+impl HtlcRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn insert(&self, htlc: &HtlcRecord) -> Result<(), ApiError> {
+        Self::insert_with(&self.pool, htlc).await
+    }
+
+    /// Insert on a caller-provided executor, e.g. inside a transaction that also updates the order
+    pub async fn insert_with<'c, E>(executor: E, htlc: &HtlcRecord) -> Result<(), ApiError>
+    where
+        E: sqlx::Executor<'c, Database = sqlx::Sqlite>,
+    {
+        let params = &htlc.params;
+        sqlx::query(&format!(
+            "INSERT INTO htlcs ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            HTLC_COLUMNS
+        ))
+        .bind(htlc.id)
+        .bind(htlc.order_id)
+        .bind(htlc.status.as_str())
+        .bind(params.network.to_string())
+        .bind(params.output_type.as_str())
+        .bind(hex::encode(params.payment_hash))
+        .bind(params.sender_pubkey.to_string())
+        .bind(params.recipient_pubkey.to_string())
+        .bind(params.timeout as i64)
+        .bind(hex::encode(&htlc.redeem_script))
+        .bind(&htlc.address)
+        .bind(htlc.min_confirmations as i64)
+        .bind(htlc.amount.map(|amount| amount as i64))
+        .bind(htlc.funding_outpoint.map(|outpoint| outpoint.txid.to_string()))
+        .bind(htlc.funding_outpoint.map(|outpoint| outpoint.vout as i64))
+        .bind(&htlc.claim_txid)
+        .bind(&htlc.refund_txid)
+        .bind(htlc.preimage.as_ref().map(hex::encode))
+        .bind(htlc.created_at)
+        .bind(htlc.updated_at)
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find(&self, htlc_id: Uuid) -> Result<HtlcRecord, ApiError> {
+        let row = sqlx::query(&format!("SELECT {} FROM htlcs WHERE id = ?", HTLC_COLUMNS))
+            .bind(htlc_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| ApiError::NotFound {
+                code: "HTLC_NOT_FOUND".to_string(),
+                message: format!("HTLC {} not found", htlc_id),
+                details: None,
+            })?;
+
+        htlc_from_row(&row)
+    }
+
+    pub async fn find_by_order(&self, order_id: Uuid) -> Result<Vec<HtlcRecord>, ApiError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM htlcs WHERE order_id = ? ORDER BY created_at",
+            HTLC_COLUMNS
+        ))
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(htlc_from_row).collect()
+    }
+
+    /// Record the verified funding output and mark the HTLC funded
+    ///
+    /// Only HTLCs that are created or already funded take a funding; a claimed or
+    /// refunded one is a conflict, so a late verification cannot reopen it.
+    pub async fn record_funding(
+        &self,
+        htlc_id: Uuid,
+        outpoint: OutPoint,
+        amount: u64,
+    ) -> Result<(), ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        Self::record_funding_with(&mut conn, htlc_id, outpoint, amount).await
+    }
+
+    /// [`Self::record_funding`] on a caller-provided connection, e.g. inside the
+    /// transaction that moves the order to funded
+    pub async fn record_funding_with(
+        conn: &mut SqliteConnection,
+        htlc_id: Uuid,
+        outpoint: OutPoint,
+        amount: u64,
+    ) -> Result<(), ApiError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE htlcs SET status = ?, funding_txid = ?, funding_vout = ?, amount = ?, updated_at = ?
+            WHERE id = ? AND status IN (?, ?)
+            "#,
+        )
+        .bind(HtlcStatus::Funded.as_str())
+        .bind(outpoint.txid.to_string())
+        .bind(outpoint.vout as i64)
+        .bind(amount as i64)
+        .bind(Utc::now())
+        .bind(htlc_id)
+        .bind(HtlcStatus::Created.as_str())
+        .bind(HtlcStatus::Funded.as_str())
+        .execute(&mut *conn)
+        .await?;
+        if result.rows_affected() > 0 {
+            return Ok(());
+        }
+
+        let status: Option<String> = sqlx::query_scalar("SELECT status FROM htlcs WHERE id = ?")
+            .bind(htlc_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+        let status = status.ok_or_else(|| ApiError::NotFound {
+            code: "HTLC_NOT_FOUND".to_string(),
+            message: format!("HTLC {} not found", htlc_id),
+            details: None,
+        })?;
+        Err(ApiError::Conflict {
+            code: "HTLC_NOT_FUNDABLE".to_string(),
+            message: format!("HTLC {} is {} and cannot record a funding", htlc_id, status),
+            details: Some(serde_json::json!({ "status": status })),
+        })
+    }
+
+    /// Take the funding of an HTLC still waiting for one, for the broadcast of `txid`
+    ///
+    /// The check and the write are one statement, so of two fundings racing for the
+    /// same HTLC only one gets to broadcast. Returns whether this one did.
+    pub async fn reserve_funding(&self, htlc_id: Uuid, txid: &str) -> Result<bool, ApiError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE htlcs SET funding_broadcast_txid = ?, updated_at = ?
+            WHERE id = ? AND status = ? AND funding_broadcast_txid IS NULL
+            "#,
+        )
+        .bind(txid)
+        .bind(Utc::now())
+        .bind(htlc_id)
+        .bind(HtlcStatus::Created.as_str())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Give back a funding reservation whose broadcast failed
+    pub async fn release_funding(&self, htlc_id: Uuid, txid: &str) -> Result<(), ApiError> {
+        sqlx::query("UPDATE htlcs SET funding_broadcast_txid = NULL WHERE id = ? AND funding_broadcast_txid = ?")
+            .bind(htlc_id)
+            .bind(txid)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record a broadcast claim together with the preimage it reveals
+    pub async fn record_claim(
+        &self,
+        htlc_id: Uuid,
+        claim_txid: &str,
+        preimage: &[u8],
+    ) -> Result<(), ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        Self::record_claim_with(&mut conn, htlc_id, claim_txid, preimage).await
+    }
+
+    /// [`Self::record_claim`] on a caller-provided connection, e.g. inside the
+    /// transaction that moves the order to claimed
+    pub async fn record_claim_with(
+        conn: &mut SqliteConnection,
+        htlc_id: Uuid,
+        claim_txid: &str,
+        preimage: &[u8],
+    ) -> Result<(), ApiError> {
+        Self::update_with(
+            conn,
+            htlc_id,
+            "UPDATE htlcs SET status = ?, claim_txid = ?, preimage = ?, updated_at = ? WHERE id = ?",
+            |query| {
+                query
+                    .bind(HtlcStatus::Claimed.as_str())
+                    .bind(claim_txid.to_string())
+                    .bind(hex::encode(preimage))
+            },
+        )
+        .await
+    }
+
+    pub async fn record_refund(&self, htlc_id: Uuid, refund_txid: &str) -> Result<(), ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        Self::record_refund_with(&mut conn, htlc_id, refund_txid).await
+    }
+
+    /// [`Self::record_refund`] on a caller-provided connection, e.g. inside the
+    /// transaction that expires the order
+    pub async fn record_refund_with(
+        conn: &mut SqliteConnection,
+        htlc_id: Uuid,
+        refund_txid: &str,
+    ) -> Result<(), ApiError> {
+        Self::update_with(
+            conn,
+            htlc_id,
+            "UPDATE htlcs SET status = ?, refund_txid = ?, updated_at = ? WHERE id = ?",
+            |query| {
+                query
+                    .bind(HtlcStatus::Refunded.as_str())
+                    .bind(refund_txid.to_string())
+            },
+        )
+        .await
+    }
+
+    /// HTLCs still waiting for a funding or a spend: no claim or refund recorded
+    pub async fn find_open(&self) -> Result<Vec<HtlcRecord>, ApiError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM htlcs WHERE claim_txid IS NULL AND refund_txid IS NULL ORDER BY updated_at",
+            HTLC_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(htlc_from_row).collect()
+    }
+
+    /// Claims and refunds whose confirmation has not been recorded yet
+    pub async fn find_unconfirmed_spends(&self) -> Result<Vec<HtlcRecord>, ApiError> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {} FROM htlcs
+            WHERE (claim_txid IS NOT NULL OR refund_txid IS NOT NULL) AND spend_confirmed_height IS NULL
+            ORDER BY updated_at
+            "#,
+            HTLC_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(htlc_from_row).collect()
+    }
+
+    /// Stop watching the recorded spend, it confirmed at `height`
+    pub async fn record_spend_confirmed(&self, htlc_id: Uuid, height: u32) -> Result<(), ApiError> {
+        self.update(
+            htlc_id,
+            "UPDATE htlcs SET spend_confirmed_height = ?, updated_at = ? WHERE id = ?",
+            |query| query.bind(height as i64),
+        )
+        .await
+    }
+
+    /// Log a fee bump and make the replacement the recorded claim or refund
+    pub async fn record_replacement(&self, replacement: &HtlcReplacement) -> Result<(), ApiError> {
+        let sql = match replacement.spend_path {
+            HtlcSpendPath::Claim => "UPDATE htlcs SET claim_txid = ?, updated_at = ? WHERE id = ? AND claim_txid = ?",
+            HtlcSpendPath::Refund => "UPDATE htlcs SET refund_txid = ?, updated_at = ? WHERE id = ? AND refund_txid = ?",
+        };
+
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(sql)
+            .bind(&replacement.txid)
+            .bind(replacement.created_at)
+            .bind(replacement.htlc_id)
+            .bind(&replacement.replaced_txid)
+            .execute(&mut *tx)
+            .await?;
+        // Someone else recorded another spend meanwhile
+        if result.rows_affected() == 0 {
+            return Err(ApiError::Conflict {
+                code: "HTLC_SPEND_CHANGED".to_string(),
+                message: format!(
+                    "HTLC {} no longer records {} as its {}",
+                    replacement.htlc_id,
+                    replacement.replaced_txid,
+                    replacement.spend_path.as_str()
+                ),
+                details: None,
+            });
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO htlc_replacements (
+                htlc_id, spend_path, replaced_txid, txid, fee, fee_rate, block_height, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(replacement.htlc_id)
+        .bind(replacement.spend_path.as_str())
+        .bind(&replacement.replaced_txid)
+        .bind(&replacement.txid)
+        .bind(replacement.fee as i64)
+        .bind(replacement.fee_rate as i64)
+        .bind(replacement.block_height as i64)
+        .bind(replacement.created_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Fee bumps of the HTLC's claim or refund, oldest first
+    pub async fn find_replacements(&self, htlc_id: Uuid) -> Result<Vec<HtlcReplacement>, ApiError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT spend_path, replaced_txid, txid, fee, fee_rate, block_height, created_at
+            FROM htlc_replacements WHERE htlc_id = ? ORDER BY id
+            "#,
+        )
+        .bind(htlc_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(HtlcReplacement {
+                    htlc_id,
+                    spend_path: HtlcSpendPath::from_str(&row.try_get::<String, _>("spend_path")?).map_err(|message| {
+                        ApiError::InternalError {
+                            code: "HTLC_CORRUPT".to_string(),
+                            message: format!("HTLC {}: {}", htlc_id, message),
+                            details: None,
+                        }
+                    })?,
+                    replaced_txid: row.try_get("replaced_txid")?,
+                    txid: row.try_get("txid")?,
+                    fee: row.try_get::<i64, _>("fee")? as u64,
+                    fee_rate: row.try_get::<i64, _>("fee_rate")? as u64,
+                    block_height: row.try_get::<i64, _>("block_height")? as u32,
+                    created_at: row.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Watch the recorded spend again, the block it confirmed in was reorged out
+    pub async fn reset_spend_confirmed(&self, htlc_id: Uuid) -> Result<(), ApiError> {
+        self.update(
+            htlc_id,
+            "UPDATE htlcs SET spend_confirmed_height = NULL, updated_at = ? WHERE id = ?",
+            |query| query,
+        )
+        .await
+    }
+
+    /// Funded HTLCs whose transactions may still be reorged: unspent, or spent in a
+    /// block above `final_height`
+    pub async fn find_reorg_exposed(&self, final_height: u32) -> Result<Vec<HtlcRecord>, ApiError> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {} FROM htlcs
+            WHERE funding_txid IS NOT NULL AND (spend_confirmed_height IS NULL OR spend_confirmed_height > ?)
+            ORDER BY updated_at
+            "#,
+            HTLC_COLUMNS
+        ))
+        .bind(final_height as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(htlc_from_row).collect()
+    }
+
+    /// Blocks the HTLC's transactions were last seen confirmed in
+    pub async fn find_confirmations(&self, htlc_id: Uuid) -> Result<Vec<TxConfirmation>, ApiError> {
+        let rows = sqlx::query(
+            "SELECT txid, kind, block_height, block_hash FROM tx_confirmations WHERE htlc_id = ? ORDER BY block_height",
+        )
+        .bind(htlc_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(TxConfirmation {
+                    txid: row.try_get("txid")?,
+                    htlc_id,
+                    kind: HtlcTransactionKind::from_str(&row.try_get::<String, _>("kind")?).map_err(|message| {
+                        ApiError::InternalError {
+                            code: "HTLC_CORRUPT".to_string(),
+                            message: format!("HTLC {}: {}", htlc_id, message),
+                            details: None,
+                        }
+                    })?,
+                    block_height: row.try_get::<i64, _>("block_height")? as u32,
+                    block_hash: row.try_get("block_hash")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Remember the block a transaction confirmed in, replacing the one it was in before
+    pub async fn record_confirmation(&self, confirmation: &TxConfirmation) -> Result<(), ApiError> {
+        sqlx::query(
+            r#"
+            INSERT INTO tx_confirmations (txid, htlc_id, kind, block_height, block_hash, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(txid) DO UPDATE SET
+                block_height = excluded.block_height,
+                block_hash = excluded.block_hash,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&confirmation.txid)
+        .bind(confirmation.htlc_id)
+        .bind(confirmation.kind.as_str())
+        .bind(confirmation.block_height as i64)
+        .bind(&confirmation.block_hash)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Forget the block of a transaction that is no longer confirmed
+    pub async fn remove_confirmation(&self, txid: &str) -> Result<(), ApiError> {
+        sqlx::query("DELETE FROM tx_confirmations WHERE txid = ?")
+            .bind(txid)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Run an update whose trailing placeholders are `updated_at` and `id`
+    async fn update<'q>(
+        &self,
+        htlc_id: Uuid,
+        sql: &'q str,
+        bind: impl FnOnce(
+            sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+        ) -> sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    ) -> Result<(), ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        Self::update_with(&mut conn, htlc_id, sql, bind).await
+    }
+
+    /// [`Self::update`] on a caller-provided connection
+    async fn update_with<'q>(
+        conn: &mut SqliteConnection,
+        htlc_id: Uuid,
+        sql: &'q str,
+        bind: impl FnOnce(
+            sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+        ) -> sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    ) -> Result<(), ApiError> {
+        let result = bind(sqlx::query(sql))
+            .bind(Utc::now())
+            .bind(htlc_id)
+            .execute(conn)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(ApiError::NotFound {
+                code: "HTLC_NOT_FOUND".to_string(),
+                message: format!("HTLC {} not found", htlc_id),
+                details: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn htlc_from_row(row: &SqliteRow) -> Result<HtlcRecord, ApiError> {
+    let id: Uuid = row.try_get("id")?;
+    let corrupt = |column: &str| ApiError::InternalError {
+        code: "HTLC_CORRUPT".to_string(),
+        message: format!("Stored {} of HTLC {} is invalid", column, id),
+        details: None,
+    };
+
+    let mut payment_hash = [0u8; 32];
+    hex::decode_to_slice(row.try_get::<String, _>("payment_hash")?, &mut payment_hash)
+        .map_err(|_| corrupt("payment_hash"))?;
+
+    let params = HtlcParams {
+        recipient_pubkey: PublicKey::from_str(&row.try_get::<String, _>("recipient_public_key")?)
+            .map_err(|_| corrupt("recipient_public_key"))?,
+        sender_pubkey: PublicKey::from_str(&row.try_get::<String, _>("sender_public_key")?)
+            .map_err(|_| corrupt("sender_public_key"))?,
+        payment_hash,
+        timeout: row.try_get::<i64, _>("timeout_height")? as u32,
+        output_type: HtlcOutputType::from_str(&row.try_get::<String, _>("output_type")?)
+            .map_err(|_| corrupt("output_type"))?,
+        network: Network::from_str(&row.try_get::<String, _>("network")?)
+            .map_err(|_| corrupt("network"))?,
+    };
+
+    let funding_txid: Option<String> = row.try_get("funding_txid")?;
+    let funding_vout: Option<i64> = row.try_get("funding_vout")?;
+    let funding_outpoint = match funding_txid.zip(funding_vout) {
+        Some((txid, vout)) => Some(OutPoint::new(
+            Txid::from_str(&txid).map_err(|_| corrupt("funding_txid"))?,
+            vout as u32,
+        )),
+        None => None,
+    };
+
+    let preimage = row
+        .try_get::<Option<String>, _>("preimage")?
+        .map(hex::decode)
+        .transpose()
+        .map_err(|_| corrupt("preimage"))?;
+
+    Ok(HtlcRecord {
+        id,
+        order_id: row.try_get("order_id")?,
+        status: HtlcStatus::from_str(&row.try_get::<String, _>("status")?)
+            .map_err(|_| corrupt("status"))?,
+        params,
+        redeem_script: hex::decode(row.try_get::<String, _>("redeem_script")?)
+            .map_err(|_| corrupt("redeem_script"))?,
+        address: row.try_get("address")?,
+        min_confirmations: row.try_get::<i64, _>("min_confirmations")? as u32,
+        amount: row.try_get::<Option<i64>, _>("amount")?.map(|amount| amount as u64),
+        funding_outpoint,
+        claim_txid: row.try_get("claim_txid")?,
+        refund_txid: row.try_get("refund_txid")?,
+        preimage,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::build_htlc_script;
+    use crate::test_support::{htlc_params, test_pool};
+
+    async fn repository() -> HtlcRepository {
+        let pool = test_pool().await;
+        HtlcRepository::new(pool)
+    }
+
+    fn record(output_type: HtlcOutputType) -> HtlcRecord {
+        let params = htlc_params(output_type);
+        let htlc_script = build_htlc_script(&params).unwrap();
+        HtlcRecord::new(params, &htlc_script)
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_find_round_trip() {
+        let repository = repository().await;
+        let mut htlc = record(HtlcOutputType::P2tr);
+        htlc.order_id = Some(Uuid::new_v4());
+        repository.insert(&htlc).await.unwrap();
+
+        let stored = repository.find(htlc.id).await.unwrap();
+        assert_eq!(stored.status, HtlcStatus::Created);
+        assert_eq!(stored.order_id, htlc.order_id);
+        assert_eq!(stored.params.output_type, HtlcOutputType::P2tr);
+        assert_eq!(stored.params.network, Network::Testnet);
+        assert_eq!(stored.params.sender_pubkey, htlc.params.sender_pubkey);
+        assert_eq!(stored.params.payment_hash, htlc.params.payment_hash);
+        assert_eq!(stored.redeem_script, htlc.redeem_script);
+        assert_eq!(stored.address, htlc.address);
+        assert!(stored.funding_outpoint.is_none());
+
+        let by_order = repository.find_by_order(htlc.order_id.unwrap()).await.unwrap();
+        assert_eq!(by_order.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_records_funding_and_spends() {
+        let repository = repository().await;
+        let htlc = record(HtlcOutputType::P2wsh);
+        repository.insert(&htlc).await.unwrap();
+
+        let outpoint = OutPoint::new(
+            Txid::from_str("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b").unwrap(),
+            1,
+        );
+        repository.record_funding(htlc.id, outpoint, 100_000).await.unwrap();
+        let funded = repository.find(htlc.id).await.unwrap();
+        assert_eq!(funded.status, HtlcStatus::Funded);
+        assert_eq!(funded.funding_outpoint, Some(outpoint));
+        assert_eq!(funded.amount, Some(100_000));
+
+        repository.record_claim(htlc.id, "ab", &[7u8; 32]).await.unwrap();
+        let claimed = repository.find(htlc.id).await.unwrap();
+        assert_eq!(claimed.status, HtlcStatus::Claimed);
+        assert_eq!(claimed.claim_txid.as_deref(), Some("ab"));
+        assert_eq!(claimed.preimage, Some(vec![7u8; 32]));
+
+        // A claimed HTLC is not reopened by a late funding
+        let error = repository.record_funding(htlc.id, outpoint, 100_000).await.unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "HTLC_NOT_FUNDABLE"));
+        assert_eq!(repository.find(htlc.id).await.unwrap().status, HtlcStatus::Claimed);
+
+        let error = repository.record_funding(Uuid::new_v4(), outpoint, 100_000).await.unwrap_err();
+        assert!(matches!(error, ApiError::NotFound { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_records_replacements_of_the_current_spend() {
+        let repository = repository().await;
+        let mut htlc = record(HtlcOutputType::P2wsh);
+        htlc.claim_txid = Some("aa".to_string());
+        repository.insert(&htlc).await.unwrap();
+        assert_eq!(repository.find_unconfirmed_spends().await.unwrap().len(), 1);
+
+        let mut replacement = HtlcReplacement {
+            htlc_id: htlc.id,
+            spend_path: HtlcSpendPath::Claim,
+            replaced_txid: "aa".to_string(),
+            txid: "bb".to_string(),
+            fee: 1_100,
+            fee_rate: 10,
+            block_height: 2_500_100,
+            created_at: Utc::now(),
+        };
+        repository.record_replacement(&replacement).await.unwrap();
+        assert_eq!(repository.find(htlc.id).await.unwrap().claim_txid.as_deref(), Some("bb"));
+
+        // Replacing a spend that is no longer the recorded one
+        replacement.txid = "cc".to_string();
+        let error = repository.record_replacement(&replacement).await.unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "HTLC_SPEND_CHANGED"));
+
+        let replacements = repository.find_replacements(htlc.id).await.unwrap();
+        assert_eq!(replacements.len(), 1);
+        assert_eq!((replacements[0].txid.as_str(), replacements[0].fee_rate), ("bb", 10));
+
+        repository.record_spend_confirmed(htlc.id, 2_500_101).await.unwrap();
+        assert!(repository.find_unconfirmed_spends().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tracks_confirmation_blocks() {
+        let repository = repository().await;
+        let htlc = record(HtlcOutputType::P2wsh);
+        repository.insert(&htlc).await.unwrap();
+        assert!(repository.find_reorg_exposed(2_500_000).await.unwrap().is_empty());
+
+        let outpoint = OutPoint::new(
+            Txid::from_str("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b").unwrap(),
+            0,
+        );
+        repository.record_funding(htlc.id, outpoint, 100_000).await.unwrap();
+        let mut confirmation = TxConfirmation {
+            txid: outpoint.txid.to_string(),
+            htlc_id: htlc.id,
+            kind: HtlcTransactionKind::Funding,
+            block_height: 2_500_001,
+            block_hash: "aa".repeat(32),
+        };
+        repository.record_confirmation(&confirmation).await.unwrap();
+        confirmation.block_height = 2_500_002;
+        confirmation.block_hash = "bb".repeat(32);
+        repository.record_confirmation(&confirmation).await.unwrap();
+        assert_eq!(repository.find_confirmations(htlc.id).await.unwrap(), vec![confirmation.clone()]);
+
+        // Spent deep enough, the HTLC is no longer exposed to reorgs
+        repository.record_claim(htlc.id, "ab", &[7u8; 32]).await.unwrap();
+        repository.record_spend_confirmed(htlc.id, 2_500_003).await.unwrap();
+        assert_eq!(repository.find_reorg_exposed(2_500_002).await.unwrap().len(), 1);
+        assert!(repository.find_reorg_exposed(2_500_003).await.unwrap().is_empty());
+        repository.reset_spend_confirmed(htlc.id).await.unwrap();
+        assert_eq!(repository.find_reorg_exposed(2_500_003).await.unwrap().len(), 1);
+
+        repository.remove_confirmation(&confirmation.txid).await.unwrap();
+        assert!(repository.find_confirmations(htlc.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_htlc_is_not_found() {
+        let repository = repository().await;
+        let error = repository.find(Uuid::new_v4()).await.unwrap_err();
+        assert!(matches!(error, ApiError::NotFound { ref code, .. } if code == "HTLC_NOT_FOUND"));
+
+        let error = repository.record_refund(Uuid::new_v4(), "ab").await.unwrap_err();
+        assert!(matches!(error, ApiError::NotFound { .. }));
+    }
+}
@@ -0,0 +1,7 @@
+pub mod htlc_repository;
+pub mod watchlist_repository;
+pub mod webhook_repository;
+
+pub use htlc_repository::HtlcRepository;
+pub use watchlist_repository::WatchlistRepository;
+pub use webhook_repository::WebhookRepository;
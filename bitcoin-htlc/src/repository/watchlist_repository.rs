@@ -0,0 +1,324 @@
+use crate::models::{ApiError, WatchEvent, WatchEventKind, WatchStatus, WatchedScript, WebhookEvent};
+use crate::repository::WebhookRepository;
+use bitcoin::{OutPoint, ScriptBuf, Txid};
+use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Scanned blocks kept below the cursor, the deepest reorg the scan can unwind
+pub const SCANNED_BLOCKS_KEPT: u32 = 144;
+
+const WATCH_COLUMNS: &str = r#"
+    id, script_pubkey, htlc_id, min_confirmations, status, funding_txid, funding_vout,
+    funding_amount, funding_height, spend_txid, spend_height, created_at, updated_at
+"#;
+
+/// Persistence for the script watchlist: `watched_scripts`, their `watch_events` and
+/// the `scanned_blocks` cursor
+#[derive(Clone)]
+pub struct WatchlistRepository {
+    pool: SqlitePool,
+}
+
+impl WatchlistRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn insert(&self, watch: &WatchedScript) -> Result<(), ApiError> {
+        Self::insert_with(&self.pool, watch).await
+    }
+
+    /// Insert on a caller-provided executor, e.g. inside the transaction creating the HTLC
+    pub async fn insert_with<'c, E>(executor: E, watch: &WatchedScript) -> Result<(), ApiError>
+    where
+        E: sqlx::Executor<'c, Database = sqlx::Sqlite>,
+    {
+        sqlx::query(&format!(
+            "INSERT INTO watched_scripts ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            WATCH_COLUMNS
+        ))
+        .bind(watch.id)
+        .bind(watch.script_pubkey.to_hex_string())
+        .bind(watch.htlc_id)
+        .bind(watch.min_confirmations as i64)
+        .bind(watch.status.as_str())
+        .bind(watch.funding_outpoint.map(|outpoint| outpoint.txid.to_string()))
+        .bind(watch.funding_outpoint.map(|outpoint| outpoint.vout as i64))
+        .bind(watch.funding_amount.map(|amount| amount as i64))
+        .bind(watch.funding_height.map(|height| height as i64))
+        .bind(&watch.spend_txid)
+        .bind(watch.spend_height.map(|height| height as i64))
+        .bind(watch.created_at)
+        .bind(watch.updated_at)
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find(&self, watch_id: Uuid) -> Result<WatchedScript, ApiError> {
+        let row = sqlx::query(&format!("SELECT {} FROM watched_scripts WHERE id = ?", WATCH_COLUMNS))
+            .bind(watch_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| ApiError::NotFound {
+                code: "WATCH_NOT_FOUND".to_string(),
+                message: format!("Watched script {} not found", watch_id),
+                details: None,
+            })?;
+
+        watch_from_row(&row)
+    }
+
+    /// Watches a block can still change: not yet spent, or spent above `final_height`
+    pub async fn find_active(&self, final_height: u32) -> Result<Vec<WatchedScript>, ApiError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM watched_scripts WHERE status != ? OR spend_height > ? ORDER BY created_at",
+            WATCH_COLUMNS
+        ))
+        .bind(WatchStatus::Spent.as_str())
+        .bind(final_height as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(watch_from_row).collect()
+    }
+
+    /// Store a watch's new state together with the events that led to it, and queue
+    /// the webhooks announcing them
+    pub async fn record_events(&self, watch: &WatchedScript, events: &[WatchEvent]) -> Result<(), ApiError> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            r#"
+            UPDATE watched_scripts SET
+                status = ?, funding_txid = ?, funding_vout = ?, funding_amount = ?, funding_height = ?,
+                spend_txid = ?, spend_height = ?, updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(watch.status.as_str())
+        .bind(watch.funding_outpoint.map(|outpoint| outpoint.txid.to_string()))
+        .bind(watch.funding_outpoint.map(|outpoint| outpoint.vout as i64))
+        .bind(watch.funding_amount.map(|amount| amount as i64))
+        .bind(watch.funding_height.map(|height| height as i64))
+        .bind(&watch.spend_txid)
+        .bind(watch.spend_height.map(|height| height as i64))
+        .bind(watch.updated_at)
+        .bind(watch.id)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(ApiError::NotFound {
+                code: "WATCH_NOT_FOUND".to_string(),
+                message: format!("Watched script {} not found", watch.id),
+                details: None,
+            });
+        }
+
+        for event in events {
+            sqlx::query(
+                "INSERT INTO watch_events (watch_id, htlc_id, kind, txid, block_height, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(event.watch_id)
+            .bind(event.htlc_id)
+            .bind(event.kind.as_str())
+            .bind(&event.txid)
+            .bind(event.block_height as i64)
+            .bind(event.created_at)
+            .execute(&mut *tx)
+            .await?;
+
+            let data = serde_json::json!({
+                "watchId": event.watch_id,
+                "htlcId": event.htlc_id,
+                "txid": event.txid,
+                "blockHeight": event.block_height,
+            });
+            WebhookRepository::enqueue_with(&mut tx, WebhookEvent::from(event.kind), data).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Events of a watch, oldest first
+    pub async fn find_events(&self, watch_id: Uuid) -> Result<Vec<WatchEvent>, ApiError> {
+        let rows = sqlx::query(
+            "SELECT htlc_id, kind, txid, block_height, created_at FROM watch_events WHERE watch_id = ? ORDER BY id",
+        )
+        .bind(watch_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(WatchEvent {
+                    watch_id,
+                    htlc_id: row.try_get("htlc_id")?,
+                    kind: WatchEventKind::from_str(&row.try_get::<String, _>("kind")?).map_err(|message| {
+                        ApiError::InternalError {
+                            code: "WATCH_CORRUPT".to_string(),
+                            message: format!("Watched script {}: {}", watch_id, message),
+                            details: None,
+                        }
+                    })?,
+                    txid: row.try_get("txid")?,
+                    block_height: row.try_get::<i64, _>("block_height")? as u32,
+                    created_at: row.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Height and hash of the last scanned block, `None` before the first scan
+    pub async fn cursor(&self) -> Result<Option<(u32, String)>, ApiError> {
+        let row = sqlx::query("SELECT height, block_hash FROM scanned_blocks ORDER BY height DESC LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.map(|row| Ok((row.try_get::<i64, _>("height")? as u32, row.try_get("block_hash")?)))
+            .transpose()
+    }
+
+    /// Move the cursor to a newly scanned block, forgetting blocks too deep to be reorged
+    pub async fn record_scanned_block(&self, height: u32, block_hash: &str) -> Result<(), ApiError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT OR REPLACE INTO scanned_blocks (height, block_hash) VALUES (?, ?)")
+            .bind(height as i64)
+            .bind(block_hash)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM scanned_blocks WHERE height < ?")
+            .bind(height.saturating_sub(SCANNED_BLOCKS_KEPT) as i64)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Step the cursor back over a block that left the active chain
+    pub async fn remove_scanned_block(&self, height: u32) -> Result<(), ApiError> {
+        sqlx::query("DELETE FROM scanned_blocks WHERE height = ?")
+            .bind(height as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Forget the cursor, so the next scan starts at the tip
+    pub async fn clear_scanned_blocks(&self) -> Result<(), ApiError> {
+        sqlx::query("DELETE FROM scanned_blocks").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+fn watch_from_row(row: &SqliteRow) -> Result<WatchedScript, ApiError> {
+    let id: Uuid = row.try_get("id")?;
+    let corrupt = |column: &str| ApiError::InternalError {
+        code: "WATCH_CORRUPT".to_string(),
+        message: format!("Stored {} of watched script {} is invalid", column, id),
+        details: None,
+    };
+
+    let funding_txid: Option<String> = row.try_get("funding_txid")?;
+    let funding_vout: Option<i64> = row.try_get("funding_vout")?;
+    let funding_outpoint = match funding_txid.zip(funding_vout) {
+        Some((txid, vout)) => Some(OutPoint::new(
+            Txid::from_str(&txid).map_err(|_| corrupt("funding_txid"))?,
+            vout as u32,
+        )),
+        None => None,
+    };
+
+    Ok(WatchedScript {
+        id,
+        script_pubkey: ScriptBuf::from_hex(&row.try_get::<String, _>("script_pubkey")?)
+            .map_err(|_| corrupt("script_pubkey"))?,
+        htlc_id: row.try_get("htlc_id")?,
+        min_confirmations: row.try_get::<i64, _>("min_confirmations")? as u32,
+        status: WatchStatus::from_str(&row.try_get::<String, _>("status")?).map_err(|_| corrupt("status"))?,
+        funding_outpoint,
+        funding_amount: row.try_get::<Option<i64>, _>("funding_amount")?.map(|amount| amount as u64),
+        funding_height: row.try_get::<Option<i64>, _>("funding_height")?.map(|height| height as u32),
+        spend_txid: row.try_get("spend_txid")?,
+        spend_height: row.try_get::<Option<i64>, _>("spend_height")?.map(|height| height as u32),
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_pool;
+    use chrono::Utc;
+
+    async fn repository() -> WatchlistRepository {
+        let pool = test_pool().await;
+        WatchlistRepository::new(pool)
+    }
+
+    #[tokio::test]
+    async fn test_records_watch_state_and_events() {
+        let repository = repository().await;
+        let watch = WatchedScript::new(ScriptBuf::new_op_return([1u8; 4]), 3, Some(Uuid::new_v4()));
+        repository.insert(&watch).await.unwrap();
+        assert_eq!(repository.find_active(2_500_000).await.unwrap().len(), 1);
+
+        let mut spent = watch.clone();
+        spent.status = WatchStatus::Spent;
+        spent.funding_outpoint = Some(OutPoint::new(
+            Txid::from_str("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b").unwrap(),
+            1,
+        ));
+        spent.funding_amount = Some(100_000);
+        spent.funding_height = Some(2_500_001);
+        spent.spend_txid = Some("ab".to_string());
+        spent.spend_height = Some(2_500_002);
+        let event = WatchEvent {
+            watch_id: watch.id,
+            htlc_id: watch.htlc_id,
+            kind: WatchEventKind::Spent,
+            txid: "ab".to_string(),
+            block_height: 2_500_002,
+            created_at: Utc::now(),
+        };
+        repository.record_events(&spent, std::slice::from_ref(&event)).await.unwrap();
+
+        let stored = repository.find(watch.id).await.unwrap();
+        assert_eq!(stored.script_pubkey, watch.script_pubkey);
+        assert_eq!(stored.status, WatchStatus::Spent);
+        assert_eq!(stored.funding_outpoint, spent.funding_outpoint);
+        assert_eq!(stored.spend_height, Some(2_500_002));
+        assert_eq!(repository.find_events(watch.id).await.unwrap(), vec![event]);
+
+        // Spent deep enough, no block can change it any more
+        assert_eq!(repository.find_active(2_500_001).await.unwrap().len(), 1);
+        assert!(repository.find_active(2_500_002).await.unwrap().is_empty());
+
+        let error = repository.find(Uuid::new_v4()).await.unwrap_err();
+        assert!(matches!(error, ApiError::NotFound { ref code, .. } if code == "WATCH_NOT_FOUND"));
+    }
+
+    #[tokio::test]
+    async fn test_cursor_follows_scanned_blocks() {
+        let repository = repository().await;
+        assert_eq!(repository.cursor().await.unwrap(), None);
+
+        repository.record_scanned_block(2_500_000, "aa").await.unwrap();
+        repository.record_scanned_block(2_500_001, "bb").await.unwrap();
+        assert_eq!(repository.cursor().await.unwrap(), Some((2_500_001, "bb".to_string())));
+
+        repository.remove_scanned_block(2_500_001).await.unwrap();
+        assert_eq!(repository.cursor().await.unwrap(), Some((2_500_000, "aa".to_string())));
+
+        // Blocks below the kept window are pruned as the cursor moves on
+        repository.record_scanned_block(2_500_000 + SCANNED_BLOCKS_KEPT + 1, "cc").await.unwrap();
+        repository.remove_scanned_block(2_500_000 + SCANNED_BLOCKS_KEPT + 1).await.unwrap();
+        assert_eq!(repository.cursor().await.unwrap(), None);
+
+        repository.record_scanned_block(2_500_002, "dd").await.unwrap();
+        repository.clear_scanned_blocks().await.unwrap();
+        assert_eq!(repository.cursor().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::models::{ApiError, WebhookDelivery, WebhookEvent};
+use chrono::Utc;
+use serde_json::json;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use uuid::Uuid;
+
+/// Persistence for webhooks: `webhooks`, their `webhook_subscriptions` and the
+/// `webhook_deliveries` outbox
+#[derive(Clone)]
+pub struct WebhookRepository {
+    pool: SqlitePool,
+}
+
+impl WebhookRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Register a webhook posting to `url` for `events`
+    pub async fn insert(
+        &self,
+        webhook_id: Uuid,
+        url: &str,
+        secret: &str,
+        events: &[WebhookEvent],
+    ) -> Result<(), ApiError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO webhooks (id, url, secret, created_at) VALUES (?, ?, ?, ?)")
+            .bind(webhook_id)
+            .bind(url)
+            .bind(secret)
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await?;
+        for event in events {
+            sqlx::query("INSERT OR IGNORE INTO webhook_subscriptions (webhook_id, event) VALUES (?, ?)")
+                .bind(webhook_id)
+                .bind(event.as_str())
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn enqueue(&self, event: WebhookEvent, data: serde_json::Value) -> Result<u64, ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        Self::enqueue_with(&mut conn, event, data).await
+    }
+
+    /// Queue `event` for every webhook subscribed to it, returning how many were queued
+    ///
+    /// Takes a connection so the notification commits, or rolls back, together with
+    /// the change it announces.
+    pub async fn enqueue_with(
+        conn: &mut SqliteConnection,
+        event: WebhookEvent,
+        data: serde_json::Value,
+    ) -> Result<u64, ApiError> {
+        let now = Utc::now();
+        let payload = json!({
+            "event": event.as_str(),
+            "createdAt": now,
+            "data": data,
+        });
+        let result = sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries (webhook_id, event, payload, attempts, next_attempt_at, created_at)
+            SELECT webhook_id, ?, ?, 0, ?, ? FROM webhook_subscriptions WHERE event = ?
+            "#,
+        )
+        .bind(event.as_str())
+        .bind(payload.to_string())
+        .bind(now.timestamp())
+        .bind(now)
+        .bind(event.as_str())
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Undelivered notifications whose next attempt is due, oldest first, skipping
+    /// those that already had `max_attempts`
+    pub async fn find_due(&self, max_attempts: u32, limit: u32) -> Result<Vec<WebhookDelivery>, ApiError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT d.id, d.webhook_id, w.url, w.secret, d.event, d.payload, d.attempts
+            FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id
+            WHERE d.delivered_at IS NULL AND d.next_attempt_at <= ? AND d.attempts < ?
+            ORDER BY d.id LIMIT ?
+            "#,
+        )
+        .bind(Utc::now().timestamp())
+        .bind(max_attempts as i64)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(WebhookDelivery {
+                    id: row.try_get("id")?,
+                    webhook_id: row.try_get("webhook_id")?,
+                    url: row.try_get("url")?,
+                    secret: row.try_get("secret")?,
+                    event: row.try_get("event")?,
+                    payload: row.try_get("payload")?,
+                    attempts: row.try_get::<i64, _>("attempts")? as u32,
+                })
+            })
+            .collect()
+    }
+
+    /// Take a delivery for one attempt, counting it and moving its next attempt to
+    /// `retry_at` (unix seconds) in case this one fails
+    ///
+    /// Guarded on the attempts it was found with, so of several instances finding the
+    /// same delivery only one posts it. Returns whether this caller got it.
+    pub async fn claim(&self, delivery: &mut WebhookDelivery, retry_at: i64) -> Result<bool, ApiError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE webhook_deliveries SET attempts = attempts + 1, next_attempt_at = ?
+            WHERE id = ? AND attempts = ? AND delivered_at IS NULL
+            "#,
+        )
+        .bind(retry_at)
+        .bind(delivery.id)
+        .bind(delivery.attempts as i64)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        delivery.attempts += 1;
+        Ok(true)
+    }
+
+    pub async fn mark_delivered(&self, delivery_id: i64) -> Result<(), ApiError> {
+        sqlx::query("UPDATE webhook_deliveries SET delivered_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(delivery_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_pool;
+
+    #[tokio::test]
+    async fn test_events_queue_for_subscribers_only() {
+        let repository = WebhookRepository::new(test_pool().await);
+        let funded_only = Uuid::new_v4();
+        let everything = Uuid::new_v4();
+        repository
+            .insert(funded_only, "https://a.example/hook", &"a".repeat(32), &[WebhookEvent::WatchFunded])
+            .await
+            .unwrap();
+        repository
+            .insert(
+                everything,
+                "https://b.example/hook",
+                &"b".repeat(32),
+                &[WebhookEvent::WatchFunded, WebhookEvent::WatchSpent, WebhookEvent::WatchSpent],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(repository.enqueue(WebhookEvent::WatchFunded, json!({ "txid": "aa" })).await.unwrap(), 2);
+        assert_eq!(repository.enqueue(WebhookEvent::WatchSpent, json!({ "txid": "bb" })).await.unwrap(), 1);
+        assert_eq!(repository.enqueue(WebhookEvent::OrderCreated, json!({})).await.unwrap(), 0);
+
+        let due = repository.find_due(3, 10).await.unwrap();
+        assert_eq!(due.len(), 3);
+        let spent = due.iter().find(|delivery| delivery.event == "watch.spent").unwrap();
+        assert_eq!(spent.webhook_id, everything);
+        assert_eq!(spent.url, "https://b.example/hook");
+        let payload: serde_json::Value = serde_json::from_str(&spent.payload).unwrap();
+        assert_eq!(payload["event"], "watch.spent");
+        assert_eq!(payload["data"]["txid"], "bb");
+    }
+
+    #[tokio::test]
+    async fn test_claimed_deliveries_wait_for_their_retry() {
+        let repository = WebhookRepository::new(test_pool().await);
+        repository
+            .insert(Uuid::new_v4(), "https://a.example/hook", &"a".repeat(32), &[WebhookEvent::OrderCompleted])
+            .await
+            .unwrap();
+        repository.enqueue(WebhookEvent::OrderCompleted, json!({})).await.unwrap();
+
+        let mut delivery = repository.find_due(3, 10).await.unwrap().remove(0);
+        let mut stale = delivery.clone();
+        assert!(repository.claim(&mut delivery, Utc::now().timestamp() + 60).await.unwrap());
+        assert_eq!(delivery.attempts, 1);
+        // Another instance that found it before the claim loses the race
+        assert!(!repository.claim(&mut stale, Utc::now().timestamp() + 60).await.unwrap());
+        assert!(repository.find_due(3, 10).await.unwrap().is_empty());
+
+        // A failed attempt is retried once due, until the attempts run out
+        assert!(repository.claim(&mut delivery, 0).await.unwrap());
+        assert_eq!(repository.find_due(3, 10).await.unwrap().len(), 1);
+        assert!(repository.find_due(2, 10).await.unwrap().is_empty());
+
+        repository.mark_delivered(delivery.id).await.unwrap();
+        assert!(repository.find_due(3, 10).await.unwrap().is_empty());
+    }
+}
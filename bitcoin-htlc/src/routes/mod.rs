@@ -7,12 +7,22 @@ pub fn configure_routes() -> Scope {
         .route("/health", web::get().to(handlers::health_check))
         .route("/orders", web::post().to(handlers::create_order))
         .route("/orders/{order_id}", web::get().to(handlers::get_order))
+        .route("/orders/{order_id}/events", web::get().to(handlers::get_order_events))
         .route("/orders/{order_id}/fusion-proof", web::post().to(handlers::submit_fusion_proof))
         .route("/htlc/create", web::post().to(handlers::create_htlc))
         .route("/htlc/verify", web::post().to(handlers::verify_htlc))
         .route("/htlc/{htlc_id}/claim", web::post().to(handlers::claim_htlc))
         .route("/htlc/{htlc_id}/refund", web::post().to(handlers::refund_htlc))
+        .route("/htlc/{htlc_id}/refund/cpfp", web::post().to(handlers::cpfp_htlc_refund))
+        .route("/htlc/{htlc_id}/replacements", web::get().to(handlers::get_htlc_replacements))
+        .route("/htlc/{htlc_id}/fund", web::post().to(handlers::fund_htlc))
+        .route("/htlc/{htlc_id}/fund/psbt", web::post().to(handlers::export_funding_psbt))
+        .route("/htlc/{htlc_id}/{spend_path}/psbt", web::post().to(handlers::export_htlc_psbt))
+        .route("/htlc/{htlc_id}/psbt", web::post().to(handlers::submit_htlc_psbt))
         .route("/transactions/{tx_id}/status", web::get().to(handlers::get_transaction_status))
+        .route("/watchlist", web::post().to(handlers::watch_script))
+        .route("/watchlist/{watch_id}", web::get().to(handlers::get_watched_script))
+        .route("/watchlist/{watch_id}/events", web::get().to(handlers::get_watch_events))
         .route("/webhooks", web::post().to(handlers::register_webhook))
         .route("/fees/estimate", web::get().to(handlers::estimate_fees))
 }
@@ -20,27 +30,17 @@ pub fn configure_routes() -> Scope {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::test_pool;
     use actix_web::{test, App};
-    use sqlx::sqlite::SqlitePoolOptions;
+    use crate::services::bitcoin::{BitcoinClient, MockBackend};
     use crate::AppState;
 
     #[actix_rt::test]
     async fn test_routes_configuration() {
-        // Create a test database pool
-        let pool = SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect(":memory:")
-            .await
-            .expect("Failed to create test database");
+        let pool = test_pool().await;
 
-        // Run migrations
-        sqlx::migrate!("./migrations")
-            .run(&pool)
-            .await
-            .expect("Failed to run migrations");
-
-        // Create app state
-        let app_state = AppState::new(pool);
+        // Create app state backed by an in-memory chain
+        let app_state = AppState::with_bitcoin_client(pool, BitcoinClient::from_backend(MockBackend::new(2_500_000)));
 
         let app = test::init_service(
             App::new()
@@ -0,0 +1,49 @@
+use crate::models::ApiError;
+use crate::services::bitcoin::{wait_for_confirmations, FeeEstimates, TransactionInfo, Utxo};
+use async_trait::async_trait;
+use bitcoin::{Block, Transaction};
+
+/// Source of chain data and broadcast endpoint
+///
+/// Implemented for Esplora REST, bitcoind JSON-RPC, Electrum servers and an in-memory
+/// chain for tests.
+/// Node-specific extras such as wallet calls or block generation stay on the concrete
+/// backend instead of failing at runtime on the others.
+#[async_trait]
+pub trait BitcoinBackend: std::fmt::Debug + Send + Sync {
+    /// Height of the current chain tip
+    async fn get_block_height(&self) -> Result<u32, ApiError>;
+
+    /// Hash of the block at `height` on the active chain
+    async fn get_block_hash(&self, height: u32) -> Result<String, ApiError>;
+
+    /// Block with every transaction in it; Electrum servers only serve headers and fail this
+    async fn get_block(&self, block_hash: &str) -> Result<Block, ApiError>;
+
+    /// Broadcast a raw transaction, returning its txid
+    async fn broadcast_transaction(&self, tx_hex: &str) -> Result<String, ApiError>;
+
+    async fn get_transaction(&self, txid: &str) -> Result<TransactionInfo, ApiError>;
+
+    /// Transaction as broadcast, for PSBT inputs that must carry the whole previous transaction
+    async fn get_raw_transaction(&self, txid: &str) -> Result<Transaction, ApiError>;
+
+    /// Unspent outputs paying to `address`, including unconfirmed ones
+    async fn get_utxos(&self, address: &str) -> Result<Vec<Utxo>, ApiError>;
+
+    /// Txid of the transaction spending `txid:vout`, confirmed or in the mempool,
+    /// `None` while the output is unspent
+    async fn get_outspend(&self, txid: &str, vout: u32) -> Result<Option<String>, ApiError>;
+
+    /// Fee rates in sat/vB
+    async fn get_fee_estimates(&self) -> Result<FeeEstimates, ApiError>;
+
+    /// Poll until `txid` has `required_confirmations`, returning the confirmations seen
+    async fn wait_for_confirmations(
+        &self,
+        txid: &str,
+        required_confirmations: u32,
+    ) -> Result<u32, ApiError> {
+        wait_for_confirmations(self, txid, required_confirmations).await
+    }
+}
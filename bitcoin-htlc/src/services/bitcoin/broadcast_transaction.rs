@@ -8,7 +8,7 @@ pub async fn broadcast_transaction(
     transaction_hex: &str
 ) -> Result<String, ApiError> {
     let response = client
-        .post(&format!("{}/tx", base_url))
+        .post(format!("{}/tx", base_url))
         .body(transaction_hex.to_string())
         .send()
         .await?;
@@ -0,0 +1,16 @@
+use bitcoin::{Block, Transaction};
+
+/// Something a chain backend pushed instead of waiting to be polled
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    /// A transaction entered the mempool, or was mined without passing through it
+    Transaction(Transaction),
+    /// A block joined the active chain at `height`; a height at or below an earlier
+    /// event's means the chain reorganized
+    Block { height: u32, block: Block },
+    /// The history of a subscribed address changed: it was paid, spent from, or one
+    /// of its transactions confirmed or was reorged out
+    AddressActivity { address: String },
+    /// Notifications were lost and could not be replayed, state should be polled
+    Resync,
+}
@@ -0,0 +1,765 @@
+use crate::models::ApiError;
+use crate::services::bitcoin::parse_rpc_transaction::fee_from_prevouts;
+use crate::services::bitcoin::{
+    json_fee_rate, scriptpubkey_type, BitcoinBackend, ChainEvent, FeeEstimateCache, FeeEstimates, PreviousOutput,
+    TransactionInfo, TransactionInput, TransactionOutput, TransactionStatus, Utxo, UtxoStatus,
+};
+use async_trait::async_trait;
+use bitcoin::{
+    block::Header,
+    consensus::deserialize,
+    hashes::{sha256, Hash},
+    Address, Block, Network, OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid,
+};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_native_tls::{native_tls, TlsConnector};
+
+const PROTOCOL_VERSION: &str = "1.4";
+
+#[derive(Debug)]
+struct Request {
+    method: String,
+    params: Vec<Value>,
+    response: oneshot::Sender<Result<Value, ApiError>>,
+}
+
+/// Electrum protocol client for ElectrumX, electrs or Fulcrum
+///
+/// `url` is `tcp://host:port` or `ssl://host:port`. One connection carries every
+/// request; it is opened on first use and reopened after it drops. Servers index
+/// by scripthash, so address lookups go through the address's scriptPubKey.
+///
+/// Addresses can be subscribed with `blockchain.scripthash.subscribe`. With
+/// [`with_chain_events`](Self::with_chain_events) every address passed to `get_utxos`
+/// is subscribed, and a change to its history is published as
+/// [`ChainEvent::AddressActivity`]. Subscriptions are renewed on reconnect, with an
+/// event per address since changes may have been missed meanwhile.
+#[derive(Debug, Clone)]
+pub struct ElectrumClient {
+    pub url: String,
+    pub network: Network,
+    /// Accept self-signed certificates, as ElectrumX servers commonly use
+    pub accept_invalid_certs: bool,
+    pub timeout: Duration,
+    pub fee_cache: Arc<FeeEstimateCache>,
+    connection: Arc<tokio::sync::Mutex<Option<mpsc::UnboundedSender<Request>>>>,
+    /// Subscribed scripthashes and the address each one stands for
+    subscriptions: Arc<Mutex<HashMap<String, String>>>,
+    chain_events: Option<broadcast::Sender<ChainEvent>>,
+}
+
+impl ElectrumClient {
+    pub fn new(url: impl Into<String>, network: Network) -> Self {
+        Self {
+            url: url.into(),
+            network,
+            accept_invalid_certs: false,
+            timeout: Duration::from_secs(30),
+            fee_cache: Arc::new(FeeEstimateCache::from_env()),
+            connection: Arc::new(tokio::sync::Mutex::new(None)),
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            chain_events: None,
+        }
+    }
+
+    /// Client for `ELECTRUM_URL` on `BITCOIN_NETWORK`, if set; `ELECTRUM_ACCEPT_INVALID_CERTS=true`
+    /// allows self-signed certificates
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("ELECTRUM_URL").ok()?;
+        let network = match std::env::var("BITCOIN_NETWORK").as_deref() {
+            Ok("mainnet") => Network::Bitcoin,
+            Ok("regtest") => Network::Regtest,
+            _ => Network::Testnet,
+        };
+        let mut client = Self::new(url, network);
+        client.accept_invalid_certs = std::env::var("ELECTRUM_ACCEPT_INVALID_CERTS").as_deref() == Ok("true");
+        Some(client)
+    }
+
+    pub fn with_fee_cache(mut self, fee_cache: FeeEstimateCache) -> Self {
+        self.fee_cache = Arc::new(fee_cache);
+        self
+    }
+
+    pub fn with_chain_events(mut self, chain_events: broadcast::Sender<ChainEvent>) -> Self {
+        self.chain_events = Some(chain_events);
+        self
+    }
+
+    /// Make a request on the shared connection, connecting first if needed
+    pub async fn call(&self, method: &str, params: Vec<Value>) -> Result<Value, ApiError> {
+        let requests = self.connection().await?;
+        self.send(&requests, method, params).await
+    }
+
+    async fn send(
+        &self,
+        requests: &mpsc::UnboundedSender<Request>,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<Value, ApiError> {
+        let (response, receiver) = oneshot::channel();
+        requests
+            .send(Request { method: method.to_string(), params, response })
+            .map_err(|_| disconnected(&self.url))?;
+        match tokio::time::timeout(self.timeout, receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(disconnected(&self.url)),
+            Err(_) => Err(ApiError::InternalError {
+                code: "ELECTRUM_TIMEOUT".to_string(),
+                message: format!("{} got no answer from {} within {:?}", method, self.url, self.timeout),
+                details: None,
+            }),
+        }
+    }
+
+    async fn connection(&self) -> Result<mpsc::UnboundedSender<Request>, ApiError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(requests) = connection.as_ref().filter(|requests| !requests.is_closed()) {
+            return Ok(requests.clone());
+        }
+
+        let requests = self.connect().await?;
+        self.send(&requests, "server.version", vec![json!("thunder-portal"), json!(PROTOCOL_VERSION)])
+            .await?;
+        let subscriptions: Vec<(String, String)> = self
+            .subscriptions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(scripthash, address)| (scripthash.clone(), address.clone()))
+            .collect();
+        for (scripthash, address) in subscriptions {
+            self.send(&requests, "blockchain.scripthash.subscribe", vec![json!(scripthash)]).await?;
+            self.publish(ChainEvent::AddressActivity { address });
+        }
+        *connection = Some(requests.clone());
+        Ok(requests)
+    }
+
+    async fn connect(&self) -> Result<mpsc::UnboundedSender<Request>, ApiError> {
+        let connect_error = |reason: String| ApiError::InternalError {
+            code: "ELECTRUM_CONNECTION_ERROR".to_string(),
+            message: format!("Cannot connect to {}: {}", self.url, reason),
+            details: None,
+        };
+        let (scheme, address) = self
+            .url
+            .split_once("://")
+            .ok_or_else(|| connect_error("expected tcp://host:port or ssl://host:port".to_string()))?;
+        let stream = TcpStream::connect(address).await.map_err(|e| connect_error(e.to_string()))?;
+
+        let notifications = self.notification_handler();
+        match scheme {
+            "tcp" => Ok(spawn_connection(stream, notifications)),
+            "ssl" | "tls" => {
+                let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+                let connector = native_tls::TlsConnector::builder()
+                    .danger_accept_invalid_certs(self.accept_invalid_certs)
+                    .build()
+                    .map_err(|e| connect_error(e.to_string()))?;
+                let stream = TlsConnector::from(connector)
+                    .connect(host, stream)
+                    .await
+                    .map_err(|e| connect_error(e.to_string()))?;
+                Ok(spawn_connection(stream, notifications))
+            }
+            other => Err(connect_error(format!("unknown scheme {}", other))),
+        }
+    }
+
+    /// Turn scripthash notifications into events for the subscribed addresses
+    fn notification_handler(&self) -> impl Fn(&str, &[Value]) + Send + 'static {
+        let subscriptions = self.subscriptions.clone();
+        let chain_events = self.chain_events.clone();
+        move |method, params| {
+            if method != "blockchain.scripthash.subscribe" {
+                return;
+            }
+            let address = params
+                .first()
+                .and_then(Value::as_str)
+                .and_then(|scripthash| subscriptions.lock().unwrap().get(scripthash).cloned());
+            if let (Some(address), Some(chain_events)) = (address, chain_events.as_ref()) {
+                let _ = chain_events.send(ChainEvent::AddressActivity { address });
+            }
+        }
+    }
+
+    fn publish(&self, event: ChainEvent) {
+        if let Some(chain_events) = &self.chain_events {
+            let _ = chain_events.send(event);
+        }
+    }
+
+    /// Subscribe to changes of an address's history, returning its current status
+    /// hash (`None` for an address that was never used)
+    pub async fn subscribe_address(&self, address: &str) -> Result<Option<String>, ApiError> {
+        let scripthash = scripthash(&self.script_pubkey(address)?);
+        self.subscriptions.lock().unwrap().insert(scripthash.clone(), address.to_string());
+        let status = self.call("blockchain.scripthash.subscribe", vec![json!(scripthash)]).await?;
+        Ok(status.as_str().map(str::to_string))
+    }
+
+    /// Confirmed and mempool transactions touching a script, as `(txid, height)` with
+    /// height 0 or below for unconfirmed ones
+    pub async fn get_history(&self, script_pubkey: &Script) -> Result<Vec<(String, i64)>, ApiError> {
+        let history = self
+            .call("blockchain.scripthash.get_history", vec![json!(scripthash(script_pubkey))])
+            .await?;
+        history
+            .as_array()
+            .ok_or_else(|| invalid("history is not a list"))?
+            .iter()
+            .map(|entry| {
+                let txid = entry.get("tx_hash").and_then(Value::as_str).ok_or_else(|| invalid("history entry has no tx_hash"))?;
+                let height = entry.get("height").and_then(Value::as_i64).ok_or_else(|| invalid("history entry has no height"))?;
+                Ok((txid.to_string(), height))
+            })
+            .collect()
+    }
+
+    pub async fn get_block_header(&self, height: u32) -> Result<Header, ApiError> {
+        let header = match self.call("blockchain.block.header", vec![json!(height)]).await {
+            Err(ApiError::InternalError { code, .. }) if code == "ELECTRUM_ERROR" => {
+                return Err(ApiError::NotFound {
+                    code: "BLOCK_NOT_FOUND".to_string(),
+                    message: format!("No block at height {}", height),
+                    details: None,
+                })
+            }
+            result => result?,
+        };
+        header
+            .as_str()
+            .and_then(|header| hex::decode(header).ok())
+            .and_then(|bytes| deserialize(&bytes).ok())
+            .ok_or_else(|| invalid("undecodable block header"))
+    }
+
+    pub async fn get_raw_transaction(&self, txid: &str) -> Result<Transaction, ApiError> {
+        let raw = match self.call("blockchain.transaction.get", vec![json!(txid), json!(false)]).await {
+            Err(ApiError::InternalError { code, .. }) if code == "ELECTRUM_ERROR" => {
+                return Err(ApiError::NotFound {
+                    code: "TRANSACTION_NOT_FOUND".to_string(),
+                    message: format!("Transaction {} not found", txid),
+                    details: None,
+                })
+            }
+            result => result?,
+        };
+        raw.as_str()
+            .and_then(|raw| hex::decode(raw).ok())
+            .and_then(|bytes| deserialize(&bytes).ok())
+            .ok_or_else(|| invalid("undecodable transaction"))
+    }
+
+    fn script_pubkey(&self, address: &str) -> Result<ScriptBuf, ApiError> {
+        Address::from_str(address)
+            .ok()
+            .and_then(|address| address.require_network(self.network).ok())
+            .map(|address| address.script_pubkey())
+            .ok_or_else(|| ApiError::BadRequest {
+                code: "INVALID_ADDRESS".to_string(),
+                message: format!("Invalid {} address {}", self.network, address),
+                details: None,
+            })
+    }
+
+    fn output(&self, output: &TxOut) -> TransactionOutput {
+        TransactionOutput {
+            scriptpubkey: hex::encode(output.script_pubkey.as_bytes()),
+            scriptpubkey_type: scriptpubkey_type(&output.script_pubkey).to_string(),
+            scriptpubkey_address: Address::from_script(&output.script_pubkey, self.network)
+                .ok()
+                .map(|address| address.to_string()),
+            value: output.value.to_sat(),
+        }
+    }
+}
+
+/// Own the stream: write requests, route responses to their callers and
+/// notifications to `notifications`, until either side closes
+fn spawn_connection<S>(stream: S, notifications: impl Fn(&str, &[Value]) + Send + 'static) -> mpsc::UnboundedSender<Request>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let (requests, mut receiver) = mpsc::unbounded_channel::<Request>();
+    tokio::spawn(async move {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        let mut pending: HashMap<u64, oneshot::Sender<Result<Value, ApiError>>> = HashMap::new();
+        let mut next_id = 0u64;
+        loop {
+            tokio::select! {
+                request = receiver.recv() => {
+                    let Some(request) = request else { break };
+                    next_id += 1;
+                    let line = json!({
+                        "jsonrpc": "2.0",
+                        "id": next_id,
+                        "method": request.method,
+                        "params": request.params,
+                    });
+                    if let Err(e) = writer.write_all(format!("{}\n", line).as_bytes()).await {
+                        warn!("Electrum connection lost while writing: {}", e);
+                        break;
+                    }
+                    pending.insert(next_id, request.response);
+                }
+                line = lines.next_line() => {
+                    let line = match line {
+                        Ok(Some(line)) => line,
+                        Ok(None) => break,
+                        Err(e) => {
+                            warn!("Electrum connection lost while reading: {}", e);
+                            break;
+                        }
+                    };
+                    let Ok(message) = serde_json::from_str::<Value>(&line) else {
+                        warn!("Ignoring malformed Electrum message: {}", line);
+                        continue;
+                    };
+                    match message.get("id").and_then(Value::as_u64) {
+                        Some(id) => {
+                            if let Some(response) = pending.remove(&id) {
+                                let _ = response.send(response_result(message));
+                            }
+                        }
+                        None => {
+                            let method = message.get("method").and_then(Value::as_str).unwrap_or_default();
+                            let params = message.get("params").and_then(Value::as_array).map_or(&[][..], Vec::as_slice);
+                            notifications(method, params);
+                        }
+                    }
+                }
+            }
+        }
+        info!("Electrum connection closed");
+        // Dropping `pending` tells waiting callers the connection is gone
+    });
+    requests
+}
+
+fn response_result(mut message: Value) -> Result<Value, ApiError> {
+    match message.get("error") {
+        Some(error) if !error.is_null() => Err(ApiError::InternalError {
+            code: "ELECTRUM_ERROR".to_string(),
+            message: format!("Electrum error: {}", error),
+            details: None,
+        }),
+        _ => Ok(message.get_mut("result").map(Value::take).unwrap_or(Value::Null)),
+    }
+}
+
+/// Electrum's index key: SHA256 of the scriptPubKey, byte-reversed, in hex
+pub fn scripthash(script_pubkey: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script_pubkey.as_bytes()).to_byte_array();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+fn disconnected(url: &str) -> ApiError {
+    ApiError::InternalError {
+        code: "ELECTRUM_CONNECTION_ERROR".to_string(),
+        message: format!("Connection to {} closed", url),
+        details: None,
+    }
+}
+
+fn invalid(reason: &str) -> ApiError {
+    ApiError::InternalError {
+        code: "INVALID_ELECTRUM_RESPONSE".to_string(),
+        message: format!("Unexpected Electrum response: {}", reason),
+        details: None,
+    }
+}
+
+#[async_trait]
+impl BitcoinBackend for ElectrumClient {
+    async fn get_block_height(&self) -> Result<u32, ApiError> {
+        let tip = self.call("blockchain.headers.subscribe", vec![]).await?;
+        tip.get("height")
+            .and_then(Value::as_u64)
+            .map(|height| height as u32)
+            .ok_or_else(|| invalid("tip has no height"))
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<String, ApiError> {
+        Ok(self.get_block_header(height).await?.block_hash().to_string())
+    }
+
+    /// The protocol only serves headers, so block scans need another backend
+    async fn get_block(&self, block_hash: &str) -> Result<Block, ApiError> {
+        Err(ApiError::InternalError {
+            code: "BLOCKS_UNAVAILABLE".to_string(),
+            message: format!("Electrum server at {} does not serve block {}", self.url, block_hash),
+            details: None,
+        })
+    }
+
+    async fn broadcast_transaction(&self, tx_hex: &str) -> Result<String, ApiError> {
+        match self.call("blockchain.transaction.broadcast", vec![json!(tx_hex)]).await {
+            Ok(txid) => txid.as_str().map(str::to_string).ok_or_else(|| invalid("broadcast returned no txid")),
+            Err(ApiError::InternalError { code, message, .. }) if code == "ELECTRUM_ERROR" => Err(ApiError::InternalError {
+                code: "BITCOIN_BROADCAST_ERROR".to_string(),
+                message: format!("Failed to broadcast transaction: {}", message),
+                details: None,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The raw transaction with its parents for the prevouts, and its height from the
+    /// history of a script it touches, since electrs has no verbose transactions
+    async fn get_transaction(&self, txid: &str) -> Result<TransactionInfo, ApiError> {
+        let transaction = self.get_raw_transaction(txid).await?;
+
+        let mut parents: HashMap<Txid, Transaction> = HashMap::new();
+        let mut vin = Vec::with_capacity(transaction.input.len());
+        for input in &transaction.input {
+            let outpoint = input.previous_output;
+            let is_coinbase = outpoint.is_null();
+            if !is_coinbase && !parents.contains_key(&outpoint.txid) {
+                let parent = self.get_raw_transaction(&outpoint.txid.to_string()).await?;
+                parents.insert(outpoint.txid, parent);
+            }
+            let prevout = parents
+                .get(&outpoint.txid)
+                .and_then(|parent| parent.output.get(outpoint.vout as usize))
+                .map(|output| PreviousOutput::from(self.output(output)));
+            vin.push(TransactionInput {
+                txid: outpoint.txid.to_string(),
+                vout: outpoint.vout,
+                prevout,
+                scriptsig: hex::encode(input.script_sig.as_bytes()),
+                witness: (!input.witness.is_empty()).then(|| input.witness.iter().map(hex::encode).collect()),
+                is_coinbase,
+                sequence: input.sequence.to_consensus_u32(),
+            });
+        }
+        let vout: Vec<TransactionOutput> = transaction.output.iter().map(|output| self.output(output)).collect();
+        let fee = fee_from_prevouts(&vin, &vout);
+
+        // Provably unspendable outputs are not indexed, any other script touched works
+        let script_pubkey = transaction
+            .output
+            .iter()
+            .map(|output| output.script_pubkey.clone())
+            .chain(vin.iter().filter_map(|input| {
+                input.prevout.as_ref().and_then(|prevout| ScriptBuf::from_hex(&prevout.scriptpubkey).ok())
+            }))
+            .find(|script| !script.is_op_return());
+        let height = match script_pubkey {
+            Some(script_pubkey) => self
+                .get_history(&script_pubkey)
+                .await?
+                .into_iter()
+                .find(|(history_txid, _)| history_txid == txid)
+                .and_then(|(_, height)| u32::try_from(height).ok())
+                .filter(|height| *height > 0),
+            None => None,
+        };
+        let header = match height {
+            Some(height) => Some(self.get_block_header(height).await?),
+            None => None,
+        };
+
+        Ok(TransactionInfo {
+            txid: txid.to_string(),
+            status: TransactionStatus {
+                confirmed: height.is_some(),
+                block_height: height,
+                block_hash: header.map(|header| header.block_hash().to_string()),
+                block_time: header.map(|header| header.time as u64),
+            },
+            fee,
+            vin,
+            vout,
+        })
+    }
+
+    async fn get_raw_transaction(&self, txid: &str) -> Result<Transaction, ApiError> {
+        ElectrumClient::get_raw_transaction(self, txid).await
+    }
+
+    async fn get_utxos(&self, address: &str) -> Result<Vec<Utxo>, ApiError> {
+        if self.chain_events.is_some() && !self.subscriptions.lock().unwrap().values().any(|a| a == address) {
+            self.subscribe_address(address).await?;
+        }
+
+        let scripthash = scripthash(&self.script_pubkey(address)?);
+        let unspent = self.call("blockchain.scripthash.listunspent", vec![json!(scripthash)]).await?;
+        unspent
+            .as_array()
+            .ok_or_else(|| invalid("unspent outputs are not a list"))?
+            .iter()
+            .map(|utxo| {
+                let height = utxo.get("height").and_then(Value::as_i64).unwrap_or(0);
+                let block_height = u32::try_from(height).ok().filter(|height| *height > 0);
+                Ok(Utxo {
+                    txid: utxo
+                        .get("tx_hash")
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| invalid("unspent output has no tx_hash"))?
+                        .to_string(),
+                    vout: utxo
+                        .get("tx_pos")
+                        .and_then(Value::as_u64)
+                        .ok_or_else(|| invalid("unspent output has no tx_pos"))? as u32,
+                    value: utxo
+                        .get("value")
+                        .and_then(Value::as_u64)
+                        .ok_or_else(|| invalid("unspent output has no value"))?,
+                    status: UtxoStatus {
+                        confirmed: block_height.is_some(),
+                        block_height,
+                        block_time: None,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Electrum has no spend index: look through the history of the output's script
+    async fn get_outspend(&self, txid: &str, vout: u32) -> Result<Option<String>, ApiError> {
+        let funding = self.get_raw_transaction(txid).await?;
+        let Some(output) = funding.output.get(vout as usize) else {
+            return Ok(None);
+        };
+        let outpoint = OutPoint::new(funding.txid(), vout);
+        for (history_txid, _) in self.get_history(&output.script_pubkey).await? {
+            if history_txid == txid {
+                continue;
+            }
+            let candidate = self.get_raw_transaction(&history_txid).await?;
+            if candidate.input.iter().any(|input| input.previous_output == outpoint) {
+                return Ok(Some(history_txid));
+            }
+        }
+        Ok(None)
+    }
+
+    /// `blockchain.estimatefee` rates, cached like Esplora's; missing estimates fall
+    /// back to the last ones or the floor, marked stale
+    async fn get_fee_estimates(&self) -> Result<FeeEstimates, ApiError> {
+        if let Some(estimates) = self.fee_cache.fresh() {
+            return Ok(estimates);
+        }
+
+        // BTC/kvB, or -1 while the server lacks data
+        let estimate = |blocks: u32| async move {
+            self.call("blockchain.estimatefee", vec![json!(blocks)])
+                .await
+                .ok()
+                .and_then(|rate| json_fee_rate(&rate).ok())
+                .map(|rate| rate.to_sat_per_vb_ceil() as u32)
+                .filter(|rate| *rate > 0)
+        };
+        match (estimate(1).await, estimate(3).await, estimate(6).await, estimate(144).await) {
+            (Some(fastest), Some(half_hour), Some(hour), Some(economy)) => {
+                let estimates = FeeEstimates { fastest, half_hour, hour, economy, stale: false };
+                self.fee_cache.store(&estimates);
+                Ok(estimates)
+            }
+            _ => {
+                warn!("Fee estimates unavailable from {}, using fallback", self.url);
+                Ok(self.fee_cache.fallback())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{absolute::LockTime, consensus::encode::serialize_hex, transaction::Version, Amount, TxIn};
+    use tokio::net::TcpListener;
+
+    /// Canned answers by `method params`; every connection made gets a handle to
+    /// push notifications or hang up
+    struct MockElectrumServer {
+        url: String,
+        responses: Arc<Mutex<HashMap<String, Value>>>,
+        connections: mpsc::UnboundedReceiver<mpsc::UnboundedSender<Option<Value>>>,
+    }
+
+    impl MockElectrumServer {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("tcp://{}", listener.local_addr().unwrap());
+            let responses: Arc<Mutex<HashMap<String, Value>>> = Arc::new(Mutex::new(HashMap::new()));
+            let (connected, connections) = mpsc::unbounded_channel();
+            let served = responses.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let (push, mut pushed) = mpsc::unbounded_channel::<Option<Value>>();
+                    let _ = connected.send(push);
+                    let responses = served.clone();
+                    tokio::spawn(async move {
+                        let (reader, mut writer) = stream.into_split();
+                        let mut lines = BufReader::new(reader).lines();
+                        loop {
+                            let reply = tokio::select! {
+                                line = lines.next_line() => {
+                                    let Ok(Some(line)) = line else { return };
+                                    let request: Value = serde_json::from_str(&line).unwrap();
+                                    let key = format!("{} {}", request["method"].as_str().unwrap(), request["params"]);
+                                    match responses.lock().unwrap().get(&key) {
+                                        Some(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+                                        None => json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": 1, "message": key } }),
+                                    }
+                                }
+                                notification = pushed.recv() => match notification.flatten() {
+                                    Some(notification) => notification,
+                                    None => return,
+                                },
+                            };
+                            writer.write_all(format!("{}\n", reply).as_bytes()).await.unwrap();
+                        }
+                    });
+                }
+            });
+            Self { url, responses, connections }
+        }
+
+        fn respond(&self, method: &str, params: Value, result: Value) {
+            self.responses.lock().unwrap().insert(format!("{} {}", method, params), result);
+        }
+    }
+
+    fn transaction(inputs: Vec<OutPoint>, outputs: Vec<(ScriptBuf, u64)>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs.into_iter().map(|previous_output| TxIn { previous_output, ..Default::default() }).collect(),
+            output: outputs
+                .into_iter()
+                .map(|(script_pubkey, value)| TxOut { value: Amount::from_sat(value), script_pubkey })
+                .collect(),
+        }
+    }
+
+    fn header(time: u32) -> Header {
+        let mut header = bitcoin::blockdata::constants::genesis_block(Network::Testnet).header;
+        header.time = time;
+        header
+    }
+
+    fn address() -> Address {
+        Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+            .unwrap()
+            .require_network(Network::Testnet)
+            .unwrap()
+    }
+
+    async fn client(server: &MockElectrumServer) -> ElectrumClient {
+        server.respond("server.version", json!(["thunder-portal", "1.4"]), json!(["ElectrumX 1.16", "1.4"]));
+        let floor = FeeEstimates { fastest: 5, half_hour: 3, hour: 2, economy: 1, stale: true };
+        ElectrumClient::new(server.url.clone(), Network::Testnet)
+            .with_fee_cache(FeeEstimateCache::new(Duration::from_secs(60), floor))
+    }
+
+    #[tokio::test]
+    async fn test_reads_tip_broadcasts_and_estimates_fees() {
+        let server = MockElectrumServer::start().await;
+        let client = client(&server).await;
+        let tip = header(1_700_000_000);
+        server.respond("blockchain.headers.subscribe", json!([]), json!({ "height": 2_500_000, "hex": serialize_hex(&tip) }));
+        server.respond("blockchain.block.header", json!([2_500_000]), json!(serialize_hex(&tip)));
+        server.respond("blockchain.transaction.broadcast", json!(["00"]), json!("ab".repeat(32)));
+        for (blocks, rate) in [(1, 0.0002), (3, 0.0001), (6, 0.00005), (144, 0.00001)] {
+            server.respond("blockchain.estimatefee", json!([blocks]), json!(rate));
+        }
+
+        assert_eq!(client.get_block_height().await.unwrap(), 2_500_000);
+        assert_eq!(client.get_block_hash(2_500_000).await.unwrap(), tip.block_hash().to_string());
+        assert!(matches!(client.get_block_hash(2_500_001).await, Err(ApiError::NotFound { .. })));
+        assert_eq!(client.broadcast_transaction("00").await.unwrap(), "ab".repeat(32));
+        assert!(matches!(
+            client.broadcast_transaction("01").await,
+            Err(ApiError::InternalError { ref code, .. }) if code == "BITCOIN_BROADCAST_ERROR"
+        ));
+        assert_eq!(
+            client.get_fee_estimates().await.unwrap(),
+            FeeEstimates { fastest: 20, half_hour: 10, hour: 5, economy: 1, stale: false }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolves_transactions_utxos_and_spends_by_scripthash() {
+        let server = MockElectrumServer::start().await;
+        let client = client(&server).await;
+        let script_pubkey = address().script_pubkey();
+        let parent = transaction(vec![OutPoint::null()], vec![(script_pubkey.clone(), 100_000)]);
+        let funding = OutPoint::new(parent.txid(), 0);
+        let child = transaction(vec![funding], vec![(ScriptBuf::new_op_return([1u8; 4]), 0), (script_pubkey.clone(), 99_000)]);
+        let block = header(1_700_000_600);
+        let hash = scripthash(&script_pubkey);
+
+        server.respond("blockchain.transaction.get", json!([parent.txid(), false]), json!(serialize_hex(&parent)));
+        server.respond("blockchain.transaction.get", json!([child.txid(), false]), json!(serialize_hex(&child)));
+        server.respond("blockchain.scripthash.get_history", json!([hash]), json!([
+            { "tx_hash": parent.txid(), "height": 2_499_990 },
+            { "tx_hash": child.txid(), "height": 2_500_000 }
+        ]));
+        server.respond("blockchain.block.header", json!([2_500_000]), json!(serialize_hex(&block)));
+        server.respond("blockchain.scripthash.listunspent", json!([hash]), json!([
+            { "tx_hash": child.txid(), "tx_pos": 1, "height": 0, "value": 99_000 }
+        ]));
+
+        let info = client.get_transaction(&child.txid().to_string()).await.unwrap();
+        assert_eq!(info.fee, 1_000);
+        assert_eq!(info.vin[0].prevout.as_ref().unwrap().value, 100_000);
+        assert_eq!(info.vout[0].scriptpubkey_type, "op_return");
+        assert_eq!(info.vout[1].scriptpubkey_address, Some(address().to_string()));
+        assert_eq!(info.status.block_height, Some(2_500_000));
+        assert_eq!(info.status.block_hash, Some(block.block_hash().to_string()));
+        assert!(matches!(
+            client.get_transaction(&"cd".repeat(32)).await,
+            Err(ApiError::NotFound { ref code, .. }) if code == "TRANSACTION_NOT_FOUND"
+        ));
+
+        let utxos = client.get_utxos(&address().to_string()).await.unwrap();
+        assert_eq!((utxos[0].vout, utxos[0].value, utxos[0].status.confirmed), (1, 99_000, false));
+
+        let parent_txid = parent.txid().to_string();
+        assert_eq!(client.get_outspend(&parent_txid, 0).await.unwrap(), Some(child.txid().to_string()));
+        assert_eq!(client.get_outspend(&child.txid().to_string(), 1).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_pushes_address_activity_and_resubscribes_after_reconnect() {
+        let mut server = MockElectrumServer::start().await;
+        let (chain_events, mut events) = broadcast::channel(16);
+        let client = client(&server).await.with_chain_events(chain_events);
+        let hash = scripthash(&address().script_pubkey());
+        let expected = address().to_string();
+        server.respond("blockchain.scripthash.subscribe", json!([hash]), Value::Null);
+        server.respond("blockchain.scripthash.listunspent", json!([hash]), json!([]));
+
+        assert!(client.get_utxos(&address().to_string()).await.unwrap().is_empty());
+        let connection = server.connections.recv().await.unwrap();
+        connection
+            .send(Some(json!({ "jsonrpc": "2.0", "method": "blockchain.scripthash.subscribe", "params": [hash, "ef".repeat(32)] })))
+            .unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+        assert!(matches!(event, ChainEvent::AddressActivity { ref address } if *address == expected));
+
+        // The server hangs up; the next call reconnects and subscribes again
+        connection.send(None).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(client.get_utxos(&address().to_string()).await.unwrap().is_empty());
+        assert!(server.connections.recv().await.is_some());
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+        assert!(matches!(event, ChainEvent::AddressActivity { .. }));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::models::ApiError;
+use crate::services::bitcoin::{
+    broadcast_transaction, get_block, get_block_hash, get_block_height, get_fee_estimates, get_outspend, get_raw_transaction, get_transaction,
+    get_utxos,
+    BitcoinBackend, FeeEstimateCache, FeeEstimates, TransactionInfo, Utxo,
+};
+use async_trait::async_trait;
+use bitcoin::{Block, Transaction};
+use log::warn;
+use reqwest::Client;
+use std::sync::Arc;
+
+/// Esplora REST API (blockstream.info, mempool.space or a self-hosted electrs)
+#[derive(Debug, Clone)]
+pub struct EsploraBackend {
+    pub base_url: String,
+    pub client: Client,
+    pub fee_cache: Arc<FeeEstimateCache>,
+}
+
+impl EsploraBackend {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            client: Client::new(),
+            fee_cache: Arc::new(FeeEstimateCache::from_env()),
+        }
+    }
+
+    pub fn with_fee_cache(mut self, fee_cache: FeeEstimateCache) -> Self {
+        self.fee_cache = Arc::new(fee_cache);
+        self
+    }
+}
+
+#[async_trait]
+impl BitcoinBackend for EsploraBackend {
+    async fn get_block_height(&self) -> Result<u32, ApiError> {
+        get_block_height(&self.client, &self.base_url).await
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<String, ApiError> {
+        get_block_hash(&self.client, &self.base_url, height).await
+    }
+
+    async fn get_block(&self, block_hash: &str) -> Result<Block, ApiError> {
+        get_block(&self.client, &self.base_url, block_hash).await
+    }
+
+    async fn broadcast_transaction(&self, tx_hex: &str) -> Result<String, ApiError> {
+        broadcast_transaction(&self.client, &self.base_url, tx_hex).await
+    }
+
+    async fn get_transaction(&self, txid: &str) -> Result<TransactionInfo, ApiError> {
+        get_transaction(&self.client, &self.base_url, txid).await
+    }
+
+    async fn get_raw_transaction(&self, txid: &str) -> Result<Transaction, ApiError> {
+        get_raw_transaction(&self.client, &self.base_url, txid).await
+    }
+
+    async fn get_utxos(&self, address: &str) -> Result<Vec<Utxo>, ApiError> {
+        get_utxos(&self.client, &self.base_url, address).await
+    }
+
+    async fn get_outspend(&self, txid: &str, vout: u32) -> Result<Option<String>, ApiError> {
+        get_outspend(&self.client, &self.base_url, txid, vout).await
+    }
+
+    /// Live estimates cached for the configured TTL; when Esplora can't be reached the
+    /// last ones, or the floor, come back marked stale instead of an error
+    async fn get_fee_estimates(&self) -> Result<FeeEstimates, ApiError> {
+        if let Some(estimates) = self.fee_cache.fresh() {
+            return Ok(estimates);
+        }
+
+        match get_fee_estimates(&self.client, &self.base_url).await {
+            Ok(estimates) => {
+                self.fee_cache.store(&estimates);
+                Ok(estimates)
+            }
+            Err(e) => {
+                warn!("Fee estimates unavailable from {}, using fallback: {}", self.base_url, e);
+                Ok(self.fee_cache.fallback())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_esplora_backend_reads_tip_height() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/blocks/tip/height")
+            .with_body("2500000")
+            .create_async()
+            .await;
+
+        let backend = EsploraBackend::new(server.url());
+        assert_eq!(backend.get_block_height().await.unwrap(), 2_500_000);
+    }
+
+    #[tokio::test]
+    async fn test_fee_estimates_are_cached_and_fall_back_stale() {
+        let mut server = mockito::Server::new_async().await;
+        let live = server
+            .mock("GET", "/fee-estimates")
+            .with_body(r#"{"1": 120.4, "3": 80.0, "6": 45.0, "144": 12.0}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let floor = FeeEstimates { fastest: 5, half_hour: 3, hour: 2, economy: 1, stale: true };
+        let backend = EsploraBackend::new(server.url())
+            .with_fee_cache(FeeEstimateCache::new(Duration::from_secs(60), floor.clone()));
+
+        let first = backend.get_fee_estimates().await.unwrap();
+        let second = backend.get_fee_estimates().await.unwrap();
+        assert_eq!(first, FeeEstimates { fastest: 121, half_hour: 80, hour: 45, economy: 12, stale: false });
+        assert_eq!(second, first);
+        live.assert_async().await;
+
+        // A fee spike must not fall back to the floor once real rates were seen
+        live.remove_async().await;
+        server.mock("GET", "/fee-estimates").with_status(503).create_async().await;
+        let expiring = EsploraBackend::new(server.url())
+            .with_fee_cache(FeeEstimateCache::new(Duration::ZERO, floor.clone()));
+        assert_eq!(expiring.get_fee_estimates().await.unwrap(), floor);
+        expiring.fee_cache.store(&first);
+        assert_eq!(
+            expiring.get_fee_estimates().await.unwrap(),
+            FeeEstimates { stale: true, ..first }
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use crate::models::ApiError;
+use bitcoin::{consensus::deserialize, Block};
+use reqwest::{Client, StatusCode};
+
+/// Get a full block, every transaction included, by its hash
+pub async fn get_block(client: &Client, base_url: &str, block_hash: &str) -> Result<Block, ApiError> {
+    let response = client
+        .get(format!("{}/block/{}/raw", base_url, block_hash))
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(ApiError::NotFound {
+            code: "BLOCK_NOT_FOUND".to_string(),
+            message: format!("Block {} not found", block_hash),
+            details: None,
+        });
+    }
+    let bytes = response.bytes().await?;
+    deserialize(&bytes).map_err(|_| ApiError::InternalError {
+        code: "INVALID_BLOCK".to_string(),
+        message: format!("Invalid serialized block {}", block_hash),
+        details: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{blockdata::constants::genesis_block, consensus::serialize, Network};
+
+    #[tokio::test]
+    async fn test_get_block_decodes_raw_block() {
+        let mut server = mockito::Server::new_async().await;
+        let genesis = genesis_block(Network::Testnet);
+        let hash = genesis.block_hash().to_string();
+        server
+            .mock("GET", format!("/block/{}/raw", hash).as_str())
+            .with_body(serialize(&genesis))
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/block/00/raw")
+            .with_status(404)
+            .with_body("Block not found")
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        assert_eq!(get_block(&client, &server.url(), &hash).await.unwrap(), genesis);
+        assert!(matches!(
+            get_block(&client, &server.url(), "00").await,
+            Err(ApiError::NotFound { .. })
+        ));
+    }
+}
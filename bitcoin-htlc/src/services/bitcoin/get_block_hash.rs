@@ -0,0 +1,44 @@
+use crate::models::ApiError;
+use reqwest::{Client, StatusCode};
+
+/// Get the hash of the block at `height` on the active chain
+pub async fn get_block_hash(client: &Client, base_url: &str, height: u32) -> Result<String, ApiError> {
+    let response = client
+        .get(format!("{}/block-height/{}", base_url, height))
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(ApiError::NotFound {
+            code: "BLOCK_NOT_FOUND".to_string(),
+            message: format!("No block at height {}", height),
+            details: None,
+        });
+    }
+    Ok(response.text().await?.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_get_block_hash_reads_active_chain() {
+        let mut server = mockito::Server::new_async().await;
+        let hash = "000000000000000a8a0d8d1b5b9b4e7c6f0b1c7c6d86b5a8b2c0d1e3f4a5b6c7";
+        server.mock("GET", "/block-height/812345").with_body(hash).create_async().await;
+        server
+            .mock("GET", "/block-height/812346")
+            .with_status(404)
+            .with_body("Block not found")
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        assert_eq!(get_block_hash(&client, &server.url(), 812_345).await.unwrap(), hash);
+        assert!(matches!(
+            get_block_hash(&client, &server.url(), 812_346).await,
+            Err(ApiError::NotFound { .. })
+        ));
+    }
+}
@@ -4,7 +4,7 @@ use reqwest::Client;
 /// Get the current block height from the Bitcoin network
 pub async fn get_block_height(client: &Client, base_url: &str) -> Result<u32, ApiError> {
     let response = client
-        .get(&format!("{}/blocks/tip/height", base_url))
+        .get(format!("{}/blocks/tip/height", base_url))
         .send()
         .await?
         .text()
@@ -1,24 +1,134 @@
 use crate::models::ApiError;
+use reqwest::Client;
 use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Get fee estimates from Esplora's `/fee-estimates`
+///
+/// The endpoint maps confirmation targets in blocks to sat/vB. Each estimate takes the
+/// rate of the largest target not above its own, so a sparse map still answers every
+/// target, and fractional rates are rounded up.
+pub async fn get_fee_estimates(client: &Client, base_url: &str) -> Result<FeeEstimates, ApiError> {
+    let rates: HashMap<String, f64> = client
+        .get(format!("{}/fee-estimates", base_url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let mut rates = rates
+        .into_iter()
+        .filter_map(|(target, rate)| Some((target.parse::<u32>().ok()?, rate)))
+        .filter(|(_, rate)| rate.is_finite() && *rate >= 0.0)
+        .collect::<Vec<_>>();
+    rates.sort_by_key(|(target, _)| *target);
+    if rates.is_empty() {
+        return Err(ApiError::InternalError {
+            code: "BITCOIN_ERROR".to_string(),
+            message: "Fee estimates response has no rates".to_string(),
+            details: None,
+        });
+    }
+
+    let rate_for = |conf_target: u32| {
+        let rate = rates
+            .iter()
+            .rev()
+            .find(|(target, _)| *target <= conf_target)
+            .unwrap_or(&rates[0])
+            .1;
+        (rate.ceil() as u32).max(1)
+    };
 
-/// Get fee estimates for Bitcoin transactions
-pub async fn get_fee_estimates() -> Result<FeeEstimates, ApiError> {
-    // For Blockstream API, we'll use a fixed fee rate for testnet
-    // In production, you'd want to use a proper fee estimation service
     Ok(FeeEstimates {
-        fastest: 5,
-        half_hour: 3,
-        hour: 2,
-        economy: 1,
+        fastest: rate_for(1),
+        half_hour: rate_for(3),
+        hour: rate_for(6),
+        economy: rate_for(144),
+        stale: false,
     })
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct FeeEstimates {
     pub fastest: u32,
     pub half_hour: u32,
     pub hour: u32,
     pub economy: u32,
+    /// Not a live estimate: a previous one or the configured floor
+    pub stale: bool,
+}
+
+/// Last fetched fee estimates, reused for `ttl` and served stale when a refresh fails
+#[derive(Debug)]
+pub struct FeeEstimateCache {
+    ttl: Duration,
+    floor: FeeEstimates,
+    cached: Mutex<Option<(Instant, FeeEstimates)>>,
+}
+
+impl FeeEstimateCache {
+    pub fn new(ttl: Duration, floor: FeeEstimates) -> Self {
+        Self {
+            ttl,
+            floor: FeeEstimates { stale: true, ..floor },
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// TTL from `FEE_ESTIMATE_CACHE_SECS` (60s), floor from `BITCOIN_FEE_FLOOR` as
+    /// comma-separated fastest, half hour, hour and economy sat/vB (5,3,2,1)
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("FEE_ESTIMATE_CACHE_SECS").ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(60));
+        let floor = std::env::var("BITCOIN_FEE_FLOOR").ok()
+            .and_then(|floor| {
+                let rates = floor
+                    .split(',')
+                    .map(|rate| rate.trim().parse::<u32>().ok().filter(|rate| *rate > 0))
+                    .collect::<Option<Vec<_>>>()?;
+                match rates[..] {
+                    [fastest, half_hour, hour, economy] => Some(FeeEstimates { fastest, half_hour, hour, economy, stale: true }),
+                    _ => None,
+                }
+            })
+            .unwrap_or(FeeEstimates { fastest: 5, half_hour: 3, hour: 2, economy: 1, stale: true });
+
+        Self::new(ttl, floor)
+    }
+
+    /// Cached estimates younger than the TTL
+    pub fn fresh(&self) -> Option<FeeEstimates> {
+        self.cached
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < self.ttl)
+            .map(|(_, estimates)| estimates.clone())
+    }
+
+    pub fn store(&self, estimates: &FeeEstimates) {
+        *self.cached.lock().unwrap() = Some((Instant::now(), estimates.clone()));
+    }
+
+    /// What to use when estimates can't be fetched: the last ones, never below the floor
+    pub fn fallback(&self) -> FeeEstimates {
+        match &*self.cached.lock().unwrap() {
+            Some((_, last)) => FeeEstimates {
+                fastest: last.fastest.max(self.floor.fastest),
+                half_hour: last.half_hour.max(self.floor.half_hour),
+                hour: last.hour.max(self.floor.hour),
+                economy: last.economy.max(self.floor.economy),
+                stale: true,
+            },
+            None => self.floor.clone(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -26,25 +136,49 @@ mod tests {
     use super::*;
 
     #[tokio::test]
-    async fn test_get_fee_estimates_returns_valid_rates() {
-        let estimates = get_fee_estimates().await.unwrap();
-        
-        assert!(estimates.fastest > estimates.half_hour);
-        assert!(estimates.half_hour > estimates.hour);
-        assert!(estimates.hour >= estimates.economy);
+    async fn test_maps_confirmation_targets_to_estimates() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/fee-estimates")
+            .with_body(r#"{"1": 87.882, "2": 87.882, "3": 61.2, "4": 40.1, "5": 33.0, "10": 20.5, "144": 1.027, "1008": 1.0}"#)
+            .create_async()
+            .await;
+
+        let estimates = get_fee_estimates(&Client::new(), &server.url()).await.unwrap();
+        assert_eq!(estimates, FeeEstimates { fastest: 88, half_hour: 62, hour: 33, economy: 2, stale: false });
+    }
+
+    #[tokio::test]
+    async fn test_sparse_or_empty_rates() {
+        let mut server = mockito::Server::new_async().await;
+        let sparse = server
+            .mock("GET", "/fee-estimates")
+            .with_body(r#"{"2": 12.0, "25": 0.5}"#)
+            .create_async()
+            .await;
+
+        // Below the smallest target the fastest known rate applies, and no rate drops below 1
+        let estimates = get_fee_estimates(&Client::new(), &server.url()).await.unwrap();
+        assert_eq!((estimates.fastest, estimates.half_hour, estimates.hour, estimates.economy), (12, 12, 12, 1));
+
+        sparse.remove_async().await;
+        server.mock("GET", "/fee-estimates").with_body("{}").create_async().await;
+        assert!(get_fee_estimates(&Client::new(), &server.url()).await.is_err());
     }
 
     #[test]
-    fn test_fee_estimates_reasonable_values() {
-        let estimates = FeeEstimates {
-            fastest: 5,
-            half_hour: 3,
-            hour: 2,
-            economy: 1,
-        };
-
-        // Testnet fees should be low
-        assert!(estimates.fastest <= 10);
-        assert!(estimates.economy >= 1);
-    }
-}
\ No newline at end of file
+    fn test_cache_expires_and_falls_back_above_floor() {
+        let floor = FeeEstimates { fastest: 5, half_hour: 3, hour: 2, economy: 1, stale: false };
+        let cache = FeeEstimateCache::new(Duration::from_secs(60), floor.clone());
+        assert_eq!(cache.fresh(), None);
+        assert_eq!(cache.fallback(), FeeEstimates { stale: true, ..floor });
+
+        cache.store(&FeeEstimates { fastest: 40, half_hour: 2, hour: 2, economy: 1, stale: false });
+        assert_eq!(cache.fresh().map(|estimates| estimates.fastest), Some(40));
+        assert_eq!(cache.fallback(), FeeEstimates { fastest: 40, half_hour: 3, hour: 2, economy: 1, stale: true });
+
+        let expired = FeeEstimateCache::new(Duration::ZERO, floor);
+        expired.store(&FeeEstimates { fastest: 40, half_hour: 20, hour: 10, economy: 1, stale: false });
+        assert_eq!(expired.fresh(), None);
+    }
+}
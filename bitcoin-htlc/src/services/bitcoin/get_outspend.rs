@@ -0,0 +1,56 @@
+use crate::models::ApiError;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+/// Get the transaction spending an output, `None` while it is unspent
+pub async fn get_outspend(
+    client: &Client,
+    base_url: &str,
+    txid: &str,
+    vout: u32,
+) -> Result<Option<String>, ApiError> {
+    let response: Outspend = client
+        .get(format!("{}/tx/{}/outspend/{}", base_url, txid, vout))
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    Ok(response.spent.then_some(response.txid).flatten())
+}
+
+/// Esplora `/tx/:txid/outspend/:vout` response
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Outspend {
+    pub spent: bool,
+    /// Spending transaction, present when `spent`
+    pub txid: Option<String>,
+    /// Input index within the spending transaction
+    pub vin: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_get_outspend_reports_spending_transaction() {
+        let mut server = mockito::Server::new_async().await;
+        let txid = "aa".repeat(32);
+        let spender = "bb".repeat(32);
+        server
+            .mock("GET", format!("/tx/{}/outspend/0", txid).as_str())
+            .with_body(format!(r#"{{"spent": true, "txid": "{}", "vin": 0, "status": {{"confirmed": false}}}}"#, spender))
+            .create_async()
+            .await;
+        server
+            .mock("GET", format!("/tx/{}/outspend/1", txid).as_str())
+            .with_body(r#"{"spent": false}"#)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        assert_eq!(get_outspend(&client, &server.url(), &txid, 0).await.unwrap(), Some(spender));
+        assert_eq!(get_outspend(&client, &server.url(), &txid, 1).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::models::ApiError;
+use bitcoin::{consensus::deserialize, Transaction};
+use reqwest::{Client, StatusCode};
+
+/// Get a transaction in its consensus serialization, as signers need it for legacy inputs
+pub async fn get_raw_transaction(client: &Client, base_url: &str, txid: &str) -> Result<Transaction, ApiError> {
+    let response = client
+        .get(format!("{}/tx/{}/raw", base_url, txid))
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(ApiError::NotFound {
+            code: "TRANSACTION_NOT_FOUND".to_string(),
+            message: format!("Transaction {} not found", txid),
+            details: None,
+        });
+    }
+    let bytes = response.bytes().await?;
+    deserialize(&bytes).map_err(|_| ApiError::InternalError {
+        code: "INVALID_TRANSACTION".to_string(),
+        message: format!("Invalid serialized transaction {}", txid),
+        details: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{blockdata::constants::genesis_block, consensus::serialize, Network};
+
+    #[tokio::test]
+    async fn test_get_raw_transaction_decodes_transaction() {
+        let mut server = mockito::Server::new_async().await;
+        let coinbase = genesis_block(Network::Testnet).txdata[0].clone();
+        let txid = coinbase.txid().to_string();
+        server
+            .mock("GET", format!("/tx/{}/raw", txid).as_str())
+            .with_body(serialize(&coinbase))
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/tx/00/raw")
+            .with_status(404)
+            .with_body("Transaction not found")
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        assert_eq!(get_raw_transaction(&client, &server.url(), &txid).await.unwrap(), coinbase);
+        assert!(matches!(
+            get_raw_transaction(&client, &server.url(), "00").await,
+            Err(ApiError::NotFound { ref code, .. }) if code == "TRANSACTION_NOT_FOUND"
+        ));
+    }
+}
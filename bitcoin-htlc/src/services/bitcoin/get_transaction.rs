@@ -1,4 +1,5 @@
 use crate::models::ApiError;
+use bitcoin::Script;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
@@ -9,7 +10,7 @@ pub async fn get_transaction(
     transaction_id: &str
 ) -> Result<TransactionInfo, ApiError> {
     let response = client
-        .get(&format!("{}/tx/{}", base_url, transaction_id))
+        .get(format!("{}/tx/{}", base_url, transaction_id))
         .send()
         .await?
         .json()
@@ -18,7 +19,7 @@ pub async fn get_transaction(
     Ok(response)
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TransactionInfo {
     pub txid: String,
     pub status: TransactionStatus,
@@ -27,10 +28,13 @@ pub struct TransactionInfo {
     pub vout: Vec<TransactionOutput>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TransactionStatus {
     pub confirmed: bool,
     pub block_height: Option<u32>,
+    /// Block the transaction confirmed in, to tell when a reorg drops it
+    #[serde(default)]
+    pub block_hash: Option<String>,
     pub block_time: Option<u64>,
 }
 
@@ -47,29 +51,71 @@ impl TransactionStatus {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Input in the Esplora `/tx` shape, which every backend produces
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TransactionInput {
+    /// All zeros with `vout` `u32::MAX` for coinbase inputs
     pub txid: String,
     pub vout: u32,
     pub prevout: Option<PreviousOutput>,
+    /// Hex scriptSig, or the coinbase data for coinbase inputs
     pub scriptsig: String,
+    /// Hex witness stack items, `None` when the input has no witness
     pub witness: Option<Vec<String>>,
+    pub is_coinbase: bool,
+    pub sequence: u32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PreviousOutput {
     pub scriptpubkey: String,
+    pub scriptpubkey_type: String,
     pub scriptpubkey_address: Option<String>,
     pub value: u64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TransactionOutput {
     pub scriptpubkey: String,
+    pub scriptpubkey_type: String,
     pub scriptpubkey_address: Option<String>,
     pub value: u64,
 }
 
+impl From<TransactionOutput> for PreviousOutput {
+    fn from(output: TransactionOutput) -> Self {
+        Self {
+            scriptpubkey: output.scriptpubkey,
+            scriptpubkey_type: output.scriptpubkey_type,
+            scriptpubkey_address: output.scriptpubkey_address,
+            value: output.value,
+        }
+    }
+}
+
+/// Esplora's name for the kind of a scriptPubKey
+pub fn scriptpubkey_type(script_pubkey: &Script) -> &'static str {
+    if script_pubkey.is_empty() {
+        "empty"
+    } else if script_pubkey.is_p2pk() {
+        "p2pk"
+    } else if script_pubkey.is_p2pkh() {
+        "p2pkh"
+    } else if script_pubkey.is_p2sh() {
+        "p2sh"
+    } else if script_pubkey.is_p2wpkh() {
+        "v0_p2wpkh"
+    } else if script_pubkey.is_p2wsh() {
+        "v0_p2wsh"
+    } else if script_pubkey.is_p2tr() {
+        "v1_p2tr"
+    } else if script_pubkey.is_op_return() {
+        "op_return"
+    } else {
+        "unknown"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,6 +125,7 @@ mod tests {
         let confirmed_status = TransactionStatus {
             confirmed: true,
             block_height: Some(100),
+            block_hash: Some("00".repeat(32)),
             block_time: Some(1234567890),
         };
         assert_eq!(confirmed_status.confirmations(), Some(1));
@@ -86,11 +133,22 @@ mod tests {
         let unconfirmed_status = TransactionStatus {
             confirmed: false,
             block_height: None,
+            block_hash: None,
             block_time: None,
         };
         assert_eq!(unconfirmed_status.confirmations(), None);
     }
 
+    #[test]
+    fn test_scriptpubkey_type_names() {
+        let p2wsh = bitcoin::ScriptBuf::from_hex("0020701a8d401c84fb13e6baf169d59684e17abd9fa216c8cc5b9fc63d622ff8c58d").unwrap();
+        assert_eq!(scriptpubkey_type(&p2wsh), "v0_p2wsh");
+        let p2pkh = bitcoin::ScriptBuf::from_hex("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac").unwrap();
+        assert_eq!(scriptpubkey_type(&p2pkh), "p2pkh");
+        assert_eq!(scriptpubkey_type(&bitcoin::ScriptBuf::from_hex("6a0474657374").unwrap()), "op_return");
+        assert_eq!(scriptpubkey_type(&bitcoin::ScriptBuf::new()), "empty");
+    }
+
     #[test]
     fn test_transaction_id_validation() {
         let valid_txid = "1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef";
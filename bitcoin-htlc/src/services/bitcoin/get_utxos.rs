@@ -9,7 +9,7 @@ pub async fn get_utxos(
     address: &str
 ) -> Result<Vec<Utxo>, ApiError> {
     let response = client
-        .get(&format!("{}/address/{}/utxo", base_url, address))
+        .get(format!("{}/address/{}/utxo", base_url, address))
         .send()
         .await?
         .json()
@@ -18,7 +18,7 @@ pub async fn get_utxos(
     Ok(response)
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Utxo {
     pub txid: String,
     pub vout: u32,
@@ -26,7 +26,7 @@ pub struct Utxo {
     pub status: UtxoStatus,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct UtxoStatus {
     pub confirmed: bool,
     pub block_height: Option<u32>,
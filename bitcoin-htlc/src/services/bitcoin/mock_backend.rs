@@ -0,0 +1,299 @@
+use crate::models::ApiError;
+use crate::services::bitcoin::{
+    BitcoinBackend, FeeEstimates, TransactionInfo, TransactionStatus, Utxo, UtxoStatus,
+};
+use async_trait::async_trait;
+use bitcoin::{Block, Transaction};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// In-memory backend for tests and local development
+///
+/// Chain state is set up by hand: the tip height, UTXOs per address and known
+/// transactions. Broadcasts are recorded and reported back with the txid of the
+/// decoded transaction, nothing is mined. Only broadcast transactions are known raw.
+#[derive(Debug)]
+pub struct MockBackend {
+    state: Mutex<MockState>,
+}
+
+#[derive(Debug)]
+struct MockState {
+    tip: u32,
+    block_hashes: HashMap<u32, String>,
+    blocks: HashMap<String, Block>,
+    utxos: HashMap<String, Vec<Utxo>>,
+    transactions: HashMap<String, TransactionInfo>,
+    raw_transactions: HashMap<String, Transaction>,
+    outspends: HashMap<(String, u32), String>,
+    broadcasts: Vec<String>,
+    fee_estimates: FeeEstimates,
+}
+
+impl MockBackend {
+    pub fn new(tip: u32) -> Self {
+        Self {
+            state: Mutex::new(MockState {
+                tip,
+                block_hashes: HashMap::new(),
+                blocks: HashMap::new(),
+                utxos: HashMap::new(),
+                transactions: HashMap::new(),
+                raw_transactions: HashMap::new(),
+                outspends: HashMap::new(),
+                broadcasts: Vec::new(),
+                fee_estimates: FeeEstimates {
+                    fastest: 5,
+                    half_hour: 3,
+                    hour: 2,
+                    economy: 1,
+                    stale: false,
+                },
+            }),
+        }
+    }
+
+    pub fn set_block_height(&self, tip: u32) {
+        self.state.lock().unwrap().tip = tip;
+    }
+
+    /// Make `hash` the block at `height`
+    pub fn set_block_hash(&self, height: u32, hash: &str) {
+        self.state.lock().unwrap().block_hashes.insert(height, hash.to_string());
+    }
+
+    pub fn set_block(&self, hash: &str, block: Block) {
+        self.state.lock().unwrap().blocks.insert(hash.to_string(), block);
+    }
+
+    pub fn set_fee_estimates(&self, fee_estimates: FeeEstimates) {
+        self.state.lock().unwrap().fee_estimates = fee_estimates;
+    }
+
+    /// Add an output paying to `address`, confirmed at `block_height` if set
+    pub fn add_utxo(&self, address: &str, txid: &str, vout: u32, value: u64, block_height: Option<u32>) {
+        self.state
+            .lock()
+            .unwrap()
+            .utxos
+            .entry(address.to_string())
+            .or_default()
+            .push(Utxo {
+                txid: txid.to_string(),
+                vout,
+                value,
+                status: UtxoStatus {
+                    confirmed: block_height.is_some(),
+                    block_height,
+                    block_time: None,
+                },
+            });
+    }
+
+    /// Make `txid` known, confirmed at `block_height` if set
+    pub fn add_transaction(&self, txid: &str, block_height: Option<u32>) {
+        self.state.lock().unwrap().transactions.insert(
+            txid.to_string(),
+            TransactionInfo {
+                txid: txid.to_string(),
+                status: TransactionStatus {
+                    confirmed: block_height.is_some(),
+                    block_height,
+                    block_hash: None,
+                    block_time: None,
+                },
+                fee: 0,
+                vin: vec![],
+                vout: vec![],
+            },
+        );
+    }
+
+    /// Mark `txid:vout` as spent by `spending_txid`
+    pub fn set_outspend(&self, txid: &str, vout: u32, spending_txid: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .outspends
+            .insert((txid.to_string(), vout), spending_txid.to_string());
+    }
+
+    /// Raw transactions broadcast so far, in order
+    pub fn broadcasts(&self) -> Vec<String> {
+        self.state.lock().unwrap().broadcasts.clone()
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[async_trait]
+impl BitcoinBackend for MockBackend {
+    async fn get_block_height(&self) -> Result<u32, ApiError> {
+        Ok(self.state.lock().unwrap().tip)
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<String, ApiError> {
+        self.state
+            .lock()
+            .unwrap()
+            .block_hashes
+            .get(&height)
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound {
+                code: "BLOCK_NOT_FOUND".to_string(),
+                message: format!("No block at height {}", height),
+                details: None,
+            })
+    }
+
+    async fn get_block(&self, block_hash: &str) -> Result<Block, ApiError> {
+        self.state
+            .lock()
+            .unwrap()
+            .blocks
+            .get(block_hash)
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound {
+                code: "BLOCK_NOT_FOUND".to_string(),
+                message: format!("Block {} not found", block_hash),
+                details: None,
+            })
+    }
+
+    async fn broadcast_transaction(&self, tx_hex: &str) -> Result<String, ApiError> {
+        let transaction: Transaction = hex::decode(tx_hex)
+            .ok()
+            .and_then(|bytes| bitcoin::consensus::deserialize(&bytes).ok())
+            .ok_or_else(|| ApiError::BadRequest {
+                code: "INVALID_TRANSACTION".to_string(),
+                message: "Failed to decode transaction".to_string(),
+                details: None,
+            })?;
+        let txid = transaction.txid().to_string();
+
+        let mut state = self.state.lock().unwrap();
+        state.broadcasts.push(tx_hex.to_string());
+        state.transactions.entry(txid.clone()).or_insert_with(|| TransactionInfo {
+            txid: txid.clone(),
+            status: TransactionStatus {
+                confirmed: false,
+                block_height: None,
+                block_hash: None,
+                block_time: None,
+            },
+            fee: 0,
+            vin: vec![],
+            vout: vec![],
+        });
+        state.raw_transactions.insert(txid.clone(), transaction);
+        Ok(txid)
+    }
+
+    async fn get_transaction(&self, txid: &str) -> Result<TransactionInfo, ApiError> {
+        self.state
+            .lock()
+            .unwrap()
+            .transactions
+            .get(txid)
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound {
+                code: "TRANSACTION_NOT_FOUND".to_string(),
+                message: format!("Transaction {} not found", txid),
+                details: None,
+            })
+    }
+
+    async fn get_raw_transaction(&self, txid: &str) -> Result<Transaction, ApiError> {
+        self.state
+            .lock()
+            .unwrap()
+            .raw_transactions
+            .get(txid)
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound {
+                code: "TRANSACTION_NOT_FOUND".to_string(),
+                message: format!("Transaction {} not found", txid),
+                details: None,
+            })
+    }
+
+    async fn get_utxos(&self, address: &str) -> Result<Vec<Utxo>, ApiError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .utxos
+            .get(address)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_outspend(&self, txid: &str, vout: u32) -> Result<Option<String>, ApiError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .outspends
+            .get(&(txid.to_string(), vout))
+            .cloned())
+    }
+
+    async fn get_fee_estimates(&self) -> Result<FeeEstimates, ApiError> {
+        Ok(self.state.lock().unwrap().fee_estimates.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reports_configured_chain_state() {
+        let backend = MockBackend::new(100);
+        backend.add_utxo("bcrt1qaddress", "aa".repeat(32).as_str(), 1, 50_000, Some(90));
+        backend.set_block_height(101);
+
+        assert_eq!(backend.get_block_height().await.unwrap(), 101);
+        let utxos = backend.get_utxos("bcrt1qaddress").await.unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].status.block_height, Some(90));
+        assert!(backend.get_utxos("bcrt1qother").await.unwrap().is_empty());
+        backend.set_outspend(&"aa".repeat(32), 1, &"cc".repeat(32));
+        assert_eq!(backend.get_outspend(&"aa".repeat(32), 1).await.unwrap(), Some("cc".repeat(32)));
+        assert_eq!(backend.get_outspend(&"aa".repeat(32), 0).await.unwrap(), None);
+        assert!(matches!(
+            backend.get_transaction(&"bb".repeat(32)).await,
+            Err(ApiError::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_records_broadcasts() {
+        let backend = MockBackend::new(100);
+        assert!(matches!(
+            backend.broadcast_transaction("not hex").await,
+            Err(ApiError::BadRequest { .. })
+        ));
+
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(1_000),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        };
+        let tx_hex = bitcoin::consensus::encode::serialize_hex(&tx);
+        let txid = backend.broadcast_transaction(&tx_hex).await.unwrap();
+
+        assert_eq!(txid, tx.txid().to_string());
+        assert_eq!(backend.broadcasts(), vec![tx_hex]);
+        assert!(!backend.get_transaction(&txid).await.unwrap().status.confirmed);
+        assert_eq!(backend.get_raw_transaction(&txid).await.unwrap(), tx);
+    }
+}
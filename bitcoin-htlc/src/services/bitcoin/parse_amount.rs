@@ -0,0 +1,179 @@
+use crate::models::ApiError;
+use bitcoin::{Amount, FeeRate};
+use serde_json::Value;
+
+/// Decimal places of a BTC amount
+const BTC_DECIMALS: u32 = 8;
+
+/// Parse a decimal BTC amount such as `0.29` or `1e-8` into an exact [`Amount`]
+///
+/// Works on the decimal digits, never on a float, so no satoshi is lost to rounding.
+/// Rejects negative values, more than 8 decimals and anything above 21M BTC.
+pub fn parse_btc_amount(btc: &str) -> Result<Amount, ApiError> {
+    parse_decimal(btc, BTC_DECIMALS)
+        .map(Amount::from_sat)
+        .filter(|amount| *amount <= Amount::MAX_MONEY)
+        .ok_or_else(|| invalid_amount(btc))
+}
+
+/// Parse a BTC/kvB fee rate, as reported by `estimatesmartfee`, into a [`FeeRate`]
+///
+/// `FeeRate` counts sat per 1000 weight units, a quarter of a kvB, so rates that don't
+/// divide evenly are rounded up rather than underpaying.
+pub fn parse_fee_rate(btc_per_kvb: &str) -> Result<FeeRate, ApiError> {
+    let sat_per_kvb = parse_decimal(btc_per_kvb, BTC_DECIMALS).ok_or_else(|| invalid_amount(btc_per_kvb))?;
+    Ok(FeeRate::from_sat_per_kwu(sat_per_kvb.div_ceil(4)))
+}
+
+/// [`parse_btc_amount`] for an RPC result field
+///
+/// serde_json keeps numbers as `f64`, whose shortest representation is the decimal the
+/// node sent for any amount up to 21M BTC with 8 decimals.
+pub fn json_btc_amount(value: &Value) -> Result<Amount, ApiError> {
+    parse_btc_amount(&json_decimal(value)?)
+}
+
+/// [`parse_fee_rate`] for an RPC result field
+pub fn json_fee_rate(value: &Value) -> Result<FeeRate, ApiError> {
+    parse_fee_rate(&json_decimal(value)?)
+}
+
+fn json_decimal(value: &Value) -> Result<String, ApiError> {
+    match value {
+        Value::Number(number) => Ok(number.to_string()),
+        Value::String(decimal) => Ok(decimal.clone()),
+        other => Err(invalid_amount(&other.to_string())),
+    }
+}
+
+/// Value of a non-negative decimal, with optional exponent, times `10^scale`
+///
+/// `None` when the result is not a whole number or does not fit in a `u64`.
+fn parse_decimal(decimal: &str, scale: u32) -> Option<u64> {
+    let (mantissa, exponent) = match decimal.find(['e', 'E']) {
+        Some(index) => (&decimal[..index], decimal[index + 1..].parse::<i32>().ok()?),
+        None => (decimal, 0),
+    };
+    let mantissa = mantissa.strip_prefix('+').unwrap_or(mantissa);
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    if !integer.bytes().chain(fraction.bytes()).all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    // All digits as one integer, with the decimal point `shift` places from its right
+    let digits = format!("{}{}", integer, fraction);
+    let digits = digits.trim_start_matches('0');
+    let shift = scale as i64 + exponent as i64 - fraction.len() as i64;
+
+    if shift >= 0 {
+        if digits.is_empty() {
+            return Some(0);
+        }
+        let factor = 10u64.checked_pow(u32::try_from(shift).ok()?)?;
+        digits.parse::<u64>().ok()?.checked_mul(factor)
+    } else {
+        // Digits below the scale must all be zero
+        let drop = shift.unsigned_abs() as usize;
+        if digits.len() <= drop {
+            return digits.is_empty().then_some(0);
+        }
+        let (kept, dropped) = digits.split_at(digits.len() - drop);
+        if dropped.bytes().any(|byte| byte != b'0') {
+            return None;
+        }
+        kept.parse::<u64>().ok()
+    }
+}
+
+fn invalid_amount(value: &str) -> ApiError {
+    ApiError::InternalError {
+        code: "INVALID_AMOUNT".to_string(),
+        message: format!("Invalid BTC amount {}", value),
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parses_known_edge_cases() {
+        let sat = |btc: &str| parse_btc_amount(btc).map(Amount::to_sat).ok();
+
+        // 0.29 * 1e8 as f64 is 28999999.999999996
+        assert_eq!(sat("0.29"), Some(29_000_000));
+        assert_eq!(sat("0.00000001"), Some(1));
+        assert_eq!(sat("1e-8"), Some(1));
+        assert_eq!(sat("1E-8"), Some(1));
+        assert_eq!(sat("2.1e7"), Some(2_100_000_000_000_000));
+        assert_eq!(sat("20999999.9769"), Some(2_099_999_997_690_000));
+        assert_eq!(sat("1.10000000"), Some(110_000_000));
+        assert_eq!(sat("0"), Some(0));
+        assert_eq!(sat("0.0"), Some(0));
+        assert_eq!(sat("0e5"), Some(0));
+        assert_eq!(sat("+1"), Some(100_000_000));
+
+        assert_eq!(sat("0.000000001"), None);
+        assert_eq!(sat("1e-9"), None);
+        assert_eq!(sat("21000000.00000001"), None);
+        assert_eq!(sat("-1"), None);
+        assert_eq!(sat(""), None);
+        assert_eq!(sat("."), None);
+        assert_eq!(sat("1.2.3"), None);
+        assert_eq!(sat("0x10"), None);
+        assert_eq!(sat("1e"), None);
+        assert_eq!(sat("99999999999999999999"), None);
+    }
+
+    #[test]
+    fn test_parses_fee_rates() {
+        // 0.00001 BTC/kvB is the default minimum relay fee, 1 sat/vB
+        let rate = parse_fee_rate("0.00001").unwrap();
+        assert_eq!(rate.to_sat_per_kwu(), 250);
+        assert_eq!(rate.to_sat_per_vb_ceil(), 1);
+        assert_eq!(parse_fee_rate("0.00001001").unwrap().to_sat_per_kwu(), 251);
+        assert_eq!(parse_fee_rate("0.0002").unwrap().to_sat_per_vb_ceil(), 20);
+        assert!(parse_fee_rate("-0.0001").is_err());
+    }
+
+    #[test]
+    fn test_parses_json_values() {
+        assert_eq!(json_btc_amount(&serde_json::json!(0.29)).unwrap().to_sat(), 29_000_000);
+        assert_eq!(json_btc_amount(&serde_json::json!(0.00000001)).unwrap().to_sat(), 1);
+        assert_eq!(json_btc_amount(&serde_json::json!(6)).unwrap().to_sat(), 600_000_000);
+        assert_eq!(json_btc_amount(&serde_json::json!("0.1")).unwrap().to_sat(), 10_000_000);
+        assert!(json_btc_amount(&serde_json::json!(null)).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_fixed_point_strings_round_trip(sat in 0..=Amount::MAX_MONEY.to_sat()) {
+            let btc = format!("{}.{:08}", sat / 100_000_000, sat % 100_000_000);
+            prop_assert_eq!(parse_btc_amount(&btc).unwrap().to_sat(), sat);
+        }
+
+        #[test]
+        fn prop_json_floats_round_trip(sat in 0..=Amount::MAX_MONEY.to_sat()) {
+            // What a node's JSON amount turns into after serde_json parsed it as f64
+            let value: Value = serde_json::from_str(&format!("{}.{:08}", sat / 100_000_000, sat % 100_000_000)).unwrap();
+            prop_assert_eq!(json_btc_amount(&value).unwrap().to_sat(), sat);
+        }
+
+        #[test]
+        fn prop_exponent_forms_agree(sat in 1..=Amount::MAX_MONEY.to_sat(), exponent in -3i32..=3) {
+            let btc = format!("{}e{}", sat as u128 * 10u128.pow((3 + exponent) as u32), -8 - 3 - exponent);
+            prop_assert_eq!(parse_btc_amount(&btc).unwrap().to_sat(), sat);
+        }
+
+        #[test]
+        fn prop_sub_satoshi_digits_are_rejected(sat in 0..=Amount::MAX_MONEY.to_sat() - 1, extra in 1u8..=9) {
+            let btc = format!("{}.{:08}{}", sat / 100_000_000, sat % 100_000_000, extra);
+            prop_assert!(parse_btc_amount(&btc).is_err());
+        }
+    }
+}
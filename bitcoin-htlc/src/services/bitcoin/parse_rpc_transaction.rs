@@ -0,0 +1,303 @@
+use crate::models::ApiError;
+use crate::services::bitcoin::{
+    json_btc_amount, scriptpubkey_type, PreviousOutput, TransactionInfo, TransactionInput, TransactionOutput,
+    TransactionStatus,
+};
+use bitcoin::ScriptBuf;
+use serde_json::Value;
+
+/// Convert verbose `getrawtransaction` output into the Esplora transaction shape
+///
+/// Inputs carry prevouts when the node returned them (verbosity 2), `block_height`
+/// comes from the header of the including block since the RPC only reports its hash.
+/// Without a `fee` field the fee is derived from the prevouts, or left at 0 if any
+/// is missing.
+pub fn parse_rpc_transaction(tx_data: &Value, block_height: Option<u32>) -> Result<TransactionInfo, ApiError> {
+    let txid = tx_data
+        .get("txid")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("transaction has no txid"))?;
+    let vin = field_array(tx_data, "vin")?
+        .iter()
+        .map(parse_input)
+        .collect::<Result<Vec<_>, _>>()?;
+    let vout = field_array(tx_data, "vout")?
+        .iter()
+        .map(parse_output)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let fee = match tx_data.get("fee") {
+        Some(fee) => btc_to_sat(fee)?,
+        None => fee_from_prevouts(&vin, &vout),
+    };
+    let confirmed = tx_data.get("confirmations").and_then(Value::as_u64).unwrap_or(0) > 0;
+
+    Ok(TransactionInfo {
+        txid: txid.to_string(),
+        status: TransactionStatus {
+            confirmed,
+            block_height: block_height.filter(|_| confirmed),
+            block_hash: tx_data
+                .get("blockhash")
+                .and_then(Value::as_str)
+                .filter(|_| confirmed)
+                .map(|hash| hash.to_string()),
+            block_time: tx_data.get("blocktime").and_then(Value::as_u64).filter(|_| confirmed),
+        },
+        fee,
+        vin,
+        vout,
+    })
+}
+
+/// Inputs minus outputs, 0 for coinbases or when a prevout is unknown
+pub fn fee_from_prevouts(vin: &[TransactionInput], vout: &[TransactionOutput]) -> u64 {
+    if vin.iter().any(|input| input.is_coinbase) {
+        return 0;
+    }
+    let input_value: Option<u64> = vin
+        .iter()
+        .map(|input| input.prevout.as_ref().map(|prevout| prevout.value))
+        .sum();
+    let output_value: u64 = vout.iter().map(|output| output.value).sum();
+    input_value.map_or(0, |input_value| input_value.saturating_sub(output_value))
+}
+
+fn parse_input(input: &Value) -> Result<TransactionInput, ApiError> {
+    let sequence = input
+        .get("sequence")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid("input has no sequence"))? as u32;
+    let witness = input
+        .get("txinwitness")
+        .and_then(Value::as_array)
+        .filter(|items| !items.is_empty())
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| item.as_str().map(str::to_string).ok_or_else(|| invalid("witness item is not hex")))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+
+    if let Some(coinbase) = input.get("coinbase").and_then(Value::as_str) {
+        return Ok(TransactionInput {
+            txid: "0".repeat(64),
+            vout: u32::MAX,
+            prevout: None,
+            scriptsig: coinbase.to_string(),
+            witness,
+            is_coinbase: true,
+            sequence,
+        });
+    }
+
+    Ok(TransactionInput {
+        txid: input
+            .get("txid")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("input has no txid"))?
+            .to_string(),
+        vout: input
+            .get("vout")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| invalid("input has no vout"))? as u32,
+        prevout: input
+            .get("prevout")
+            .map(|prevout| parse_output(prevout).map(PreviousOutput::from))
+            .transpose()?,
+        scriptsig: input
+            .get("scriptSig")
+            .and_then(|script_sig| script_sig.get("hex"))
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        witness,
+        is_coinbase: false,
+        sequence,
+    })
+}
+
+/// Parse a `vout` entry, or an input's `prevout` which has the same layout
+fn parse_output(output: &Value) -> Result<TransactionOutput, ApiError> {
+    let script_pubkey = output
+        .get("scriptPubKey")
+        .ok_or_else(|| invalid("output has no scriptPubKey"))?;
+    let script_hex = script_pubkey
+        .get("hex")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("scriptPubKey has no hex"))?;
+    let script = ScriptBuf::from_hex(script_hex).map_err(|_| invalid("scriptPubKey is not hex"))?;
+
+    // Nodes before v22 list addresses in an array
+    let address = script_pubkey
+        .get("address")
+        .and_then(Value::as_str)
+        .or_else(|| {
+            script_pubkey
+                .get("addresses")
+                .and_then(Value::as_array)
+                .filter(|addresses| addresses.len() == 1)
+                .and_then(|addresses| addresses[0].as_str())
+        })
+        .map(str::to_string);
+
+    Ok(TransactionOutput {
+        scriptpubkey: script_hex.to_string(),
+        scriptpubkey_type: scriptpubkey_type(&script).to_string(),
+        scriptpubkey_address: address,
+        value: btc_to_sat(output.get("value").ok_or_else(|| invalid("output has no value"))?)?,
+    })
+}
+
+fn btc_to_sat(value: &Value) -> Result<u64, ApiError> {
+    json_btc_amount(value)
+        .map(|amount| amount.to_sat())
+        .map_err(|e| invalid(&e.to_string()))
+}
+
+fn field_array<'a>(tx_data: &'a Value, field: &str) -> Result<&'a Vec<Value>, ApiError> {
+    tx_data
+        .get(field)
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid(&format!("transaction has no {}", field)))
+}
+
+fn invalid(reason: &str) -> ApiError {
+    ApiError::InternalError {
+        code: "INVALID_RPC_TRANSACTION".to_string(),
+        message: format!("Unexpected getrawtransaction response: {}", reason),
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+    const PARENT_TXID: &str = "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098";
+
+    fn p2wsh_spend() -> Value {
+        json!({
+            "txid": TXID,
+            "hash": TXID,
+            "version": 2,
+            "locktime": 0,
+            "vin": [{
+                "txid": PARENT_TXID,
+                "vout": 1,
+                "scriptSig": { "asm": "", "hex": "" },
+                "txinwitness": ["3044", "0707", "01", "63a820"],
+                "prevout": {
+                    "generated": false,
+                    "height": 2_499_990,
+                    "value": 0.29,
+                    "scriptPubKey": {
+                        "asm": "0 701a8d401c84fb13e6baf169d59684e17abd9fa216c8cc5b9fc63d622ff8c58d",
+                        "hex": "0020701a8d401c84fb13e6baf169d59684e17abd9fa216c8cc5b9fc63d622ff8c58d",
+                        "address": "tb1qwqdg6squsna38e46795at95yu9atm8azzmyvckulcc7kytlcckxswvvzej",
+                        "type": "witness_v0_scripthash"
+                    }
+                },
+                "sequence": 4294967293u32
+            }],
+            "vout": [{
+                "value": 0.28999,
+                "n": 0,
+                "scriptPubKey": {
+                    "asm": "0 751e76e8199196d454941c45d1b3a323f1433bd6",
+                    "hex": "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                    "address": "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+                    "type": "witness_v0_keyhash"
+                }
+            }],
+            "fee": 0.00001,
+            "blockhash": "000000000000000a8a0d8d1b5b9b4e7c6f0b1c7c6d86b5a8b2c0d1e3f4a5b6c7",
+            "confirmations": 3,
+            "time": 1_700_000_000u64,
+            "blocktime": 1_700_000_000u64
+        })
+    }
+
+    #[test]
+    fn test_parses_inputs_outputs_and_exact_amounts() {
+        let info = parse_rpc_transaction(&p2wsh_spend(), Some(2_500_000)).unwrap();
+
+        assert_eq!(info.txid, TXID);
+        assert_eq!(info.fee, 1_000);
+        assert!(info.status.confirmed);
+        assert_eq!(info.status.block_height, Some(2_500_000));
+        assert_eq!(info.status.block_time, Some(1_700_000_000));
+        assert_eq!(
+            info.status.block_hash.as_deref(),
+            Some("000000000000000a8a0d8d1b5b9b4e7c6f0b1c7c6d86b5a8b2c0d1e3f4a5b6c7")
+        );
+
+        let input = &info.vin[0];
+        assert_eq!(input.txid, PARENT_TXID);
+        assert_eq!(input.vout, 1);
+        assert_eq!(input.scriptsig, "");
+        assert_eq!(input.witness.as_deref(), Some(&["3044".to_string(), "0707".to_string(), "01".to_string(), "63a820".to_string()][..]));
+        assert_eq!(input.sequence, 0xfffffffd);
+        assert!(!input.is_coinbase);
+        let prevout = input.prevout.as_ref().unwrap();
+        // 0.29 BTC is 28999999.999... as a float times 1e8
+        assert_eq!(prevout.value, 29_000_000);
+        assert_eq!(prevout.scriptpubkey_type, "v0_p2wsh");
+
+        let output = &info.vout[0];
+        assert_eq!(output.value, 28_999_000);
+        assert_eq!(output.scriptpubkey_type, "v0_p2wpkh");
+        assert_eq!(output.scriptpubkey_address.as_deref(), Some("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"));
+    }
+
+    #[test]
+    fn test_fee_falls_back_to_prevouts() {
+        let mut tx_data = p2wsh_spend();
+        tx_data.as_object_mut().unwrap().remove("fee");
+        assert_eq!(parse_rpc_transaction(&tx_data, Some(2_500_000)).unwrap().fee, 1_000);
+
+        tx_data["vin"][0].as_object_mut().unwrap().remove("prevout");
+        let info = parse_rpc_transaction(&tx_data, Some(2_500_000)).unwrap();
+        assert!(info.vin[0].prevout.is_none());
+        assert_eq!(info.fee, 0);
+    }
+
+    #[test]
+    fn test_parses_coinbase_and_unconfirmed() {
+        let tx_data = json!({
+            "txid": TXID,
+            "vin": [{ "coinbase": "03a0252600", "sequence": 4294967295u32 }],
+            "vout": [{
+                "value": 6.25,
+                "n": 0,
+                "scriptPubKey": { "hex": "6a0474657374", "type": "nulldata" }
+            }]
+        });
+        let info = parse_rpc_transaction(&tx_data, None).unwrap();
+
+        assert!(!info.status.confirmed);
+        assert_eq!(info.status.block_height, None);
+        assert!(info.vin[0].is_coinbase);
+        assert_eq!(info.vin[0].scriptsig, "03a0252600");
+        assert_eq!(info.vin[0].vout, u32::MAX);
+        assert!(info.vin[0].witness.is_none());
+        assert_eq!(info.vout[0].value, 625_000_000);
+        assert_eq!(info.vout[0].scriptpubkey_type, "op_return");
+        assert_eq!(info.vout[0].scriptpubkey_address, None);
+        assert_eq!(info.fee, 0);
+    }
+
+    #[test]
+    fn test_rejects_malformed_response() {
+        let mut tx_data = p2wsh_spend();
+        tx_data["vout"][0]["value"] = json!(-1.0);
+        assert!(matches!(
+            parse_rpc_transaction(&tx_data, None),
+            Err(ApiError::InternalError { ref code, .. }) if code == "INVALID_RPC_TRANSACTION"
+        ));
+        assert!(parse_rpc_transaction(&json!({ "txid": TXID }), None).is_err());
+    }
+}
@@ -1,4 +1,11 @@
 use crate::models::ApiError;
+use crate::services::bitcoin::parse_rpc_transaction::fee_from_prevouts;
+use crate::services::bitcoin::{
+    json_btc_amount, json_fee_rate, parse_rpc_transaction, BitcoinBackend, FeeEstimates, PreviousOutput, TransactionInfo, Utxo,
+    UtxoStatus,
+};
+use async_trait::async_trait;
+use bitcoin::{consensus::deserialize, Amount, Block, Transaction};
 use reqwest::Client;
 use serde_json::{json, Value};
 use base64::{Engine as _, engine::general_purpose};
@@ -105,6 +112,68 @@ impl BitcoinRpcClient {
         self.rpc_call("getrawtransaction", params).await
     }
 
+    /// Verbose transaction whose inputs include their prevouts and which reports its fee
+    ///
+    /// Nodes before v25 treat verbosity 2 as 1 and leave both out.
+    pub async fn get_raw_transaction_with_prevouts(&self, txid: &str) -> Result<Value, ApiError> {
+        let params = vec![json!(txid), json!(2)];
+        self.rpc_call("getrawtransaction", params).await
+    }
+
+    /// Get a block header, including its height
+    pub async fn get_block_header(&self, block_hash: &str) -> Result<Value, ApiError> {
+        let params = vec![json!(block_hash), json!(true)];
+        self.rpc_call("getblockheader", params).await
+    }
+
+    /// Hash of the block at `height` on the active chain
+    pub async fn get_block_hash(&self, height: u32) -> Result<String, ApiError> {
+        let result = self.rpc_call("getblockhash", vec![json!(height)]).await?;
+        result.as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| ApiError::InternalError {
+                code: "INVALID_BLOCK_HASH".to_string(),
+                message: "Invalid block hash format".to_string(),
+                details: None,
+            })
+    }
+
+    /// Get a block; verbosity 2 includes every transaction decoded
+    pub async fn get_block(&self, block_hash: &str, verbosity: u8) -> Result<Value, ApiError> {
+        let params = vec![json!(block_hash), json!(verbosity)];
+        self.rpc_call("getblock", params).await
+    }
+
+    /// Block decoded from its serialization (`getblock` verbosity 0)
+    pub async fn get_raw_block(&self, block_hash: &str) -> Result<Block, ApiError> {
+        let result = self.get_block(block_hash, 0).await?;
+        result.as_str()
+            .and_then(|block_hex| hex::decode(block_hex).ok())
+            .and_then(|bytes| deserialize(&bytes).ok())
+            .ok_or_else(|| ApiError::InternalError {
+                code: "INVALID_BLOCK".to_string(),
+                message: format!("Invalid serialized block {}", block_hash),
+                details: None,
+            })
+    }
+
+    /// Unspent output details, `null` once it is spent (in the mempool too, if `include_mempool`)
+    pub async fn get_tx_out(&self, txid: &str, vout: u32, include_mempool: bool) -> Result<Value, ApiError> {
+        let params = vec![json!(txid), json!(vout), json!(include_mempool)];
+        self.rpc_call("gettxout", params).await
+    }
+
+    /// Mempool transaction spending an output, bitcoind v24 and later
+    pub async fn get_tx_spending_prevout(&self, txid: &str, vout: u32) -> Result<Option<String>, ApiError> {
+        let params = vec![json!([{ "txid": txid, "vout": vout }])];
+        let result = self.rpc_call("gettxspendingprevout", params).await?;
+        Ok(result
+            .get(0)
+            .and_then(|entry| entry.get("spendingtxid"))
+            .and_then(Value::as_str)
+            .map(|s| s.to_string()))
+    }
+
     /// Get transaction info
     pub async fn get_transaction(&self, txid: &str) -> Result<Value, ApiError> {
         let params = vec![json!(txid)];
@@ -170,14 +239,9 @@ impl BitcoinRpcClient {
     }
 
     /// Get wallet balance
-    pub async fn get_balance(&self) -> Result<f64, ApiError> {
+    pub async fn get_balance(&self) -> Result<Amount, ApiError> {
         let result = self.rpc_call("getbalance", vec![]).await?;
-        result.as_f64()
-            .ok_or_else(|| ApiError::InternalError {
-                code: "INVALID_BALANCE".to_string(),
-                message: "Invalid balance format".to_string(),
-                details: None,
-            })
+        json_btc_amount(&result)
     }
 
     /// Estimate smart fee
@@ -210,4 +274,305 @@ impl BitcoinRpcClient {
         let params = vec![json!(tx_hex)];
         self.rpc_call("signrawtransactionwithwallet", params).await
     }
-}
\ No newline at end of file
+}
+
+impl Default for BitcoinRpcClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl BitcoinBackend for BitcoinRpcClient {
+    async fn get_block_height(&self) -> Result<u32, ApiError> {
+        self.get_block_count().await
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<String, ApiError> {
+        BitcoinRpcClient::get_block_hash(self, height).await
+    }
+
+    async fn get_block(&self, block_hash: &str) -> Result<Block, ApiError> {
+        self.get_raw_block(block_hash).await
+    }
+
+    async fn broadcast_transaction(&self, tx_hex: &str) -> Result<String, ApiError> {
+        self.send_raw_transaction(tx_hex).await
+    }
+
+    async fn get_transaction(&self, txid: &str) -> Result<TransactionInfo, ApiError> {
+        let tx_data = self.get_raw_transaction_with_prevouts(txid).await?;
+        let block_height = match tx_data.get("blockhash").and_then(Value::as_str) {
+            Some(block_hash) => self
+                .get_block_header(block_hash)
+                .await?
+                .get("height")
+                .and_then(Value::as_u64)
+                .map(|height| height as u32),
+            None => None,
+        };
+        let mut info = parse_rpc_transaction(&tx_data, block_height)?;
+
+        // Without prevouts in the response, look the spent outputs up in their parents
+        let mut fetched_prevouts = false;
+        for input in info.vin.iter_mut().filter(|input| !input.is_coinbase && input.prevout.is_none()) {
+            let parent = parse_rpc_transaction(&self.get_raw_transaction(&input.txid, true).await?, None)?;
+            input.prevout = parent.vout.into_iter().nth(input.vout as usize).map(PreviousOutput::from);
+            fetched_prevouts = true;
+        }
+        if fetched_prevouts && tx_data.get("fee").is_none() {
+            info.fee = fee_from_prevouts(&info.vin, &info.vout);
+        }
+
+        Ok(info)
+    }
+
+    async fn get_raw_transaction(&self, txid: &str) -> Result<Transaction, ApiError> {
+        let result = BitcoinRpcClient::get_raw_transaction(self, txid, false).await?;
+        result.as_str()
+            .and_then(|tx_hex| hex::decode(tx_hex).ok())
+            .and_then(|bytes| deserialize(&bytes).ok())
+            .ok_or_else(|| ApiError::InternalError {
+                code: "INVALID_TRANSACTION".to_string(),
+                message: format!("Invalid serialized transaction {}", txid),
+                details: None,
+            })
+    }
+
+    async fn get_utxos(&self, address: &str) -> Result<Vec<Utxo>, ApiError> {
+        let result = self.list_unspent(0, None, Some(vec![address.to_string()])).await?;
+        let tip = self.get_block_count().await?;
+        
+        let utxos = result.as_array()
+            .ok_or_else(|| ApiError::InternalError {
+                code: "INVALID_UTXOS".to_string(),
+                message: "Invalid UTXOs format".to_string(),
+                details: None,
+            })?
+            .iter()
+            .filter_map(|utxo| {
+                let confirmations = utxo.get("confirmations").and_then(|c| c.as_u64()).unwrap_or(0) as u32;
+                let value = match json_btc_amount(utxo.get("amount")?) {
+                    Ok(amount) => amount.to_sat(),
+                    Err(e) => return Some(Err(e)),
+                };
+                Some(Ok(Utxo {
+                    txid: utxo.get("txid")?.as_str()?.to_string(),
+                    vout: utxo.get("vout")?.as_u64()? as u32,
+                    value,
+                    status: UtxoStatus {
+                        confirmed: confirmations > 0,
+                        // listunspent only reports depth, derive the inclusion height from the tip
+                        block_height: (confirmations > 0).then(|| tip + 1 - confirmations),
+                        block_time: None,
+                    },
+                }))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(utxos)
+    }
+
+    async fn get_outspend(&self, txid: &str, vout: u32) -> Result<Option<String>, ApiError> {
+        if let Some(spending_txid) = self.get_tx_spending_prevout(txid, vout).await? {
+            return Ok(Some(spending_txid));
+        }
+        if !self.get_tx_out(txid, vout, true).await?.is_null() {
+            return Ok(None);
+        }
+
+        // Spent in a block; bitcoind has no spend index, so walk the blocks since the output confirmed
+        let funding = self.get_raw_transaction(txid, true).await?;
+        let Some(block_hash) = funding.get("blockhash").and_then(Value::as_str) else {
+            return Ok(None);
+        };
+        let Some(start) = self.get_block_header(block_hash).await?.get("height").and_then(Value::as_u64) else {
+            return Ok(None);
+        };
+        let tip = self.get_block_count().await?;
+        for height in start as u32..=tip {
+            let block = self.get_block(&self.get_block_hash(height).await?, 2).await?;
+            let spender = block
+                .get("tx")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .find(|tx| {
+                    tx.get("vin").and_then(Value::as_array).into_iter().flatten().any(|input| {
+                        input.get("txid").and_then(Value::as_str) == Some(txid)
+                            && input.get("vout").and_then(Value::as_u64) == Some(vout as u64)
+                    })
+                });
+            if let Some(spender) = spender.and_then(|tx| tx.get("txid")).and_then(Value::as_str) {
+                return Ok(Some(spender.to_string()));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn get_fee_estimates(&self) -> Result<FeeEstimates, ApiError> {
+        // estimatesmartfee reports BTC/kvB, or no feerate while it lacks data
+        let estimate = |conf_target: u32| async move {
+            self.estimate_smart_fee(conf_target).await
+                .ok()
+                .and_then(|v| v.get("feerate").and_then(|rate| json_fee_rate(rate).ok()))
+                .map(|rate| rate.to_sat_per_vb_ceil() as u32)
+        };
+        let fast = estimate(1).await;
+        let medium = estimate(6).await;
+        let slow = estimate(144).await;
+        let stale = fast.is_none() || medium.is_none() || slow.is_none();
+
+        Ok(FeeEstimates {
+            fastest: fast.unwrap_or(10),
+            half_hour: medium.unwrap_or(5),
+            hour: medium.unwrap_or(5),
+            economy: slow.unwrap_or(1),
+            stale,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+    const PARENT_TXID: &str = "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098";
+    const BLOCK_HASH: &str = "000000000000000a8a0d8d1b5b9b4e7c6f0b1c7c6d86b5a8b2c0d1e3f4a5b6c7";
+
+    fn output(value: f64, hex: &str) -> Value {
+        json!({ "value": value, "scriptPubKey": { "hex": hex } })
+    }
+
+    async fn rpc_mock(server: &mut mockito::ServerGuard, method: &str, params: Value, result: Value) -> mockito::Mock {
+        server
+            .mock("POST", "/")
+            .match_body(Matcher::PartialJson(json!({ "method": method, "params": params })))
+            .with_body(json!({ "result": result, "error": null, "id": 1 }).to_string())
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_get_transaction_fills_prevouts_from_parents() {
+        let mut server = mockito::Server::new_async().await;
+        // A pre-v25 node ignores verbosity 2: no prevouts and no fee
+        rpc_mock(&mut server, "getrawtransaction", json!([TXID, 2]), json!({
+            "txid": TXID,
+            "vin": [{
+                "txid": PARENT_TXID,
+                "vout": 1,
+                "scriptSig": { "hex": "" },
+                "txinwitness": ["3044", "0707"],
+                "sequence": 4294967295u32
+            }],
+            "vout": [output(0.0009, "0014751e76e8199196d454941c45d1b3a323f1433bd6")],
+            "blockhash": BLOCK_HASH,
+            "confirmations": 2,
+            "blocktime": 1_700_000_000u64
+        })).await;
+        rpc_mock(&mut server, "getblockheader", json!([BLOCK_HASH, true]), json!({ "hash": BLOCK_HASH, "height": 812_345 })).await;
+        rpc_mock(&mut server, "getrawtransaction", json!([PARENT_TXID, true]), json!({
+            "txid": PARENT_TXID,
+            "vin": [{ "coinbase": "03a0252600", "sequence": 4294967295u32 }],
+            "vout": [
+                output(0.5, "0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+                output(0.001, "0020701a8d401c84fb13e6baf169d59684e17abd9fa216c8cc5b9fc63d622ff8c58d")
+            ]
+        })).await;
+
+        let client = BitcoinRpcClient {
+            rpc_url: server.url(),
+            username: "user".to_string(),
+            password: "password".to_string(),
+            client: Client::new(),
+        };
+        let info = BitcoinBackend::get_transaction(&client, TXID).await.unwrap();
+
+        assert_eq!(info.status.block_height, Some(812_345));
+        let prevout = info.vin[0].prevout.as_ref().unwrap();
+        assert_eq!(prevout.value, 100_000);
+        assert_eq!(prevout.scriptpubkey_type, "v0_p2wsh");
+        assert_eq!(info.vin[0].witness, Some(vec!["3044".to_string(), "0707".to_string()]));
+        assert_eq!(info.fee, 10_000);
+    }
+
+    #[tokio::test]
+    async fn test_get_outspend_checks_mempool_then_blocks() {
+        let mut server = mockito::Server::new_async().await;
+        let spender = "cc".repeat(32);
+        rpc_mock(&mut server, "gettxspendingprevout", json!([[{ "txid": PARENT_TXID, "vout": 0 }]]), json!([
+            { "txid": PARENT_TXID, "vout": 0, "spendingtxid": spender }
+        ])).await;
+        rpc_mock(&mut server, "gettxspendingprevout", json!([[{ "txid": PARENT_TXID, "vout": 1 }]]), json!([
+            { "txid": PARENT_TXID, "vout": 1 }
+        ])).await;
+        rpc_mock(&mut server, "gettxspendingprevout", json!([[{ "txid": PARENT_TXID, "vout": 2 }]]), json!([
+            { "txid": PARENT_TXID, "vout": 2 }
+        ])).await;
+        rpc_mock(&mut server, "gettxout", json!([PARENT_TXID, 1, true]), Value::Null).await;
+        rpc_mock(&mut server, "gettxout", json!([PARENT_TXID, 2, true]), json!({ "value": 0.001, "confirmations": 3 })).await;
+        rpc_mock(&mut server, "getrawtransaction", json!([PARENT_TXID, true]), json!({
+            "txid": PARENT_TXID,
+            "vin": [{ "coinbase": "03a0252600", "sequence": 4294967295u32 }],
+            "vout": [],
+            "blockhash": BLOCK_HASH
+        })).await;
+        rpc_mock(&mut server, "getblockheader", json!([BLOCK_HASH, true]), json!({ "hash": BLOCK_HASH, "height": 100 })).await;
+        rpc_mock(&mut server, "getblockcount", json!([]), json!(101)).await;
+        rpc_mock(&mut server, "getblockhash", json!([100]), json!(BLOCK_HASH)).await;
+        rpc_mock(&mut server, "getblockhash", json!([101]), json!("ee".repeat(32))).await;
+        rpc_mock(&mut server, "getblock", json!([BLOCK_HASH, 2]), json!({ "tx": [{ "txid": PARENT_TXID, "vin": [] }] })).await;
+        rpc_mock(&mut server, "getblock", json!(["ee".repeat(32), 2]), json!({ "tx": [
+            { "txid": TXID, "vin": [{ "txid": PARENT_TXID, "vout": 1 }] }
+        ] })).await;
+
+        let client = BitcoinRpcClient {
+            rpc_url: server.url(),
+            username: "user".to_string(),
+            password: "password".to_string(),
+            client: Client::new(),
+        };
+
+        assert_eq!(BitcoinBackend::get_outspend(&client, PARENT_TXID, 0).await.unwrap(), Some(spender));
+        assert_eq!(BitcoinBackend::get_outspend(&client, PARENT_TXID, 1).await.unwrap(), Some(TXID.to_string()));
+        assert_eq!(BitcoinBackend::get_outspend(&client, PARENT_TXID, 2).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_amounts_and_fee_rates_are_exact() {
+        let mut server = mockito::Server::new_async().await;
+        let address = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+        rpc_mock(&mut server, "listunspent", json!([0, 9999999, [address]]), json!([
+            { "txid": TXID, "vout": 0, "amount": 0.29, "confirmations": 3 },
+            { "txid": PARENT_TXID, "vout": 2, "amount": 0.00000001, "confirmations": 0 }
+        ])).await;
+        rpc_mock(&mut server, "getblockcount", json!([]), json!(812_345)).await;
+        rpc_mock(&mut server, "estimatesmartfee", json!([1]), json!({ "feerate": 0.00012345, "blocks": 2 })).await;
+        rpc_mock(&mut server, "estimatesmartfee", json!([6]), json!({ "feerate": 0.00001, "blocks": 6 })).await;
+        rpc_mock(&mut server, "estimatesmartfee", json!([144]), json!({ "errors": ["Insufficient data or no feerate found"], "blocks": 0 })).await;
+
+        let client = BitcoinRpcClient {
+            rpc_url: server.url(),
+            username: "user".to_string(),
+            password: "password".to_string(),
+            client: Client::new(),
+        };
+
+        let utxos = BitcoinBackend::get_utxos(&client, address).await.unwrap();
+        assert_eq!(utxos[0].value, 29_000_000);
+        assert_eq!(utxos[0].status.block_height, Some(812_343));
+        assert_eq!(utxos[1].value, 1);
+        assert!(!utxos[1].status.confirmed);
+
+        let fees = BitcoinBackend::get_fee_estimates(&client).await.unwrap();
+        // 12345 sat/kvB rounds up to 13 sat/vB
+        assert_eq!(fees.fastest, 13);
+        assert_eq!(fees.half_hour, 1);
+        assert_eq!(fees.economy, 1);
+        // No estimate for 144 blocks, so the economy rate is the fallback
+        assert!(fees.stale);
+    }
+}
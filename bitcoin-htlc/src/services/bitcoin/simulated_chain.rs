@@ -0,0 +1,789 @@
+use crate::models::ApiError;
+use crate::services::bitcoin::{
+    scriptpubkey_type, BitcoinBackend, FeeEstimates, PreviousOutput, TransactionInfo,
+    TransactionInput, TransactionOutput, TransactionStatus, Utxo, UtxoStatus,
+};
+use crate::services::transaction::verify_spend;
+use async_trait::async_trait;
+use bitcoin::{
+    absolute::LockTime,
+    block::{Header, Version as BlockVersion},
+    consensus::deserialize,
+    hashes::{sha256d, Hash},
+    Address, Amount, Block, BlockHash, CompactTarget, FeeRate, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    TxMerkleNode, Txid, Witness,
+};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Timestamp of the first simulated block; every following block is ten minutes later
+const BASE_BLOCK_TIME: u64 = 1_700_000_000;
+/// Bitcoin Core's default `-incrementalrelayfee`, what a replacement pays on top
+const INCREMENTAL_RELAY_FEE: FeeRate = FeeRate::from_sat_per_vb_unchecked(1);
+
+/// In-memory chain that validates what is broadcast to it
+///
+/// Keeps blocks, a mempool and the transactions in them, and derives the UTXO set from
+/// those. Broadcasts are only accepted when their inputs exist and are unspent, they
+/// don't create value, their locktime is final for the next block and every input
+/// passes [`verify_spend`]. Conflicting spends replace RBF-signaling mempool
+/// transactions under the BIP125 fee rules. Coins enter through
+/// [`SimulatedChain::fund`], time moves with [`SimulatedChain::mine`] and
+/// [`SimulatedChain::reorg`] replaces recent blocks.
+#[derive(Debug)]
+pub struct SimulatedChain {
+    network: Network,
+    state: Mutex<ChainState>,
+}
+
+#[derive(Debug)]
+struct ChainState {
+    /// Height of `blocks[0]`, the chain below it is assumed empty
+    base_height: u32,
+    blocks: Vec<SimulatedBlock>,
+    /// Unconfirmed transactions in acceptance order, so parents come before children
+    mempool: Vec<Txid>,
+    transactions: HashMap<Txid, Transaction>,
+    /// Transactions created by `fund`, which have no real inputs
+    faucet: HashSet<Txid>,
+    /// Mixed into block hashes so blocks replaced by a reorg get new hashes
+    nonce: u64,
+}
+
+#[derive(Debug)]
+struct SimulatedBlock {
+    hash: BlockHash,
+    txids: Vec<Txid>,
+}
+
+#[derive(Debug, Clone)]
+struct Coin {
+    output: TxOut,
+    height: Option<u32>,
+}
+
+impl SimulatedChain {
+    /// Empty chain whose tip is at `height`
+    pub fn new(network: Network, height: u32) -> Self {
+        let mut state = ChainState {
+            base_height: height,
+            blocks: Vec::new(),
+            mempool: Vec::new(),
+            transactions: HashMap::new(),
+            faucet: HashSet::new(),
+            nonce: 0,
+        };
+        state.push_block(Vec::new());
+        Self {
+            network,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Send `amount` out of thin air to `address`, unconfirmed until the next block
+    pub fn fund(&self, address: &Address, amount: Amount) -> OutPoint {
+        let mut state = self.state.lock().unwrap();
+        state.nonce += 1;
+        let transaction = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                // Unique coinbase-style scriptSig keeps faucet txids distinct
+                script_sig: ScriptBuf::from(state.nonce.to_le_bytes().to_vec()),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: amount,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        let txid = transaction.txid();
+        state.faucet.insert(txid);
+        state.transactions.insert(txid, transaction);
+        state.mempool.push(txid);
+        OutPoint::new(txid, 0)
+    }
+
+    /// Mine `blocks` blocks, the first one confirming the whole mempool
+    pub fn mine(&self, blocks: u32) -> Vec<BlockHash> {
+        let mut state = self.state.lock().unwrap();
+        (0..blocks)
+            .map(|_| {
+                let txids = std::mem::take(&mut state.mempool);
+                state.push_block(txids)
+            })
+            .collect()
+    }
+
+    /// Mine `blocks` blocks that leave the mempool alone, as when its fees are too low
+    pub fn mine_empty(&self, blocks: u32) -> Vec<BlockHash> {
+        let mut state = self.state.lock().unwrap();
+        (0..blocks).map(|_| state.push_block(Vec::new())).collect()
+    }
+
+    /// Replace the last `depth` blocks with as many empty ones
+    ///
+    /// Transactions from the disconnected blocks go back to the mempool the way a node
+    /// resubmits them, and are dropped if they are no longer valid at the lower tip. The
+    /// tip height is unchanged but every replaced block has a new hash.
+    pub fn reorg(&self, depth: u32) -> Vec<BlockHash> {
+        let mut state = self.state.lock().unwrap();
+        let depth = (depth as usize).min(state.blocks.len() - 1);
+        let keep = state.blocks.len() - depth;
+        let disconnected = state.blocks.split_off(keep);
+
+        let mut candidates: Vec<Txid> = disconnected
+            .into_iter()
+            .flat_map(|block| block.txids)
+            .collect();
+        candidates.append(&mut state.mempool);
+        state.revalidate_mempool(candidates);
+
+        (0..depth).map(|_| state.push_block(Vec::new())).collect()
+    }
+
+    /// Drop an unconfirmed transaction and everything spending it from the mempool
+    pub fn evict(&self, txid: &Txid) {
+        let mut state = self.state.lock().unwrap();
+        if !state.mempool.contains(txid) {
+            return;
+        }
+        let candidates: Vec<Txid> = state.mempool.iter().filter(|t| *t != txid).copied().collect();
+        state.mempool.clear();
+        state.transactions.remove(txid);
+        state.revalidate_mempool(candidates);
+    }
+
+    /// A transaction the chain knows of, confirmed or in the mempool
+    pub fn transaction(&self, txid: &Txid) -> Option<Transaction> {
+        self.state.lock().unwrap().transactions.get(txid).cloned()
+    }
+
+    pub fn mempool(&self) -> Vec<Txid> {
+        self.state.lock().unwrap().mempool.clone()
+    }
+
+    pub fn tip_hash(&self) -> BlockHash {
+        self.state.lock().unwrap().blocks.last().unwrap().hash
+    }
+
+    /// Hash of the block at `height` on the current chain
+    pub fn block_hash(&self, height: u32) -> Option<BlockHash> {
+        let state = self.state.lock().unwrap();
+        let index = height.checked_sub(state.base_height)? as usize;
+        state.blocks.get(index).map(|block| block.hash)
+    }
+
+    fn address(&self, script_pubkey: &ScriptBuf) -> Option<String> {
+        Address::from_script(script_pubkey, self.network)
+            .ok()
+            .map(|address| address.to_string())
+    }
+}
+
+impl ChainState {
+    fn tip(&self) -> u32 {
+        self.base_height + self.blocks.len() as u32 - 1
+    }
+
+    fn push_block(&mut self, txids: Vec<Txid>) -> BlockHash {
+        self.nonce += 1;
+        let mut header = Vec::new();
+        if let Some(previous) = self.blocks.last() {
+            header.extend_from_slice(previous.hash.as_byte_array());
+        }
+        header.extend_from_slice(&(self.base_height + self.blocks.len() as u32).to_le_bytes());
+        header.extend_from_slice(&self.nonce.to_le_bytes());
+        for txid in &txids {
+            header.extend_from_slice(txid.as_byte_array());
+        }
+        let hash = BlockHash::from_raw_hash(sha256d::Hash::hash(&header));
+        self.blocks.push(SimulatedBlock { hash, txids });
+        hash
+    }
+
+    fn confirmation_height(&self, txid: &Txid) -> Option<u32> {
+        self.blocks
+            .iter()
+            .position(|block| block.txids.contains(txid))
+            .map(|index| self.base_height + index as u32)
+    }
+
+    /// Unspent outputs after the chain and the mempool, unconfirmed ones without a height
+    fn coins(&self) -> HashMap<OutPoint, Coin> {
+        let base_height = self.base_height;
+        let confirmed = self.blocks.iter().enumerate().flat_map(|(index, block)| {
+            block.txids.iter().map(move |txid| (txid, Some(base_height + index as u32)))
+        });
+        let unconfirmed = self.mempool.iter().map(|txid| (txid, None));
+
+        let mut coins = HashMap::new();
+        for (txid, height) in confirmed.chain(unconfirmed) {
+            let transaction = &self.transactions[txid];
+            for input in &transaction.input {
+                coins.remove(&input.previous_output);
+            }
+            for (vout, output) in transaction.output.iter().enumerate() {
+                coins.insert(
+                    OutPoint::new(*txid, vout as u32),
+                    Coin {
+                        output: output.clone(),
+                        height,
+                    },
+                );
+            }
+        }
+        coins
+    }
+
+    /// Mempool policy and consensus checks for a transaction entering the mempool
+    ///
+    /// Returns the mempool transactions it replaces, which must have signaled RBF.
+    fn check_transaction(&self, transaction: &Transaction) -> Result<Vec<Txid>, String> {
+        if transaction.input.is_empty() {
+            return Err("bad-txns-vin-empty".to_string());
+        }
+        if transaction.output.is_empty() {
+            return Err("bad-txns-vout-empty".to_string());
+        }
+
+        let coins = self.coins();
+        let mut seen = HashSet::new();
+        let mut prevouts = Vec::new();
+        let mut conflicts = Vec::new();
+        for input in &transaction.input {
+            let outpoint = input.previous_output;
+            if !seen.insert(outpoint) {
+                return Err("bad-txns-inputs-duplicate".to_string());
+            }
+            if let Some(coin) = coins.get(&outpoint) {
+                prevouts.push(coin.output.clone());
+                continue;
+            }
+            let spender = self.mempool.iter().copied().find(|txid| {
+                self.transactions[txid]
+                    .input
+                    .iter()
+                    .any(|input| input.previous_output == outpoint)
+            });
+            let Some(spender) = spender else {
+                return Err(format!("bad-txns-inputs-missingorspent: {}", outpoint));
+            };
+            // BIP125: only transactions that opted in may be replaced
+            if !self.transactions[&spender].is_explicitly_rbf() {
+                return Err(format!("txn-mempool-conflict: {} is spent by {}", outpoint, spender));
+            }
+            if !conflicts.contains(&spender) {
+                conflicts.push(spender);
+            }
+            prevouts.push(self.transactions[&outpoint.txid].output[outpoint.vout as usize].clone());
+        }
+
+        let input_value: Amount = prevouts.iter().map(|output| output.value).sum();
+        let output_value: Amount = transaction.output.iter().map(|output| output.value).sum();
+        if input_value < output_value {
+            return Err(format!("bad-txns-in-belowout: {} < {}", input_value, output_value));
+        }
+
+        if !self.is_final(transaction) {
+            return Err(format!(
+                "non-final: locktime {} not reached at height {}",
+                transaction.lock_time,
+                self.tip() + 1
+            ));
+        }
+
+        for index in 0..transaction.input.len() {
+            verify_spend(transaction, index, &prevouts)
+                .map_err(|e| format!("mandatory-script-verify-flag-failed (input {}): {}", index, e))?;
+        }
+
+        if conflicts.is_empty() {
+            return Ok(conflicts);
+        }
+        self.check_replacement(transaction, input_value - output_value, conflicts)
+    }
+
+    /// BIP125 fee rules for a transaction replacing `conflicts`, returning everything it evicts
+    fn check_replacement(&self, transaction: &Transaction, fee: Amount, conflicts: Vec<Txid>) -> Result<Vec<Txid>, String> {
+        let vsize = transaction.vsize() as u64;
+        for txid in &conflicts {
+            let conflict = &self.transactions[txid];
+            // Compare sat/vB cross-multiplied, exact where rates would round
+            if fee.to_sat() * conflict.vsize() as u64 <= self.fee(conflict).to_sat() * vsize {
+                return Err(format!("insufficient fee, rejecting replacement {}; new feerate not above {}", transaction.txid(), txid));
+            }
+        }
+
+        // Descendants of the replaced transactions leave the mempool with them
+        let mut evicted = conflicts;
+        for txid in &self.mempool {
+            let spends_evicted = self.transactions[txid]
+                .input
+                .iter()
+                .any(|input| evicted.contains(&input.previous_output.txid));
+            if spends_evicted && !evicted.contains(txid) {
+                evicted.push(*txid);
+            }
+        }
+
+        let evicted_fee: Amount = evicted.iter().map(|txid| self.fee(&self.transactions[txid])).sum();
+        let required = evicted_fee + INCREMENTAL_RELAY_FEE.fee_vb(vsize).unwrap_or(Amount::MAX);
+        if fee < required {
+            return Err(format!(
+                "insufficient fee, rejecting replacement {}; {} < {} replaced plus incremental relay fee",
+                transaction.txid(),
+                fee,
+                required
+            ));
+        }
+        Ok(evicted)
+    }
+
+    /// Fee a known transaction pays, nothing for faucet transactions
+    fn fee(&self, transaction: &Transaction) -> Amount {
+        if self.faucet.contains(&transaction.txid()) {
+            return Amount::ZERO;
+        }
+        let input_value: Amount = transaction
+            .input
+            .iter()
+            .filter_map(|input| self.transactions.get(&input.previous_output.txid)?.output.get(input.previous_output.vout as usize))
+            .map(|output| output.value)
+            .sum();
+        let output_value: Amount = transaction.output.iter().map(|output| output.value).sum();
+        input_value.checked_sub(output_value).unwrap_or(Amount::ZERO)
+    }
+
+    /// Whether the transaction may be included in the next block
+    fn is_final(&self, transaction: &Transaction) -> bool {
+        if transaction.input.iter().all(|input| input.sequence == Sequence::MAX) {
+            return true;
+        }
+        let next_height = self.tip() + 1;
+        match transaction.lock_time {
+            LockTime::Blocks(height) => height.to_consensus_u32() < next_height,
+            LockTime::Seconds(time) => (time.to_consensus_u32() as u64) < block_time(self.tip()),
+        }
+    }
+
+    /// Rebuild the mempool from `candidates`, dropping transactions that no longer fit
+    fn revalidate_mempool(&mut self, candidates: Vec<Txid>) {
+        for txid in candidates {
+            let valid = self.faucet.contains(&txid)
+                || self
+                    .transactions
+                    .get(&txid)
+                    .is_some_and(|transaction| self.check_transaction(transaction).is_ok_and(|replaced| replaced.is_empty()));
+            if valid {
+                self.mempool.push(txid);
+            } else {
+                self.transactions.remove(&txid);
+            }
+        }
+    }
+}
+
+fn block_time(height: u32) -> u64 {
+    BASE_BLOCK_TIME + height as u64 * 600
+}
+
+fn broadcast_error(reason: String) -> ApiError {
+    ApiError::InternalError {
+        code: "BITCOIN_BROADCAST_ERROR".to_string(),
+        message: format!("Failed to broadcast transaction: {}", reason),
+        details: None,
+    }
+}
+
+#[async_trait]
+impl BitcoinBackend for SimulatedChain {
+    async fn get_block_height(&self) -> Result<u32, ApiError> {
+        Ok(self.state.lock().unwrap().tip())
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<String, ApiError> {
+        self.block_hash(height)
+            .map(|hash| hash.to_string())
+            .ok_or_else(|| ApiError::NotFound {
+                code: "BLOCK_NOT_FOUND".to_string(),
+                message: format!("No block at height {}", height),
+                details: None,
+            })
+    }
+
+    /// Headers carry the simulated heights and times but not a real proof of work, so
+    /// `block_hash()` of the result differs from the hash it was looked up by
+    async fn get_block(&self, block_hash: &str) -> Result<Block, ApiError> {
+        let state = self.state.lock().unwrap();
+        let index = state
+            .blocks
+            .iter()
+            .position(|block| block.hash.to_string() == block_hash)
+            .ok_or_else(|| ApiError::NotFound {
+                code: "BLOCK_NOT_FOUND".to_string(),
+                message: format!("Block {} not found", block_hash),
+                details: None,
+            })?;
+        let height = state.base_height + index as u32;
+
+        Ok(Block {
+            header: Header {
+                version: BlockVersion::TWO,
+                prev_blockhash: index
+                    .checked_sub(1)
+                    .map(|previous| state.blocks[previous].hash)
+                    .unwrap_or_else(BlockHash::all_zeros),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: block_time(height) as u32,
+                bits: CompactTarget::from_consensus(0x207f_ffff),
+                nonce: 0,
+            },
+            txdata: state.blocks[index]
+                .txids
+                .iter()
+                .map(|txid| state.transactions[txid].clone())
+                .collect(),
+        })
+    }
+
+    async fn broadcast_transaction(&self, tx_hex: &str) -> Result<String, ApiError> {
+        let transaction: Transaction = hex::decode(tx_hex)
+            .ok()
+            .and_then(|bytes| deserialize(&bytes).ok())
+            .ok_or_else(|| broadcast_error("TX decode failed".to_string()))?;
+        let txid = transaction.txid();
+
+        let mut state = self.state.lock().unwrap();
+        if state.transactions.contains_key(&txid) {
+            return Ok(txid.to_string());
+        }
+        let replaced = state.check_transaction(&transaction).map_err(broadcast_error)?;
+        state.mempool.retain(|txid| !replaced.contains(txid));
+        for txid in &replaced {
+            state.transactions.remove(txid);
+        }
+        state.transactions.insert(txid, transaction);
+        state.mempool.push(txid);
+        Ok(txid.to_string())
+    }
+
+    async fn get_transaction(&self, txid: &str) -> Result<TransactionInfo, ApiError> {
+        let not_found = || ApiError::NotFound {
+            code: "TRANSACTION_NOT_FOUND".to_string(),
+            message: format!("Transaction {} not found", txid),
+            details: None,
+        };
+        let parsed = Txid::from_str(txid).map_err(|_| not_found())?;
+        let state = self.state.lock().unwrap();
+        let transaction = state.transactions.get(&parsed).ok_or_else(not_found)?;
+
+        // Spent outputs are gone from the UTXO set, look prevouts up by transaction
+        let prevout = |outpoint: &OutPoint| -> Option<TxOut> {
+            state
+                .transactions
+                .get(&outpoint.txid)
+                .and_then(|parent| parent.output.get(outpoint.vout as usize))
+                .cloned()
+        };
+        let vin: Vec<TransactionInput> = transaction
+            .input
+            .iter()
+            .map(|input| TransactionInput {
+                txid: input.previous_output.txid.to_string(),
+                vout: input.previous_output.vout,
+                prevout: prevout(&input.previous_output).map(|output| PreviousOutput {
+                    scriptpubkey: hex::encode(output.script_pubkey.as_bytes()),
+                    scriptpubkey_type: scriptpubkey_type(&output.script_pubkey).to_string(),
+                    scriptpubkey_address: self.address(&output.script_pubkey),
+                    value: output.value.to_sat(),
+                }),
+                scriptsig: hex::encode(input.script_sig.as_bytes()),
+                witness: (!input.witness.is_empty())
+                    .then(|| input.witness.iter().map(hex::encode).collect()),
+                is_coinbase: input.previous_output.is_null(),
+                sequence: input.sequence.to_consensus_u32(),
+            })
+            .collect();
+        let vout: Vec<TransactionOutput> = transaction
+            .output
+            .iter()
+            .map(|output| TransactionOutput {
+                scriptpubkey: hex::encode(output.script_pubkey.as_bytes()),
+                scriptpubkey_type: scriptpubkey_type(&output.script_pubkey).to_string(),
+                scriptpubkey_address: self.address(&output.script_pubkey),
+                value: output.value.to_sat(),
+            })
+            .collect();
+
+        let fee = if state.faucet.contains(&parsed) {
+            0
+        } else {
+            let input_value: u64 = vin.iter().filter_map(|input| input.prevout.as_ref()).map(|output| output.value).sum();
+            let output_value: u64 = vout.iter().map(|output| output.value).sum();
+            input_value.saturating_sub(output_value)
+        };
+        let block_height = state.confirmation_height(&parsed);
+
+        Ok(TransactionInfo {
+            txid: txid.to_string(),
+            status: TransactionStatus {
+                confirmed: block_height.is_some(),
+                block_height,
+                block_hash: block_height
+                    .map(|height| state.blocks[(height - state.base_height) as usize].hash.to_string()),
+                block_time: block_height.map(block_time),
+            },
+            fee,
+            vin,
+            vout,
+        })
+    }
+
+    async fn get_raw_transaction(&self, txid: &str) -> Result<Transaction, ApiError> {
+        Txid::from_str(txid)
+            .ok()
+            .and_then(|parsed| self.transaction(&parsed))
+            .ok_or_else(|| ApiError::NotFound {
+                code: "TRANSACTION_NOT_FOUND".to_string(),
+                message: format!("Transaction {} not found", txid),
+                details: None,
+            })
+    }
+
+    async fn get_utxos(&self, address: &str) -> Result<Vec<Utxo>, ApiError> {
+        let script_pubkey = Address::from_str(address)
+            .ok()
+            .and_then(|address| address.require_network(self.network).ok())
+            .map(|address| address.script_pubkey())
+            .ok_or_else(|| ApiError::BadRequest {
+                code: "INVALID_ADDRESS".to_string(),
+                message: format!("Invalid {} address {}", self.network, address),
+                details: None,
+            })?;
+
+        let state = self.state.lock().unwrap();
+        let mut utxos: Vec<Utxo> = state
+            .coins()
+            .into_iter()
+            .filter(|(_, coin)| coin.output.script_pubkey == script_pubkey)
+            .map(|(outpoint, coin)| Utxo {
+                txid: outpoint.txid.to_string(),
+                vout: outpoint.vout,
+                value: coin.output.value.to_sat(),
+                status: UtxoStatus {
+                    confirmed: coin.height.is_some(),
+                    block_height: coin.height,
+                    block_time: coin.height.map(block_time),
+                },
+            })
+            .collect();
+        utxos.sort_by(|a, b| (&a.txid, a.vout).cmp(&(&b.txid, b.vout)));
+        Ok(utxos)
+    }
+
+    async fn get_outspend(&self, txid: &str, vout: u32) -> Result<Option<String>, ApiError> {
+        let outpoint = OutPoint::new(
+            Txid::from_str(txid).map_err(|_| ApiError::BadRequest {
+                code: "INVALID_TXID".to_string(),
+                message: format!("Invalid txid {}", txid),
+                details: None,
+            })?,
+            vout,
+        );
+        let state = self.state.lock().unwrap();
+        let spender = state
+            .blocks
+            .iter()
+            .flat_map(|block| block.txids.iter())
+            .chain(state.mempool.iter())
+            .find(|txid| {
+                state.transactions[*txid]
+                    .input
+                    .iter()
+                    .any(|input| input.previous_output == outpoint)
+            });
+        Ok(spender.map(|txid| txid.to_string()))
+    }
+
+    async fn get_fee_estimates(&self) -> Result<FeeEstimates, ApiError> {
+        Ok(FeeEstimates {
+            fastest: 5,
+            half_hour: 3,
+            hour: 2,
+            economy: 1,
+            stale: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::transaction::create_funding_transaction;
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+
+    fn wallet(seed: u8) -> (SecretKey, Address) {
+        let secret_key = SecretKey::from_slice(&[seed; 32]).unwrap();
+        let public_key = bitcoin::PublicKey::new(secret_key.public_key(&Secp256k1::new()));
+        let address = Address::p2wpkh(&public_key, Network::Regtest).unwrap();
+        (secret_key, address)
+    }
+
+    /// Spend `outpoint` of the wallet to `destination`, signed with `secret_key`
+    fn spend(outpoint: OutPoint, value: Amount, secret_key: &SecretKey, destination: &Address, fee: Amount) -> Transaction {
+        let (_, source) = wallet(9);
+        let mut transaction = create_funding_transaction(vec![(outpoint, value)], destination, value - fee, &source, fee).unwrap();
+        sign_p2wpkh(&mut transaction, value, secret_key);
+        transaction
+    }
+
+    fn sign_p2wpkh(transaction: &mut Transaction, value: Amount, secret_key: &SecretKey) {
+        let secp = Secp256k1::new();
+        let public_key = bitcoin::PublicKey::new(secret_key.public_key(&secp));
+        let script_pubkey = ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash().unwrap());
+        let sighash = SighashCache::new(&*transaction)
+            .p2wpkh_signature_hash(0, &script_pubkey, value, EcdsaSighashType::All)
+            .unwrap();
+        let signature = secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), secret_key);
+        transaction.input[0].witness = Witness::p2wpkh(
+            &bitcoin::ecdsa::Signature::sighash_all(signature),
+            &public_key.inner,
+        );
+    }
+
+    fn rejection(result: Result<String, ApiError>) -> String {
+        match result {
+            Err(ApiError::InternalError { code, message, .. }) if code == "BITCOIN_BROADCAST_ERROR" => message,
+            other => panic!("expected a broadcast rejection, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accepts_signed_spend_and_mines_it() {
+        let chain = SimulatedChain::new(Network::Regtest, 100);
+        let (secret_key, address) = wallet(1);
+        let (_, destination) = wallet(2);
+        let funding = chain.fund(&address, Amount::from_sat(50_000));
+        chain.mine(1);
+        let funding_txid = funding.txid.to_string();
+        assert_eq!(chain.get_outspend(&funding_txid, 0).await.unwrap(), None);
+
+        let transaction = spend(funding, Amount::from_sat(50_000), &secret_key, &destination, Amount::from_sat(500));
+        let txid = chain.broadcast_transaction(&serialize_hex(&transaction)).await.unwrap();
+        assert_eq!(chain.mempool(), vec![transaction.txid()]);
+        assert!(chain.get_utxos(&address.to_string()).await.unwrap().is_empty());
+        assert_eq!(chain.get_outspend(&funding_txid, 0).await.unwrap(), Some(txid.clone()));
+
+        chain.mine(2);
+        assert_eq!(chain.get_outspend(&funding_txid, 0).await.unwrap(), Some(txid.clone()));
+        assert_eq!(chain.get_block_height().await.unwrap(), 103);
+        let info = chain.get_transaction(&txid).await.unwrap();
+        assert_eq!(info.status.block_height, Some(102));
+        assert_eq!(info.fee, 500);
+        assert_eq!(info.vin[0].prevout.as_ref().unwrap().scriptpubkey_address, Some(address.to_string()));
+
+        let utxos = chain.get_utxos(&destination.to_string()).await.unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].value, 49_500);
+        assert_eq!(utxos[0].status.block_height, Some(102));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_spends() {
+        let chain = SimulatedChain::new(Network::Regtest, 100);
+        let (secret_key, address) = wallet(1);
+        let (other_key, destination) = wallet(2);
+        let funding = chain.fund(&address, Amount::from_sat(50_000));
+
+        // Wrong key
+        let forged = spend(funding, Amount::from_sat(50_000), &other_key, &destination, Amount::from_sat(500));
+        assert!(rejection(chain.broadcast_transaction(&serialize_hex(&forged)).await).contains("mandatory-script-verify-flag-failed"));
+
+        // Creating value
+        let mut inflated = spend(funding, Amount::from_sat(50_000), &secret_key, &destination, Amount::from_sat(500));
+        inflated.output[0].value = Amount::from_sat(60_000);
+        assert!(rejection(chain.broadcast_transaction(&serialize_hex(&inflated)).await).contains("bad-txns-in-belowout"));
+
+        // Unknown input
+        let missing = spend(OutPoint::new(funding.txid, 1), Amount::from_sat(50_000), &secret_key, &destination, Amount::from_sat(500));
+        assert!(rejection(chain.broadcast_transaction(&serialize_hex(&missing)).await).contains("bad-txns-inputs-missingorspent"));
+
+        // Double spend of an output already spent in the mempool by a final transaction
+        let mut first = spend(funding, Amount::from_sat(50_000), &secret_key, &destination, Amount::from_sat(500));
+        first.input[0].sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+        sign_p2wpkh(&mut first, Amount::from_sat(50_000), &secret_key);
+        chain.broadcast_transaction(&serialize_hex(&first)).await.unwrap();
+        let second = spend(funding, Amount::from_sat(50_000), &secret_key, &destination, Amount::from_sat(1_000));
+        assert!(rejection(chain.broadcast_transaction(&serialize_hex(&second)).await).contains("txn-mempool-conflict"));
+
+        // Height locked past the next block
+        let mut locked = spend(funding, Amount::from_sat(50_000), &secret_key, &destination, Amount::from_sat(500));
+        locked.lock_time = LockTime::from_height(101).unwrap();
+        chain.evict(&first.txid());
+        sign_p2wpkh(&mut locked, Amount::from_sat(50_000), &secret_key);
+        assert!(rejection(chain.broadcast_transaction(&serialize_hex(&locked)).await).contains("non-final"));
+        chain.mine(1);
+        chain.broadcast_transaction(&serialize_hex(&locked)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replaces_signaling_transactions_under_bip125() {
+        let chain = SimulatedChain::new(Network::Regtest, 100);
+        let (secret_key, address) = wallet(1);
+        let (child_key, destination) = wallet(2);
+        let (_, other) = wallet(3);
+        let funding = chain.fund(&address, Amount::from_sat(50_000));
+        chain.mine(1);
+
+        let original = spend(funding, Amount::from_sat(50_000), &secret_key, &destination, Amount::from_sat(500));
+        assert!(original.is_explicitly_rbf());
+        chain.broadcast_transaction(&serialize_hex(&original)).await.unwrap();
+        let child = spend(OutPoint::new(original.txid(), 0), Amount::from_sat(49_500), &child_key, &other, Amount::from_sat(500));
+        chain.broadcast_transaction(&serialize_hex(&child)).await.unwrap();
+
+        // Same fee rate, then a higher rate that does not pay for the evicted child
+        let same_rate = spend(funding, Amount::from_sat(50_000), &secret_key, &other, Amount::from_sat(500));
+        assert!(rejection(chain.broadcast_transaction(&serialize_hex(&same_rate)).await).contains("insufficient fee"));
+        let below_package = spend(funding, Amount::from_sat(50_000), &secret_key, &other, Amount::from_sat(900));
+        assert!(rejection(chain.broadcast_transaction(&serialize_hex(&below_package)).await).contains("insufficient fee"));
+
+        let replacement = spend(funding, Amount::from_sat(50_000), &secret_key, &other, Amount::from_sat(1_200));
+        let txid = chain.broadcast_transaction(&serialize_hex(&replacement)).await.unwrap();
+        assert_eq!(chain.mempool(), vec![replacement.txid()]);
+        assert!(chain.get_transaction(&original.txid().to_string()).await.is_err());
+        chain.mine(1);
+        assert!(chain.get_transaction(&txid).await.unwrap().status.confirmed);
+    }
+
+    #[tokio::test]
+    async fn test_reorg_returns_transactions_to_the_mempool() {
+        let chain = SimulatedChain::new(Network::Regtest, 100);
+        let (secret_key, address) = wallet(1);
+        let (_, destination) = wallet(2);
+        let funding = chain.fund(&address, Amount::from_sat(50_000));
+        chain.mine(1);
+        let transaction = spend(funding, Amount::from_sat(50_000), &secret_key, &destination, Amount::from_sat(500));
+        let txid = chain.broadcast_transaction(&serialize_hex(&transaction)).await.unwrap();
+        let mined = chain.mine(3);
+        assert_eq!(chain.block_hash(102), Some(mined[0]));
+
+        let replaced = chain.reorg(3);
+        assert_eq!(chain.get_block_height().await.unwrap(), 104);
+        assert_ne!(chain.block_hash(102), Some(mined[0]));
+        assert_eq!(chain.tip_hash(), replaced[2]);
+        assert_eq!(chain.mempool(), vec![transaction.txid()]);
+        assert!(!chain.get_transaction(&txid).await.unwrap().status.confirmed);
+
+        // Reorging out the funding as well keeps both, parent first
+        chain.reorg(4);
+        assert_eq!(chain.mempool(), vec![funding.txid, transaction.txid()]);
+        chain.mine(1);
+        assert_eq!(chain.get_transaction(&txid).await.unwrap().status.block_height, Some(105));
+    }
+}
@@ -1,12 +1,13 @@
 use crate::models::ApiError;
-use crate::services::bitcoin::get_transaction::get_transaction;
-use reqwest::Client;
+use crate::services::bitcoin::BitcoinBackend;
 use tokio::time::{sleep, Duration};
 
 /// Wait for a transaction to reach the required number of confirmations
-pub async fn wait_for_confirmations(
-    client: &Client,
-    base_url: &str,
+///
+/// Blocks the caller and does not notice reorgs; the orchestrator follows HTLC
+/// transactions with [`track_confirmations`](crate::services::order::track_confirmations) instead.
+pub async fn wait_for_confirmations<B: BitcoinBackend + ?Sized>(
+    backend: &B,
     transaction_id: &str,
     required_confirmations: u32,
 ) -> Result<u32, ApiError> {
@@ -14,11 +15,14 @@ pub async fn wait_for_confirmations(
     const MAX_ATTEMPTS: u32 = 120; // 10 minutes with 5-second intervals
     
     loop {
-        let transaction_info = get_transaction(client, base_url, transaction_id).await?;
-        
-        if let Some(confirmations) = transaction_info.status.confirmations() {
-            if confirmations >= required_confirmations {
-                return Ok(confirmations);
+        // Not yet known to the backend is the same as unconfirmed
+        if let Ok(transaction_info) = backend.get_transaction(transaction_id).await {
+            if let Some(height) = transaction_info.status.block_height {
+                let tip = backend.get_block_height().await?;
+                let confirmations = tip.saturating_sub(height) + 1;
+                if confirmations >= required_confirmations {
+                    return Ok(confirmations);
+                }
             }
         }
         
@@ -37,6 +41,8 @@ pub async fn wait_for_confirmations(
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::services::bitcoin::MockBackend;
 
     #[test]
     fn test_max_attempts_calculation() {
@@ -63,4 +69,13 @@ mod tests {
         assert!(fast_confirmations < standard_confirmations);
         assert!(standard_confirmations < secure_confirmations);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_counts_confirmations_from_the_tip() {
+        let txid = "1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef";
+        let backend = MockBackend::new(800_005);
+        backend.add_transaction(txid, Some(800_000));
+
+        assert_eq!(wait_for_confirmations(&backend, txid, 6).await.unwrap(), 6);
+    }
+}
@@ -0,0 +1,397 @@
+use crate::models::ApiError;
+use crate::services::bitcoin::{BitcoinRpcClient, ChainEvent};
+use bitcoin::{consensus::deserialize, Block, BlockHash, Transaction};
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use zeromq::{Socket, SocketRecv, SubSocket, ZmqMessage};
+
+const TOPICS: [&str; 3] = ["rawblock", "hashblock", "rawtx"];
+/// Blocks remembered to find where the chain forked after a reorg
+const RECENT_BLOCKS: usize = 144;
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Subscriber to bitcoind's `zmqpubrawblock`, `zmqpubhashblock` and `zmqpubrawtx`
+///
+/// All three topics are expected on one endpoint. Blocks are announced in chain order:
+/// ones missed while disconnected, or skipped over by a gap in the notifications, are
+/// fetched over RPC from the last block seen. Missed mempool transactions cannot be
+/// replayed, a [`ChainEvent::Resync`] is sent instead. ZMQ gives no sign of a dead
+/// publisher, so a connection silent for `idle_timeout` is reopened.
+pub struct ZmqSubscriber {
+    endpoint: String,
+    rpc: BitcoinRpcClient,
+    idle_timeout: Duration,
+    backoff: Duration,
+    /// Last sequence number per topic
+    sequences: HashMap<String, u32>,
+    /// Most recent blocks announced, newest last
+    recent: VecDeque<(u32, BlockHash)>,
+}
+
+impl ZmqSubscriber {
+    pub fn new(endpoint: impl Into<String>, rpc: BitcoinRpcClient) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            rpc,
+            idle_timeout: Duration::from_secs(300),
+            backoff: MIN_BACKOFF,
+            sequences: HashMap::new(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Subscriber to `BITCOIN_ZMQ_URL` with the RPC node from the environment, if set
+    pub fn from_env() -> Option<Self> {
+        let endpoint = std::env::var("BITCOIN_ZMQ_URL").ok()?;
+        let subscriber = Self::new(endpoint, BitcoinRpcClient::new());
+        Some(
+            match std::env::var("BITCOIN_ZMQ_IDLE_SECS").ok().and_then(|secs| secs.parse().ok()) {
+                Some(secs) => subscriber.with_idle_timeout(Duration::from_secs(secs)),
+                None => subscriber,
+            },
+        )
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Publish events to `events` until the task is aborted, reconnecting as needed
+    pub fn spawn(mut self, events: broadcast::Sender<ChainEvent>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let e = self.listen(&events).await;
+                warn!("ZMQ subscription to {} lost: {}, retrying in {:?}", self.endpoint, e, self.backoff);
+                tokio::time::sleep(self.backoff).await;
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+            }
+        })
+    }
+
+    /// Connect, catch up with what was missed and relay notifications until an error
+    async fn listen(&mut self, events: &broadcast::Sender<ChainEvent>) -> ApiError {
+        let mut socket = SubSocket::new();
+        if let Err(e) = socket.connect(&self.endpoint).await {
+            return zmq_error(e);
+        }
+        for topic in TOPICS {
+            if let Err(e) = socket.subscribe(topic).await {
+                return zmq_error(e);
+            }
+        }
+        info!("Subscribed to {} at {}", TOPICS.join(", "), self.endpoint);
+        self.backoff = MIN_BACKOFF;
+        // A restarted node numbers its notifications from 0 again
+        self.sequences.clear();
+
+        if let Err(e) = self.catch_up(events).await {
+            return e;
+        }
+        let _ = events.send(ChainEvent::Resync);
+
+        loop {
+            let message = match tokio::time::timeout(self.idle_timeout, socket.recv()).await {
+                Ok(Ok(message)) => message,
+                Ok(Err(e)) => return zmq_error(e),
+                Err(_) => {
+                    return ApiError::InternalError {
+                        code: "ZMQ_IDLE".to_string(),
+                        message: format!("No notification for {:?}", self.idle_timeout),
+                        details: None,
+                    }
+                }
+            };
+            match self.handle(message, events).await {
+                Ok(()) => {}
+                Err(ApiError::InternalError { code, message, .. }) if code == "INVALID_ZMQ_NOTIFICATION" => {
+                    warn!("{}", message);
+                }
+                // Reconnecting retries the RPC calls while catching up
+                Err(e) => return e,
+            }
+        }
+    }
+
+    async fn handle(&mut self, message: ZmqMessage, events: &broadcast::Sender<ChainEvent>) -> Result<(), ApiError> {
+        let frames = message.into_vec();
+        let [topic, body, sequence] = frames.as_slice() else {
+            return Err(invalid(&format!("{} frames", frames.len())));
+        };
+        let topic = String::from_utf8_lossy(topic).to_string();
+        let sequence = <[u8; 4]>::try_from(sequence.as_ref())
+            .map(u32::from_le_bytes)
+            .map_err(|_| invalid("sequence is not 4 bytes"))?;
+        let missed = self
+            .sequences
+            .insert(topic.clone(), sequence)
+            .is_some_and(|last| sequence > last.wrapping_add(1));
+
+        match topic.as_str() {
+            "rawtx" => {
+                if missed {
+                    let _ = events.send(ChainEvent::Resync);
+                }
+                let tx: Transaction = deserialize(body).map_err(|_| invalid("undecodable rawtx"))?;
+                let _ = events.send(ChainEvent::Transaction(tx));
+            }
+            "rawblock" => {
+                let block: Block = deserialize(body).map_err(|_| invalid("undecodable rawblock"))?;
+                self.on_block(block, events).await?;
+            }
+            "hashblock" => {
+                let hash = BlockHash::from_str(&hex::encode(body)).map_err(|_| invalid("hashblock is not 32 bytes"))?;
+                if !self.recent.iter().any(|(_, seen)| *seen == hash) {
+                    let block = self.rpc.get_raw_block(&hash.to_string()).await?;
+                    self.on_block(block, events).await?;
+                }
+            }
+            _ => warn!("Ignoring ZMQ notification on topic {}", topic),
+        }
+        Ok(())
+    }
+
+    /// Announce a block that extends the last one, or catch up to the tip otherwise
+    async fn on_block(&mut self, block: Block, events: &broadcast::Sender<ChainEvent>) -> Result<(), ApiError> {
+        let hash = block.block_hash();
+        if self.recent.iter().any(|(_, seen)| *seen == hash) {
+            return Ok(());
+        }
+        let height = match self.recent.back() {
+            Some(&(height, last)) if block.header.prev_blockhash == last => height + 1,
+            Some(_) => return self.catch_up(events).await,
+            None => self
+                .rpc
+                .get_block_header(&hash.to_string())
+                .await?
+                .get("height")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid("block header has no height"))? as u32,
+        };
+        self.announce(height, block, events);
+        Ok(())
+    }
+
+    /// Announce every block from the last one still on the active chain up to the tip
+    ///
+    /// Does nothing before the first block was seen; there is nothing to catch up with.
+    async fn catch_up(&mut self, events: &broadcast::Sender<ChainEvent>) -> Result<(), ApiError> {
+        let Some(&(oldest, _)) = self.recent.front() else {
+            return Ok(());
+        };
+        let mut fork_height = oldest.saturating_sub(1);
+        while let Some(&(height, hash)) = self.recent.back() {
+            if self.rpc.get_block_hash(height).await? == hash.to_string() {
+                fork_height = height;
+                break;
+            }
+            self.recent.pop_back();
+        }
+
+        let tip = self.rpc.get_block_count().await?;
+        if tip > fork_height + 1 {
+            info!("Replaying blocks {} to {} over RPC", fork_height + 1, tip);
+        }
+        for height in fork_height + 1..=tip {
+            let block = self.rpc.get_raw_block(&self.rpc.get_block_hash(height).await?).await?;
+            self.announce(height, block, events);
+        }
+        Ok(())
+    }
+
+    fn announce(&mut self, height: u32, block: Block, events: &broadcast::Sender<ChainEvent>) {
+        self.recent.retain(|&(seen, _)| seen < height);
+        self.recent.push_back((height, block.block_hash()));
+        if self.recent.len() > RECENT_BLOCKS {
+            self.recent.pop_front();
+        }
+        let _ = events.send(ChainEvent::Block { height, block });
+    }
+}
+
+fn zmq_error(e: zeromq::ZmqError) -> ApiError {
+    ApiError::InternalError {
+        code: "ZMQ_ERROR".to_string(),
+        message: format!("ZMQ error: {}", e),
+        details: None,
+    }
+}
+
+fn invalid(reason: &str) -> ApiError {
+    ApiError::InternalError {
+        code: "INVALID_ZMQ_NOTIFICATION".to_string(),
+        message: format!("Unexpected ZMQ notification: {}", reason),
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{
+        block::{Header, Version},
+        blockdata::constants::genesis_block,
+        consensus::serialize,
+        hash_types::TxMerkleNode,
+        hashes::Hash,
+        CompactTarget, Network,
+    };
+    use mockito::Matcher;
+    use reqwest::Client;
+    use serde_json::json;
+    use zeromq::{PubSocket, SocketSend};
+
+    fn block(prev: &Block, nonce: u32) -> Block {
+        Block {
+            header: Header {
+                version: Version::TWO,
+                prev_blockhash: prev.block_hash(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: prev.header.time + 600,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce,
+            },
+            txdata: prev.txdata.clone(),
+        }
+    }
+
+    async fn rpc_mock(server: &mut mockito::ServerGuard, method: &str, params: Value, result: Value) -> mockito::Mock {
+        server
+            .mock("POST", "/")
+            .match_body(Matcher::PartialJson(json!({ "method": method, "params": params })))
+            .with_body(json!({ "result": result, "error": null, "id": 1 }).to_string())
+            .create_async()
+            .await
+    }
+
+    /// Serve `blocks[i]` at height `first + i`, with the last one as the tip
+    async fn serve_chain(server: &mut mockito::ServerGuard, first: u32, blocks: &[&Block]) {
+        for (height, block) in (first..).zip(blocks) {
+            let hash = block.block_hash().to_string();
+            rpc_mock(server, "getblockhash", json!([height]), json!(hash)).await;
+            rpc_mock(server, "getblock", json!([hash, 0]), json!(hex::encode(serialize(*block)))).await;
+            rpc_mock(server, "getblockheader", json!([hash, true]), json!({ "hash": hash, "height": height })).await;
+        }
+        rpc_mock(server, "getblockcount", json!([]), json!(first + blocks.len() as u32 - 1)).await;
+    }
+
+    async fn start(server: &mockito::ServerGuard, idle_timeout: Duration) -> (PubSocket, broadcast::Receiver<ChainEvent>) {
+        let mut publisher = PubSocket::new();
+        let endpoint = publisher.bind("tcp://127.0.0.1:0").await.unwrap();
+        let rpc = BitcoinRpcClient {
+            rpc_url: server.url(),
+            username: "user".to_string(),
+            password: "password".to_string(),
+            client: Client::new(),
+        };
+        let (sender, receiver) = broadcast::channel(64);
+        ZmqSubscriber::new(endpoint.to_string(), rpc)
+            .with_idle_timeout(idle_timeout)
+            .spawn(sender);
+        (publisher, receiver)
+    }
+
+    fn notification(topic: &str, body: Vec<u8>, sequence: u32) -> ZmqMessage {
+        let mut message = ZmqMessage::from(topic);
+        message.push_back(body.into());
+        message.push_back(sequence.to_le_bytes().to_vec().into());
+        message
+    }
+
+    async fn next_event(events: &mut broadcast::Receiver<ChainEvent>) -> ChainEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap()
+    }
+
+    /// Publish `first` until it is announced; a PUB socket drops everything sent
+    /// before the subscription reached it
+    async fn handshake(publisher: &mut PubSocket, events: &mut broadcast::Receiver<ChainEvent>, first: &Block) {
+        loop {
+            publisher.send(notification("rawblock", serialize(first), 0)).await.unwrap();
+            if let Ok(Ok(ChainEvent::Block { block, .. })) =
+                tokio::time::timeout(Duration::from_millis(50), events.recv()).await
+            {
+                assert_eq!(block, *first);
+                return;
+            }
+        }
+    }
+
+    fn block_height(event: ChainEvent) -> (u32, BlockHash) {
+        match event {
+            ChainEvent::Block { height, block } => (height, block.block_hash()),
+            other => panic!("expected a block, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replays_skipped_blocks_and_flags_lost_transactions() {
+        let genesis = genesis_block(Network::Regtest);
+        let b101 = block(&genesis, 1);
+        let b102 = block(&b101, 2);
+        let b103 = block(&b102, 3);
+        let mut server = mockito::Server::new_async().await;
+        serve_chain(&mut server, 101, &[&b101, &b102, &b103]).await;
+
+        let (mut publisher, mut events) = start(&server, Duration::from_secs(30)).await;
+        handshake(&mut publisher, &mut events, &b101).await;
+        while let Ok(event) = events.try_recv() {
+            assert!(matches!(event, ChainEvent::Resync | ChainEvent::Block { height: 101, .. }));
+        }
+
+        // The notification for 102 was lost, 103 arrives with a gap
+        publisher.send(notification("rawblock", serialize(&b103), 2)).await.unwrap();
+        assert_eq!(block_height(next_event(&mut events).await), (102, b102.block_hash()));
+        assert_eq!(block_height(next_event(&mut events).await), (103, b103.block_hash()));
+
+        // Already announced
+        publisher.send(notification("hashblock", b103.block_hash().to_byte_array().iter().rev().copied().collect(), 3)).await.unwrap();
+
+        let tx = genesis.txdata[0].clone();
+        publisher.send(notification("rawtx", serialize(&tx), 0)).await.unwrap();
+        publisher.send(notification("rawtx", serialize(&tx), 4)).await.unwrap();
+        assert!(matches!(next_event(&mut events).await, ChainEvent::Transaction(ref seen) if *seen == tx));
+        assert!(matches!(next_event(&mut events).await, ChainEvent::Resync));
+        assert!(matches!(next_event(&mut events).await, ChainEvent::Transaction(_)));
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_after_silence_and_follows_reorgs() {
+        let genesis = genesis_block(Network::Regtest);
+        let b101 = block(&genesis, 1);
+        let (stale_b102, b102) = (block(&b101, 2), block(&b101, 20));
+        let b103 = block(&b102, 3);
+        let mut server = mockito::Server::new_async().await;
+        serve_chain(&mut server, 101, &[&b101]).await;
+
+        let (mut publisher, mut events) = start(&server, Duration::from_millis(500)).await;
+        handshake(&mut publisher, &mut events, &b101).await;
+        publisher.send(notification("rawblock", serialize(&stale_b102), 1)).await.unwrap();
+        loop {
+            if let ChainEvent::Block { height, .. } = next_event(&mut events).await {
+                assert_eq!(height, 102);
+                break;
+            }
+        }
+
+        // While nothing is published, 102 is replaced and 103 is mined on top
+        server.reset();
+        serve_chain(&mut server, 101, &[&b101, &b102, &b103]).await;
+        let mut replayed = Vec::new();
+        while replayed.len() < 2 {
+            if let ChainEvent::Block { height, block } = next_event(&mut events).await {
+                replayed.push((height, block.block_hash()));
+            }
+        }
+        assert_eq!(replayed, vec![(102, b102.block_hash()), (103, b103.block_hash())]);
+    }
+}
@@ -1,9 +1,10 @@
 use bitcoin::{
     blockdata::{opcodes, script::Builder},
-    hashes::{sha256d, Hash},
-    Address, Network,
+    hashes::Hash,
+    Address, ScriptBuf,
 };
-use crate::models::{ApiError, HtlcParams, HtlcScript};
+use crate::models::{ApiError, HtlcOutputType, HtlcParams, HtlcScript};
+use crate::services::htlc::build_taproot_htlc;
 
 /// Build HTLC script from parameters
 pub fn build_htlc_script(params: &HtlcParams) -> Result<HtlcScript, ApiError> {
@@ -13,7 +14,7 @@ pub fn build_htlc_script(params: &HtlcParams) -> Result<HtlcScript, ApiError> {
         .push_opcode(opcodes::all::OP_IF)
             // Check hash of preimage
             .push_opcode(opcodes::all::OP_SHA256)
-            .push_slice(&params.payment_hash)
+            .push_slice(params.payment_hash)
             .push_opcode(opcodes::all::OP_EQUALVERIFY)
             // Check recipient signature
             .push_key(&params.recipient_pubkey)
@@ -30,29 +31,50 @@ pub fn build_htlc_script(params: &HtlcParams) -> Result<HtlcScript, ApiError> {
         .push_opcode(opcodes::all::OP_ENDIF)
         .into_script();
 
-    // Calculate script hash for P2SH
-    let script_hash = sha256d::Hash::hash(redeem_script.as_bytes()).to_byte_array();
+    let mut script_hash = redeem_script.wscript_hash().to_byte_array().to_vec();
+    let mut taproot = None;
 
-    // Create P2SH address
-    let network = Network::Testnet; // TODO: Make configurable
-    let p2sh_address = Address::p2sh(&redeem_script, network)
-        .map_err(|error| ApiError::InternalError {
-            code: "BITCOIN_ADDRESS_ERROR".to_string(),
-            message: format!("Failed to create P2SH address: {}", error),
-            details: None,
-        })?;
+    // Commit to the redeem script according to the requested output type
+    let address = match params.output_type {
+        HtlcOutputType::P2sh => {
+            script_hash = redeem_script.script_hash().to_byte_array().to_vec();
+            Address::p2sh(&redeem_script, params.network)
+                .map_err(|error| ApiError::InternalError {
+                    code: "BITCOIN_ADDRESS_ERROR".to_string(),
+                    message: format!("Failed to create P2SH address: {}", error),
+                    details: None,
+                })?
+        }
+        HtlcOutputType::P2shP2wsh => Address::p2shwsh(&redeem_script, params.network),
+        HtlcOutputType::P2wsh => Address::p2wsh(&redeem_script, params.network),
+        HtlcOutputType::P2tr => {
+            let (tree, address) = build_taproot_htlc(params)?;
+            script_hash = tree.merkle_root.to_byte_array().to_vec();
+            taproot = Some(tree);
+            address
+        }
+    };
 
     Ok(HtlcScript {
         redeem_script: redeem_script.to_bytes(),
         script_hash,
-        p2sh_address: p2sh_address.to_string(),
+        address: address.to_string(),
+        script_pubkey: address.script_pubkey(),
+        output_type: params.output_type,
+        taproot,
     })
 }
 
+/// The script a P2SH-P2WSH scriptSig pushes: the v0 witness program of the redeem script
+pub fn nested_witness_program(redeem_script: &[u8]) -> ScriptBuf {
+    ScriptBuf::from(redeem_script.to_vec()).to_p2wsh()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::Network;
 
     #[test]
     fn test_build_htlc_script_creates_valid_script() {
@@ -76,6 +98,8 @@ mod tests {
             recipient_pubkey,
             payment_hash: [0u8; 32],
             timeout: 144,
+            output_type: HtlcOutputType::P2sh,
+            network: Network::Testnet,
         };
         
         let result = build_htlc_script(&params);
@@ -83,8 +107,8 @@ mod tests {
         
         let script = result.unwrap();
         assert!(!script.redeem_script.is_empty());
-        assert_eq!(script.script_hash.len(), 32);
-        assert!(script.p2sh_address.starts_with("2")); // Testnet P2SH
+        assert_eq!(script.script_hash.len(), 20);
+        assert!(script.address.starts_with("2")); // Testnet P2SH
     }
 
     #[test]
@@ -104,12 +128,90 @@ mod tests {
             },
             payment_hash: [0u8; 32],
             timeout: 144,
+            output_type: HtlcOutputType::P2sh,
+            network: Network::Testnet,
         };
         
         let script1 = build_htlc_script(&params).unwrap();
         let script2 = build_htlc_script(&params).unwrap();
         
         assert_eq!(script1.redeem_script, script2.redeem_script);
-        assert_eq!(script1.p2sh_address, script2.p2sh_address);
+        assert_eq!(script1.address, script2.address);
+    }
+
+    fn params_with_output_type(output_type: HtlcOutputType) -> HtlcParams {
+        let secp = Secp256k1::new();
+        let sender_secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let recipient_secret_key = SecretKey::from_slice(&[2u8; 32]).unwrap();
+
+        HtlcParams {
+            sender_pubkey: bitcoin::PublicKey::new(sender_secret_key.public_key(&secp)),
+            recipient_pubkey: bitcoin::PublicKey::new(recipient_secret_key.public_key(&secp)),
+            payment_hash: [0u8; 32],
+            timeout: 144,
+            output_type,
+            network: Network::Testnet,
+        }
+    }
+
+    #[test]
+    fn test_build_htlc_script_p2sh_hash_matches_address() {
+        let script = build_htlc_script(&params_with_output_type(HtlcOutputType::P2sh)).unwrap();
+        let redeem_script = ScriptBuf::from(script.redeem_script.clone());
+
+        assert!(script.script_pubkey.is_p2sh());
+        assert_eq!(script.script_pubkey, redeem_script.to_p2sh());
+        // OP_HASH160 <20 bytes> OP_EQUAL
+        assert_eq!(&script.script_pubkey.as_bytes()[2..22], &script.script_hash[..]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_build_htlc_script_p2wsh_output() {
+        let script = build_htlc_script(&params_with_output_type(HtlcOutputType::P2wsh)).unwrap();
+        let redeem_script = ScriptBuf::from(script.redeem_script.clone());
+
+        assert!(script.address.starts_with("tb1q"));
+        assert!(script.script_pubkey.is_p2wsh());
+        assert_eq!(script.script_pubkey, redeem_script.to_p2wsh());
+        assert_eq!(&script.script_pubkey.as_bytes()[2..], &script.script_hash[..]);
+    }
+
+    #[test]
+    fn test_build_htlc_script_nested_p2wsh_output() {
+        let script = build_htlc_script(&params_with_output_type(HtlcOutputType::P2shP2wsh)).unwrap();
+        let witness_program = nested_witness_program(&script.redeem_script);
+
+        assert!(script.address.starts_with('2'));
+        assert!(script.script_pubkey.is_p2sh());
+        assert_eq!(script.script_pubkey, witness_program.to_p2sh());
+    }
+
+    #[test]
+    fn test_build_htlc_script_output_types_share_redeem_script() {
+        let p2sh = build_htlc_script(&params_with_output_type(HtlcOutputType::P2sh)).unwrap();
+        let nested = build_htlc_script(&params_with_output_type(HtlcOutputType::P2shP2wsh)).unwrap();
+        let native = build_htlc_script(&params_with_output_type(HtlcOutputType::P2wsh)).unwrap();
+
+        assert_eq!(p2sh.redeem_script, nested.redeem_script);
+        assert_eq!(p2sh.redeem_script, native.redeem_script);
+        assert_ne!(p2sh.script_pubkey, nested.script_pubkey);
+        assert_eq!(
+            p2sh.script_pubkey,
+            ScriptBuf::from(p2sh.redeem_script.clone()).to_p2sh()
+        );
+    }
+
+    #[test]
+    fn test_build_htlc_script_p2tr_output() {
+        let script = build_htlc_script(&params_with_output_type(HtlcOutputType::P2tr)).unwrap();
+        let taproot = script.taproot.as_ref().unwrap();
+
+        assert!(script.address.starts_with("tb1p"));
+        assert!(script.script_pubkey.is_p2tr());
+        assert_eq!(script.script_hash, taproot.merkle_root.to_byte_array().to_vec());
+        assert!(build_htlc_script(&params_with_output_type(HtlcOutputType::P2wsh))
+            .unwrap()
+            .taproot
+            .is_none());
+    }
+}
@@ -0,0 +1,169 @@
+use bitcoin::{
+    blockdata::script::{Builder, PushBytesBuf},
+    ecdsa::Signature,
+    hashes::Hash,
+    secp256k1::Message,
+    sighash::{EcdsaSighashType, SighashCache},
+    Amount, ScriptBuf, Transaction, Witness,
+};
+use crate::models::{ApiError, HtlcOutputType, HtlcScript};
+use crate::services::htlc::build_htlc_script::nested_witness_program;
+
+/// Compute the message to sign for spending an HTLC input
+///
+/// Legacy P2SH outputs use the pre-segwit sighash over the redeem script, both segwit
+/// variants use BIP143 with the redeem script as witness script. Taproot outputs are
+/// signed with BIP341 sighashes by the taproot transaction builders instead.
+pub fn htlc_signature_message(
+    transaction: &Transaction,
+    input_index: usize,
+    htlc_script: &HtlcScript,
+    htlc_amount: Amount,
+    sighash_type: EcdsaSighashType,
+) -> Result<Message, ApiError> {
+    if !htlc_script.output_type.uses_redeem_script() {
+        return Err(taproot_unsupported());
+    }
+
+    let redeem_script = ScriptBuf::from(htlc_script.redeem_script.clone());
+    let sighash_cache = SighashCache::new(transaction);
+
+    let digest = if htlc_script.output_type.is_segwit() {
+        let mut sighash_cache = sighash_cache;
+        sighash_cache
+            .p2wsh_signature_hash(input_index, &redeem_script, htlc_amount, sighash_type)
+            .map(|sighash| sighash.to_byte_array())
+    } else {
+        sighash_cache
+            .legacy_signature_hash(input_index, &redeem_script, sighash_type.to_u32())
+            .map(|sighash| sighash.to_byte_array())
+    }
+    .map_err(|e| ApiError::InternalError {
+        code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+        message: format!("Failed to compute sighash: {}", e),
+        details: None,
+    })?;
+
+    Ok(Message::from_digest(digest))
+}
+
+/// Build the scriptSig and witness for the claim (IF) branch
+pub fn build_claim_spend(
+    htlc_script: &HtlcScript,
+    signature: &Signature,
+    preimage: &[u8],
+) -> Result<(ScriptBuf, Witness), ApiError> {
+    let stack = vec![signature.to_vec(), preimage.to_vec(), vec![1u8]];
+    build_spend(htlc_script, stack)
+}
+
+/// Build the scriptSig and witness for the refund (ELSE) branch
+pub fn build_refund_spend(
+    htlc_script: &HtlcScript,
+    signature: &Signature,
+) -> Result<(ScriptBuf, Witness), ApiError> {
+    let stack = vec![signature.to_vec(), vec![]];
+    build_spend(htlc_script, stack)
+}
+
+/// Place the branch stack items and the redeem script where the output type expects them
+fn build_spend(
+    htlc_script: &HtlcScript,
+    stack: Vec<Vec<u8>>,
+) -> Result<(ScriptBuf, Witness), ApiError> {
+    match htlc_script.output_type {
+        HtlcOutputType::P2sh => {
+            let mut builder = Builder::new();
+            for item in stack.iter().chain(std::iter::once(&htlc_script.redeem_script)) {
+                builder = match item.as_slice() {
+                    // Minimal encodings keep the scriptSig push-only and standard
+                    [] => builder.push_int(0),
+                    [1] => builder.push_int(1),
+                    bytes => builder.push_slice(to_push_bytes(bytes)?),
+                };
+            }
+            Ok((builder.into_script(), Witness::new()))
+        }
+        HtlcOutputType::P2shP2wsh => {
+            let witness_program = nested_witness_program(&htlc_script.redeem_script);
+            let script_sig = Builder::new()
+                .push_slice(to_push_bytes(witness_program.as_bytes())?)
+                .into_script();
+            Ok((script_sig, build_witness(stack, &htlc_script.redeem_script)))
+        }
+        HtlcOutputType::P2wsh => {
+            Ok((ScriptBuf::new(), build_witness(stack, &htlc_script.redeem_script)))
+        }
+        HtlcOutputType::P2tr => Err(taproot_unsupported()),
+    }
+}
+
+fn taproot_unsupported() -> ApiError {
+    ApiError::InternalError {
+        code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+        message: "Taproot HTLCs are spent through tap leaves, not the redeem script".to_string(),
+        details: None,
+    }
+}
+
+fn build_witness(stack: Vec<Vec<u8>>, redeem_script: &[u8]) -> Witness {
+    let mut witness = Witness::new();
+    for item in stack {
+        witness.push(item);
+    }
+    witness.push(redeem_script);
+    witness
+}
+
+fn to_push_bytes(bytes: &[u8]) -> Result<PushBytesBuf, ApiError> {
+    PushBytesBuf::try_from(bytes.to_vec()).map_err(|_| ApiError::InternalError {
+        code: "BITCOIN_SCRIPT_ERROR".to_string(),
+        message: format!("Push of {} bytes exceeds script limits", bytes.len()),
+        details: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::htlc::build_htlc_script;
+    use crate::test_support::htlc_params;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    fn dummy_signature() -> Signature {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        Signature::sighash_all(secp.sign_ecdsa(&Message::from_digest([7u8; 32]), &key))
+    }
+
+    #[test]
+    fn test_p2wsh_claim_uses_witness_only() {
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap();
+        let (script_sig, witness) = build_claim_spend(&htlc, &dummy_signature(), &[9u8; 32]).unwrap();
+
+        assert!(script_sig.is_empty());
+        assert_eq!(witness.len(), 4);
+        assert_eq!(witness.last().unwrap(), htlc.redeem_script.as_slice());
+    }
+
+    #[test]
+    fn test_nested_refund_pushes_witness_program() {
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2shP2wsh)).unwrap();
+        let (script_sig, witness) = build_refund_spend(&htlc, &dummy_signature()).unwrap();
+
+        let witness_program = nested_witness_program(&htlc.redeem_script);
+        assert_eq!(&script_sig.as_bytes()[1..], witness_program.as_bytes());
+        assert_eq!(witness.len(), 3);
+        assert!(witness.nth(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_p2sh_claim_uses_script_sig_only() {
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2sh)).unwrap();
+        let (script_sig, witness) = build_claim_spend(&htlc, &dummy_signature(), &[9u8; 32]).unwrap();
+
+        assert!(witness.is_empty());
+        assert!(script_sig.is_push_only());
+        assert!(script_sig.as_bytes().ends_with(&htlc.redeem_script));
+    }
+}
@@ -0,0 +1,143 @@
+use bitcoin::{
+    blockdata::{opcodes, script::Builder},
+    key::{Secp256k1, XOnlyPublicKey},
+    taproot::{LeafVersion, TaprootBuilder},
+    Address, ScriptBuf,
+};
+use crate::models::{ApiError, HtlcParams, TaprootHtlc};
+use crate::services::htlc::musig2::KeyAggContext;
+
+/// Build the Taproot commitment of an HTLC
+///
+/// The internal key is the MuSig2 aggregate of recipient and sender, so a cooperative
+/// close is a plain key-path spend. The hashlock and timelock branches become two
+/// tap leaves that are only revealed when one side has to enforce the contract alone.
+pub fn build_taproot_htlc(params: &HtlcParams) -> Result<(TaprootHtlc, Address), ApiError> {
+    let secp = Secp256k1::verification_only();
+
+    let recipient = XOnlyPublicKey::from(params.recipient_pubkey.inner);
+    let sender = XOnlyPublicKey::from(params.sender_pubkey.inner);
+
+    let claim_leaf = Builder::new()
+        .push_opcode(opcodes::all::OP_SHA256)
+        .push_slice(params.payment_hash)
+        .push_opcode(opcodes::all::OP_EQUALVERIFY)
+        .push_x_only_key(&recipient)
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .into_script();
+
+    let refund_leaf = Builder::new()
+        .push_int(params.timeout as i64)
+        .push_opcode(opcodes::all::OP_CLTV)
+        .push_opcode(opcodes::all::OP_DROP)
+        .push_x_only_key(&sender)
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .into_script();
+
+    let internal_key = cooperative_key_context(params)?.aggregate_xonly();
+
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(1, claim_leaf.clone())
+        .and_then(|builder| builder.add_leaf(1, refund_leaf.clone()))
+        .map_err(|e| taproot_error(format!("Failed to build tap tree: {}", e)))?
+        .finalize(&secp, internal_key)
+        .map_err(|_| taproot_error("Tap tree is incomplete".to_string()))?;
+
+    let merkle_root = spend_info
+        .merkle_root()
+        .ok_or_else(|| taproot_error("Tap tree has no merkle root".to_string()))?;
+    let control_block = |leaf: &ScriptBuf| {
+        spend_info
+            .control_block(&(leaf.clone(), LeafVersion::TapScript))
+            .ok_or_else(|| taproot_error("Leaf missing from tap tree".to_string()))
+    };
+
+    let taproot = TaprootHtlc {
+        internal_key,
+        output_key: spend_info.output_key(),
+        merkle_root,
+        claim_control_block: control_block(&claim_leaf)?,
+        refund_control_block: control_block(&refund_leaf)?,
+        claim_leaf,
+        refund_leaf,
+    };
+    let address = Address::p2tr_tweaked(taproot.output_key, params.network);
+
+    Ok((taproot, address))
+}
+
+/// MuSig2 context of the recipient and sender keys, before the Taproot tweak
+///
+/// Both parties tweak it with the HTLC merkle root to co-sign a key path spend.
+pub fn cooperative_key_context(params: &HtlcParams) -> Result<KeyAggContext, ApiError> {
+    KeyAggContext::new(&[params.recipient_pubkey.inner, params.sender_pubkey.inner])
+}
+
+fn taproot_error(message: String) -> ApiError {
+    ApiError::InternalError {
+        code: "BITCOIN_SCRIPT_ERROR".to_string(),
+        message,
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::taproot::TapLeafHash;
+    use crate::models::HtlcOutputType;
+    use crate::test_support::htlc_params;
+
+    #[test]
+    fn test_taproot_htlc_address_commits_to_output_key() {
+        let (taproot, address) = build_taproot_htlc(&htlc_params(HtlcOutputType::P2tr)).unwrap();
+
+        assert!(address.to_string().starts_with("tb1p"));
+        assert_eq!(&address.script_pubkey().as_bytes()[2..], &taproot.output_key.serialize()[..]);
+    }
+
+    #[test]
+    fn test_taproot_htlc_control_blocks_verify() {
+        let secp = Secp256k1::verification_only();
+        let (taproot, _) = build_taproot_htlc(&htlc_params(HtlcOutputType::P2tr)).unwrap();
+        let output_key = taproot.output_key.to_inner();
+
+        assert!(taproot.claim_control_block.verify_taproot_commitment(&secp, output_key, &taproot.claim_leaf));
+        assert!(taproot.refund_control_block.verify_taproot_commitment(&secp, output_key, &taproot.refund_leaf));
+        assert!(!taproot.claim_control_block.verify_taproot_commitment(&secp, output_key, &taproot.refund_leaf));
+    }
+
+    #[test]
+    fn test_taproot_htlc_leaves_are_siblings() {
+        let (taproot, _) = build_taproot_htlc(&htlc_params(HtlcOutputType::P2tr)).unwrap();
+
+        assert_eq!(taproot.claim_control_block.merkle_branch.len(), 1);
+        assert_eq!(
+            taproot.claim_control_block.merkle_branch.as_inner()[0].to_byte_array(),
+            TapLeafHash::from_script(&taproot.refund_leaf, LeafVersion::TapScript).to_byte_array()
+        );
+    }
+
+    #[test]
+    fn test_cooperative_context_tweaks_to_output_key() {
+        let (taproot, _) = build_taproot_htlc(&htlc_params(HtlcOutputType::P2tr)).unwrap();
+        let context = cooperative_key_context(&htlc_params(HtlcOutputType::P2tr))
+            .unwrap()
+            .with_taproot_tweak(Some(taproot.merkle_root))
+            .unwrap();
+
+        assert_eq!(context.output_key(), taproot.output_key.to_inner());
+    }
+
+    #[test]
+    fn test_taproot_internal_key_ignores_key_order() {
+        let mut swapped = htlc_params(HtlcOutputType::P2tr);
+        std::mem::swap(&mut swapped.sender_pubkey, &mut swapped.recipient_pubkey);
+
+        let (original, _) = build_taproot_htlc(&htlc_params(HtlcOutputType::P2tr)).unwrap();
+        let (reversed, _) = build_taproot_htlc(&swapped).unwrap();
+        assert_eq!(original.internal_key, reversed.internal_key);
+        assert_ne!(original.output_key, reversed.output_key);
+    }
+}
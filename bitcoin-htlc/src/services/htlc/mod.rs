@@ -1,10 +1,19 @@
 pub mod build_htlc_script;
+pub mod build_htlc_spend;
+pub mod build_taproot_htlc;
 pub mod generate_preimage;
+pub mod musig2;
 pub mod parse_htlc_script;
+pub mod parse_htlc_spend;
+pub mod verify_htlc_output;
 pub mod verify_htlc_script;
 
 // Re-export functions for easy access
 pub use build_htlc_script::build_htlc_script;
+pub use build_htlc_spend::{build_claim_spend, build_refund_spend, htlc_signature_message};
+pub use build_taproot_htlc::{build_taproot_htlc, cooperative_key_context};
 pub use generate_preimage::{generate_preimage, hash_preimage};
 pub use parse_htlc_script::parse_htlc_script;
-pub use verify_htlc_script::verify_htlc_script;
\ No newline at end of file
+pub use parse_htlc_spend::{parse_htlc_spend, HtlcSpend};
+pub use verify_htlc_output::{verify_htlc_output, HtlcOutputVerification};
+pub use verify_htlc_script::verify_htlc_script;
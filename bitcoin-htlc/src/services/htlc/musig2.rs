@@ -0,0 +1,381 @@
+//! MuSig2 (BIP327) key aggregation and signing for the Taproot HTLC key path
+//!
+//! The user and resolver aggregate their keys into the Taproot internal key, so a
+//! cooperative swap settles with a single BIP340 signature that is indistinguishable
+//! from an ordinary single-key spend. The protocol itself comes from the `musig2`
+//! crate, which is tested against the BIP327 vectors; this module only adapts it to
+//! the `bitcoin` key types and to [`ApiError`].
+
+use bitcoin::{
+    key::XOnlyPublicKey,
+    secp256k1::{schnorr, Message, PublicKey, SecretKey},
+    taproot::TapNodeHash,
+};
+use ::musig2::{AggNonce, BinaryEncoding, PubNonce, SecNonce};
+use crate::models::ApiError;
+
+/// Aggregated key together with the accumulated tweak state (`gacc`/`tacc` in BIP327)
+#[derive(Debug, Clone)]
+pub struct KeyAggContext(::musig2::KeyAggContext);
+
+impl KeyAggContext {
+    /// Aggregate keys after sorting them (KeySort), so participants can list them in any order
+    pub fn new(pubkeys: &[PublicKey]) -> Result<Self, ApiError> {
+        let mut sorted = pubkeys.to_vec();
+        sorted.sort_by_key(|pubkey| pubkey.serialize());
+        Self::from_ordered(&sorted)
+    }
+
+    /// Aggregate keys in the given order (KeyAgg)
+    pub fn from_ordered(pubkeys: &[PublicKey]) -> Result<Self, ApiError> {
+        if pubkeys.is_empty() {
+            return Err(musig_error("Cannot aggregate an empty key list"));
+        }
+        ::musig2::KeyAggContext::new(pubkeys.iter().copied())
+            .map(Self)
+            .map_err(|e| musig_error(format!("Aggregate key is invalid: {}", e)))
+    }
+
+    /// The aggregate key before any tweak, used as the Taproot internal key
+    pub fn aggregate_xonly(&self) -> XOnlyPublicKey {
+        self.0.aggregated_pubkey_untweaked::<PublicKey>().x_only_public_key().0
+    }
+
+    /// Apply the BIP341 Taproot tweak for the given tap tree
+    pub fn with_taproot_tweak(self, merkle_root: Option<TapNodeHash>) -> Result<Self, ApiError> {
+        let tweaked = match merkle_root {
+            Some(merkle_root) => self.0.with_taproot_tweak(merkle_root.as_ref()),
+            None => self.0.with_unspendable_taproot_tweak(),
+        };
+        tweaked
+            .map(Self)
+            .map_err(|e| musig_error(format!("Failed to tweak aggregate key: {}", e)))
+    }
+
+    /// The (possibly tweaked) key the final signature verifies against
+    pub fn output_key(&self) -> XOnlyPublicKey {
+        self.0.aggregated_pubkey::<PublicKey>().x_only_public_key().0
+    }
+
+    pub fn pubkeys(&self) -> Vec<PublicKey> {
+        self.0.pubkeys().iter().map(|pubkey| PublicKey::from(*pubkey)).collect()
+    }
+}
+
+/// Secret nonce pair; consumed by [`partial_sign`] so it cannot be reused
+#[derive(Debug)]
+pub struct SecretNonce(SecNonce);
+
+/// Public nonce pair exchanged in the first signing round
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicNonce(PubNonce);
+
+impl PublicNonce {
+    pub fn serialize(&self) -> [u8; 66] {
+        self.0.to_bytes()
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, ApiError> {
+        PubNonce::from_bytes(bytes)
+            .map(Self)
+            .map_err(|e| musig_error(format!("Invalid public nonce: {}", e)))
+    }
+}
+
+/// Sum of every signer's public nonce, the input to the second signing round
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregateNonce(AggNonce);
+
+impl AggregateNonce {
+    pub fn serialize(&self) -> [u8; 66] {
+        self.0.to_bytes()
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, ApiError> {
+        AggNonce::from_bytes(bytes)
+            .map(Self)
+            .map_err(|e| musig_error(format!("Invalid aggregate nonce: {}", e)))
+    }
+}
+
+/// Partial signature produced in the second signing round
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialSignature(::musig2::PartialSignature);
+
+impl PartialSignature {
+    pub fn serialize(&self) -> [u8; 32] {
+        self.0.serialize()
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, ApiError> {
+        ::musig2::PartialSignature::from_slice(bytes)
+            .map(Self)
+            .map_err(|e| musig_error(format!("Invalid partial signature: {}", e)))
+    }
+}
+
+/// Generate a fresh nonce pair with BIP327 NonceGen
+///
+/// Fresh randomness is mixed with the signer's key, the aggregate key and the
+/// message, so a faulty RNG alone does not repeat a nonce across sessions.
+pub fn generate_nonce(
+    secret_key: &SecretKey,
+    context: &KeyAggContext,
+    message: &Message,
+) -> Result<(SecretNonce, PublicNonce), ApiError> {
+    use rand::RngCore;
+
+    let mut seed = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut seed);
+    let public_key = secret_key.public_key(bitcoin::secp256k1::SECP256K1);
+    if !context.pubkeys().contains(&public_key) {
+        return Err(musig_error("Signer key is not part of the aggregate"));
+    }
+
+    let secret_nonce = SecNonce::build(seed)
+        .with_seckey(*secret_key)
+        .with_pubkey(public_key)
+        .with_aggregated_pubkey(context.0.aggregated_pubkey::<::musig2::secp::Point>())
+        .with_message(message.as_ref())
+        .build();
+    let public_nonce = secret_nonce.public_nonce();
+
+    Ok((SecretNonce(secret_nonce), PublicNonce(public_nonce)))
+}
+
+/// Sum the participants' public nonces (NonceAgg)
+pub fn aggregate_nonces(nonces: &[PublicNonce]) -> Result<AggregateNonce, ApiError> {
+    if nonces.is_empty() {
+        return Err(musig_error("No public nonces to aggregate"));
+    }
+    Ok(AggregateNonce(AggNonce::sum(nonces.iter().map(|nonce| &nonce.0))))
+}
+
+/// Produce this signer's partial signature over `message`
+///
+/// The signature is checked with [`verify_partial_signature`] before it is returned.
+pub fn partial_sign(
+    context: &KeyAggContext,
+    secret_nonce: SecretNonce,
+    secret_key: &SecretKey,
+    aggregate_nonce: &AggregateNonce,
+    message: &Message,
+) -> Result<PartialSignature, ApiError> {
+    ::musig2::sign_partial(&context.0, *secret_key, secret_nonce.0, &aggregate_nonce.0, message.as_ref())
+        .map(PartialSignature)
+        .map_err(|e| musig_error(format!("Failed to sign: {}", e)))
+}
+
+/// Check a co-signer's partial signature (PartialSigVerify)
+///
+/// Run this on every partial signature received from another party before
+/// aggregating, so a bad contribution is attributed to its signer.
+pub fn verify_partial_signature(
+    context: &KeyAggContext,
+    partial_signature: &PartialSignature,
+    aggregate_nonce: &AggregateNonce,
+    signer: &PublicKey,
+    signer_nonce: &PublicNonce,
+    message: &Message,
+) -> Result<(), ApiError> {
+    ::musig2::verify_partial(
+        &context.0,
+        partial_signature.0,
+        &aggregate_nonce.0,
+        *signer,
+        &signer_nonce.0,
+        message.as_ref(),
+    )
+    .map_err(|e| ApiError::BadRequest {
+        code: "INVALID_PARTIAL_SIGNATURE".to_string(),
+        message: format!("Partial signature does not verify: {}", e),
+        details: None,
+    })
+}
+
+/// Combine partial signatures into the final BIP340 signature
+pub fn aggregate_partial_signatures(
+    context: &KeyAggContext,
+    aggregate_nonce: &AggregateNonce,
+    message: &Message,
+    partial_signatures: &[PartialSignature],
+) -> Result<schnorr::Signature, ApiError> {
+    if partial_signatures.is_empty() {
+        return Err(musig_error("No partial signatures to aggregate"));
+    }
+    ::musig2::aggregate_partial_signatures(
+        &context.0,
+        &aggregate_nonce.0,
+        partial_signatures.iter().map(|partial| partial.0),
+        message.as_ref(),
+    )
+    .map_err(|e| musig_error(format!("Invalid aggregate signature: {}", e)))
+}
+
+fn musig_error(message: impl Into<String>) -> ApiError {
+    ApiError::InternalError {
+        code: "MUSIG2_ERROR".to_string(),
+        message: message.into(),
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{hashes::Hash, key::Secp256k1};
+    use std::str::FromStr;
+
+    fn bip327_keys() -> Vec<PublicKey> {
+        [
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66",
+        ]
+        .iter()
+        .map(|key| PublicKey::from_str(key).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_key_aggregation_matches_bip327_vectors() {
+        let keys = bip327_keys();
+        let cases = [
+            (vec![0, 1, 2], "90539eede565f5d054f32cc0c220126889ed1e5d193baf15aef344fe59d4610c"),
+            (vec![2, 1, 0], "6204de8b083426dc6eaf9502d27024d53fc826bf7d2012148a0575435df54b2b"),
+            (vec![0, 0, 0], "b436e3bad62b8cd409969a224731c193d051162d8c5ae8b109306127da3aa935"),
+            (vec![0, 0, 1, 1], "69bc22bfa5d106306e48a20679de1d7389386124d07571d0d872686028c26a3e"),
+        ];
+
+        for (indices, expected) in cases {
+            let ordered: Vec<PublicKey> = indices.iter().map(|i| keys[*i]).collect();
+            let context = KeyAggContext::from_ordered(&ordered).unwrap();
+            assert_eq!(hex::encode(context.aggregate_xonly().serialize()), expected);
+        }
+    }
+
+    #[test]
+    fn test_key_aggregation_is_order_independent() {
+        let keys = bip327_keys();
+        let forward = KeyAggContext::new(&[keys[0], keys[1]]).unwrap();
+        let backward = KeyAggContext::new(&[keys[1], keys[0]]).unwrap();
+
+        assert_eq!(forward.aggregate_xonly(), backward.aggregate_xonly());
+    }
+
+    fn signer_keys() -> [SecretKey; 2] {
+        [
+            SecretKey::from_slice(&[0x11u8; 32]).unwrap(),
+            SecretKey::from_slice(&[0x22u8; 32]).unwrap(),
+        ]
+    }
+
+    fn two_party_context() -> KeyAggContext {
+        let secp = Secp256k1::new();
+        let [user_key, resolver_key] = signer_keys();
+        KeyAggContext::new(&[user_key.public_key(&secp), resolver_key.public_key(&secp)]).unwrap()
+    }
+
+    /// Run both signing rounds, checking each partial signature the way a co-signer would
+    fn sign_two_party(context: &KeyAggContext, message: &Message) -> schnorr::Signature {
+        let secp = Secp256k1::new();
+        let keys = signer_keys();
+
+        let nonces: Vec<_> = keys
+            .iter()
+            .map(|key| generate_nonce(key, context, message).unwrap())
+            .collect();
+        let public_nonces: Vec<PublicNonce> = nonces.iter().map(|(_, public)| public.clone()).collect();
+        let aggregate_nonce = aggregate_nonces(&public_nonces).unwrap();
+
+        let partials: Vec<PartialSignature> = nonces
+            .into_iter()
+            .zip(&keys)
+            .map(|((secret, _), key)| partial_sign(context, secret, key, &aggregate_nonce, message).unwrap())
+            .collect();
+        for ((partial, key), public_nonce) in partials.iter().zip(&keys).zip(&public_nonces) {
+            verify_partial_signature(
+                context,
+                partial,
+                &aggregate_nonce,
+                &key.public_key(&secp),
+                public_nonce,
+                message,
+            )
+            .unwrap();
+        }
+
+        aggregate_partial_signatures(context, &aggregate_nonce, message, &partials).unwrap()
+    }
+
+    #[test]
+    fn test_two_party_signature_verifies_untweaked() {
+        let secp = Secp256k1::new();
+        let context = two_party_context();
+        let message = Message::from_digest([0x42u8; 32]);
+
+        let signature = sign_two_party(&context, &message);
+        assert!(secp.verify_schnorr(&signature, &message, &context.output_key()).is_ok());
+    }
+
+    #[test]
+    fn test_two_party_signature_verifies_with_taproot_tweak() {
+        let secp = Secp256k1::new();
+        let merkle_root = TapNodeHash::from_byte_array([0x07u8; 32]);
+        let context = two_party_context().with_taproot_tweak(Some(merkle_root)).unwrap();
+        assert_ne!(context.output_key(), context.aggregate_xonly());
+
+        for digest in [[0x01u8; 32], [0x99u8; 32], [0xfeu8; 32]] {
+            let message = Message::from_digest(digest);
+            let signature = sign_two_party(&context, &message);
+            assert!(secp.verify_schnorr(&signature, &message, &context.output_key()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_nonces_are_fresh_per_session() {
+        let context = two_party_context();
+        let message = Message::from_digest([0x42u8; 32]);
+        let [user_key, _] = signer_keys();
+
+        let (_, first) = generate_nonce(&user_key, &context, &message).unwrap();
+        let (_, second) = generate_nonce(&user_key, &context, &message).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(PublicNonce::from_slice(&first.serialize()).unwrap(), first);
+    }
+
+    #[test]
+    fn test_generate_nonce_rejects_foreign_key() {
+        let context = two_party_context();
+        let message = Message::from_digest([0x42u8; 32]);
+        let outsider = SecretKey::from_slice(&[0x33u8; 32]).unwrap();
+
+        assert!(generate_nonce(&outsider, &context, &message).is_err());
+    }
+
+    #[test]
+    fn test_partial_signature_verification_rejects_wrong_contribution() {
+        let secp = Secp256k1::new();
+        let context = two_party_context();
+        let message = Message::from_digest([0x42u8; 32]);
+        let [user_key, resolver_key] = signer_keys();
+
+        let (user_secnonce, user_pubnonce) = generate_nonce(&user_key, &context, &message).unwrap();
+        let (_, resolver_pubnonce) = generate_nonce(&resolver_key, &context, &message).unwrap();
+        let aggregate_nonce = aggregate_nonces(&[user_pubnonce.clone(), resolver_pubnonce.clone()]).unwrap();
+        let partial = partial_sign(&context, user_secnonce, &user_key, &aggregate_nonce, &message).unwrap();
+
+        let user_pubkey = user_key.public_key(&secp);
+        let verify = |signer: &PublicKey, nonce: &PublicNonce, message: &Message| {
+            verify_partial_signature(&context, &partial, &aggregate_nonce, signer, nonce, message)
+        };
+        assert!(verify(&user_pubkey, &user_pubnonce, &message).is_ok());
+        // Attributed to the other signer, paired with the other nonce, or over another message
+        assert!(verify(&resolver_key.public_key(&secp), &user_pubnonce, &message).is_err());
+        assert!(verify(&user_pubkey, &resolver_pubnonce, &message).is_err());
+        assert!(verify(&user_pubkey, &user_pubnonce, &Message::from_digest([0x43u8; 32])).is_err());
+
+        let error = verify(&user_pubkey, &resolver_pubnonce, &message).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "INVALID_PARTIAL_SIGNATURE"));
+    }
+}
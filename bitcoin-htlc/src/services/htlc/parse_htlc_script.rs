@@ -1,32 +1,528 @@
-use crate::models::{ApiError, HtlcParams};
-
-/// Parse HTLC script and extract parameters
-#[allow(dead_code)]
-pub fn parse_htlc_script(_script: &[u8]) -> Result<HtlcParams, ApiError> {
-    // This is a simplified parser - in production you'd want more robust parsing
-    // For now, we'll return an error as this is complex to implement correctly
-    Err(ApiError::InternalError {
-        code: "BITCOIN_SCRIPT_ERROR".to_string(),
-        message: "HTLC script parsing not implemented".to_string(),
-        details: None,
+use bitcoin::{
+    blockdata::{
+        opcodes::{all::*, Opcode},
+        script::Instruction,
+    },
+    Network, PublicKey, Script,
+};
+use serde_json::json;
+use crate::models::{ApiError, HtlcOutputType, HtlcParams};
+
+/// Parse an HTLC redeem script back into its parameters
+///
+/// Accepts the template produced by `build_htlc_script`:
+///
+/// ```text
+/// OP_IF
+///     OP_SHA256 <payment_hash> OP_EQUALVERIFY <recipient_pubkey> OP_CHECKSIG
+/// OP_ELSE
+///     <timeout> OP_CHECKLOCKTIMEVERIFY OP_DROP <sender_pubkey> OP_CHECKSIG
+/// OP_ENDIF
+/// ```
+///
+/// Keys may be compressed or uncompressed. Variants `HtlcParams` cannot describe
+/// are rejected as unsupported: a preimage size guard (`OP_SIZE <32> OP_EQUALVERIFY`)
+/// and key hash commitments (`OP_DUP OP_HASH160 <hash>`), whose keys cannot be
+/// recovered.
+///
+/// The redeem script does not encode how it is wrapped, so the returned params carry
+/// the default output type; `network` is the one the script is expected on.
+pub fn parse_htlc_script(script: &[u8], network: Network) -> Result<HtlcParams, ApiError> {
+    let mut parser = TemplateParser::new(Script::from_bytes(script))?;
+
+    parser.expect_op(OP_IF)?;
+    parser.reject_size_guard()?;
+    parser.expect_op(OP_SHA256)?;
+    let (offset, hash) = parser.expect_push("payment hash")?;
+    let payment_hash: [u8; 32] = hash.try_into().map_err(|_| {
+        template_error(
+            offset,
+            format!("Payment hash must be 32 bytes, found {}", hash.len()),
+            json!({ "expected_length": 32, "found_length": hash.len() }),
+        )
+    })?;
+    parser.expect_op(OP_EQUALVERIFY)?;
+    let recipient_pubkey = parser.expect_pubkey("recipient")?;
+    parser.expect_op(OP_CHECKSIG)?;
+
+    parser.expect_op(OP_ELSE)?;
+    let timeout = parser.expect_timeout()?;
+    parser.expect_op(OP_CLTV)?;
+    parser.expect_op(OP_DROP)?;
+    let sender_pubkey = parser.expect_pubkey("sender")?;
+    parser.expect_op(OP_CHECKSIG)?;
+    parser.expect_op(OP_ENDIF)?;
+    parser.expect_end()?;
+
+    Ok(HtlcParams {
+        sender_pubkey,
+        recipient_pubkey,
+        payment_hash,
+        timeout,
+        output_type: HtlcOutputType::default(),
+        network,
     })
 }
 
+/// Walks the instructions of a script, keeping byte offsets for error reporting
+struct TemplateParser<'a> {
+    script: &'a Script,
+    instructions: Vec<(usize, Instruction<'a>)>,
+    position: usize,
+}
+
+impl<'a> TemplateParser<'a> {
+    fn new(script: &'a Script) -> Result<Self, ApiError> {
+        let mut instructions = Vec::new();
+        for item in script.instruction_indices() {
+            match item {
+                Ok(instruction) => instructions.push(instruction),
+                Err(e) => {
+                    let offset = instructions
+                        .last()
+                        .map(|(offset, instruction): &(usize, Instruction)| {
+                            offset + instruction_len(instruction)
+                        })
+                        .unwrap_or(0);
+                    return Err(template_error(
+                        offset,
+                        format!("Malformed script: {}", e),
+                        json!({}),
+                    ));
+                }
+            }
+        }
+
+        Ok(Self {
+            script,
+            instructions,
+            position: 0,
+        })
+    }
+
+    fn peek_op(&self) -> Option<Opcode> {
+        match self.instructions.get(self.position) {
+            Some((_, Instruction::Op(opcode))) => Some(*opcode),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self, expected: &str) -> Result<(usize, Instruction<'a>), ApiError> {
+        let instruction = self.instructions.get(self.position).copied().ok_or_else(|| {
+            template_error(
+                self.script.len(),
+                format!("Script ends early, expected {}", expected),
+                json!({ "expected": expected }),
+            )
+        })?;
+        self.position += 1;
+        Ok(instruction)
+    }
+
+    fn expect_op(&mut self, expected: Opcode) -> Result<(), ApiError> {
+        let expected_name = expected.to_string();
+        match self.next(&expected_name)? {
+            (_, Instruction::Op(opcode)) if opcode == expected => Ok(()),
+            (offset, Instruction::Op(opcode)) => Err(template_error(
+                offset,
+                format!("Unexpected opcode {} at offset {}, expected {}", opcode, offset, expected_name),
+                json!({ "expected": expected_name, "found": opcode.to_string() }),
+            )),
+            (offset, Instruction::PushBytes(bytes)) => Err(template_error(
+                offset,
+                format!(
+                    "Unexpected {} byte push at offset {}, expected {}",
+                    bytes.len(),
+                    offset,
+                    expected_name
+                ),
+                json!({ "expected": expected_name, "found": hex::encode(bytes.as_bytes()) }),
+            )),
+        }
+    }
+
+    fn expect_push(&mut self, expected: &str) -> Result<(usize, &'a [u8]), ApiError> {
+        match self.next(expected)? {
+            (offset, Instruction::PushBytes(bytes)) => {
+                self.check_minimal_push(offset, bytes.as_bytes())?;
+                Ok((offset, bytes.as_bytes()))
+            }
+            (offset, Instruction::Op(opcode)) => {
+                if opcode == OP_DUP {
+                    self.reject_key_hash_template(offset, opcode)?;
+                }
+                Err(template_error(
+                    offset,
+                    format!("Unexpected opcode {} at offset {}, expected {}", opcode, offset, expected),
+                    json!({ "expected": expected, "found": opcode.to_string() }),
+                ))
+            }
+        }
+    }
+
+    fn expect_pubkey(&mut self, role: &str) -> Result<PublicKey, ApiError> {
+        let expected = format!("{} public key", role);
+        let (offset, bytes) = self.expect_push(&expected)?;
+
+        let valid_length = matches!(
+            (bytes.len(), bytes.first()),
+            (33, Some(0x02 | 0x03)) | (65, Some(0x04))
+        );
+        if !valid_length {
+            return Err(template_error(
+                offset,
+                format!(
+                    "Invalid {} at offset {}: expected 33 byte compressed or 65 byte uncompressed key, found {} bytes",
+                    expected,
+                    offset,
+                    bytes.len()
+                ),
+                json!({ "expected_length": [33, 65], "found_length": bytes.len() }),
+            ));
+        }
+
+        PublicKey::from_slice(bytes).map_err(|e| {
+            template_error(
+                offset,
+                format!("Invalid {} at offset {}: {}", expected, offset, e),
+                json!({ "found": hex::encode(bytes) }),
+            )
+        })
+    }
+
+    /// CLTV operand: a small-integer opcode or a minimally encoded script number
+    fn expect_timeout(&mut self) -> Result<u32, ApiError> {
+        let (offset, value) = match self.next("timeout")? {
+            (offset, Instruction::Op(opcode)) => match small_int(opcode) {
+                Some(value) => (offset, value),
+                None => {
+                    return Err(template_error(
+                        offset,
+                        format!("Unexpected opcode {} at offset {}, expected timeout", opcode, offset),
+                        json!({ "expected": "timeout", "found": opcode.to_string() }),
+                    ))
+                }
+            },
+            (offset, Instruction::PushBytes(bytes)) => {
+                self.check_minimal_push(offset, bytes.as_bytes())?;
+                let value = decode_script_num(bytes.as_bytes(), 5).map_err(|e| {
+                    template_error(
+                        offset,
+                        format!("Invalid timeout at offset {}: {}", offset, e),
+                        json!({ "found": hex::encode(bytes.as_bytes()) }),
+                    )
+                })?;
+                (offset, value)
+            }
+        };
+
+        u32::try_from(value)
+            .ok()
+            .filter(|timeout| *timeout > 0)
+            .ok_or_else(|| {
+                template_error(
+                    offset,
+                    format!("Timeout {} at offset {} is out of range", value, offset),
+                    json!({ "found": value }),
+                )
+            })
+    }
+
+    fn expect_end(&self) -> Result<(), ApiError> {
+        match self.instructions.get(self.position) {
+            None => Ok(()),
+            Some((offset, _)) => Err(template_error(
+                *offset,
+                format!("Unexpected trailing data at offset {}", offset),
+                json!({ "trailing_bytes": self.script.len() - offset }),
+            )),
+        }
+    }
+
+    /// Pushes must use the shortest encoding, like `Builder` produces and the
+    /// MINIMALDATA policy requires
+    fn check_minimal_push(&self, offset: usize, bytes: &[u8]) -> Result<(), ApiError> {
+        let opcode = self.script.as_bytes()[offset];
+        let canonical = match bytes {
+            [] => opcode == OP_PUSHBYTES_0.to_u8(),
+            [value] if (1..=16).contains(value) || *value == 0x81 => false,
+            _ if bytes.len() <= 75 => opcode as usize == bytes.len(),
+            _ if bytes.len() <= 255 => opcode == OP_PUSHDATA1.to_u8(),
+            _ if bytes.len() <= 65535 => opcode == OP_PUSHDATA2.to_u8(),
+            _ => true,
+        };
+        if canonical {
+            return Ok(());
+        }
+        Err(template_error(
+            offset,
+            format!("Non-canonical push of {} bytes at offset {}", bytes.len(), offset),
+            json!({ "opcode": format!("{:#04x}", opcode), "length": bytes.len() }),
+        ))
+    }
+
+    /// Building from `HtlcParams` never emits the guard, so accepting it would
+    /// return params that describe a different script
+    fn reject_size_guard(&self) -> Result<(), ApiError> {
+        if self.peek_op() != Some(OP_SIZE) {
+            return Ok(());
+        }
+        let offset = self.instructions[self.position].0;
+        Err(ApiError::BadRequest {
+            code: "UNSUPPORTED_HTLC_TEMPLATE".to_string(),
+            message: format!(
+                "Preimage size guard at offset {}: HTLC parameters cannot represent it",
+                offset
+            ),
+            details: Some(json!({ "offset": offset, "found": OP_SIZE.to_string() })),
+        })
+    }
+
+    fn reject_key_hash_template(&self, offset: usize, opcode: Opcode) -> Result<(), ApiError> {
+        let followed_by_hash160 = self
+            .instructions
+            .get(self.position)
+            .is_some_and(|(_, next)| *next == Instruction::Op(OP_HASH160));
+        if opcode == OP_DUP && followed_by_hash160 {
+            return Err(ApiError::BadRequest {
+                code: "UNSUPPORTED_HTLC_TEMPLATE".to_string(),
+                message: format!(
+                    "Pubkey-hash HTLC template at offset {}: public keys cannot be recovered",
+                    offset
+                ),
+                details: Some(json!({ "offset": offset })),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn small_int(opcode: Opcode) -> Option<i64> {
+    let byte = opcode.to_u8();
+    if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&byte) {
+        Some((byte - OP_PUSHNUM_1.to_u8() + 1) as i64)
+    } else {
+        None
+    }
+}
+
+fn instruction_len(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::Op(_) => 1,
+        Instruction::PushBytes(bytes) => match bytes.len() {
+            0..=75 => 1 + bytes.len(),
+            76..=255 => 2 + bytes.len(),
+            256..=65535 => 3 + bytes.len(),
+            _ => 5 + bytes.len(),
+        },
+    }
+}
+
+fn template_error(
+    offset: usize,
+    message: impl Into<String>,
+    details: serde_json::Value,
+) -> ApiError {
+    let mut details = details;
+    if let Some(object) = details.as_object_mut() {
+        object.insert("offset".to_string(), json!(offset));
+    }
+    ApiError::BadRequest {
+        code: "INVALID_HTLC_SCRIPT".to_string(),
+        message: message.into(),
+        details: Some(details),
+    }
+}
+
+/// Decode a minimally encoded CScriptNum of at most `max_len` bytes
+fn decode_script_num(bytes: &[u8], max_len: usize) -> Result<i64, &'static str> {
+    if bytes.len() > max_len {
+        return Err("script number overflow");
+    }
+    let Some((last, _)) = bytes.split_last() else {
+        return Ok(0);
+    };
+    if *last & 0x7f == 0 && (bytes.len() == 1 || bytes[bytes.len() - 2] & 0x80 == 0) {
+        return Err("script number is not minimally encoded");
+    }
+
+    let mut value: i64 = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        value |= (*byte as i64) << (8 * i);
+    }
+    if *last & 0x80 != 0 {
+        let mask = !(0x80i64 << (8 * (bytes.len() - 1)));
+        return Ok(-(value & mask));
+    }
+    Ok(value)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::htlc::build_htlc_script;
+    use bitcoin::blockdata::script::Builder;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    fn params(timeout: u32) -> HtlcParams {
+        let secp = Secp256k1::new();
+        let sender_secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let recipient_secret_key = SecretKey::from_slice(&[2u8; 32]).unwrap();
+
+        HtlcParams {
+            sender_pubkey: PublicKey::new(sender_secret_key.public_key(&secp)),
+            recipient_pubkey: PublicKey::new(recipient_secret_key.public_key(&secp)),
+            payment_hash: [0xabu8; 32],
+            timeout,
+            output_type: HtlcOutputType::default(),
+            network: Network::Bitcoin,
+        }
+    }
 
     #[test]
-    fn test_parse_htlc_script_not_implemented() {
-        let mock_script = vec![0u8; 100];
-        let result = parse_htlc_script(&mock_script);
-        
-        assert!(result.is_err());
-        match result {
-            Err(ApiError::InternalError { message, .. }) => {
-                assert_eq!(message, "HTLC script parsing not implemented");
+    fn test_script_num_rejects_non_minimal() {
+        assert_eq!(decode_script_num(&[0x90, 0xa6, 0x25], 5), Ok(2_467_472));
+        assert!(decode_script_num(&[0x01, 0x00], 5).is_err());
+        assert!(decode_script_num(&[0x00], 5).is_err());
+        assert!(decode_script_num(&[0x80, 0x00], 5).is_ok());
+        assert!(decode_script_num(&[0x01; 6], 5).is_err());
+    }
+
+    fn error_code(error: ApiError) -> (String, String) {
+        match error {
+            ApiError::BadRequest { code, message, .. } => (code, message),
+            other => panic!("Expected BadRequest, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_htlc_script_round_trips_builder_output() {
+        for timeout in [1, 16, 17, 144, 500_000, 1_700_000_000] {
+            let expected = params(timeout);
+            let script = build_htlc_script(&expected).unwrap();
+            let parsed = parse_htlc_script(&script.redeem_script, Network::Testnet).unwrap();
+
+            assert_eq!(parsed.sender_pubkey, expected.sender_pubkey);
+            assert_eq!(parsed.recipient_pubkey, expected.recipient_pubkey);
+            assert_eq!(parsed.payment_hash, expected.payment_hash);
+            assert_eq!(parsed.timeout, timeout);
+            assert_eq!(parsed.network, Network::Testnet);
+        }
+    }
+
+    #[test]
+    fn test_parse_htlc_script_accepts_uncompressed_keys() {
+        let p = params(144);
+        let mut recipient = p.recipient_pubkey;
+        recipient.compressed = false;
+
+        let script = Builder::new()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_SHA256)
+            .push_slice(p.payment_hash)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_key(&recipient)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ELSE)
+            .push_int(144)
+            .push_opcode(OP_CLTV)
+            .push_opcode(OP_DROP)
+            .push_key(&p.sender_pubkey)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ENDIF)
+            .into_script();
+
+        let parsed = parse_htlc_script(script.as_bytes(), Network::Bitcoin).unwrap();
+        assert_eq!(parsed.recipient_pubkey, recipient);
+        assert!(!parsed.recipient_pubkey.compressed);
+    }
+
+    #[test]
+    fn test_parse_htlc_script_rejects_size_guard() {
+        let p = params(144);
+        let mut script = vec![OP_IF.to_u8(), OP_SIZE.to_u8(), 0x01, 32, OP_EQUALVERIFY.to_u8()];
+        script.extend_from_slice(&build_htlc_script(&p).unwrap().redeem_script[1..]);
+
+        match parse_htlc_script(&script, Network::Bitcoin).unwrap_err() {
+            ApiError::BadRequest { code, message, details } => {
+                assert_eq!(code, "UNSUPPORTED_HTLC_TEMPLATE");
+                assert!(message.contains("offset 1"));
+                assert_eq!(details.unwrap()["found"], "OP_SIZE");
             }
-            _ => panic!("Expected BitcoinError"),
+            other => panic!("Expected BadRequest, got {:?}", other),
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_htlc_script_reports_unknown_opcode_offset() {
+        let mut script = build_htlc_script(&params(144)).unwrap().redeem_script;
+        // Replace OP_SHA256 with OP_HASH256
+        script[1] = OP_HASH256.to_u8();
+
+        let (code, message) = error_code(parse_htlc_script(&script, Network::Bitcoin).unwrap_err());
+        assert_eq!(code, "INVALID_HTLC_SCRIPT");
+        assert!(message.contains("OP_HASH256"));
+        assert!(message.contains("offset 1"));
+    }
+
+    #[test]
+    fn test_parse_htlc_script_rejects_non_canonical_push() {
+        let p = params(144);
+        let mut script = vec![OP_IF.to_u8(), OP_SHA256.to_u8(), OP_PUSHDATA1.to_u8(), 32];
+        script.extend_from_slice(&p.payment_hash);
+        script.extend_from_slice(&build_htlc_script(&p).unwrap().redeem_script[35..]);
+
+        let (code, message) = error_code(parse_htlc_script(&script, Network::Bitcoin).unwrap_err());
+        assert_eq!(code, "INVALID_HTLC_SCRIPT");
+        assert!(message.contains("Non-canonical push"));
+    }
+
+    #[test]
+    fn test_parse_htlc_script_rejects_wrong_key_length() {
+        let p = params(144);
+        let script = Builder::new()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_SHA256)
+            .push_slice(p.payment_hash)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_slice([2u8; 32])
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+
+        let (_, message) = error_code(parse_htlc_script(script.as_bytes(), Network::Bitcoin).unwrap_err());
+        assert!(message.contains("recipient public key"));
+        assert!(message.contains("found 32 bytes"));
+    }
+
+    #[test]
+    fn test_parse_htlc_script_rejects_key_hash_template() {
+        let script = Builder::new()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_SHA256)
+            .push_slice([0xabu8; 32])
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice([0u8; 20])
+            .into_script();
+
+        let (code, _) = error_code(parse_htlc_script(script.as_bytes(), Network::Bitcoin).unwrap_err());
+        assert_eq!(code, "UNSUPPORTED_HTLC_TEMPLATE");
+    }
+
+    #[test]
+    fn test_parse_htlc_script_rejects_truncated_and_trailing_data() {
+        let script = build_htlc_script(&params(144)).unwrap().redeem_script;
+
+        let (_, message) = error_code(parse_htlc_script(&script[..script.len() - 1], Network::Bitcoin).unwrap_err());
+        assert!(message.contains("expected OP_ENDIF"));
+
+        let mut trailing = script.clone();
+        trailing.push(OP_NOP.to_u8());
+        let (_, message) = error_code(parse_htlc_script(&trailing, Network::Bitcoin).unwrap_err());
+        assert!(message.contains("trailing data"));
+
+        let (_, message) = error_code(parse_htlc_script(&[0u8; 100], Network::Bitcoin).unwrap_err());
+        assert!(message.contains("expected OP_IF"));
+    }
+}
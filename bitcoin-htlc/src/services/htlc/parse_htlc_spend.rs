@@ -0,0 +1,216 @@
+use bitcoin::{
+    blockdata::{opcodes::all::OP_PUSHNUM_1, script::Instruction},
+    taproot::TAPROOT_ANNEX_PREFIX,
+    Script, Witness,
+};
+use serde_json::json;
+use crate::models::{ApiError, HtlcOutputType, HtlcParams};
+use crate::services::htlc::{build_htlc_script, hash_preimage};
+
+/// Branch an input took to spend an HTLC, with what it revealed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HtlcSpend {
+    /// Hashlock branch; the preimage hashes to the payment hash
+    Claim { preimage: Vec<u8> },
+    /// Timelock branch
+    Refund,
+    /// Taproot key path, co-signed by both parties and revealing nothing
+    Cooperative,
+}
+
+/// Classify an input spending the HTLC from its scriptSig and witness
+///
+/// The inverse of [`build_claim_spend`](crate::services::htlc::build_claim_spend) and
+/// [`build_refund_spend`](crate::services::htlc::build_refund_spend): the redeem script, or
+/// the tap leaf for P2TR, must be the one built from `params`, and the branch is told
+/// by the IF selector or by which leaf is revealed. A claim is only reported with a
+/// preimage that matches `params.payment_hash`.
+pub fn parse_htlc_spend(
+    params: &HtlcParams,
+    script_sig: &Script,
+    witness: &Witness,
+) -> Result<HtlcSpend, ApiError> {
+    let htlc_script = build_htlc_script(params)?;
+
+    let spend = match htlc_script.output_type {
+        HtlcOutputType::P2sh => parse_redeem_script_stack(push_stack(script_sig)?, &htlc_script.redeem_script)?,
+        HtlcOutputType::P2shP2wsh | HtlcOutputType::P2wsh => {
+            parse_redeem_script_stack(witness.to_vec(), &htlc_script.redeem_script)?
+        }
+        HtlcOutputType::P2tr => {
+            let taproot = htlc_script.taproot.as_ref().ok_or_else(|| unrecognized("HTLC has no tap tree"))?;
+            let mut stack = witness.to_vec();
+            if stack.len() >= 2 && stack.last().and_then(|item| item.first()) == Some(&TAPROOT_ANNEX_PREFIX) {
+                stack.pop();
+            }
+            match stack.len() {
+                1 => HtlcSpend::Cooperative,
+                len if len >= 3 => {
+                    let leaf = &stack[len - 2];
+                    if leaf == taproot.claim_leaf.as_bytes() && len == 4 {
+                        HtlcSpend::Claim { preimage: stack[1].clone() }
+                    } else if leaf == taproot.refund_leaf.as_bytes() && len == 3 {
+                        HtlcSpend::Refund
+                    } else {
+                        return Err(unrecognized("Witness reveals neither HTLC leaf"));
+                    }
+                }
+                _ => return Err(unrecognized("Witness is not a key path or script path spend")),
+            }
+        }
+    };
+
+    if let HtlcSpend::Claim { ref preimage } = spend {
+        if hash_preimage(preimage) != params.payment_hash {
+            return Err(ApiError::BadRequest {
+                code: "PREIMAGE_MISMATCH".to_string(),
+                message: "Revealed preimage does not hash to the payment hash".to_string(),
+                details: Some(json!({
+                    "preimage": hex::encode(preimage),
+                    "payment_hash": hex::encode(params.payment_hash),
+                })),
+            });
+        }
+    }
+    Ok(spend)
+}
+
+/// Stack items `<signature> [<preimage>] <selector> <redeem_script>`
+fn parse_redeem_script_stack(stack: Vec<Vec<u8>>, redeem_script: &[u8]) -> Result<HtlcSpend, ApiError> {
+    if stack.last().map(Vec::as_slice) != Some(redeem_script) {
+        return Err(unrecognized("Spend does not reveal the HTLC redeem script"));
+    }
+    let selector = stack.len().checked_sub(2).map(|index| stack[index].as_slice());
+    match (stack.len(), selector) {
+        (4, Some([1])) => Ok(HtlcSpend::Claim { preimage: stack[1].clone() }),
+        (3, Some([])) => Ok(HtlcSpend::Refund),
+        _ => Err(unrecognized("Spend stack matches neither HTLC branch")),
+    }
+}
+
+/// Items a push-only scriptSig leaves on the stack
+fn push_stack(script_sig: &Script) -> Result<Vec<Vec<u8>>, ApiError> {
+    script_sig
+        .instructions()
+        .map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(bytes)) => Ok(bytes.as_bytes().to_vec()),
+            Ok(Instruction::Op(OP_PUSHNUM_1)) => Ok(vec![1u8]),
+            _ => Err(unrecognized("scriptSig is not push-only")),
+        })
+        .collect()
+}
+
+fn unrecognized(message: &str) -> ApiError {
+    ApiError::BadRequest {
+        code: "UNRECOGNIZED_HTLC_SPEND".to_string(),
+        message: message.to_string(),
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::transaction::{create_claim_transaction, create_cooperative_close_transaction, create_refund_transaction};
+    use crate::test_support::{htlc_params, key, HTLC_TIMEOUT, PREIMAGE};
+    use bitcoin::{Address, Amount, Network, OutPoint};
+    use std::str::FromStr;
+
+    fn destination() -> Address {
+        Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+            .unwrap()
+            .require_network(Network::Testnet)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_classifies_claims_and_refunds_of_every_output_type() {
+        for output_type in [
+            HtlcOutputType::P2sh,
+            HtlcOutputType::P2shP2wsh,
+            HtlcOutputType::P2wsh,
+            HtlcOutputType::P2tr,
+        ] {
+            let params = htlc_params(output_type);
+            let htlc = build_htlc_script(&params).unwrap();
+            let amount = Amount::from_sat(100_000);
+            let fee = Amount::from_sat(1_000);
+
+            let claim = create_claim_transaction(OutPoint::default(), amount, &htlc, &PREIMAGE, &key(1).inner, &destination(), fee).unwrap();
+            let input = &claim.input[0];
+            assert_eq!(
+                parse_htlc_spend(&params, &input.script_sig, &input.witness).unwrap(),
+                HtlcSpend::Claim { preimage: PREIMAGE.to_vec() },
+                "{:?}",
+                output_type
+            );
+
+            let refund = create_refund_transaction(OutPoint::default(), amount, &htlc, &key(2).inner, &destination(), HTLC_TIMEOUT, fee).unwrap();
+            let input = &refund.input[0];
+            assert_eq!(
+                parse_htlc_spend(&params, &input.script_sig, &input.witness).unwrap(),
+                HtlcSpend::Refund,
+                "{:?}",
+                output_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_taproot_key_path_is_cooperative() {
+        let params = htlc_params(HtlcOutputType::P2tr);
+        let htlc = build_htlc_script(&params).unwrap();
+        let (mut close, _) = create_cooperative_close_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &destination(),
+            Amount::from_sat(1_000),
+        )
+        .unwrap();
+        close.input[0].witness.push([0u8; 64]);
+
+        let input = &close.input[0];
+        assert_eq!(parse_htlc_spend(&params, &input.script_sig, &input.witness).unwrap(), HtlcSpend::Cooperative);
+    }
+
+    #[test]
+    fn test_rejects_spends_of_other_htlcs() {
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let other = HtlcParams { timeout: HTLC_TIMEOUT + 1, ..params.clone() };
+        let claim = create_claim_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &build_htlc_script(&other).unwrap(),
+            &PREIMAGE,
+            &key(1).inner,
+            &destination(),
+            Amount::from_sat(1_000),
+        )
+        .unwrap();
+
+        let input = &claim.input[0];
+        let result = parse_htlc_spend(&params, &input.script_sig, &input.witness);
+        assert!(matches!(result, Err(ApiError::BadRequest { ref code, .. }) if code == "UNRECOGNIZED_HTLC_SPEND"));
+    }
+
+    #[test]
+    fn test_rejects_preimage_not_matching_the_payment_hash() {
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let htlc = build_htlc_script(&params).unwrap();
+        let claim = create_claim_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &[8u8; 32],
+            &key(1).inner,
+            &destination(),
+            Amount::from_sat(1_000),
+        )
+        .unwrap();
+
+        let input = &claim.input[0];
+        let result = parse_htlc_spend(&params, &input.script_sig, &input.witness);
+        assert!(matches!(result, Err(ApiError::BadRequest { ref code, .. }) if code == "PREIMAGE_MISMATCH"));
+    }
+}
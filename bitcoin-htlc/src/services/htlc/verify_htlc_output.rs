@@ -0,0 +1,177 @@
+use bitcoin::{Network, Transaction};
+use crate::models::{ApiError, HtlcOutputType, HtlcParams, HtlcScript};
+use crate::services::htlc::build_htlc_script::build_htlc_script;
+
+/// Locktime values at or above this threshold are timestamps, not heights
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Result of checking a funding output against the HTLC it should pay to
+#[derive(Debug, Clone)]
+pub struct HtlcOutputVerification {
+    /// HTLC rebuilt from the expected parameters
+    pub htlc_script: HtlcScript,
+    pub actual_amount: u64,
+    pub validation_errors: Vec<String>,
+}
+
+impl HtlcOutputVerification {
+    pub fn is_valid(&self) -> bool {
+        self.validation_errors.is_empty()
+    }
+}
+
+/// Check that `transaction` output `output_index` funds the HTLC described by `params`
+///
+/// The HTLC is rebuilt from the expected keys, payment hash and timeout, so a matching
+/// scriptPubKey proves all of them at once. Every mismatch is collected rather than
+/// returned early so the caller sees the full picture.
+pub fn verify_htlc_output(
+    transaction: &Transaction,
+    output_index: u32,
+    params: &HtlcParams,
+    expected_amount: u64,
+    expected_network: Network,
+) -> Result<HtlcOutputVerification, ApiError> {
+    let output = transaction.output.get(output_index as usize).ok_or_else(|| {
+        ApiError::BadRequest {
+            code: "INVALID_OUTPUT_INDEX".to_string(),
+            message: format!(
+                "Transaction {} has {} outputs, output {} does not exist",
+                transaction.txid(),
+                transaction.output.len(),
+                output_index
+            ),
+            details: None,
+        }
+    })?;
+
+    let htlc_script = build_htlc_script(params)?;
+    let actual_amount = output.value.to_sat();
+    let mut validation_errors = Vec::new();
+
+    if params.network != expected_network {
+        validation_errors.push(format!(
+            "Network mismatch: HTLC is for {}, service runs on {}",
+            params.network, expected_network
+        ));
+    }
+
+    if params.timeout >= LOCKTIME_THRESHOLD {
+        validation_errors.push(format!(
+            "Timeout {} is a timestamp, expected a block height below {}",
+            params.timeout, LOCKTIME_THRESHOLD
+        ));
+    }
+
+    if output.script_pubkey != htlc_script.script_pubkey {
+        let other_type = [
+            HtlcOutputType::P2sh,
+            HtlcOutputType::P2shP2wsh,
+            HtlcOutputType::P2wsh,
+            HtlcOutputType::P2tr,
+        ]
+        .into_iter()
+        .filter(|output_type| *output_type != params.output_type)
+        .find(|output_type| {
+            build_htlc_script(&HtlcParams { output_type: *output_type, ..params.clone() })
+                .is_ok_and(|other| other.script_pubkey == output.script_pubkey)
+        });
+
+        validation_errors.push(match other_type {
+            Some(output_type) => format!(
+                "Output type mismatch: output pays to a {:?} HTLC, expected {:?}",
+                output_type, params.output_type
+            ),
+            None => format!(
+                "scriptPubKey mismatch: expected {} for the given keys, payment hash and timeout, found {}",
+                htlc_script.script_pubkey.to_hex_string(),
+                output.script_pubkey.to_hex_string()
+            ),
+        });
+    }
+
+    if actual_amount != expected_amount {
+        validation_errors.push(format!(
+            "Amount mismatch: expected {} sats, found {} sats",
+            expected_amount, actual_amount
+        ));
+    }
+
+    Ok(HtlcOutputVerification {
+        htlc_script,
+        actual_amount,
+        validation_errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::htlc_params;
+    use bitcoin::{absolute::LockTime, transaction::Version, Amount, TxOut};
+
+    fn funding_transaction(params: &HtlcParams, amount: u64) -> Transaction {
+        let htlc = build_htlc_script(params).unwrap();
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: bitcoin::ScriptBuf::new_op_return([0u8; 4]),
+                },
+                TxOut {
+                    value: Amount::from_sat(amount),
+                    script_pubkey: htlc.script_pubkey,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_verify_htlc_output_accepts_matching_output() {
+        let transaction = funding_transaction(&htlc_params(HtlcOutputType::P2wsh), 100_000);
+        let result = verify_htlc_output(&transaction, 1, &htlc_params(HtlcOutputType::P2wsh), 100_000, Network::Testnet).unwrap();
+
+        assert!(result.is_valid(), "{:?}", result.validation_errors);
+        assert_eq!(result.actual_amount, 100_000);
+        assert!(result.htlc_script.address.starts_with("tb1q"));
+    }
+
+    #[test]
+    fn test_verify_htlc_output_reports_every_mismatch() {
+        let transaction = funding_transaction(&htlc_params(HtlcOutputType::P2wsh), 90_000);
+        let mut expected = htlc_params(HtlcOutputType::P2wsh);
+        expected.timeout += 1;
+
+        let result = verify_htlc_output(&transaction, 1, &expected, 100_000, Network::Bitcoin).unwrap();
+
+        assert_eq!(result.validation_errors.len(), 3, "{:?}", result.validation_errors);
+        assert!(result.validation_errors[0].starts_with("Network mismatch"));
+        assert!(result.validation_errors[1].starts_with("scriptPubKey mismatch"));
+        assert!(result.validation_errors[2].starts_with("Amount mismatch"));
+    }
+
+    #[test]
+    fn test_verify_htlc_output_detects_output_type_mismatch() {
+        let transaction = funding_transaction(&htlc_params(HtlcOutputType::P2shP2wsh), 100_000);
+
+        let result = verify_htlc_output(&transaction, 1, &htlc_params(HtlcOutputType::P2wsh), 100_000, Network::Testnet).unwrap();
+        assert_eq!(result.validation_errors.len(), 1);
+        assert!(result.validation_errors[0].contains("P2shP2wsh"));
+    }
+
+    #[test]
+    fn test_verify_htlc_output_rejects_timestamp_timeout_and_bad_index() {
+        let mut expected = htlc_params(HtlcOutputType::P2wsh);
+        expected.timeout = 1_700_000_000;
+        let transaction = funding_transaction(&expected, 100_000);
+
+        let result = verify_htlc_output(&transaction, 1, &expected, 100_000, Network::Testnet).unwrap();
+        assert_eq!(result.validation_errors.len(), 1);
+        assert!(result.validation_errors[0].contains("timestamp"));
+
+        assert!(verify_htlc_output(&transaction, 2, &expected, 100_000, Network::Testnet).is_err());
+    }
+}
@@ -14,7 +14,9 @@ pub fn verify_htlc_script(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::HtlcOutputType;
     use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::Network;
 
     #[test]
     fn test_verify_htlc_script_matching() {
@@ -33,6 +35,8 @@ mod tests {
             },
             payment_hash: [0u8; 32],
             timeout: 144,
+            output_type: HtlcOutputType::P2wsh,
+            network: Network::Testnet,
         };
         
         let built_script = build_htlc_script(&params).unwrap();
@@ -58,6 +62,8 @@ mod tests {
             },
             payment_hash: [0u8; 32],
             timeout: 144,
+            output_type: HtlcOutputType::P2wsh,
+            network: Network::Testnet,
         };
         
         let params2 = HtlcParams {
@@ -71,6 +77,8 @@ mod tests {
             },
             payment_hash: [1u8; 32], // Different hash
             timeout: 144,
+            output_type: HtlcOutputType::P2wsh,
+            network: Network::Testnet,
         };
         
         let built_script = build_htlc_script(&params1).unwrap();
@@ -2,6 +2,9 @@ pub mod htlc;
 pub mod transaction;
 pub mod bitcoin;
 pub mod order;
+pub mod orchestrator;
+pub mod watchlist;
+pub mod webhooks;
 
 // Re-export commonly used items
 pub use htlc::{
@@ -9,14 +12,21 @@ pub use htlc::{
     generate_preimage,
     hash_preimage,
     parse_htlc_script,
+    parse_htlc_spend,
     verify_htlc_script,
 };
 
 pub use transaction::{
     create_funding_transaction,
+    create_funding_transaction_at_fee_rate,
     create_claim_transaction,
+    create_claim_transaction_at_fee_rate,
     create_refund_transaction,
+    create_refund_transaction_at_fee_rate,
+    create_signed_funding_transaction,
+    verify_spend,
 };
 
 pub use bitcoin::BitcoinClient;
-pub use order::OrderService;
\ No newline at end of file
+pub use order::OrderService;
+pub use orchestrator::Orchestrator;
\ No newline at end of file
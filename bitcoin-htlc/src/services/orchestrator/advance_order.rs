@@ -0,0 +1,224 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::htlc::HtlcSpend;
+use crate::services::order::claim_htlc::select_funding_utxo;
+use crate::services::order::{transition_order, OrderService};
+use bitcoin::secp256k1::Secp256k1;
+use chrono::Utc;
+use log::{info, warn};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Fire the next step of a swap, if its precondition holds
+///
+/// Every step reads its inputs from the database and the chain, so a step that was
+/// interrupted is simply retried on the next tick. Returns the status the order moved
+/// to, or `None` if it is still waiting.
+///
+/// Fusion order states are advanced by whoever watches Ethereum; here they are only
+/// checked for HTLC expiry.
+pub async fn advance_order(
+    service: &OrderService,
+    order: &Order,
+    tip: u32,
+) -> Result<Option<OrderStatus>, ApiError> {
+    let status = OrderStatus::from_str(&order.status).map_err(|message| ApiError::InternalError {
+        code: "ORDER_CORRUPT".to_string(),
+        message: format!("Order {}: {}", order.id, message),
+        details: None,
+    })?;
+
+    let htlc = match order.htlc_id {
+        Some(htlc_id) => Some(HtlcRepository::new(service.pool().clone()).find(htlc_id).await?),
+        None => None,
+    };
+    let Some(htlc) = htlc else {
+        // Nothing is locked on chain yet, the order only times out
+        if order.expires_at < Utc::now() && status.can_transition_to(OrderStatus::Expired) {
+            transition(service, order.id, OrderStatus::Expired, "order expired before an HTLC was created").await?;
+            return Ok(Some(OrderStatus::Expired));
+        }
+        return Ok(None);
+    };
+
+    match status {
+        OrderStatus::BitcoinHtlcCreated => {
+            if tip >= htlc.params.timeout {
+                transition(service, order.id, OrderStatus::Expired, "HTLC timed out unfunded").await?;
+                return Ok(Some(OrderStatus::Expired));
+            }
+            detect_funding(service, order, &htlc, tip).await
+        }
+        OrderStatus::BitcoinHtlcFunded => {
+            // The counterparty may claim before the funding has all its confirmations
+            if let Some(moved) = detect_spend(service, status, &htlc).await? {
+                return Ok(Some(moved));
+            }
+            if let Some(expired) = refund_if_expired(service, &htlc, tip).await? {
+                return Ok(Some(expired));
+            }
+            let utxos = service.bitcoin_client().get_utxos(&htlc.address).await?;
+            let expected_amount = htlc.amount.or(order.bitcoin_amount.map(|amount| amount as u64));
+            match select_funding_utxo(&utxos, htlc.funding_outpoint, expected_amount, tip, htlc.min_confirmations) {
+                Ok(_) => {
+                    transition(service, order.id, OrderStatus::BitcoinHtlcConfirmed, "HTLC funding confirmed").await?;
+                    Ok(Some(OrderStatus::BitcoinHtlcConfirmed))
+                }
+                Err(ApiError::Conflict { code, .. }) if code == "HTLC_FUNDING_UNCONFIRMED" => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+        OrderStatus::BitcoinHtlcConfirmed
+        | OrderStatus::FusionOrderFillable
+        | OrderStatus::FusionOrderFilling
+        | OrderStatus::FusionOrderFilled => {
+            if let Some(moved) = detect_spend(service, status, &htlc).await? {
+                return Ok(Some(moved));
+            }
+            refund_if_expired(service, &htlc, tip).await
+        }
+        OrderStatus::PreimageRevealed => claim_with_revealed_preimage(service, &htlc).await,
+        OrderStatus::BitcoinHtlcClaimed => complete_if_claim_confirmed(service, order, &htlc, tip).await,
+        _ => Ok(None),
+    }
+}
+
+pub(super) async fn transition(
+    service: &OrderService,
+    order_id: Uuid,
+    to: OrderStatus,
+    cause: &str,
+) -> Result<(), ApiError> {
+    let mut conn = service.pool().acquire().await?;
+    transition_order(&mut conn, order_id, to, &format!("orchestrator: {}", cause)).await?;
+    info!("Order {} moved to {}: {}", order_id, to.as_str(), cause);
+    Ok(())
+}
+
+/// Record the output paying the HTLC address that covers the order amount
+///
+/// The output is picked by [`select_funding_utxo`] with one confirmation, under the
+/// same amount and ambiguity rules as a funding verified through the API. Without an
+/// order amount a funding cannot be told apart from any other payment to the address,
+/// so it has to be verified explicitly.
+async fn detect_funding(
+    service: &OrderService,
+    order: &Order,
+    htlc: &HtlcRecord,
+    tip: u32,
+) -> Result<Option<OrderStatus>, ApiError> {
+    let utxos = service.bitcoin_client().get_utxos(&htlc.address).await?;
+    if utxos.is_empty() {
+        return Ok(None);
+    }
+    let Some(required) = htlc.amount.or(order.bitcoin_amount.map(|amount| amount as u64)) else {
+        warn!("HTLC {} of order {} was paid, but the order has no amount to detect its funding by", htlc.id, order.id);
+        return Ok(None);
+    };
+
+    match select_funding_utxo(&utxos, htlc.funding_outpoint, Some(required), tip, 1) {
+        Ok((outpoint, amount)) => {
+            service.record_htlc_funding(htlc, outpoint, amount.to_sat()).await?;
+            Ok(Some(OrderStatus::BitcoinHtlcFunded))
+        }
+        Err(ApiError::BadRequest { code, .. }) if code == "HTLC_NOT_FUNDED" => {
+            warn!("HTLC {} of order {} is underfunded, waiting for {} sat", htlc.id, order.id, required);
+            Ok(None)
+        }
+        Err(ApiError::Conflict { code, .. }) if code == "HTLC_FUNDING_UNCONFIRMED" => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Pick up a claim or refund of the HTLC that someone else broadcast
+///
+/// A claim by the user is how a BTC_TO_ETH resolver learns the preimage.
+pub(super) async fn detect_spend(
+    service: &OrderService,
+    status: OrderStatus,
+    htlc: &HtlcRecord,
+) -> Result<Option<OrderStatus>, ApiError> {
+    Ok(match service.watch_htlc_spend(htlc.id).await? {
+        Some(HtlcSpend::Claim { .. }) if status.can_transition_to(OrderStatus::PreimageRevealed) => {
+            Some(OrderStatus::PreimageRevealed)
+        }
+        Some(HtlcSpend::Claim { .. }) if status.can_transition_to(OrderStatus::BitcoinHtlcClaimed) => {
+            Some(OrderStatus::BitcoinHtlcClaimed)
+        }
+        Some(HtlcSpend::Refund) if status.can_transition_to(OrderStatus::Expired) => Some(OrderStatus::Expired),
+        _ => None,
+    })
+}
+
+/// Refund once the timeout passed, if the service holds the sender key
+async fn refund_if_expired(
+    service: &OrderService,
+    htlc: &HtlcRecord,
+    tip: u32,
+) -> Result<Option<OrderStatus>, ApiError> {
+    if tip < htlc.params.timeout {
+        return Ok(None);
+    }
+    let holds_sender_key = service
+        .resolver_private_key()
+        .is_some_and(|key| key.public_key(&Secp256k1::new()) == htlc.params.sender_pubkey);
+    if !holds_sender_key {
+        return Ok(None);
+    }
+
+    let refund = service.refund_htlc(htlc.id).await?;
+    info!("Refunded expired HTLC {} in {}", htlc.id, refund.transaction_id);
+    Ok(Some(OrderStatus::Expired))
+}
+
+/// Sweep the HTLC with a preimage that became public, if the service holds the recipient key
+async fn claim_with_revealed_preimage(
+    service: &OrderService,
+    htlc: &HtlcRecord,
+) -> Result<Option<OrderStatus>, ApiError> {
+    let Some(ref preimage) = htlc.preimage else {
+        return Ok(None);
+    };
+    let holds_recipient_key = service
+        .resolver_private_key()
+        .is_some_and(|key| key.public_key(&Secp256k1::new()) == htlc.params.recipient_pubkey);
+    if !holds_recipient_key || htlc.claim_txid.is_some() {
+        return Ok(None);
+    }
+
+    let claim = service
+        .claim_htlc(
+            htlc.id,
+            ClaimRequest {
+                preimage: hex::encode(preimage),
+                bitcoin_tx_hex: None,
+            },
+        )
+        .await?;
+    info!("Claimed HTLC {} in {}", htlc.id, claim.transaction_id);
+    Ok(Some(OrderStatus::BitcoinHtlcClaimed))
+}
+
+async fn complete_if_claim_confirmed(
+    service: &OrderService,
+    order: &Order,
+    htlc: &HtlcRecord,
+    tip: u32,
+) -> Result<Option<OrderStatus>, ApiError> {
+    let Some(claim_txid) = htlc.claim_txid.as_deref().or(order.htlc_claim_tx.as_deref()) else {
+        return Ok(None);
+    };
+
+    let status = service.bitcoin_client().get_transaction(claim_txid).await?.status;
+    let confirmations = match (status.confirmed, status.block_height) {
+        (true, Some(height)) => tip.saturating_sub(height) + 1,
+        (true, None) => 1,
+        (false, _) => 0,
+    };
+    if confirmations < htlc.min_confirmations.max(1) {
+        return Ok(None);
+    }
+
+    transition(service, order.id, OrderStatus::Completed, "claim confirmed").await?;
+    Ok(Some(OrderStatus::Completed))
+}
@@ -0,0 +1,174 @@
+use super::advance_order::{detect_spend, transition};
+use crate::models::*;
+use crate::repository::WatchlistRepository;
+use crate::services::order::claim_htlc::expected_funding_amount;
+use crate::services::order::OrderService;
+use log::warn;
+use std::str::FromStr;
+
+/// Move an order along with what the watchlist saw happen to its HTLC
+///
+/// A `funded` event records the funding if it covers the order amount, and is ignored
+/// for an order without one; `confirmed` confirms the order once that funding is deep
+/// enough, `spent` picks up the claim or refund and `reorged` rolls back what rested on
+/// the lost block. Events only speed orders up: [`advance_order`](super::advance_order)
+/// and the confirmation tracking find the same changes by polling, so an event that
+/// finds the order in another status is ignored. Returns the status the order moved to.
+pub async fn apply_watch_event(
+    service: &OrderService,
+    order: &Order,
+    htlc: &HtlcRecord,
+    event: &WatchEvent,
+) -> Result<Option<OrderStatus>, ApiError> {
+    let status = order_status(order)?;
+    match event.kind {
+        WatchEventKind::Funded if status == OrderStatus::BitcoinHtlcCreated => {
+            let watch = WatchlistRepository::new(service.pool().clone()).find(event.watch_id).await?;
+            let (Some(outpoint), Some(amount)) = (watch.funding_outpoint, watch.funding_amount) else {
+                return Ok(None);
+            };
+            // A reorg later in the same scan may have taken the funding away again
+            if outpoint.txid.to_string() != event.txid {
+                return Ok(None);
+            }
+            let Some(required) = expected_funding_amount(service.pool(), htlc).await? else {
+                warn!("HTLC {} of order {} was paid, but the order has no amount to detect its funding by", htlc.id, order.id);
+                return Ok(None);
+            };
+            if amount < required {
+                warn!("HTLC {} of order {} is underfunded, waiting for {} sat", htlc.id, order.id, required);
+                return Ok(None);
+            }
+            service.record_htlc_funding(htlc, outpoint, amount).await?;
+            Ok(Some(OrderStatus::BitcoinHtlcFunded))
+        }
+        WatchEventKind::Confirmed if status == OrderStatus::BitcoinHtlcFunded => {
+            if htlc.funding_outpoint.map(|outpoint| outpoint.txid.to_string()).as_ref() != Some(&event.txid) {
+                return Ok(None);
+            }
+            transition(service, order.id, OrderStatus::BitcoinHtlcConfirmed, "HTLC funding confirmed").await?;
+            Ok(Some(OrderStatus::BitcoinHtlcConfirmed))
+        }
+        WatchEventKind::Spent => detect_spend(service, status, htlc).await,
+        WatchEventKind::Reorged => {
+            service.track_confirmations(htlc.id).await?;
+            let reloaded: Order = sqlx::query_as("SELECT * FROM orders WHERE id = ?")
+                .bind(order.id)
+                .fetch_one(service.pool())
+                .await?;
+            let moved = order_status(&reloaded)?;
+            Ok((moved != status).then_some(moved))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn order_status(order: &Order) -> Result<OrderStatus, ApiError> {
+    OrderStatus::from_str(&order.status).map_err(|message| ApiError::InternalError {
+        code: "ORDER_CORRUPT".to_string(),
+        message: format!("Order {}: {}", order.id, message),
+        details: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::HtlcRepository;
+    use crate::services::bitcoin::{BitcoinClient, SimulatedChain};
+    use crate::services::build_htlc_script;
+    use crate::test_support::{htlc_params, insert_order, test_pool};
+    use bitcoin::{Address, Amount, Network};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    /// Order for 100 000 sat waiting for the funding of a watched HTLC
+    async fn setup() -> (OrderService, Arc<SimulatedChain>, HtlcRecord) {
+        let pool = test_pool().await;
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::BitcoinHtlcCreated).await;
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let mut htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        htlc.order_id = Some(order_id);
+        htlc.min_confirmations = 2;
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+        let script_pubkey = Address::from_str(&htlc.address).unwrap().assume_checked().script_pubkey();
+        WatchlistRepository::new(pool.clone())
+            .insert(&WatchedScript::new(script_pubkey, 2, Some(htlc.id)))
+            .await
+            .unwrap();
+
+        let chain = Arc::new(SimulatedChain::new(Network::Testnet, 2_500_000));
+        let service = OrderService::new(pool, BitcoinClient::from_arc(chain.clone()));
+        // The first scan only places the cursor at the tip
+        assert!(service.scan_watchlist().await.unwrap().is_empty());
+        (service, chain, htlc)
+    }
+
+    /// Scan the new blocks and apply every event to the order, as the orchestrator does
+    async fn scan_and_apply(service: &OrderService, htlc_id: Uuid) -> Vec<(WatchEventKind, Option<OrderStatus>)> {
+        let mut applied = Vec::new();
+        for event in service.scan_watchlist().await.unwrap() {
+            let htlc = HtlcRepository::new(service.pool().clone()).find(htlc_id).await.unwrap();
+            let order: Order = sqlx::query_as("SELECT * FROM orders WHERE id = ?")
+                .bind(htlc.order_id)
+                .fetch_one(service.pool())
+                .await
+                .unwrap();
+            applied.push((event.kind, apply_watch_event(service, &order, &htlc, &event).await.unwrap()));
+        }
+        applied
+    }
+
+    #[tokio::test]
+    async fn test_watch_events_fund_confirm_and_roll_back_the_order() {
+        let (service, chain, htlc) = setup().await;
+        chain.fund(&Address::from_str(&htlc.address).unwrap().assume_checked(), Amount::from_sat(100_000));
+        chain.mine(1);
+        assert_eq!(
+            scan_and_apply(&service, htlc.id).await,
+            vec![(WatchEventKind::Funded, Some(OrderStatus::BitcoinHtlcFunded))]
+        );
+
+        chain.mine(1);
+        assert_eq!(
+            scan_and_apply(&service, htlc.id).await,
+            vec![(WatchEventKind::Confirmed, Some(OrderStatus::BitcoinHtlcConfirmed))]
+        );
+        // Where the funding confirmed is recorded later in the same tick
+        service.track_confirmations(htlc.id).await.unwrap();
+
+        // Both blocks are replaced and the funding is back in the mempool
+        chain.reorg(2);
+        assert_eq!(
+            scan_and_apply(&service, htlc.id).await,
+            vec![(WatchEventKind::Reorged, Some(OrderStatus::BitcoinHtlcFunded))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_underfunded_htlc_leaves_the_order_waiting() {
+        let (service, chain, htlc) = setup().await;
+        chain.fund(&Address::from_str(&htlc.address).unwrap().assume_checked(), Amount::from_sat(60_000));
+        chain.mine(1);
+
+        assert_eq!(scan_and_apply(&service, htlc.id).await, vec![(WatchEventKind::Funded, None)]);
+        let order = crate::services::order::get_order(service.pool(), htlc.order_id.unwrap()).await.unwrap();
+        assert_eq!(order.status, OrderStatus::BitcoinHtlcCreated);
+    }
+
+    #[tokio::test]
+    async fn test_funding_of_an_order_without_amount_is_not_detected() {
+        let (service, chain, htlc) = setup().await;
+        sqlx::query("UPDATE orders SET bitcoin_amount = NULL WHERE id = ?")
+            .bind(htlc.order_id)
+            .execute(service.pool())
+            .await
+            .unwrap();
+        chain.fund(&Address::from_str(&htlc.address).unwrap().assume_checked(), Amount::from_sat(1_000));
+        chain.mine(1);
+
+        assert_eq!(scan_and_apply(&service, htlc.id).await, vec![(WatchEventKind::Funded, None)]);
+        let stored = HtlcRepository::new(service.pool().clone()).find(htlc.id).await.unwrap();
+        assert!(stored.funding_outpoint.is_none());
+    }
+}
@@ -0,0 +1,88 @@
+use crate::models::ApiError;
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Take the lease on an order for `lease`
+///
+/// Succeeds if the order is unlocked, its lease ran out (the holder died), or this
+/// instance already holds it. The check and the write are one statement, so two
+/// instances racing for the same order cannot both win.
+pub async fn lock_order(
+    pool: &SqlitePool,
+    order_id: Uuid,
+    instance_id: &str,
+    lease: Duration,
+) -> Result<bool, ApiError> {
+    let now = Utc::now().timestamp();
+    let result = sqlx::query(
+        r#"
+        UPDATE orders SET locked_by = ?, locked_until = ?
+        WHERE id = ? AND (locked_by IS NULL OR locked_by = ? OR locked_until < ?)
+        "#,
+    )
+    .bind(instance_id)
+    .bind(now + lease.as_secs() as i64)
+    .bind(order_id)
+    .bind(instance_id)
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Give the lease back if this instance still holds it
+pub async fn unlock_order(
+    pool: &SqlitePool,
+    order_id: Uuid,
+    instance_id: &str,
+) -> Result<(), ApiError> {
+    sqlx::query("UPDATE orders SET locked_by = NULL, locked_until = NULL WHERE id = ? AND locked_by = ?")
+        .bind(order_id)
+        .bind(instance_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{OrderStatus, SwapDirection};
+    use crate::test_support::{insert_order, test_pool};
+
+    #[tokio::test]
+    async fn test_lock_is_exclusive_until_released() {
+        let pool = test_pool().await;
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::Created).await;
+        let lease = Duration::from_secs(60);
+
+        assert!(lock_order(&pool, order_id, "a", lease).await.unwrap());
+        assert!(!lock_order(&pool, order_id, "b", lease).await.unwrap());
+        // Re-entrant for the holder, which extends the lease
+        assert!(lock_order(&pool, order_id, "a", lease).await.unwrap());
+
+        // Only the holder can release
+        unlock_order(&pool, order_id, "b").await.unwrap();
+        assert!(!lock_order(&pool, order_id, "b", lease).await.unwrap());
+        unlock_order(&pool, order_id, "a").await.unwrap();
+        assert!(lock_order(&pool, order_id, "b", lease).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_expired_lease_can_be_taken_over() {
+        let pool = test_pool().await;
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::Created).await;
+
+        sqlx::query("UPDATE orders SET locked_by = 'crashed', locked_until = ? WHERE id = ?")
+            .bind(Utc::now().timestamp() - 1)
+            .bind(order_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(lock_order(&pool, order_id, "b", Duration::from_secs(60)).await.unwrap());
+    }
+}
@@ -0,0 +1,542 @@
+pub mod advance_order;
+pub mod apply_watch_event;
+pub mod lock_order;
+
+// Re-export functions for easy access
+pub use advance_order::advance_order;
+pub use apply_watch_event::apply_watch_event;
+pub use lock_order::{lock_order, unlock_order};
+
+use crate::models::{ApiError, Order, OrderStatus, WatchEvent};
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::ChainEvent;
+use crate::services::order::track_confirmations::FINALITY_DEPTH;
+use crate::services::order::OrderService;
+use crate::services::webhooks;
+use bitcoin::{Address, OutPoint, ScriptBuf, Transaction};
+use log::{error, info, warn};
+use sqlx::Row;
+use std::collections::HashSet;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Background task driving active orders through their lifecycle
+///
+/// All progress lives in the database, so a restarted instance picks up where the
+/// previous one stopped. Orders are leased one at a time, so several instances can
+/// share a database without acting on the same order twice.
+///
+/// With [`with_chain_events`](Self::with_chain_events) a tick also runs as soon as a
+/// block arrives or a transaction touches an HTLC, instead of on the next interval.
+#[derive(Clone)]
+pub struct Orchestrator {
+    order_service: OrderService,
+    instance_id: String,
+    interval: Duration,
+    lease: Duration,
+    chain_events: Option<broadcast::Sender<ChainEvent>>,
+    /// Posts webhook notifications, with a timeout so a slow receiver can't hold up a tick
+    webhook_client: reqwest::Client,
+    /// What chain events are matched against, `None` until loaded or after a failed refresh
+    open_htlcs: Arc<RwLock<Option<OpenHtlcs>>>,
+}
+
+/// Scripts and funding outpoints of the HTLCs still waiting for a funding or a spend
+#[derive(Debug, Default)]
+struct OpenHtlcs {
+    script_pubkeys: HashSet<ScriptBuf>,
+    funding_outpoints: HashSet<OutPoint>,
+}
+
+impl OpenHtlcs {
+    fn touched_by(&self, tx: &Transaction) -> bool {
+        tx.output.iter().any(|output| self.script_pubkeys.contains(&output.script_pubkey))
+            || tx.input.iter().any(|input| self.funding_outpoints.contains(&input.previous_output))
+    }
+}
+
+impl Orchestrator {
+    pub fn new(order_service: OrderService) -> Self {
+        let interval = env::var("ORCHESTRATOR_INTERVAL_SECS").ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(30));
+
+        Self {
+            order_service,
+            instance_id: env::var("ORCHESTRATOR_INSTANCE_ID")
+                .unwrap_or_else(|_| Uuid::new_v4().to_string()),
+            interval,
+            // Long enough for a step that broadcasts, short enough to recover from a crash quickly
+            lease: Duration::from_secs(120),
+            chain_events: None,
+            webhook_client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("Failed to create webhook HTTP client"),
+            open_htlcs: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Tick on chain notifications too, e.g. from a [`ZmqSubscriber`](crate::services::bitcoin::ZmqSubscriber)
+    pub fn with_chain_events(mut self, chain_events: broadcast::Sender<ChainEvent>) -> Self {
+        self.chain_events = Some(chain_events);
+        self
+    }
+
+    pub fn with_instance_id(mut self, instance_id: impl Into<String>) -> Self {
+        self.instance_id = instance_id.into();
+        self
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Run ticks until the task is aborted
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let mut chain_events = self.chain_events.as_ref().map(broadcast::Sender::subscribe);
+            loop {
+                // Picks up the HTLCs opened, funded and spent by the previous tick
+                if chain_events.is_some() {
+                    self.refresh_open_htlcs().await;
+                }
+                match chain_events.as_mut() {
+                    Some(events) => {
+                        let wake = tokio::select! {
+                            _ = interval.tick() => true,
+                            event = events.recv() => match event {
+                                Ok(event) => self.wakes_on(&event),
+                                // Events were dropped, one of them may have mattered
+                                Err(RecvError::Lagged(_)) => true,
+                                Err(RecvError::Closed) => {
+                                    chain_events = None;
+                                    false
+                                }
+                            },
+                        };
+                        if !wake {
+                            continue;
+                        }
+                        interval.reset();
+                    }
+                    None => {
+                        interval.tick().await;
+                    }
+                }
+                if let Err(e) = self.tick().await {
+                    error!("Orchestrator tick failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Whether a chain notification can move an order: blocks, address activity and
+    /// lost notifications always can, a transaction only if it pays or spends an open HTLC
+    ///
+    /// Matches against the HTLCs open as of the last refresh, so a busy mempool doesn't
+    /// cost a query per transaction. An HTLC opened since wakes on the next block instead.
+    fn wakes_on(&self, event: &ChainEvent) -> bool {
+        let ChainEvent::Transaction(tx) = event else {
+            return true;
+        };
+        match self.open_htlcs.read().expect("open HTLCs lock poisoned").as_ref() {
+            Some(open_htlcs) => open_htlcs.touched_by(tx),
+            // Without a view of the open HTLCs any transaction may matter
+            None => true,
+        }
+    }
+
+    /// Reload the scripts and funding outpoints chain events are matched against
+    async fn refresh_open_htlcs(&self) {
+        let open_htlcs = match HtlcRepository::new(self.order_service.pool().clone()).find_open().await {
+            Ok(htlcs) => Some(OpenHtlcs {
+                script_pubkeys: htlcs
+                    .iter()
+                    .filter_map(|htlc| Address::from_str(&htlc.address).ok())
+                    .map(|address| address.assume_checked().script_pubkey())
+                    .collect(),
+                funding_outpoints: htlcs.iter().filter_map(|htlc| htlc.funding_outpoint).collect(),
+            }),
+            Err(e) => {
+                warn!("Open HTLCs could not be loaded, waking on every transaction: {}", e);
+                None
+            }
+        };
+        *self.open_htlcs.write().expect("open HTLCs lock poisoned") = open_htlcs;
+    }
+
+    /// Scan new blocks for watched scripts, catch up with reorgs, try to advance every
+    /// active order once, bump stuck spends and post webhook notifications, returning
+    /// how many orders moved
+    pub async fn tick(&self) -> Result<usize, ApiError> {
+        self.scan_watchlist().await;
+        self.track_confirmations().await?;
+        let advanced = self.advance_orders().await?;
+        self.bump_spends().await?;
+        self.deliver_webhooks().await;
+        Ok(advanced)
+    }
+
+    async fn advance_orders(&self) -> Result<usize, ApiError> {
+        let pool = self.order_service.pool();
+        let order_ids: Vec<Uuid> = sqlx::query(
+            "SELECT id FROM orders WHERE status NOT IN (?, ?, ?) ORDER BY updated_at",
+        )
+        .bind(OrderStatus::Completed.as_str())
+        .bind(OrderStatus::Expired.as_str())
+        .bind(OrderStatus::Failed.as_str())
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("id"))
+        .collect::<Result<_, _>>()?;
+        if order_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let tip = self.order_service.bitcoin_client().get_block_height().await?;
+        let mut advanced = 0;
+        for order_id in order_ids {
+            if !lock_order(pool, order_id, &self.instance_id, self.lease).await? {
+                continue;
+            }
+
+            // Reload under the lock, another instance may have moved the order meanwhile
+            let result = match sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE id = ?")
+                .bind(order_id)
+                .fetch_one(pool)
+                .await
+            {
+                Ok(order) => advance_order(&self.order_service, &order, tip).await,
+                Err(e) => Err(e.into()),
+            };
+            unlock_order(pool, order_id, &self.instance_id).await?;
+
+            match result {
+                Ok(Some(_)) => advanced += 1,
+                Ok(None) => {}
+                Err(e) => warn!("Order {} could not be advanced: {}", order_id, e),
+            }
+        }
+
+        Ok(advanced)
+    }
+
+    /// Record fundings, spends and reorgs of watched scripts, HTLC addresses among them,
+    /// and move the orders of those HTLCs along
+    ///
+    /// Runs first, so the order steps of the same tick see the blocks it scanned. A
+    /// backend that can't serve blocks only leaves the watchlist behind, orders still move.
+    async fn scan_watchlist(&self) {
+        let events = match self.order_service.scan_watchlist().await {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Watchlist could not be scanned: {}", e);
+                return;
+            }
+        };
+        for event in &events {
+            if let Err(e) = self.apply_watch_event(event).await {
+                warn!("{} event of watched script {} could not be applied: {}", event.kind.as_str(), event.watch_id, e);
+            }
+        }
+    }
+
+    /// Apply a watch event to the order of its HTLC, under the order's lease
+    ///
+    /// An order leased by another instance is skipped; polling catches it up.
+    async fn apply_watch_event(&self, event: &WatchEvent) -> Result<(), ApiError> {
+        let Some(htlc_id) = event.htlc_id else {
+            return Ok(());
+        };
+        let pool = self.order_service.pool();
+        let repository = HtlcRepository::new(pool.clone());
+        let Some(order_id) = repository.find(htlc_id).await?.order_id else {
+            return Ok(());
+        };
+        if !lock_order(pool, order_id, &self.instance_id, self.lease).await? {
+            return Ok(());
+        }
+
+        // Reload under the lock, like the order steps do
+        let result = async {
+            let order = sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE id = ?")
+                .bind(order_id)
+                .fetch_one(pool)
+                .await?;
+            let htlc = repository.find(htlc_id).await?;
+            apply_watch_event(&self.order_service, &order, &htlc, event).await
+        }
+        .await;
+        unlock_order(pool, order_id, &self.instance_id).await?;
+
+        if let Some(status) = result? {
+            info!("Order {} moved to {} on {} {}", order_id, status.as_str(), event.kind.as_str(), event.txid);
+        }
+        Ok(())
+    }
+
+    /// Post due webhook notifications; an unreachable receiver only delays its own
+    async fn deliver_webhooks(&self) {
+        if let Err(e) = webhooks::deliver_webhooks(self.order_service.pool(), &self.webhook_client).await {
+            warn!("Webhooks could not be delivered: {}", e);
+        }
+    }
+
+    /// Check the blocks HTLC transactions confirmed in are still on the active chain
+    ///
+    /// Runs before orders advance, so an order whose funding was reorged out is rolled
+    /// back before anything acts on its confirmation.
+    async fn track_confirmations(&self) -> Result<(), ApiError> {
+        let pool = self.order_service.pool();
+        let tip = self.order_service.bitcoin_client().get_block_height().await?;
+        let htlcs = HtlcRepository::new(pool.clone())
+            .find_reorg_exposed(tip.saturating_sub(FINALITY_DEPTH))
+            .await?;
+        for htlc in htlcs {
+            if let Some(order_id) = htlc.order_id {
+                if !lock_order(pool, order_id, &self.instance_id, self.lease).await? {
+                    continue;
+                }
+            }
+            let result = self.order_service.track_confirmations(htlc.id).await;
+            if let Some(order_id) = htlc.order_id {
+                unlock_order(pool, order_id, &self.instance_id).await?;
+            }
+
+            if let Err(e) = result {
+                warn!("Confirmations of HTLC {} could not be tracked: {}", htlc.id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fee-bump claims and refunds still waiting for a confirmation
+    ///
+    /// Spends of an order are bumped under its lease, so two instances never both
+    /// replace the same spend.
+    async fn bump_spends(&self) -> Result<(), ApiError> {
+        let pool = self.order_service.pool();
+        let htlcs = HtlcRepository::new(pool.clone()).find_unconfirmed_spends().await?;
+        for htlc in htlcs {
+            if let Some(order_id) = htlc.order_id {
+                if !lock_order(pool, order_id, &self.instance_id, self.lease).await? {
+                    continue;
+                }
+            }
+            let result = self.order_service.bump_htlc_spend(htlc.id).await;
+            if let Some(order_id) = htlc.order_id {
+                unlock_order(pool, order_id, &self.instance_id).await?;
+            }
+
+            match result {
+                Ok(Some(replacement)) => info!(
+                    "Replaced {} {} of HTLC {} with {} at {} sat/vB",
+                    replacement.spend_path.as_str(),
+                    replacement.replaced_txid,
+                    htlc.id,
+                    replacement.txid,
+                    replacement.fee_rate
+                ),
+                Ok(None) => {}
+                Err(e) => warn!("Spend of HTLC {} could not be bumped: {}", htlc.id, e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{HtlcOutputType, HtlcRecord, SwapDirection};
+    use crate::repository::HtlcRepository;
+    use crate::services::bitcoin::{BitcoinClient, MockBackend, SimulatedChain};
+    use crate::services::build_htlc_script;
+    use crate::services::order::get_order;
+    use crate::services::transaction::create_claim_transaction;
+    use crate::test_support::{htlc_params, insert_order, key, test_pool, PREIMAGE};
+    use bitcoin::{consensus::encode::serialize_hex, Amount, Network};
+    use chrono::{Duration as ChronoDuration, Utc};
+    use sqlx::SqlitePool;
+    use std::sync::Arc;
+
+    const FUNDING_TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+    /// Order waiting in `status` on an HTLC that wants three confirmations
+    async fn order_with_htlc(pool: &SqlitePool, status: OrderStatus) -> (Uuid, HtlcRecord) {
+        let order_id = insert_order(pool, SwapDirection::EthToBtc, status).await;
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let mut htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        htlc.order_id = Some(order_id);
+        htlc.min_confirmations = 3;
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+        sqlx::query("UPDATE orders SET htlc_id = ?, htlc_address = ? WHERE id = ?")
+            .bind(htlc.id)
+            .bind(&htlc.address)
+            .bind(order_id)
+            .execute(pool)
+            .await
+            .unwrap();
+        (order_id, htlc)
+    }
+
+    async fn expired_order(pool: &SqlitePool) -> Uuid {
+        let order_id = insert_order(pool, SwapDirection::EthToBtc, OrderStatus::Created).await;
+        sqlx::query("UPDATE orders SET expires_at = ? WHERE id = ?")
+            .bind(Utc::now() - ChronoDuration::minutes(1))
+            .bind(order_id)
+            .execute(pool)
+            .await
+            .unwrap();
+        order_id
+    }
+
+    fn orchestrator(pool: SqlitePool, backend: &Arc<MockBackend>, instance_id: &str) -> Orchestrator {
+        let bitcoin_client = BitcoinClient::from_arc(backend.clone());
+        Orchestrator::new(OrderService::new(pool, bitcoin_client)).with_instance_id(instance_id)
+    }
+
+    #[tokio::test]
+    async fn test_tick_follows_funding_to_confirmation() {
+        let pool = test_pool().await;
+        let (order_id, htlc) = order_with_htlc(&pool, OrderStatus::BitcoinHtlcCreated).await;
+
+        let backend = Arc::new(MockBackend::new(2_500_000));
+        backend.add_utxo(&htlc.address, FUNDING_TXID, 0, 100_000, Some(2_499_999));
+        let orchestrator = orchestrator(pool.clone(), &backend, "a");
+
+        // Funding is seen, but has only two of three confirmations
+        assert_eq!(orchestrator.tick().await.unwrap(), 1);
+        assert_eq!(get_order(&pool, order_id).await.unwrap().status, OrderStatus::BitcoinHtlcFunded);
+        assert_eq!(orchestrator.tick().await.unwrap(), 0);
+
+        backend.set_block_height(2_500_001);
+        assert_eq!(orchestrator.tick().await.unwrap(), 1);
+        assert_eq!(get_order(&pool, order_id).await.unwrap().status, OrderStatus::BitcoinHtlcConfirmed);
+
+        let stored = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        assert_eq!(stored.funding_outpoint.unwrap().txid.to_string(), FUNDING_TXID);
+    }
+
+    #[tokio::test]
+    async fn test_tick_picks_up_a_claim_before_confirmation() {
+        let pool = test_pool().await;
+        let (order_id, htlc) = order_with_htlc(&pool, OrderStatus::BitcoinHtlcCreated).await;
+
+        let chain = Arc::new(SimulatedChain::new(Network::Testnet, 2_500_000));
+        let address = Address::from_str(&htlc.address).unwrap().assume_checked();
+        let outpoint = chain.fund(&address, Amount::from_sat(100_000));
+        chain.mine(1);
+        let client = BitcoinClient::from_arc(chain.clone());
+        let orchestrator = Orchestrator::new(OrderService::new(pool.clone(), client.clone())).with_instance_id("a");
+
+        assert_eq!(orchestrator.tick().await.unwrap(), 1);
+        assert_eq!(get_order(&pool, order_id).await.unwrap().status, OrderStatus::BitcoinHtlcFunded);
+
+        // Claimed with one of the three confirmations the HTLC wants
+        let claim = create_claim_transaction(
+            outpoint,
+            Amount::from_sat(100_000),
+            &build_htlc_script(&htlc.params).unwrap(),
+            &PREIMAGE,
+            &key(1).inner,
+            &address,
+            Amount::from_sat(1_000),
+        )
+        .unwrap();
+        client.broadcast_transaction(&serialize_hex(&claim)).await.unwrap();
+
+        assert_eq!(orchestrator.tick().await.unwrap(), 1);
+        assert_eq!(get_order(&pool, order_id).await.unwrap().status, OrderStatus::BitcoinHtlcClaimed);
+        let stored = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        assert_eq!(stored.preimage.as_deref(), Some(&PREIMAGE[..]));
+    }
+
+    #[tokio::test]
+    async fn test_tick_does_not_detect_funding_without_an_amount() {
+        let pool = test_pool().await;
+        let (order_id, htlc) = order_with_htlc(&pool, OrderStatus::BitcoinHtlcCreated).await;
+        sqlx::query("UPDATE orders SET bitcoin_amount = NULL WHERE id = ?")
+            .bind(order_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let backend = Arc::new(MockBackend::new(2_500_000));
+        backend.add_utxo(&htlc.address, FUNDING_TXID, 0, 1_000, Some(2_499_999));
+
+        assert_eq!(orchestrator(pool.clone(), &backend, "a").tick().await.unwrap(), 0);
+        assert_eq!(get_order(&pool, order_id).await.unwrap().status, OrderStatus::BitcoinHtlcCreated);
+        assert!(HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap().funding_outpoint.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_chain_events_wake_only_for_htlc_transactions() {
+        use bitcoin::{absolute::LockTime, transaction::Version, Amount, OutPoint, ScriptBuf, TxIn, TxOut, Txid};
+
+        let pool = test_pool().await;
+        let (_, htlc) = order_with_htlc(&pool, OrderStatus::BitcoinHtlcCreated).await;
+        let orchestrator = orchestrator(pool.clone(), &Arc::new(MockBackend::new(2_500_000)), "a");
+        let transaction = |input: OutPoint, script_pubkey: ScriptBuf| {
+            ChainEvent::Transaction(Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![TxIn { previous_output: input, ..Default::default() }],
+                output: vec![TxOut { value: Amount::from_sat(100_000), script_pubkey }],
+            })
+        };
+        let htlc_script = Address::from_str(&htlc.address).unwrap().assume_checked().script_pubkey();
+        let funding = OutPoint::new(Txid::from_str(FUNDING_TXID).unwrap(), 0);
+
+        // Nothing loaded yet, every transaction may matter
+        assert!(orchestrator.wakes_on(&transaction(OutPoint::null(), ScriptBuf::new())));
+
+        orchestrator.refresh_open_htlcs().await;
+        assert!(orchestrator.wakes_on(&ChainEvent::Resync));
+        assert!(orchestrator.wakes_on(&transaction(OutPoint::null(), htlc_script)));
+        assert!(!orchestrator.wakes_on(&transaction(funding, ScriptBuf::new())));
+
+        // The funding is only matched once the open HTLCs are refreshed
+        HtlcRepository::new(pool.clone()).record_funding(htlc.id, funding, 100_000).await.unwrap();
+        assert!(!orchestrator.wakes_on(&transaction(funding, ScriptBuf::new())));
+        orchestrator.refresh_open_htlcs().await;
+        assert!(orchestrator.wakes_on(&transaction(funding, ScriptBuf::new())));
+        assert!(!orchestrator.wakes_on(&transaction(OutPoint::null(), ScriptBuf::new())));
+    }
+
+    #[tokio::test]
+    async fn test_tick_expires_stale_orders_and_skips_locked_ones() {
+        let pool = test_pool().await;
+        let stale = expired_order(&pool).await;
+        let locked = expired_order(&pool).await;
+        let fresh = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::Created).await;
+        assert!(lock_order(&pool, locked, "b", Duration::from_secs(60)).await.unwrap());
+
+        let backend = Arc::new(MockBackend::new(2_500_000));
+
+        assert_eq!(orchestrator(pool.clone(), &backend, "a").tick().await.unwrap(), 1);
+        assert_eq!(get_order(&pool, stale).await.unwrap().status, OrderStatus::Expired);
+        assert_eq!(get_order(&pool, locked).await.unwrap().status, OrderStatus::Created);
+        assert_eq!(get_order(&pool, fresh).await.unwrap().status, OrderStatus::Created);
+
+        // The lease of the other instance is untouched
+        let locked_by: Option<String> = sqlx::query("SELECT locked_by FROM orders WHERE id = ?")
+            .bind(locked)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .try_get("locked_by")
+            .unwrap();
+        assert_eq!(locked_by.as_deref(), Some("b"));
+    }
+}
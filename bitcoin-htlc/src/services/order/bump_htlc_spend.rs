@@ -0,0 +1,257 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::{BitcoinClient, FeeEstimates, TransactionInfo};
+use crate::services::order::claim_htlc::wallet_address;
+use crate::services::transaction::{htlc_claim_vsize, htlc_refund_vsize};
+use crate::services::{build_htlc_script, create_claim_transaction_at_fee_rate, create_refund_transaction_at_fee_rate};
+use bitcoin::{consensus::encode::serialize_hex, key::Secp256k1, Amount, FeeRate, Network, OutPoint, PrivateKey, Sequence, Txid};
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Bitcoin Core's default `-incrementalrelayfee` in sat/vB, what a replacement pays on top
+const INCREMENTAL_RELAY_FEE: u64 = 1;
+
+/// Refunds have no deadline of their own; their fee escalates over the day after the
+/// timeout, while the counterparty can still claim with a preimage it learned late
+const REFUND_ESCALATION_BLOCKS: u32 = 144;
+
+/// Replace a stuck claim or refund the service signed with one paying a higher fee
+///
+/// The fee rate follows [`spend_fee_rate`] for the blocks left before the timeout, so a
+/// claim is bumped harder the closer the sender gets to refunding. Replacements keep the
+/// BIP125 rules: they only replace spends that signal RBF and pay the replaced fee plus
+/// the incremental relay fee on their own size. Every replacement is recorded against
+/// the HTLC. Returns `None` when there is nothing to bump, and marks spends found
+/// confirmed so they are no longer watched.
+pub async fn bump_htlc_spend(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    network: Network,
+    spend_key: Option<&PrivateKey>,
+    htlc_id: Uuid,
+) -> Result<Option<HtlcReplacement>, ApiError> {
+    let repository = HtlcRepository::new(pool.clone());
+    let htlc = repository.find(htlc_id).await?;
+    let (path, txid, signer) = match (&htlc.claim_txid, &htlc.refund_txid) {
+        (Some(txid), _) => (HtlcSpendPath::Claim, txid.clone(), htlc.params.recipient_pubkey),
+        (None, Some(txid)) => (HtlcSpendPath::Refund, txid.clone(), htlc.params.sender_pubkey),
+        (None, None) => return Ok(None),
+    };
+
+    let current = match bitcoin_client.get_transaction(&txid).await {
+        Ok(current) => Some(current),
+        // Dropped from mempools, rebroadcast at the scheduled rate
+        Err(ApiError::NotFound { .. }) => None,
+        Err(e) => return Err(e),
+    };
+    if let Some(height) = current.as_ref().and_then(|current| current.status.block_height) {
+        repository.record_spend_confirmed(htlc.id, height).await?;
+        return Ok(None);
+    }
+
+    // Only spends the service signed to its own wallet are re-signed, never redirected
+    let Some(spend_key) = spend_key.filter(|key| key.public_key(&Secp256k1::new()) == signer) else {
+        return Ok(None);
+    };
+    let wallet = wallet_address(spend_key, network)?;
+    if let Some(ref current) = current {
+        let pays_wallet = current.vout.len() == 1
+            && current.vout[0].scriptpubkey_address.as_deref() == Some(wallet.to_string().as_str());
+        if !pays_wallet || !signals_rbf(current) {
+            return Ok(None);
+        }
+    }
+    let Some((outpoint, htlc_amount)) = spent_output(current.as_ref(), &htlc)? else {
+        return Ok(None);
+    };
+
+    let htlc_script = build_htlc_script(&htlc.params)?;
+    let destination = wallet.script_pubkey();
+    let preimage = match path {
+        HtlcSpendPath::Claim => Some(htlc.preimage.clone().ok_or_else(|| ApiError::InternalError {
+            code: "HTLC_CORRUPT".to_string(),
+            message: format!("HTLC {} records a claim without its preimage", htlc.id),
+            details: None,
+        })?),
+        HtlcSpendPath::Refund => None,
+    };
+    let vsize = match preimage {
+        Some(ref preimage) => htlc_claim_vsize(&htlc_script, preimage.len(), &destination)?,
+        None => htlc_refund_vsize(&htlc_script, &destination)?,
+    };
+
+    let tip = bitcoin_client.get_block_height().await?;
+    let blocks_left = match path {
+        HtlcSpendPath::Claim => htlc.params.timeout.saturating_sub(tip),
+        HtlcSpendPath::Refund => REFUND_ESCALATION_BLOCKS.saturating_sub(tip.saturating_sub(htlc.params.timeout)),
+    };
+    let target = spend_fee_rate(&bitcoin_client.get_fee_estimates().await?, blocks_left).to_sat_per_vb_ceil();
+    let replaced_fee = current.as_ref().map_or(0, |current| current.fee);
+    if target * vsize <= replaced_fee {
+        return Ok(None);
+    }
+    let fee_rate = target.max((replaced_fee + INCREMENTAL_RELAY_FEE * vsize).div_ceil(vsize));
+
+    let transaction = match preimage {
+        Some(ref preimage) => create_claim_transaction_at_fee_rate(
+            outpoint,
+            htlc_amount,
+            &htlc_script,
+            preimage,
+            &spend_key.inner,
+            &wallet,
+            FeeRate::from_sat_per_vb_unchecked(fee_rate),
+        )?,
+        None => create_refund_transaction_at_fee_rate(
+            outpoint,
+            htlc_amount,
+            &htlc_script,
+            &spend_key.inner,
+            &wallet,
+            htlc.params.timeout,
+            FeeRate::from_sat_per_vb_unchecked(fee_rate),
+        )?,
+    };
+    let new_txid = bitcoin_client.broadcast_transaction(&serialize_hex(&transaction)).await?;
+
+    let replacement = HtlcReplacement {
+        htlc_id: htlc.id,
+        spend_path: path,
+        replaced_txid: txid,
+        txid: new_txid,
+        fee: htlc_amount.to_sat() - transaction.output[0].value.to_sat(),
+        fee_rate,
+        block_height: tip,
+        created_at: Utc::now(),
+    };
+    repository.record_replacement(&replacement).await?;
+    if let Some(order_id) = htlc.order_id {
+        let sql = match path {
+            HtlcSpendPath::Claim => "UPDATE orders SET htlc_claim_tx = ? WHERE id = ?",
+            HtlcSpendPath::Refund => "UPDATE orders SET htlc_refund_tx = ? WHERE id = ?",
+        };
+        sqlx::query(sql).bind(&replacement.txid).bind(order_id).execute(pool).await?;
+    }
+
+    Ok(Some(replacement))
+}
+
+/// Fee rate a spend should pay with `blocks_left` before its deadline
+///
+/// The half hour estimate while there is time, then the fastest estimate, doubled and
+/// quadrupled over the last blocks.
+pub(crate) fn spend_fee_rate(estimates: &FeeEstimates, blocks_left: u32) -> FeeRate {
+    let sat_per_vb = match blocks_left {
+        72.. => estimates.half_hour,
+        12..=71 => estimates.fastest,
+        3..=11 => estimates.fastest.saturating_mul(2),
+        _ => estimates.fastest.saturating_mul(4),
+    };
+    FeeRate::from_sat_per_vb_unchecked(sat_per_vb.into())
+}
+
+/// Whether the transaction opted in to replacement under BIP125
+fn signals_rbf(transaction: &TransactionInfo) -> bool {
+    transaction
+        .vin
+        .iter()
+        .any(|input| input.sequence < Sequence::ENABLE_LOCKTIME_NO_RBF.to_consensus_u32())
+}
+
+/// HTLC output the spend consumes, from the spend itself or the recorded funding
+fn spent_output(current: Option<&TransactionInfo>, htlc: &HtlcRecord) -> Result<Option<(OutPoint, Amount)>, ApiError> {
+    let Some(input) = current.and_then(|current| current.vin.first()) else {
+        return Ok(htlc.funding_outpoint.zip(htlc.amount.map(Amount::from_sat)));
+    };
+    let txid = Txid::from_str(&input.txid).map_err(|_| ApiError::InternalError {
+        code: "INVALID_TRANSACTION".to_string(),
+        message: format!("Invalid input txid {}", input.txid),
+        details: None,
+    })?;
+    Ok(input
+        .prevout
+        .as_ref()
+        .map(|prevout| (OutPoint::new(txid, input.vout), Amount::from_sat(prevout.value))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::order::{claim_htlc, refund_htlc};
+    use crate::test_support::{funded_htlc, key, PREIMAGE};
+
+    const TIMEOUT: u32 = 2_500_100;
+
+    #[test]
+    fn test_spend_fee_rate_escalates_towards_the_deadline() {
+        let estimates = FeeEstimates { fastest: 10, half_hour: 6, hour: 4, economy: 1, stale: false };
+        let rates: Vec<u64> = [100, 72, 30, 11, 3, 2, 0]
+            .into_iter()
+            .map(|blocks_left| spend_fee_rate(&estimates, blocks_left).to_sat_per_vb_ceil())
+            .collect();
+        assert_eq!(rates, vec![6, 6, 10, 20, 20, 40, 40]);
+    }
+
+    #[tokio::test]
+    async fn test_bumps_claim_as_timeout_nears() {
+        let (pool, chain, client, htlc) = funded_htlc(TIMEOUT).await;
+        let request = ClaimRequest { preimage: hex::encode(PREIMAGE), bitcoin_tx_hex: None };
+        let claim = claim_htlc(&pool, &client, Network::Testnet, Some(&key(1)), htlc.id, request).await.unwrap();
+
+        // Far from the timeout the half hour rate it was broadcast at is enough
+        let bump = bump_htlc_spend(&pool, &client, Network::Testnet, Some(&key(1)), htlc.id).await.unwrap();
+        assert!(bump.is_none());
+
+        // Ten blocks before the timeout the claim is replaced at twice the fastest rate
+        chain.mine_empty(89);
+        let first = bump_htlc_spend(&pool, &client, Network::Testnet, Some(&key(1)), htlc.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((first.replaced_txid.as_str(), first.fee_rate), (claim.transaction_id.as_str(), 10));
+        assert_eq!(chain.mempool(), vec![Txid::from_str(&first.txid).unwrap()]);
+        assert!(bump_htlc_spend(&pool, &client, Network::Testnet, Some(&key(1)), htlc.id).await.unwrap().is_none());
+
+        // Past the timeout it is bumped again, paying for the replaced fee as BIP125 asks
+        chain.mine_empty(10);
+        let second = bump_htlc_spend(&pool, &client, Network::Testnet, Some(&key(1)), htlc.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((second.replaced_txid.as_str(), second.fee_rate), (first.txid.as_str(), 20));
+        assert!(second.fee > first.fee);
+
+        let stored = HtlcRepository::new(pool.clone());
+        assert_eq!(stored.find(htlc.id).await.unwrap().claim_txid, Some(second.txid.clone()));
+        assert_eq!(stored.find_replacements(htlc.id).await.unwrap().len(), 2);
+
+        // Once confirmed the claim is no longer watched
+        chain.mine(1);
+        assert!(bump_htlc_spend(&pool, &client, Network::Testnet, Some(&key(1)), htlc.id).await.unwrap().is_none());
+        assert!(stored.find_unconfirmed_spends().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bumps_refund_signed_by_the_service() {
+        let (pool, chain, client, htlc) = funded_htlc(TIMEOUT).await;
+        chain.mine(99);
+        let refund = refund_htlc(&pool, &client, Network::Testnet, Some(&key(2)), htlc.id).await.unwrap();
+        chain.mine_empty(REFUND_ESCALATION_BLOCKS);
+
+        // Without the sender key the refund is left alone
+        let bump = bump_htlc_spend(&pool, &client, Network::Testnet, Some(&key(1)), htlc.id).await.unwrap();
+        assert!(bump.is_none());
+
+        // A day after the timeout it pays four times the fastest rate
+        let bump = bump_htlc_spend(&pool, &client, Network::Testnet, Some(&key(2)), htlc.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((bump.spend_path, bump.replaced_txid.as_str(), bump.fee_rate), (HtlcSpendPath::Refund, refund.transaction_id.as_str(), 20));
+        assert_eq!(chain.mempool(), vec![Txid::from_str(&bump.txid).unwrap()]);
+        let stored = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        assert_eq!(stored.refund_txid, Some(bump.txid));
+    }
+}
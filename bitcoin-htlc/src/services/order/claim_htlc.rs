@@ -0,0 +1,560 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::order::transition_order::{ensure_transition, transition_order};
+use crate::services::bitcoin::{BitcoinClient, Utxo};
+use crate::services::{build_htlc_script, create_claim_transaction_at_fee_rate, hash_preimage, verify_spend};
+use bitcoin::{
+    consensus::{deserialize, encode::serialize_hex},
+    key::Secp256k1,
+    Address, Amount, FeeRate, Network, OutPoint, PrivateKey, Transaction, TxOut, Txid,
+};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Claim an HTLC with its preimage and broadcast the spend
+///
+/// The spend is either the caller's signed `bitcoin_tx_hex` or, when the service holds
+/// the recipient key, a claim it builds and signs at the current fee rate.
+pub async fn claim_htlc(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    network: Network,
+    claim_key: Option<&PrivateKey>,
+    htlc_id: Uuid,
+    request: ClaimRequest,
+) -> Result<ClaimResponse, ApiError> {
+    let htlc = HtlcRepository::new(pool.clone()).find(htlc_id).await?;
+    ensure_unspent(&htlc)?;
+    if let Some(order_id) = htlc.order_id {
+        ensure_transition(pool, order_id, OrderStatus::BitcoinHtlcClaimed).await?;
+    }
+    let htlc_script = build_htlc_script(&htlc.params)?;
+
+    let preimage = hex::decode(&request.preimage).map_err(|_| ApiError::BadRequest {
+        code: "INVALID_PREIMAGE".to_string(),
+        message: "Preimage must be hex encoded".to_string(),
+        details: None,
+    })?;
+    if hash_preimage(&preimage) != htlc.params.payment_hash {
+        return Err(ApiError::BadRequest {
+            code: "INVALID_PREIMAGE".to_string(),
+            message: "Preimage does not hash to the HTLC payment hash".to_string(),
+            details: None,
+        });
+    }
+
+    // Locate the funding outpoint
+    let tip = bitcoin_client.get_block_height().await?;
+    let utxos = bitcoin_client.get_utxos(&htlc.address).await?;
+    let expected_amount = expected_funding_amount(pool, &htlc).await?;
+    let (outpoint, htlc_amount) =
+        select_funding_utxo(&utxos, htlc.funding_outpoint, expected_amount, tip, htlc.min_confirmations)?;
+
+    let transaction = match request.bitcoin_tx_hex {
+        Some(ref tx_hex) => {
+            check_submitted_claim(tx_hex, &htlc_script, outpoint, htlc_amount, &preimage)?
+        }
+        None => {
+            let claim_key = claim_key
+                .filter(|key| key.public_key(&Secp256k1::new()) == htlc.params.recipient_pubkey)
+                .ok_or_else(|| ApiError::BadRequest {
+                    code: "CLAIM_KEY_UNAVAILABLE".to_string(),
+                    message: "Service does not hold the HTLC recipient key, submit a signed bitcoin_tx_hex".to_string(),
+                    details: None,
+                })?;
+            let fee_rate = FeeRate::from_sat_per_vb_unchecked(bitcoin_client.get_fee_estimates().await?.half_hour.into());
+            build_claim(&htlc_script, outpoint, htlc_amount, &preimage, claim_key, fee_rate, network)?
+        }
+    };
+
+    let txid = bitcoin_client.broadcast_transaction(&serialize_hex(&transaction)).await?;
+    record_claim_broadcast(pool, &htlc, &txid, &preimage).await?;
+
+    let claim_address = transaction
+        .output
+        .first()
+        .and_then(|output| Address::from_script(&output.script_pubkey, network).ok())
+        .map(|address| address.to_string())
+        .unwrap_or_default();
+
+    Ok(ClaimResponse {
+        transaction_id: txid,
+        status: "broadcast".to_string(),
+        claim_address,
+        claimed_amount: transaction.output.iter().map(|output| output.value.to_sat()).sum(),
+    })
+}
+
+/// Record a broadcast claim on the HTLC and move its order, if any, to claimed
+///
+/// Both are written in one transaction, so the HTLC is never claimed while its order
+/// still waits, nor the other way around.
+pub(crate) async fn record_claim_broadcast(
+    pool: &SqlitePool,
+    htlc: &HtlcRecord,
+    txid: &str,
+    preimage: &[u8],
+) -> Result<(), ApiError> {
+    let mut tx = pool.begin().await?;
+    HtlcRepository::record_claim_with(&mut tx, htlc.id, txid, preimage).await?;
+    if let Some(order_id) = htlc.order_id {
+        transition_order(&mut tx, order_id, OrderStatus::BitcoinHtlcClaimed, &format!("claim {} broadcast", txid)).await?;
+        sqlx::query("UPDATE orders SET htlc_claim_tx = ? WHERE id = ?")
+            .bind(txid)
+            .bind(order_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Reject HTLCs whose spend has already been recorded
+pub(crate) fn ensure_unspent(htlc: &HtlcRecord) -> Result<(), ApiError> {
+    if let Some(ref claim_tx) = htlc.claim_txid {
+        return Err(ApiError::Conflict {
+            code: "HTLC_ALREADY_CLAIMED".to_string(),
+            message: format!("HTLC {} was already claimed", htlc.id),
+            details: Some(json!({ "claim_tx": claim_tx })),
+        });
+    }
+    if let Some(ref refund_tx) = htlc.refund_txid {
+        return Err(ApiError::Conflict {
+            code: "HTLC_ALREADY_REFUNDED".to_string(),
+            message: format!("HTLC {} was already refunded", htlc.id),
+            details: Some(json!({ "refund_tx": refund_tx })),
+        });
+    }
+    Ok(())
+}
+
+/// Amount the HTLC should hold: its recorded funding, else the Bitcoin amount of its order
+pub(crate) async fn expected_funding_amount(
+    pool: &SqlitePool,
+    htlc: &HtlcRecord,
+) -> Result<Option<u64>, ApiError> {
+    if htlc.amount.is_some() {
+        return Ok(htlc.amount);
+    }
+    let Some(order_id) = htlc.order_id else {
+        return Ok(None);
+    };
+    let amount: Option<i64> = sqlx::query_scalar("SELECT bitcoin_amount FROM orders WHERE id = ?")
+        .bind(order_id)
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+    Ok(amount.map(|amount| amount as u64))
+}
+
+/// Pick the HTLC output among the address UTXOs and check its confirmations
+///
+/// Without a recorded funding outpoint the output must cover `expected_amount`, and
+/// exactly one output may qualify: with several the funding cannot be told apart from
+/// other payments to the address.
+pub(crate) fn select_funding_utxo(
+    utxos: &[Utxo],
+    funding_outpoint: Option<OutPoint>,
+    expected_amount: Option<u64>,
+    tip: u32,
+    confirmations_required: u32,
+) -> Result<(OutPoint, Amount), ApiError> {
+    let utxo = match funding_outpoint {
+        Some(outpoint) => utxos.iter().find(|utxo| {
+            utxo.txid == outpoint.txid.to_string() && utxo.vout == outpoint.vout
+        }),
+        None => {
+            let candidates: Vec<&Utxo> = utxos
+                .iter()
+                .filter(|utxo| expected_amount.is_none_or(|amount| utxo.value >= amount))
+                .collect();
+            if candidates.len() > 1 {
+                return Err(ApiError::Conflict {
+                    code: "HTLC_FUNDING_AMBIGUOUS".to_string(),
+                    message: format!(
+                        "{} outputs at the HTLC address could be its funding, record the funding outpoint first",
+                        candidates.len()
+                    ),
+                    details: Some(json!({
+                        "outpoints": candidates
+                            .iter()
+                            .map(|utxo| format!("{}:{}", utxo.txid, utxo.vout))
+                            .collect::<Vec<_>>(),
+                        "expected_amount": expected_amount,
+                    })),
+                });
+            }
+            candidates.first().copied()
+        }
+    };
+
+    let utxo = utxo.ok_or_else(|| match funding_outpoint {
+        Some(outpoint) => ApiError::Conflict {
+            code: "HTLC_ALREADY_SPENT".to_string(),
+            message: format!("HTLC funding output {} is already spent", outpoint),
+            details: None,
+        },
+        None => ApiError::BadRequest {
+            code: "HTLC_NOT_FUNDED".to_string(),
+            message: match expected_amount {
+                Some(amount) => format!("No funding output of at least {} sat at the HTLC address", amount),
+                None => "No funding output found at the HTLC address".to_string(),
+            },
+            details: None,
+        },
+    })?;
+
+    let confirmations = match (utxo.status.confirmed, utxo.status.block_height) {
+        (false, _) => 0,
+        (true, Some(height)) => tip.saturating_sub(height) + 1,
+        (true, None) => 1,
+    };
+    if confirmations < confirmations_required.max(1) {
+        return Err(ApiError::Conflict {
+            code: "HTLC_FUNDING_UNCONFIRMED".to_string(),
+            message: format!(
+                "HTLC funding has {} of {} required confirmations",
+                confirmations,
+                confirmations_required.max(1)
+            ),
+            details: Some(json!({
+                "txid": utxo.txid,
+                "confirmations": confirmations,
+                "required": confirmations_required.max(1),
+            })),
+        });
+    }
+
+    let txid = Txid::from_str(&utxo.txid).map_err(|_| ApiError::InternalError {
+        code: "INVALID_UTXOS".to_string(),
+        message: format!("Invalid UTXO txid {}", utxo.txid),
+        details: None,
+    })?;
+    Ok((OutPoint::new(txid, utxo.vout), Amount::from_sat(utxo.value)))
+}
+
+/// Build and sign a claim paying to the claim key's P2WPKH address
+fn build_claim(
+    htlc_script: &HtlcScript,
+    outpoint: OutPoint,
+    htlc_amount: Amount,
+    preimage: &[u8],
+    claim_key: &PrivateKey,
+    fee_rate: FeeRate,
+    network: Network,
+) -> Result<Transaction, ApiError> {
+    let claim_address = wallet_address(claim_key, network)?;
+    create_claim_transaction_at_fee_rate(
+        outpoint,
+        htlc_amount,
+        htlc_script,
+        preimage,
+        &claim_key.inner,
+        &claim_address,
+        fee_rate,
+    )
+}
+
+/// P2WPKH address of a service key, where swept HTLC funds are sent
+pub(crate) fn wallet_address(key: &PrivateKey, network: Network) -> Result<Address, ApiError> {
+    Address::p2wpkh(&key.public_key(&Secp256k1::new()), network).map_err(|e| {
+        ApiError::InternalError {
+            code: "BITCOIN_ADDRESS_ERROR".to_string(),
+            message: format!("Failed to derive wallet address: {}", e),
+            details: None,
+        }
+    })
+}
+
+/// Check that a caller-signed claim spends the HTLC and reveals the preimage
+pub(crate) fn check_submitted_claim(
+    tx_hex: &str,
+    htlc_script: &HtlcScript,
+    outpoint: OutPoint,
+    htlc_amount: Amount,
+    preimage: &[u8],
+) -> Result<Transaction, ApiError> {
+    let transaction: Transaction = hex::decode(tx_hex)
+        .ok()
+        .and_then(|bytes| deserialize(&bytes).ok())
+        .ok_or_else(|| ApiError::BadRequest {
+            code: "INVALID_TRANSACTION".to_string(),
+            message: "bitcoin_tx_hex is not a valid transaction".to_string(),
+            details: None,
+        })?;
+
+    if transaction.input.len() != 1 || transaction.input[0].previous_output != outpoint {
+        return Err(ApiError::BadRequest {
+            code: "INVALID_CLAIM_TRANSACTION".to_string(),
+            message: format!("Claim transaction must spend only the HTLC output {}", outpoint),
+            details: None,
+        });
+    }
+
+    let prevout = TxOut {
+        value: htlc_amount,
+        script_pubkey: htlc_script.script_pubkey.clone(),
+    };
+    verify_spend(&transaction, 0, &[prevout])?;
+
+    let input = &transaction.input[0];
+    let reveals_preimage = input.witness.iter().any(|item| item == preimage)
+        || input.script_sig.instructions().any(|instruction| {
+            instruction.is_ok_and(|instruction| {
+                instruction.push_bytes().is_some_and(|bytes| bytes.as_bytes() == preimage)
+            })
+        });
+    if !reveals_preimage {
+        return Err(ApiError::BadRequest {
+            code: "INVALID_CLAIM_TRANSACTION".to_string(),
+            message: "Transaction does not spend the HTLC through the claim branch".to_string(),
+            details: None,
+        });
+    }
+
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::bitcoin::UtxoStatus;
+    use crate::services::{build_htlc_script, create_claim_transaction};
+    use crate::test_support::{htlc_params, insert_order, key, test_pool, PREIMAGE};
+    const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+    fn utxo(txid: &str, confirmed: bool, block_height: Option<u32>) -> Utxo {
+        Utxo {
+            txid: txid.to_string(),
+            vout: 1,
+            value: 100_000,
+            status: UtxoStatus {
+                confirmed,
+                block_height,
+                block_time: None,
+            },
+        }
+    }
+
+    fn funding_outpoint() -> OutPoint {
+        OutPoint::new(Txid::from_str(TXID).unwrap(), 1)
+    }
+
+    #[test]
+    fn test_select_funding_utxo_counts_confirmations() {
+        let utxos = [utxo(TXID, true, Some(100))];
+
+        let (outpoint, amount) = select_funding_utxo(&utxos, Some(funding_outpoint()), None, 102, 3).unwrap();
+        assert_eq!(outpoint.vout, 1);
+        assert_eq!(amount, Amount::from_sat(100_000));
+
+        let error = select_funding_utxo(&utxos, Some(funding_outpoint()), None, 101, 3).unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "HTLC_FUNDING_UNCONFIRMED"));
+    }
+
+    #[test]
+    fn test_select_funding_utxo_rejects_mempool_funding() {
+        let error = select_funding_utxo(&[utxo(TXID, false, None)], None, None, 100, 0).unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "HTLC_FUNDING_UNCONFIRMED"));
+    }
+
+    #[test]
+    fn test_select_funding_utxo_distinguishes_spent_from_unfunded() {
+        let error = select_funding_utxo(&[], Some(funding_outpoint()), None, 100, 1).unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "HTLC_ALREADY_SPENT"));
+
+        // Another output at the same address does not stand in for the recorded funding
+        let other_output = [utxo(TXID, true, Some(100))];
+        let error = select_funding_utxo(&other_output, Some(OutPoint::new(funding_outpoint().txid, 0)), None, 100, 1)
+            .unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "HTLC_ALREADY_SPENT"));
+
+        let error = select_funding_utxo(&[], None, None, 100, 1).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "HTLC_NOT_FUNDED"));
+    }
+
+    #[test]
+    fn test_select_funding_utxo_without_outpoint_requires_one_covering_output() {
+        const OTHER_TXID: &str = "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098";
+        let mut dust = utxo(OTHER_TXID, true, Some(100));
+        dust.value = 546;
+        let utxos = [dust, utxo(TXID, true, Some(90))];
+
+        // Outputs below the expected amount are not candidates
+        let (outpoint, amount) = select_funding_utxo(&utxos, None, Some(100_000), 100, 1).unwrap();
+        assert_eq!(outpoint, funding_outpoint());
+        assert_eq!(amount, Amount::from_sat(100_000));
+
+        let error = select_funding_utxo(&utxos, None, Some(100_001), 100, 1).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "HTLC_NOT_FUNDED"));
+
+        let error = select_funding_utxo(&utxos, None, None, 100, 1).unwrap_err();
+        match error {
+            ApiError::Conflict { code, details, .. } => {
+                assert_eq!(code, "HTLC_FUNDING_AMBIGUOUS");
+                assert_eq!(details.unwrap()["outpoints"].as_array().unwrap().len(), 2);
+            }
+            other => panic!("Expected Conflict, got {:?}", other),
+        }
+    }
+
+    async fn pool_with_htlc(htlc_id: Uuid, claim_tx: Option<&str>) -> SqlitePool {
+        let pool = test_pool().await;
+
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let mut htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        htlc.id = htlc_id;
+        htlc.claim_txid = claim_tx.map(str::to_string);
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+        pool
+    }
+
+    fn claim_request(preimage: [u8; 32]) -> ClaimRequest {
+        ClaimRequest {
+            preimage: hex::encode(preimage),
+            bitcoin_tx_hex: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_claim_htlc_unknown_id() {
+        let pool = pool_with_htlc(Uuid::new_v4(), None).await;
+        let result = claim_htlc(
+            &pool,
+            &BitcoinClient::new(),
+            Network::Testnet,
+            None,
+            Uuid::new_v4(),
+            claim_request(PREIMAGE),
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiError::NotFound { ref code, .. }) if code == "HTLC_NOT_FOUND"));
+    }
+
+    #[tokio::test]
+    async fn test_claim_htlc_rejects_wrong_preimage() {
+        let htlc_id = Uuid::new_v4();
+        let pool = pool_with_htlc(htlc_id, None).await;
+        let result = claim_htlc(
+            &pool,
+            &BitcoinClient::new(),
+            Network::Testnet,
+            None,
+            htlc_id,
+            claim_request([8u8; 32]),
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiError::BadRequest { ref code, .. }) if code == "INVALID_PREIMAGE"));
+    }
+
+    #[tokio::test]
+    async fn test_claim_htlc_rejects_already_claimed() {
+        let htlc_id = Uuid::new_v4();
+        let pool = pool_with_htlc(htlc_id, Some(TXID)).await;
+        let result = claim_htlc(
+            &pool,
+            &BitcoinClient::new(),
+            Network::Testnet,
+            None,
+            htlc_id,
+            claim_request(PREIMAGE),
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiError::Conflict { ref code, .. }) if code == "HTLC_ALREADY_CLAIMED"));
+    }
+
+    #[tokio::test]
+    async fn test_claim_htlc_broadcasts_and_records_claim() {
+        let htlc_id = Uuid::new_v4();
+        let pool = pool_with_htlc(htlc_id, None).await;
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::BitcoinHtlcConfirmed).await;
+        sqlx::query("UPDATE htlcs SET order_id = ? WHERE id = ?")
+            .bind(order_id)
+            .bind(htlc_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // The funding is not recorded, so it is told apart from dust by the order amount
+        let htlc = HtlcRepository::new(pool.clone()).find(htlc_id).await.unwrap();
+        let address = Address::from_str(&htlc.address).unwrap().assume_checked();
+        let chain = std::sync::Arc::new(crate::services::bitcoin::SimulatedChain::new(Network::Testnet, 2_500_000));
+        chain.fund(&address, Amount::from_sat(546));
+        let funding = chain.fund(&address, Amount::from_sat(100_000));
+        chain.mine(1);
+        let client = BitcoinClient::from_arc(chain.clone());
+
+        let claim_key = key(1);
+        let response = claim_htlc(&pool, &client, Network::Testnet, Some(&claim_key), htlc_id, claim_request(PREIMAGE))
+            .await
+            .unwrap();
+        assert_eq!(response.status, "broadcast");
+        assert!(response.claimed_amount < 100_000);
+        assert_eq!(response.claim_address, wallet_address(&claim_key, Network::Testnet).unwrap().to_string());
+
+        // The simulated chain accepted the spend of the funding output, not of the dust
+        assert_eq!(chain.mempool(), vec![Txid::from_str(&response.transaction_id).unwrap()]);
+        chain.mine(1);
+        let remaining = client.get_utxos(&htlc.address).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_ne!(remaining[0].txid, funding.txid.to_string());
+
+        let stored = HtlcRepository::new(pool.clone()).find(htlc_id).await.unwrap();
+        assert_eq!(stored.status, HtlcStatus::Claimed);
+        assert_eq!(stored.claim_txid.as_deref(), Some(response.transaction_id.as_str()));
+        assert_eq!(stored.preimage.as_deref(), Some(&PREIMAGE[..]));
+
+        let (status, claim_tx): (String, Option<String>) =
+            sqlx::query_as("SELECT status, htlc_claim_tx FROM orders WHERE id = ?")
+                .bind(order_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(status, OrderStatus::BitcoinHtlcClaimed.as_str());
+        assert_eq!(claim_tx, Some(response.transaction_id));
+    }
+
+    #[tokio::test]
+    async fn test_failed_order_update_leaves_htlc_unclaimed() {
+        let htlc_id = Uuid::new_v4();
+        let pool = pool_with_htlc(htlc_id, None).await;
+        // A completed order cannot move to claimed, so the transaction fails after the HTLC update
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::Completed).await;
+        let mut htlc = HtlcRepository::new(pool.clone()).find(htlc_id).await.unwrap();
+        htlc.order_id = Some(order_id);
+
+        let error = record_claim_broadcast(&pool, &htlc, TXID, &PREIMAGE).await.unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "ILLEGAL_ORDER_TRANSITION"));
+
+        let stored = HtlcRepository::new(pool.clone()).find(htlc_id).await.unwrap();
+        assert_eq!(stored.status, HtlcStatus::Created);
+        assert_eq!(stored.claim_txid, None);
+        assert_eq!(stored.preimage, None);
+    }
+
+    #[test]
+    fn test_check_submitted_claim_verifies_spend() {
+        let recipient_key = key(1).inner;
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let htlc = build_htlc_script(&params).unwrap();
+        let outpoint = funding_outpoint();
+        let amount = Amount::from_sat(100_000);
+        let destination = Address::p2wpkh(&params.recipient_pubkey, Network::Testnet).unwrap();
+
+        let claim = create_claim_transaction(
+            outpoint, amount, &htlc, &PREIMAGE, &recipient_key, &destination, Amount::from_sat(500),
+        )
+        .unwrap();
+        let claim_hex = serialize_hex(&claim);
+
+        assert!(check_submitted_claim(&claim_hex, &htlc, outpoint, amount, &PREIMAGE).is_ok());
+        assert!(check_submitted_claim(&claim_hex, &htlc, OutPoint::new(outpoint.txid, 0), amount, &PREIMAGE).is_err());
+        assert!(check_submitted_claim("00", &htlc, outpoint, amount, &PREIMAGE).is_err());
+    }
+}
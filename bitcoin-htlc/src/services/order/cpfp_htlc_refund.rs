@@ -0,0 +1,141 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::BitcoinClient;
+use crate::services::build_htlc_script;
+use crate::services::order::claim_htlc::wallet_address;
+use crate::services::order::export_htlc_psbt::parse_address;
+use crate::services::transaction::{create_cpfp_transaction, htlc_refund_vsize};
+use bitcoin::{consensus::encode::serialize_hex, key::Secp256k1, Amount, FeeRate, Network, OutPoint, PrivateKey, TxOut, Txid};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Speed up an unconfirmed refund with a child spending its output
+///
+/// For when replacing the refund is not an option, e.g. nodes that rejected the
+/// replacement or a refund signed elsewhere to the service wallet. The child spends
+/// the refund output at the resolver wallet and pays whatever brings the pair up to the
+/// package fee rate.
+pub async fn cpfp_htlc_refund(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    network: Network,
+    refund_key: Option<&PrivateKey>,
+    htlc_id: Uuid,
+    request: CpfpRequest,
+) -> Result<CpfpResponse, ApiError> {
+    let htlc = HtlcRepository::new(pool.clone()).find(htlc_id).await?;
+    let refund_txid = htlc.refund_txid.clone().ok_or_else(|| ApiError::Conflict {
+        code: "HTLC_NOT_REFUNDED".to_string(),
+        message: format!("HTLC {} has no refund to pay for", htlc.id),
+        details: None,
+    })?;
+    let refund_key = refund_key
+        .filter(|key| key.public_key(&Secp256k1::new()) == htlc.params.sender_pubkey)
+        .ok_or_else(|| ApiError::BadRequest {
+            code: "REFUND_KEY_UNAVAILABLE".to_string(),
+            message: "Service does not hold the HTLC sender key".to_string(),
+            details: None,
+        })?;
+    let wallet = wallet_address(refund_key, network)?;
+    let destination = match request.destination_address {
+        Some(ref address) => parse_address(address, network, "destination_address")?,
+        None => wallet.clone(),
+    };
+
+    let refund = bitcoin_client.get_transaction(&refund_txid).await?;
+    if refund.status.confirmed {
+        return Err(ApiError::Conflict {
+            code: "REFUND_CONFIRMED".to_string(),
+            message: format!("Refund {} is already confirmed", refund_txid),
+            details: Some(json!({ "block_height": refund.status.block_height })),
+        });
+    }
+    let wallet_script = wallet.script_pubkey();
+    let (vout, output) = refund
+        .vout
+        .iter()
+        .enumerate()
+        .find(|(_, output)| output.scriptpubkey == hex::encode(wallet_script.as_bytes()))
+        .ok_or_else(|| ApiError::Conflict {
+            code: "REFUND_NOT_SPENDABLE".to_string(),
+            message: format!("Refund {} does not pay to the service wallet", refund_txid),
+            details: None,
+        })?;
+    let parent_outpoint = OutPoint::new(
+        Txid::from_str(&refund.txid).map_err(|_| ApiError::InternalError {
+            code: "INVALID_TRANSACTION".to_string(),
+            message: format!("Invalid refund txid {}", refund.txid),
+            details: None,
+        })?,
+        vout as u32,
+    );
+    let parent_output = TxOut {
+        value: Amount::from_sat(output.value),
+        script_pubkey: wallet_script.clone(),
+    };
+
+    // The refund's signed size is only estimated, and never below what it really is
+    let parent_vsize = htlc_refund_vsize(&build_htlc_script(&htlc.params)?, &wallet_script)?;
+    let sat_per_vb = match request.fee_rate {
+        Some(sat_per_vb) => sat_per_vb,
+        None => bitcoin_client.get_fee_estimates().await?.fastest.into(),
+    };
+    let child = create_cpfp_transaction(
+        parent_outpoint,
+        &parent_output,
+        parent_vsize,
+        Amount::from_sat(refund.fee),
+        refund_key,
+        &destination,
+        FeeRate::from_sat_per_vb_unchecked(sat_per_vb),
+    )?;
+    let txid = bitcoin_client.broadcast_transaction(&serialize_hex(&child)).await?;
+
+    Ok(CpfpResponse {
+        transaction_id: txid,
+        parent_transaction_id: refund_txid,
+        destination_address: destination.to_string(),
+        amount: child.output[0].value.to_sat(),
+        fee: output.value - child.output[0].value.to_sat(),
+        package_fee_rate: sat_per_vb,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::order::refund_htlc;
+    use crate::test_support::{funded_htlc, key};
+
+    #[tokio::test]
+    async fn test_child_pays_for_unconfirmed_refund() {
+        let (pool, chain, client, htlc) = funded_htlc(2_500_010).await;
+        chain.mine(9);
+
+        let result = cpfp_htlc_refund(&pool, &client, Network::Testnet, Some(&key(2)), htlc.id, CpfpRequest::default()).await;
+        assert!(matches!(result, Err(ApiError::Conflict { ref code, .. }) if code == "HTLC_NOT_REFUNDED"));
+
+        let refund = refund_htlc(&pool, &client, Network::Testnet, Some(&key(2)), htlc.id).await.unwrap();
+        let request = CpfpRequest { destination_address: None, fee_rate: Some(25) };
+        let response = cpfp_htlc_refund(&pool, &client, Network::Testnet, Some(&key(2)), htlc.id, request.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.parent_transaction_id, refund.transaction_id);
+        assert_eq!(response.destination_address, refund.refund_address);
+        assert_eq!(chain.mempool().len(), 2);
+
+        // Refund fee and child fee together cover the package rate, a P2WPKH child being 110 vB
+        let parent_fee = client.get_transaction(&refund.transaction_id).await.unwrap().fee;
+        let child = client.get_transaction(&response.transaction_id).await.unwrap();
+        assert_eq!(child.fee, response.fee);
+        let wallet_script = wallet_address(&key(2), Network::Testnet).unwrap().script_pubkey();
+        let parent_vsize = htlc_refund_vsize(&build_htlc_script(&htlc.params).unwrap(), &wallet_script).unwrap();
+        assert!(parent_fee + child.fee >= 25 * (parent_vsize + 110));
+
+        chain.mine(1);
+        let result = cpfp_htlc_refund(&pool, &client, Network::Testnet, Some(&key(2)), htlc.id, request).await;
+        assert!(matches!(result, Err(ApiError::Conflict { ref code, .. }) if code == "REFUND_CONFIRMED"));
+    }
+}
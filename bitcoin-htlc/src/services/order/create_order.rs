@@ -1,4 +1,5 @@
 use crate::models::*;
+use crate::services::order::transition_order::record_order_event;
 use bitcoin::PublicKey;
 use chrono::{Duration, Utc};
 use sqlx::SqlitePool;
@@ -57,10 +58,7 @@ pub async fn create_order(
     });
 
     // Insert order into database
-    let direction_str = match request.direction {
-        SwapDirection::EthToBtc => "ETH_TO_BTC",
-        SwapDirection::BtcToEth => "BTC_TO_ETH",
-    };
+    let direction_str = request.direction.as_str();
     
     // Create temporary variables to avoid lifetime issues
     let bitcoin_amount = match request.direction {
@@ -71,12 +69,13 @@ pub async fn create_order(
     // Use resolver pubkey from request or config (if available)
     let resolver_pubkey_str = request.resolver_public_key.clone()
         .or_else(|| resolver_pubkey.map(|pk| pk.to_string()))
-        .unwrap_or_else(|| "".to_string());
+        .unwrap_or_default();
     let bitcoin_timeout = timeouts.bitcoin_blocks as i64;
     let ethereum_timeout = timeouts.ethereum_blocks as i64;
     let bitcoin_confirmations = confirmations.bitcoin as i64;
     let ethereum_confirmations = confirmations.ethereum as i64;
 
+    let mut tx = pool.begin().await?;
     sqlx::query!(
         r#"
         INSERT INTO orders (
@@ -105,8 +104,10 @@ pub async fn create_order(
         now,
         expires_at,
     )
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
+    record_order_event(&mut tx, order_id, None, OrderStatus::Created, "order created").await?;
+    tx.commit().await?;
 
     // Build response based on direction
     let (expected_steps, eth_to_btc_instructions, btc_to_eth_instructions) = match request.direction {
@@ -116,7 +117,7 @@ pub async fn create_order(
                     resolver_address: "0x1234567890123456789012345678901234567890".to_string(), // TODO: From config
                     preimage_hash: request.preimage_hash.clone(),
                     token_amount: request.amount.clone(),
-                    deadline: (Utc::now().timestamp() + (ethereum_timeout as i64 * 15)).to_string(), // Estimate 15 sec/block
+                    deadline: (Utc::now().timestamp() + (ethereum_timeout * 15)).to_string(), // Estimate 15 sec/block
                 },
             };
             (
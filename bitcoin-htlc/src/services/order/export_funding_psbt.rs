@@ -0,0 +1,52 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::BitcoinClient;
+use crate::services::build_htlc_script;
+use crate::services::order::claim_htlc::ensure_unspent;
+use crate::services::order::export_htlc_psbt::{parse_address, psbt_fee_rate};
+use crate::services::order::fund_htlc::wallet_coins;
+use crate::services::transaction::{create_funding_psbt, encode_psbt, select_coins};
+use bitcoin::{Amount, Network};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// Unsigned funding of an HTLC from the caller's wallet as a base64 PSBT
+///
+/// Coins are selected among the UTXOs of `wallet_address` as in
+/// [`fund_htlc`](crate::services::order::fund_htlc), which also receives the change. The
+/// wallet signs, finalizes and broadcasts the funding itself.
+pub async fn export_funding_psbt(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    network: Network,
+    htlc_id: Uuid,
+    request: FundingPsbtRequest,
+) -> Result<PsbtResponse, ApiError> {
+    let htlc = HtlcRepository::new(pool.clone()).find(htlc_id).await?;
+    ensure_unspent(&htlc)?;
+    let htlc_script = build_htlc_script(&htlc.params)?;
+    let wallet_address = parse_address(&request.wallet_address, network, "wallet_address")?;
+    let htlc_amount = Amount::from_sat(request.amount);
+
+    let coins = wallet_coins(bitcoin_client, &wallet_address).await?;
+    let fee_rate = psbt_fee_rate(bitcoin_client, request.fee_rate).await?;
+    let inputs = select_coins(
+        &coins,
+        htlc_amount,
+        &htlc_script.script_pubkey,
+        &wallet_address.script_pubkey(),
+        fee_rate,
+    )?;
+    let psbt = create_funding_psbt(inputs, &htlc_script, htlc_amount, &wallet_address, fee_rate)?;
+    let fee = psbt.fee().map_err(|e| ApiError::InternalError {
+        code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+        message: format!("Invalid funding PSBT: {}", e),
+        details: None,
+    })?;
+
+    Ok(PsbtResponse {
+        psbt: encode_psbt(&psbt),
+        fee: fee.to_sat(),
+        fee_rate: fee_rate.to_sat_per_vb_ceil(),
+    })
+}
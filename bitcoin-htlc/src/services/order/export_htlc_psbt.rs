@@ -0,0 +1,218 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::BitcoinClient;
+use crate::services::order::claim_htlc::{ensure_unspent, expected_funding_amount, select_funding_utxo};
+use crate::services::order::refund_htlc::ensure_refundable;
+use crate::services::transaction::{create_claim_psbt, create_refund_psbt, encode_psbt, HtlcFunding};
+use bitcoin::{
+    bip32::{DerivationPath, Fingerprint, KeySource},
+    Address, FeeRate, Network,
+};
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Unsigned claim or refund of a funded HTLC as a base64 PSBT
+///
+/// For signers the service holds no key for, such as hardware and air-gapped wallets.
+/// Refund PSBTs can be exported and signed before the timeout; their locktime keeps
+/// them from confirming earlier. Signed PSBTs come back through
+/// [`submit_htlc_psbt`](crate::services::order::submit_htlc_psbt).
+pub async fn export_htlc_psbt(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    network: Network,
+    htlc_id: Uuid,
+    path: HtlcSpendPath,
+    request: SpendPsbtRequest,
+) -> Result<PsbtResponse, ApiError> {
+    let htlc = HtlcRepository::new(pool.clone()).find(htlc_id).await?;
+    match path {
+        HtlcSpendPath::Claim => ensure_unspent(&htlc)?,
+        HtlcSpendPath::Refund => ensure_refundable(&htlc)?,
+    }
+
+    let destination = parse_address(&request.destination_address, network, "destination_address")?;
+    let key_source = request.key_origin.as_deref().map(parse_key_origin).transpose()?;
+    let preimage = request
+        .preimage
+        .as_deref()
+        .map(hex::decode)
+        .transpose()
+        .map_err(|_| ApiError::BadRequest {
+            code: "INVALID_PREIMAGE".to_string(),
+            message: "Preimage must be hex encoded".to_string(),
+            details: None,
+        })?;
+
+    let tip = bitcoin_client.get_block_height().await?;
+    let utxos = bitcoin_client.get_utxos(&htlc.address).await?;
+    let confirmations_required = match path {
+        HtlcSpendPath::Claim => htlc.min_confirmations,
+        HtlcSpendPath::Refund => 1,
+    };
+    let expected_amount = expected_funding_amount(pool, &htlc).await?;
+    let (outpoint, htlc_amount) =
+        select_funding_utxo(&utxos, htlc.funding_outpoint, expected_amount, tip, confirmations_required)?;
+    // Legacy signers check the spent amount against the whole previous transaction
+    let funding_transaction = match htlc.params.output_type {
+        HtlcOutputType::P2sh => Some(bitcoin_client.get_raw_transaction(&outpoint.txid.to_string()).await?),
+        _ => None,
+    };
+    let funding = HtlcFunding {
+        outpoint,
+        amount: htlc_amount,
+        transaction: funding_transaction.as_ref(),
+    };
+
+    let fee_rate = psbt_fee_rate(bitcoin_client, request.fee_rate).await?;
+    let psbt = match path {
+        HtlcSpendPath::Claim => create_claim_psbt(
+            funding,
+            &htlc.params,
+            preimage.as_deref(),
+            &destination,
+            fee_rate,
+            key_source,
+        )?,
+        HtlcSpendPath::Refund => {
+            create_refund_psbt(funding, &htlc.params, &destination, fee_rate, key_source)?
+        }
+    };
+
+    Ok(PsbtResponse {
+        psbt: encode_psbt(&psbt),
+        fee: htlc_amount.to_sat() - psbt.unsigned_tx.output[0].value.to_sat(),
+        fee_rate: fee_rate.to_sat_per_vb_ceil(),
+    })
+}
+
+/// The caller's sat/vB rate, or the half hour estimate
+pub(crate) async fn psbt_fee_rate(bitcoin_client: &BitcoinClient, sat_per_vb: Option<u64>) -> Result<FeeRate, ApiError> {
+    let sat_per_vb = match sat_per_vb {
+        Some(sat_per_vb) => sat_per_vb,
+        None => bitcoin_client.get_fee_estimates().await?.half_hour.into(),
+    };
+    Ok(FeeRate::from_sat_per_vb_unchecked(sat_per_vb))
+}
+
+pub(crate) fn parse_address(address: &str, network: Network, field: &str) -> Result<Address, ApiError> {
+    Address::from_str(address)
+        .ok()
+        .and_then(|address| address.require_network(network).ok())
+        .ok_or_else(|| ApiError::BadRequest {
+            code: "INVALID_ADDRESS".to_string(),
+            message: format!("{} is not a valid {} address", field, network),
+            details: None,
+        })
+}
+
+/// Parse a `[fingerprint/path]` key origin, as written in output descriptors
+fn parse_key_origin(origin: &str) -> Result<KeySource, ApiError> {
+    let invalid = || ApiError::BadRequest {
+        code: "INVALID_KEY_ORIGIN".to_string(),
+        message: format!("Key origin {} is not of the form [fingerprint/path]", origin),
+        details: None,
+    };
+
+    let origin = origin.trim().trim_start_matches('[').trim_end_matches(']');
+    let (fingerprint, path) = origin.split_once('/').unwrap_or((origin, ""));
+    let fingerprint = Fingerprint::from_str(fingerprint).map_err(|_| invalid())?;
+    let path = DerivationPath::from_str(format!("m/{}", path).trim_end_matches('/')).map_err(|_| invalid())?;
+    Ok((fingerprint, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::bitcoin::SimulatedChain;
+    use crate::services::build_htlc_script;
+    use crate::services::transaction::{decode_psbt, finalize_htlc_psbt};
+    use crate::test_support::{htlc_params, key, test_pool, PREIMAGE};
+    use bitcoin::{consensus::encode::serialize_hex, key::Secp256k1, Amount};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_key_origin() {
+        let (fingerprint, path) = parse_key_origin("[d34db33f/84'/1'/0'/0/7]").unwrap();
+        assert_eq!(fingerprint, Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]));
+        assert_eq!(path, DerivationPath::from_str("m/84'/1'/0'/0/7").unwrap());
+        assert_eq!(parse_key_origin("d34db33f").unwrap().1, DerivationPath::master());
+        assert!(parse_key_origin("[xyz/0]").is_err());
+        assert!(parse_key_origin("[d34db33f/a]").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_exports_claim_psbt_for_funded_htlc() {
+        let pool = test_pool().await;
+
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+        let chain = Arc::new(SimulatedChain::new(Network::Testnet, 2_500_000));
+        let client = BitcoinClient::from_arc(chain.clone());
+        let request = SpendPsbtRequest {
+            destination_address: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
+            preimage: None,
+            fee_rate: Some(3),
+            key_origin: None,
+        };
+
+        let result = export_htlc_psbt(&pool, &client, Network::Testnet, htlc.id, HtlcSpendPath::Claim, request.clone()).await;
+        assert!(matches!(result, Err(ApiError::BadRequest { ref code, .. }) if code == "HTLC_NOT_FUNDED"));
+
+        let address = Address::from_str(&htlc.address).unwrap().assume_checked();
+        chain.fund(&address, Amount::from_sat(100_000));
+        chain.mine(1);
+
+        let response = export_htlc_psbt(&pool, &client, Network::Testnet, htlc.id, HtlcSpendPath::Claim, request.clone())
+            .await
+            .unwrap();
+        let psbt = decode_psbt(&response.psbt).unwrap();
+        assert_eq!(psbt.fee().unwrap().to_sat(), response.fee);
+        assert_eq!(response.fee_rate, 3);
+        assert!(psbt.inputs[0].bip32_derivation.contains_key(&params.recipient_pubkey.inner));
+
+        let mainnet = SpendPsbtRequest {
+            destination_address: "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4".to_string(),
+            ..request
+        };
+        let result = export_htlc_psbt(&pool, &client, Network::Testnet, htlc.id, HtlcSpendPath::Refund, mainnet).await;
+        assert!(matches!(result, Err(ApiError::BadRequest { ref code, .. }) if code == "INVALID_ADDRESS"));
+    }
+
+    #[tokio::test]
+    async fn test_exports_p2sh_psbt_with_the_funding_transaction() {
+        let pool = test_pool().await;
+
+        let params = htlc_params(HtlcOutputType::P2sh);
+        let htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+        let chain = Arc::new(SimulatedChain::new(Network::Testnet, 2_500_000));
+        let client = BitcoinClient::from_arc(chain.clone());
+        let outpoint = chain.fund(&Address::from_str(&htlc.address).unwrap().assume_checked(), Amount::from_sat(100_000));
+        chain.mine(1);
+        let request = SpendPsbtRequest {
+            destination_address: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
+            preimage: Some(hex::encode(PREIMAGE)),
+            fee_rate: Some(3),
+            key_origin: None,
+        };
+
+        let response = export_htlc_psbt(&pool, &client, Network::Testnet, htlc.id, HtlcSpendPath::Claim, request)
+            .await
+            .unwrap();
+        let mut psbt = decode_psbt(&response.psbt).unwrap();
+        assert_eq!(psbt.inputs[0].non_witness_utxo, chain.transaction(&outpoint.txid));
+        assert_eq!(psbt.inputs[0].witness_utxo, None);
+
+        // A legacy signer signs from the funding transaction, and the chain accepts the claim
+        let secp = Secp256k1::new();
+        psbt.sign(&BTreeMap::from([(key(1).public_key(&secp), key(1))]), &secp).unwrap();
+        let claim = finalize_htlc_psbt(psbt, &params, None).unwrap();
+        client.broadcast_transaction(&serialize_hex(&claim.transaction)).await.unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::BitcoinClient;
+use crate::services::create_signed_funding_transaction;
+use crate::services::order::claim_htlc::wallet_address;
+use crate::services::order::export_htlc_psbt::psbt_fee_rate;
+use bitcoin::{consensus::encode::serialize_hex, key::Secp256k1, Address, Amount, Network, OutPoint, PrivateKey, Txid};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Lock the requested amount in a stored HTLC from the resolver wallet and broadcast the funding
+///
+/// For HTLCs the resolver is the sender of, as in ETH_TO_BTC swaps. Coins come from the
+/// P2WPKH address of the resolver key, which also receives the change. The HTLC is
+/// reserved for the signed funding before it is broadcast, so concurrent calls fund it
+/// once. The orchestrator records the funding once it shows up at the HTLC address.
+pub async fn fund_htlc(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    network: Network,
+    funding_key: Option<&PrivateKey>,
+    htlc_id: Uuid,
+    request: FundHtlcRequest,
+) -> Result<FundHtlcResponse, ApiError> {
+    let repository = HtlcRepository::new(pool.clone());
+    let htlc = repository.find(htlc_id).await?;
+    let funding_key = funding_key
+        .filter(|key| key.public_key(&Secp256k1::new()) == htlc.params.sender_pubkey)
+        .ok_or_else(|| ApiError::BadRequest {
+            code: "FUNDING_KEY_UNAVAILABLE".to_string(),
+            message: "Service does not hold the HTLC sender key".to_string(),
+            details: None,
+        })?;
+
+    // Funding twice would lock the resolver's coins in an output only one claim can spend
+    let existing = bitcoin_client.get_utxos(&htlc.address).await?;
+    if htlc.status != HtlcStatus::Created || !existing.is_empty() {
+        return Err(already_funded(&htlc, json!({ "status": htlc.status.as_str(), "outputs": existing.len() })));
+    }
+
+    let htlc_address = Address::from_str(&htlc.address)
+        .ok()
+        .and_then(|address| address.require_network(network).ok())
+        .ok_or_else(|| ApiError::InternalError {
+            code: "HTLC_CORRUPT".to_string(),
+            message: format!("HTLC {} has an invalid address for {}", htlc.id, network),
+            details: None,
+        })?;
+    let wallet_address = wallet_address(funding_key, network)?;
+    let coins = wallet_coins(bitcoin_client, &wallet_address).await?;
+
+    let fee_rate = psbt_fee_rate(bitcoin_client, request.fee_rate).await?;
+    let amount = Amount::from_sat(request.amount);
+    let transaction = create_signed_funding_transaction(
+        &coins,
+        &htlc_address,
+        amount,
+        funding_key,
+        &wallet_address,
+        fee_rate,
+    )?;
+
+    let spent: Amount = transaction
+        .input
+        .iter()
+        .filter_map(|input| coins.iter().find(|(outpoint, _)| *outpoint == input.previous_output))
+        .map(|(_, value)| *value)
+        .sum();
+    let fee = spent - transaction.output.iter().map(|output| output.value).sum();
+
+    let txid = transaction.txid().to_string();
+    if !repository.reserve_funding(htlc.id, &txid).await? {
+        return Err(already_funded(&htlc, json!({ "status": htlc.status.as_str() })));
+    }
+    let transaction_id = match bitcoin_client.broadcast_transaction(&serialize_hex(&transaction)).await {
+        Ok(transaction_id) => transaction_id,
+        Err(e) => {
+            repository.release_funding(htlc.id, &txid).await?;
+            return Err(e);
+        }
+    };
+
+    Ok(FundHtlcResponse {
+        transaction_id,
+        htlc_address: htlc.address,
+        amount: amount.to_sat(),
+        fee: fee.to_sat(),
+    })
+}
+
+fn already_funded(htlc: &HtlcRecord, details: serde_json::Value) -> ApiError {
+    ApiError::Conflict {
+        code: "HTLC_ALREADY_FUNDED".to_string(),
+        message: format!("HTLC {} is already funded", htlc.id),
+        details: Some(details),
+    }
+}
+
+/// Spendable coins of a wallet address, as coin selection takes them
+pub(crate) async fn wallet_coins(
+    bitcoin_client: &BitcoinClient,
+    wallet_address: &Address,
+) -> Result<Vec<(OutPoint, Amount)>, ApiError> {
+    bitcoin_client
+        .get_utxos(&wallet_address.to_string())
+        .await?
+        .iter()
+        .map(|utxo| {
+            let txid = Txid::from_str(&utxo.txid).map_err(|_| ApiError::InternalError {
+                code: "INVALID_UTXOS".to_string(),
+                message: format!("Invalid UTXO txid {}", utxo.txid),
+                details: None,
+            })?;
+            Ok((OutPoint::new(txid, utxo.vout), Amount::from_sat(utxo.value)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::bitcoin::SimulatedChain;
+    use crate::services::build_htlc_script;
+    use crate::test_support::{htlc_params, key, test_pool};
+    use std::sync::Arc;
+
+    async fn setup() -> (SqlitePool, Arc<SimulatedChain>, BitcoinClient, PrivateKey, HtlcRecord) {
+        let pool = test_pool().await;
+
+        // The resolver funds as the sender of the HTLC
+        let resolver = key(2);
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+        let chain = Arc::new(SimulatedChain::new(Network::Testnet, 2_500_000));
+        let client = BitcoinClient::from_arc(chain.clone());
+        (pool, chain, client, resolver, htlc)
+    }
+
+    fn request() -> FundHtlcRequest {
+        FundHtlcRequest {
+            amount: 100_000,
+            fee_rate: Some(2),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_funds_htlc_from_resolver_wallet() {
+        let (pool, chain, client, resolver, htlc) = setup().await;
+        let wallet = wallet_address(&resolver, Network::Testnet).unwrap();
+        chain.fund(&wallet, Amount::from_sat(70_000));
+        chain.fund(&wallet, Amount::from_sat(50_000));
+        chain.fund(&wallet, Amount::from_sat(5_000));
+        chain.mine(1);
+
+        // The chain checks every signature before accepting the funding
+        let response = fund_htlc(&pool, &client, Network::Testnet, Some(&resolver), htlc.id, request())
+            .await
+            .unwrap();
+        chain.mine(1);
+        let txid = response.transaction_id;
+        assert_eq!(response.htlc_address, htlc.address);
+        assert_eq!(response.fee, client.get_transaction(&txid).await.unwrap().fee);
+
+        let funded = client.get_utxos(&htlc.address).await.unwrap();
+        assert_eq!((funded[0].txid.as_str(), funded[0].value), (txid.as_str(), 100_000));
+        let change = client.get_utxos(&wallet.to_string()).await.unwrap();
+        assert!(change.iter().any(|utxo| utxo.txid == txid));
+
+        let again = fund_htlc(&pool, &client, Network::Testnet, Some(&resolver), htlc.id, request()).await;
+        assert!(matches!(again, Err(ApiError::Conflict { ref code, .. }) if code == "HTLC_ALREADY_FUNDED"));
+    }
+
+    #[tokio::test]
+    async fn test_funding_in_flight_is_not_broadcast_again() {
+        let (pool, chain, client, resolver, htlc) = setup().await;
+        chain.fund(&wallet_address(&resolver, Network::Testnet).unwrap(), Amount::from_sat(150_000));
+        chain.mine(1);
+
+        // Another call signed its funding and is broadcasting it
+        let repository = HtlcRepository::new(pool.clone());
+        assert!(repository.reserve_funding(htlc.id, &"aa".repeat(32)).await.unwrap());
+        let result = fund_htlc(&pool, &client, Network::Testnet, Some(&resolver), htlc.id, request()).await;
+        assert!(matches!(result, Err(ApiError::Conflict { ref code, .. }) if code == "HTLC_ALREADY_FUNDED"));
+        assert!(chain.mempool().is_empty());
+
+        // Its broadcast failed and the HTLC can be funded again
+        repository.release_funding(htlc.id, &"aa".repeat(32)).await.unwrap();
+        fund_htlc(&pool, &client, Network::Testnet, Some(&resolver), htlc.id, request())
+            .await
+            .unwrap();
+        assert_eq!(chain.mempool().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_requires_sender_key_and_funds() {
+        let (pool, chain, client, resolver, htlc) = setup().await;
+        let other = key(3);
+
+        let result = fund_htlc(&pool, &client, Network::Testnet, Some(&other), htlc.id, request()).await;
+        assert!(matches!(result, Err(ApiError::BadRequest { ref code, .. }) if code == "FUNDING_KEY_UNAVAILABLE"));
+
+        chain.fund(&wallet_address(&resolver, Network::Testnet).unwrap(), Amount::from_sat(60_000));
+        let result = fund_htlc(&pool, &client, Network::Testnet, Some(&resolver), htlc.id, request()).await;
+        assert!(matches!(result, Err(ApiError::InternalError { ref code, .. }) if code == "INSUFFICIENT_FUNDS"));
+    }
+}
@@ -0,0 +1,15 @@
+use crate::models::{ApiError, HtlcReplacement};
+use crate::repository::HtlcRepository;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// Fee bumps of an HTLC's claim or refund, oldest first
+pub async fn get_htlc_replacements(
+    pool: &SqlitePool,
+    htlc_id: Uuid,
+) -> Result<Vec<HtlcReplacement>, ApiError> {
+    let repository = HtlcRepository::new(pool.clone());
+    // Distinguish an unknown HTLC from one never bumped
+    repository.find(htlc_id).await?;
+    repository.find_replacements(htlc_id).await
+}
@@ -1,64 +1,43 @@
 use crate::models::{ApiError, Order, OrderDetails, SwapDirection, OrderStatus, OrderAmounts, OrderAddresses, HtlcDetails, FusionOrder, OrderTimestamps, OrderConfirmations};
 use sqlx::SqlitePool;
+use std::str::FromStr;
 use uuid::Uuid;
 
 /// Get order details by ID
 pub async fn get_order(
-    _pool: &SqlitePool,
+    pool: &SqlitePool,
     order_id: Uuid,
 ) -> Result<OrderDetails, ApiError> {
-    // TODO: Fix SQLx offline mode
-    let _ = order_id; // Acknowledge the parameter  
-    return Err(ApiError::InternalError {
-        code: "SQLX_DISABLED".to_string(),
-        message: "SQLx queries disabled for testing".to_string(),
+    let order = fetch_order(pool, order_id).await?;
+    order_details(order)
+}
+
+/// Load the raw order row
+pub(crate) async fn fetch_order(pool: &SqlitePool, order_id: Uuid) -> Result<Order, ApiError> {
+    sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE id = ?")
+        .bind(order_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound {
+            code: "ORDER_NOT_FOUND".to_string(),
+            message: format!("Order {} not found", order_id),
+            details: None,
+        })
+}
+
+fn order_details(order: Order) -> Result<OrderDetails, ApiError> {
+    let corrupt = |message: String| ApiError::InternalError {
+        code: "ORDER_CORRUPT".to_string(),
+        message: format!("Order {}: {}", order.id, message),
         details: None,
-    });
-    
-    // The following code is temporarily commented out due to SQLx offline mode issues
-    #[allow(unreachable_code)]
-    {
-    /*
-    let order = sqlx::query_as!(
-        Order,
-        "SELECT * FROM orders WHERE id = ?",
-        order_id
-    )
-    .fetch_one(pool)
-    .await
-    .map_err(|_| ApiError::NotFound(format!("Order {} not found", order_id)))?;
-    */
-    
-    // Mock order for testing
-    let order = Order {
-        id: order_id,
-        direction: "ETH_TO_BTC".to_string(),
-        status: "created".to_string(),
-        preimage_hash: "test_hash".to_string(),
-        bitcoin_amount: Some(100000),
-        bitcoin_address: Some("tb1qtest".to_string()),
-        bitcoin_public_key: Some("03test".to_string()),
-        ethereum_address: Some("0xtest".to_string()),
-        resolver_public_key: "03resolver".to_string(),
-        bitcoin_timeout_blocks: 144,
-        ethereum_timeout_blocks: 300,
-        bitcoin_confirmations_required: 3,
-        ethereum_confirmations_required: 12,
-        fusion_order_id: None,
-        fusion_order_hash: None,
-        htlc_id: None,
-        htlc_address: None,
-        htlc_redeem_script: None,
-        htlc_funding_tx: None,
-        created_at: chrono::Utc::now(),
-        updated_at: chrono::Utc::now(),
-        expires_at: chrono::Utc::now() + chrono::Duration::hours(1),
     };
-    
+    let direction = SwapDirection::from_str(&order.direction).map_err(corrupt)?;
+    let status = OrderStatus::from_str(&order.status).map_err(corrupt)?;
+
     Ok(OrderDetails {
         order_id: order.id,
-        direction: SwapDirection::EthToBtc,
-        status: OrderStatus::Created,
+        direction,
+        status,
         amounts: OrderAmounts {
             bitcoin_amount: order.bitcoin_amount.map(|a| a as u64),
             ethereum_amount: None,
@@ -72,8 +51,8 @@ pub async fn get_order(
             address: order.htlc_address.clone().unwrap_or_default(),
             redeem_script: order.htlc_redeem_script.clone().unwrap_or_default(),
             funding_tx: order.htlc_funding_tx.clone(),
-            claim_tx: None,
-            refund_tx: None,
+            claim_tx: order.htlc_claim_tx.clone(),
+            refund_tx: order.htlc_refund_tx.clone(),
         }),
         fusion_order: order.fusion_order_id.map(|id| FusionOrder {
             order_id: id,
@@ -92,29 +71,71 @@ pub async fn get_order(
             ethereum_current: 0,
         },
     })
-    }
 }
 
-use chrono;
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::{CreateOrderRequest, TokenInfo};
+    use crate::services::order::create_order;
+    use crate::test_support::test_pool;
+
+    fn eth_to_btc_request() -> CreateOrderRequest {
+        CreateOrderRequest {
+            direction: SwapDirection::EthToBtc,
+            amount: "1000000".to_string(),
+            from_token: Some(TokenInfo {
+                symbol: "USDC".to_string(),
+                address: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string(),
+            }),
+            bitcoin_address: Some("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string()),
+            bitcoin_public_key: Some("02789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd".to_string()),
+            to_token: None,
+            ethereum_address: None,
+            preimage_hash: "1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef".to_string(),
+            resolver_public_key: Some("03789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd".to_string()),
+            timeouts: None,
+            confirmation_requirements: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_order_reads_created_order() {
+        let pool = test_pool().await;
+        let created = create_order(&pool, None, eth_to_btc_request()).await.unwrap();
+
+        let order = get_order(&pool, created.order_id).await.unwrap();
+        assert_eq!(order.order_id, created.order_id);
+        assert!(matches!(order.direction, SwapDirection::EthToBtc));
+        assert!(matches!(order.status, OrderStatus::Created));
+        assert_eq!(
+            order.addresses.bitcoin_address.as_deref(),
+            Some("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+        );
+        assert_eq!(order.confirmations.bitcoin_required, 3);
+        assert_eq!(order.confirmations.ethereum_required, 12);
+        assert!(order.htlc_details.is_none());
+        assert_eq!(order.timestamps.expires_at, created.expires_at);
+    }
 
-    #[test]
-    fn test_order_not_found_error() {
-        let order_id = Uuid::new_v4();
-        let error_message = format!("Order {} not found", order_id);
-        assert!(error_message.contains(&order_id.to_string()));
+    #[tokio::test]
+    async fn test_get_order_unknown_id() {
+        let pool = test_pool().await;
+        let result = get_order(&pool, Uuid::new_v4()).await;
+        assert!(matches!(result, Err(ApiError::NotFound { ref code, .. }) if code == "ORDER_NOT_FOUND"));
     }
 
     #[tokio::test]
-    async fn test_get_order_returns_error_in_test_mode() {
-        // Since SQLx is disabled for testing, this should return an error
-        let pool = SqlitePool::connect(":memory:").await.unwrap();
-        let order_id = Uuid::new_v4();
-        
-        let result = get_order(&pool, order_id).await;
-        assert!(result.is_err());
+    async fn test_get_order_rejects_unknown_status() {
+        let pool = test_pool().await;
+        let created = create_order(&pool, None, eth_to_btc_request()).await.unwrap();
+        sqlx::query("UPDATE orders SET status = 'bogus' WHERE id = ?")
+            .bind(created.order_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let result = get_order(&pool, created.order_id).await;
+        assert!(matches!(result, Err(ApiError::InternalError { ref code, .. }) if code == "ORDER_CORRUPT"));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,51 @@
+use crate::models::{ApiError, OrderEvent, OrderStatus};
+use sqlx::{Row, SqlitePool};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Status history of an order, oldest first
+pub async fn get_order_events(
+    pool: &SqlitePool,
+    order_id: Uuid,
+) -> Result<Vec<OrderEvent>, ApiError> {
+    // Distinguish an unknown order from one without history
+    sqlx::query("SELECT id FROM orders WHERE id = ?")
+        .bind(order_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound {
+            code: "ORDER_NOT_FOUND".to_string(),
+            message: format!("Order {} not found", order_id),
+            details: None,
+        })?;
+
+    let rows = sqlx::query(
+        "SELECT from_status, to_status, cause, created_at FROM order_events WHERE order_id = ? ORDER BY id",
+    )
+    .bind(order_id)
+    .fetch_all(pool)
+    .await?;
+
+    let parse_status = |status: String| {
+        OrderStatus::from_str(&status).map_err(|message| ApiError::InternalError {
+            code: "ORDER_CORRUPT".to_string(),
+            message: format!("Order {}: {}", order_id, message),
+            details: None,
+        })
+    };
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(OrderEvent {
+                order_id,
+                from_status: row
+                    .try_get::<Option<String>, _>("from_status")?
+                    .map(parse_status)
+                    .transpose()?,
+                to_status: parse_status(row.try_get("to_status")?)?,
+                cause: row.try_get("cause")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
@@ -1,16 +1,47 @@
+pub mod bump_htlc_spend;
+pub mod claim_htlc;
+pub mod cpfp_htlc_refund;
 pub mod create_order;
+pub mod export_funding_psbt;
+pub mod export_htlc_psbt;
+pub mod fund_htlc;
 pub mod get_order;
+pub mod get_order_events;
+pub mod get_htlc_replacements;
+pub mod record_htlc_funding;
+pub mod refund_htlc;
 pub mod submit_fusion_proof;
+pub mod submit_htlc_psbt;
+pub mod track_confirmations;
+pub mod transition_order;
+pub mod watch_htlc_spend;
 
 // Re-export functions for easy access
+pub use bump_htlc_spend::bump_htlc_spend;
+pub use claim_htlc::claim_htlc;
+pub use cpfp_htlc_refund::cpfp_htlc_refund;
 pub use create_order::create_order;
+pub use export_funding_psbt::export_funding_psbt;
+pub use export_htlc_psbt::export_htlc_psbt;
+pub use fund_htlc::fund_htlc;
 pub use get_order::get_order;
+pub use get_order_events::get_order_events;
+pub use get_htlc_replacements::get_htlc_replacements;
+pub use record_htlc_funding::record_htlc_funding;
+pub use refund_htlc::refund_htlc;
 pub use submit_fusion_proof::submit_fusion_proof;
+pub use submit_htlc_psbt::submit_htlc_psbt;
+pub use track_confirmations::{track_confirmations, ConfirmationChange};
+pub use transition_order::{roll_back_order, transition_order};
+pub use watch_htlc_spend::watch_htlc_spend;
 
 // Re-export OrderService for backward compatibility
 use crate::models::*;
 use crate::services::bitcoin::BitcoinClient;
-use bitcoin::{Network, PublicKey};
+use crate::services::htlc::HtlcSpend;
+use crate::services::watchlist;
+use crate::services::webhooks;
+use bitcoin::{Network, OutPoint, PrivateKey, PublicKey};
 use sqlx::SqlitePool;
 use std::env;
 use std::str::FromStr;
@@ -20,34 +51,52 @@ use uuid::Uuid;
 pub struct OrderService {
     pool: SqlitePool,
     bitcoin_client: BitcoinClient,
-    #[allow(dead_code)]
     network: Network,
     resolver_pubkey: Option<PublicKey>,
+    resolver_private_key: Option<PrivateKey>,
 }
 
 impl OrderService {
     pub fn new(pool: SqlitePool, bitcoin_client: BitcoinClient) -> Self {
         let network = match env::var("BITCOIN_NETWORK").as_deref() {
             Ok("mainnet") => Network::Bitcoin,
-            Ok("testnet") | _ => Network::Testnet,
+            _ => Network::Testnet,
         };
 
         // Resolver public key is optional - only needed if Thunder Portal acts as a resolver
         let resolver_pubkey = env::var("RESOLVER_PUBLIC_KEY").ok()
             .and_then(|key| PublicKey::from_str(&key).ok());
 
+        // Resolver private key (WIF) lets the service sign HTLC spends on the resolver's behalf.
+        // A key that is set but unreadable stops startup rather than silently disabling signing.
+        let resolver_private_key = env::var("RESOLVER_PRIVATE_KEY").ok()
+            .map(|key| PrivateKey::from_wif(&key).expect("RESOLVER_PRIVATE_KEY must be a WIF private key"));
+
         Self {
             pool,
             bitcoin_client,
             network,
             resolver_pubkey,
+            resolver_private_key,
         }
     }
     
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
     pub fn bitcoin_client(&self) -> &BitcoinClient {
         &self.bitcoin_client
     }
 
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn resolver_private_key(&self) -> Option<&PrivateKey> {
+        self.resolver_private_key.as_ref()
+    }
+
     pub async fn create_order(&self, request: CreateOrderRequest) -> Result<CreateOrderResponse, ApiError> {
         // Use resolver pubkey from request if provided, otherwise use configured one (if any)
         let resolver_pubkey = request.resolver_public_key.as_ref()
@@ -61,11 +110,135 @@ impl OrderService {
         get_order(&self.pool, order_id).await
     }
 
+    pub async fn get_order_events(&self, order_id: Uuid) -> Result<Vec<OrderEvent>, ApiError> {
+        get_order_events(&self.pool, order_id).await
+    }
+
     pub async fn submit_fusion_proof(
         &self,
         order_id: Uuid,
         proof: FusionProofRequest,
     ) -> Result<FusionProofResponse, ApiError> {
-        submit_fusion_proof(&self.pool, &self.bitcoin_client, order_id, proof).await
+        submit_fusion_proof(&self.pool, &self.bitcoin_client, self.network, order_id, proof).await
+    }
+
+    pub async fn claim_htlc(
+        &self,
+        htlc_id: Uuid,
+        request: ClaimRequest,
+    ) -> Result<ClaimResponse, ApiError> {
+        claim_htlc(
+            &self.pool,
+            &self.bitcoin_client,
+            self.network,
+            self.resolver_private_key.as_ref(),
+            htlc_id,
+            request,
+        )
+        .await
+    }
+
+    pub async fn record_htlc_funding(
+        &self,
+        htlc: &HtlcRecord,
+        outpoint: OutPoint,
+        amount: u64,
+    ) -> Result<(), ApiError> {
+        record_htlc_funding(&self.pool, htlc, outpoint, amount).await
+    }
+
+    pub async fn fund_htlc(&self, htlc_id: Uuid, request: FundHtlcRequest) -> Result<FundHtlcResponse, ApiError> {
+        fund_htlc(
+            &self.pool,
+            &self.bitcoin_client,
+            self.network,
+            self.resolver_private_key.as_ref(),
+            htlc_id,
+            request,
+        )
+        .await
+    }
+
+    pub async fn export_htlc_psbt(
+        &self,
+        htlc_id: Uuid,
+        path: HtlcSpendPath,
+        request: SpendPsbtRequest,
+    ) -> Result<PsbtResponse, ApiError> {
+        export_htlc_psbt(&self.pool, &self.bitcoin_client, self.network, htlc_id, path, request).await
+    }
+
+    pub async fn export_funding_psbt(&self, htlc_id: Uuid, request: FundingPsbtRequest) -> Result<PsbtResponse, ApiError> {
+        export_funding_psbt(&self.pool, &self.bitcoin_client, self.network, htlc_id, request).await
+    }
+
+    pub async fn submit_htlc_psbt(&self, htlc_id: Uuid, request: SubmitPsbtRequest) -> Result<SubmitPsbtResponse, ApiError> {
+        submit_htlc_psbt(&self.pool, &self.bitcoin_client, self.network, htlc_id, request).await
+    }
+
+    pub async fn cpfp_htlc_refund(&self, htlc_id: Uuid, request: CpfpRequest) -> Result<CpfpResponse, ApiError> {
+        cpfp_htlc_refund(
+            &self.pool,
+            &self.bitcoin_client,
+            self.network,
+            self.resolver_private_key.as_ref(),
+            htlc_id,
+            request,
+        )
+        .await
+    }
+
+    pub async fn bump_htlc_spend(&self, htlc_id: Uuid) -> Result<Option<HtlcReplacement>, ApiError> {
+        bump_htlc_spend(
+            &self.pool,
+            &self.bitcoin_client,
+            self.network,
+            self.resolver_private_key.as_ref(),
+            htlc_id,
+        )
+        .await
+    }
+
+    pub async fn track_confirmations(&self, htlc_id: Uuid) -> Result<Vec<ConfirmationChange>, ApiError> {
+        track_confirmations(&self.pool, &self.bitcoin_client, htlc_id).await
+    }
+
+    pub async fn watch_htlc_spend(&self, htlc_id: Uuid) -> Result<Option<HtlcSpend>, ApiError> {
+        watch_htlc_spend(&self.pool, &self.bitcoin_client, htlc_id).await
+    }
+
+    pub async fn get_htlc_replacements(&self, htlc_id: Uuid) -> Result<Vec<HtlcReplacement>, ApiError> {
+        get_htlc_replacements(&self.pool, htlc_id).await
+    }
+
+    pub async fn refund_htlc(&self, htlc_id: Uuid) -> Result<RefundResponse, ApiError> {
+        refund_htlc(
+            &self.pool,
+            &self.bitcoin_client,
+            self.network,
+            self.resolver_private_key.as_ref(),
+            htlc_id,
+        )
+        .await
+    }
+
+    pub async fn watch_script(&self, request: WatchScriptRequest) -> Result<WatchedScript, ApiError> {
+        watchlist::watch_script(&self.pool, self.network, request).await
+    }
+
+    pub async fn get_watched_script(&self, watch_id: Uuid) -> Result<WatchedScript, ApiError> {
+        watchlist::get_watched_script(&self.pool, watch_id).await
+    }
+
+    pub async fn get_watch_events(&self, watch_id: Uuid) -> Result<Vec<WatchEvent>, ApiError> {
+        watchlist::get_watch_events(&self.pool, watch_id).await
+    }
+
+    pub async fn scan_watchlist(&self) -> Result<Vec<WatchEvent>, ApiError> {
+        watchlist::scan_watchlist(&self.pool, &self.bitcoin_client).await
+    }
+
+    pub async fn register_webhook(&self, registration: WebhookRegistration) -> Result<WebhookResponse, ApiError> {
+        webhooks::register_webhook(&self.pool, registration).await
     }
 }
\ No newline at end of file
@@ -0,0 +1,110 @@
+use crate::models::{ApiError, HtlcRecord, OrderStatus};
+use crate::repository::HtlcRepository;
+use crate::services::order::transition_order::transition_order;
+use bitcoin::OutPoint;
+use sqlx::{Row, SqlitePool};
+use std::str::FromStr;
+
+/// Record a verified funding output of a stored HTLC
+///
+/// An order still waiting for its HTLC to be funded moves on to `bitcoin_htlc_funded`;
+/// orders that are further along are left alone so repeated verification is harmless.
+/// The HTLC and its order are updated in one transaction, so neither is funded alone.
+pub async fn record_htlc_funding(
+    pool: &SqlitePool,
+    htlc: &HtlcRecord,
+    outpoint: OutPoint,
+    amount: u64,
+) -> Result<(), ApiError> {
+    let mut tx = pool.begin().await?;
+    HtlcRepository::record_funding_with(&mut tx, htlc.id, outpoint, amount).await?;
+
+    let Some(order_id) = htlc.order_id else {
+        tx.commit().await?;
+        return Ok(());
+    };
+
+    let status: String = sqlx::query("SELECT status FROM orders WHERE id = ?")
+        .bind(order_id)
+        .fetch_one(&mut *tx)
+        .await?
+        .try_get("status")?;
+    if OrderStatus::from_str(&status) == Ok(OrderStatus::BitcoinHtlcCreated) {
+        transition_order(
+            &mut tx,
+            order_id,
+            OrderStatus::BitcoinHtlcFunded,
+            &format!("funding {} verified", outpoint),
+        )
+        .await?;
+    }
+    sqlx::query("UPDATE orders SET htlc_funding_tx = ? WHERE id = ?")
+        .bind(outpoint.txid.to_string())
+        .bind(order_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{HtlcOutputType, SwapDirection};
+    use crate::services::build_htlc_script;
+    use crate::services::order::get_order;
+    use crate::test_support::{htlc_params, insert_order, test_pool};
+    use bitcoin::Txid;
+    use uuid::Uuid;
+
+    async fn pool_with_order_htlc() -> (SqlitePool, HtlcRecord) {
+        let pool = test_pool().await;
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::BitcoinHtlcCreated).await;
+
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let mut htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        htlc.order_id = Some(order_id);
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+        (pool, htlc)
+    }
+
+    #[tokio::test]
+    async fn test_funding_moves_order_to_funded_once() {
+        let (pool, htlc) = pool_with_order_htlc().await;
+        let outpoint = OutPoint::new(
+            Txid::from_str("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b").unwrap(),
+            0,
+        );
+        let order_id = htlc.order_id.unwrap();
+
+        record_htlc_funding(&pool, &htlc, outpoint, 100_000).await.unwrap();
+        // Verifying the same funding again is not an illegal transition
+        record_htlc_funding(&pool, &htlc, outpoint, 100_000).await.unwrap();
+
+        let order = get_order(&pool, order_id).await.unwrap();
+        assert_eq!(order.status, OrderStatus::BitcoinHtlcFunded);
+
+        let events = crate::services::order::get_order_events(&pool, order_id).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].to_status, OrderStatus::BitcoinHtlcFunded);
+    }
+
+    #[tokio::test]
+    async fn test_failed_order_update_leaves_htlc_unfunded() {
+        let (pool, mut htlc) = pool_with_order_htlc().await;
+        let outpoint = OutPoint::new(
+            Txid::from_str("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b").unwrap(),
+            0,
+        );
+        // An order that cannot be read makes the transaction fail after the HTLC update
+        htlc.order_id = Some(Uuid::new_v4());
+
+        assert!(record_htlc_funding(&pool, &htlc, outpoint, 100_000).await.is_err());
+
+        let stored = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        assert_eq!(stored.status, crate::models::HtlcStatus::Created);
+        assert_eq!(stored.funding_outpoint, None);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::models::*;
+use crate::repository::{HtlcRepository, WebhookRepository};
+use crate::services::order::transition_order::{ensure_transition, transition_order};
+use crate::services::bitcoin::BitcoinClient;
+use crate::services::{build_htlc_script, create_refund_transaction_at_fee_rate};
+use crate::services::order::claim_htlc::{ensure_unspent, expected_funding_amount, select_funding_utxo, wallet_address};
+use bitcoin::{consensus::encode::serialize_hex, key::Secp256k1, FeeRate, Network, PrivateKey};
+use serde_json::json;
+use sqlx::{SqliteConnection, SqlitePool};
+use uuid::Uuid;
+
+/// Refund an expired HTLC to the sender and mark its order, if any, expired
+pub async fn refund_htlc(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    network: Network,
+    refund_key: Option<&PrivateKey>,
+    htlc_id: Uuid,
+) -> Result<RefundResponse, ApiError> {
+    let htlc = HtlcRepository::new(pool.clone()).find(htlc_id).await?;
+    ensure_refundable(&htlc)?;
+    if let Some(order_id) = htlc.order_id {
+        ensure_transition(pool, order_id, OrderStatus::Expired).await?;
+    }
+    let htlc_script = build_htlc_script(&htlc.params)?;
+
+    let refund_key = refund_key
+        .filter(|key| key.public_key(&Secp256k1::new()) == htlc.params.sender_pubkey)
+        .ok_or_else(|| ApiError::BadRequest {
+            code: "REFUND_KEY_UNAVAILABLE".to_string(),
+            message: "Service does not hold the HTLC sender key".to_string(),
+            details: None,
+        })?;
+
+    // CLTV is satisfied once the next block is past the timeout height
+    let tip = bitcoin_client.get_block_height().await?;
+    ensure_expired(&htlc, tip)?;
+
+    let utxos = bitcoin_client.get_utxos(&htlc.address).await?;
+    let expected_amount = expected_funding_amount(pool, &htlc).await?;
+    let (outpoint, htlc_amount) = select_funding_utxo(&utxos, htlc.funding_outpoint, expected_amount, tip, 1)?;
+
+    let refund_address = wallet_address(refund_key, network)?;
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(bitcoin_client.get_fee_estimates().await?.half_hour.into());
+    let transaction = create_refund_transaction_at_fee_rate(
+        outpoint,
+        htlc_amount,
+        &htlc_script,
+        &refund_key.inner,
+        &refund_address,
+        htlc.params.timeout,
+        fee_rate,
+    )?;
+
+    let txid = bitcoin_client.broadcast_transaction(&serialize_hex(&transaction)).await?;
+    record_refund_broadcast(pool, &htlc, &txid).await?;
+
+    Ok(RefundResponse {
+        transaction_id: txid,
+        status: "broadcast".to_string(),
+        refund_address: refund_address.to_string(),
+        refunded_amount: transaction.output[0].value.to_sat(),
+    })
+}
+
+/// Record a broadcast refund on the HTLC and expire its order, if any, in one transaction
+pub(crate) async fn record_refund_broadcast(pool: &SqlitePool, htlc: &HtlcRecord, txid: &str) -> Result<(), ApiError> {
+    let mut tx = pool.begin().await?;
+    HtlcRepository::record_refund_with(&mut tx, htlc.id, txid).await?;
+    if let Some(order_id) = htlc.order_id {
+        transition_order(&mut tx, order_id, OrderStatus::Expired, &format!("refund {} broadcast", txid)).await?;
+        sqlx::query("UPDATE orders SET htlc_refund_tx = ? WHERE id = ?")
+            .bind(txid)
+            .bind(order_id)
+            .execute(&mut *tx)
+            .await?;
+        announce_refund(&mut tx, order_id, htlc.id, txid).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Queue the `order.refunded` webhooks, inside the transaction recording the refund
+pub(crate) async fn announce_refund(
+    conn: &mut SqliteConnection,
+    order_id: Uuid,
+    htlc_id: Uuid,
+    txid: &str,
+) -> Result<(), ApiError> {
+    let data = json!({ "orderId": order_id, "htlcId": htlc_id, "txid": txid });
+    WebhookRepository::enqueue_with(conn, WebhookEvent::OrderRefunded, data).await?;
+    Ok(())
+}
+
+/// A refund is only allowed while nobody has claimed the HTLC or revealed its preimage
+pub(crate) fn ensure_refundable(htlc: &HtlcRecord) -> Result<(), ApiError> {
+    ensure_unspent(htlc)?;
+    if htlc.status == HtlcStatus::Claimed || htlc.preimage.is_some() {
+        return Err(ApiError::Conflict {
+            code: "HTLC_ALREADY_CLAIMED".to_string(),
+            message: format!("HTLC {} has a claim in progress", htlc.id),
+            details: None,
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn ensure_expired(htlc: &HtlcRecord, tip: u32) -> Result<(), ApiError> {
+    if tip < htlc.params.timeout {
+        return Err(ApiError::Conflict {
+            code: "HTLC_NOT_EXPIRED".to_string(),
+            message: format!(
+                "HTLC timeout height {} not reached, chain tip is {}",
+                htlc.params.timeout, tip
+            ),
+            details: Some(json!({
+                "timeout_height": htlc.params.timeout,
+                "current_height": tip,
+                "blocks_remaining": htlc.params.timeout - tip,
+            })),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::build_htlc_script;
+    use crate::test_support::{htlc_params, insert_order, test_pool, HTLC_TIMEOUT};
+
+    fn htlc_record(preimage: Option<[u8; 32]>, claim_tx: Option<&str>) -> HtlcRecord {
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let mut htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        htlc.status = HtlcStatus::Funded;
+        htlc.preimage = preimage.map(|preimage| preimage.to_vec());
+        htlc.claim_txid = claim_tx.map(str::to_string);
+        htlc
+    }
+
+    #[test]
+    fn test_ensure_expired_requires_tip_at_timeout() {
+        let htlc = htlc_record(None, None);
+
+        let error = ensure_expired(&htlc, HTLC_TIMEOUT - 4).unwrap_err();
+        match error {
+            ApiError::Conflict { code, details, .. } => {
+                assert_eq!(code, "HTLC_NOT_EXPIRED");
+                assert_eq!(details.unwrap()["blocks_remaining"], 4);
+            }
+            other => panic!("Expected Conflict, got {:?}", other),
+        }
+        assert!(ensure_expired(&htlc, HTLC_TIMEOUT).is_ok());
+    }
+
+    #[test]
+    fn test_ensure_refundable_rejects_observed_claim() {
+        assert!(ensure_refundable(&htlc_record(None, None)).is_ok());
+        assert!(ensure_refundable(&htlc_record(Some([7u8; 32]), None)).is_err());
+
+        let error = ensure_refundable(&htlc_record(None, Some("ab"))).unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "HTLC_ALREADY_CLAIMED"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_order_update_leaves_htlc_unrefunded() {
+        let pool = test_pool().await;
+        // A completed order cannot expire, so the transaction fails after the HTLC update
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::Completed).await;
+        let mut htlc = htlc_record(None, None);
+        htlc.order_id = Some(order_id);
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+        let error = record_refund_broadcast(&pool, &htlc, "ab").await.unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "ILLEGAL_ORDER_TRANSITION"));
+
+        let stored = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        assert_eq!(stored.status, HtlcStatus::Funded);
+        assert_eq!(stored.refund_txid, None);
+    }
+
+    #[tokio::test]
+    async fn test_refund_htlc_unknown_id() {
+        let pool = test_pool().await;
+
+        let result = refund_htlc(&pool, &BitcoinClient::new(), Network::Testnet, None, Uuid::new_v4()).await;
+        assert!(matches!(result, Err(ApiError::NotFound { .. })));
+    }
+}
@@ -1,155 +1,109 @@
 use crate::models::*;
+use crate::repository::{HtlcRepository, WatchlistRepository};
+use crate::services::order::get_order::fetch_order;
+use crate::services::order::transition_order::transition_order;
 use crate::services::{bitcoin::BitcoinClient, build_htlc_script};
-use bitcoin::PublicKey;
-use chrono::Utc;
+use bitcoin::{Network, PublicKey};
 use sqlx::SqlitePool;
 use std::str::FromStr;
 use uuid::Uuid;
 
 /// Submit fusion proof for an order
 pub async fn submit_fusion_proof(
-    _pool: &SqlitePool,
+    pool: &SqlitePool,
     bitcoin_client: &BitcoinClient,
+    network: Network,
     order_id: Uuid,
     proof: FusionProofRequest,
 ) -> Result<FusionProofResponse, ApiError> {
-    // TODO: Fix SQLx offline mode
-    let _ = (order_id, proof, bitcoin_client); // Acknowledge parameters
-    return Err(ApiError::InternalError {
-        code: "SQLX_DISABLED".to_string(),
-        message: "SQLx queries disabled for testing".to_string(),
-        details: None,
-    });
-    
-    #[allow(unreachable_code)]
-    {
     // Get order from database
-    /*
-    let order = sqlx::query_as!(
-        Order,
-        "SELECT * FROM orders WHERE id = ?",
-        order_id
-    )
-    .fetch_one(pool)
-    .await
-    .map_err(|_| ApiError::NotFound(format!("Order {} not found", order_id)))?;
-    */
-    
-    // Mock order for testing
-    let order = Order {
-        id: order_id,
-        direction: "ETH_TO_BTC".to_string(),
-        status: "created".to_string(),
-        preimage_hash: "test_hash".to_string(),
-        bitcoin_amount: Some(100000),
-        bitcoin_address: Some("tb1qtest".to_string()),
-        bitcoin_public_key: Some("03789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd".to_string()),
-        ethereum_address: Some("0xtest".to_string()),
-        resolver_public_key: "03789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd".to_string(),
-        bitcoin_timeout_blocks: 144,
-        ethereum_timeout_blocks: 300,
-        bitcoin_confirmations_required: 3,
-        ethereum_confirmations_required: 12,
-        fusion_order_id: None,
-        fusion_order_hash: None,
-        htlc_id: None,
-        htlc_address: None,
-        htlc_redeem_script: None,
-        htlc_funding_tx: None,
-        created_at: chrono::Utc::now(),
-        updated_at: chrono::Utc::now(),
-        expires_at: chrono::Utc::now() + chrono::Duration::hours(1),
-    };
-    
-    // Verify order is in correct state
-    if order.status != "created" && order.status != "awaiting_fusion_proof" {
-        return Err(ApiError::BadRequest {
-            code: "INVALID_ORDER_STATE".to_string(),
-            message: format!("Order is in {} state, cannot submit fusion proof", order.status),
-            details: None,
-        });
-    }
+    let order = fetch_order(pool, order_id).await?;
     
     // Parse bitcoin public key
     let bitcoin_pubkey = PublicKey::from_str(
         order.bitcoin_public_key.as_ref()
-            .ok_or_else(|| ApiError::InternalError {
+            .ok_or_else(|| ApiError::BadRequest {
                 code: "MISSING_BITCOIN_KEY".to_string(),
-                message: "Bitcoin public key not set".to_string(),
+                message: "Order has no bitcoin public key to lock the HTLC to".to_string(),
                 details: None,
             })?
     )?;
     
     let resolver_pubkey = PublicKey::from_str(&order.resolver_public_key)?;
     
-    // Get current block height
+    // Timeout is relative to the current chain tip
     let current_block_height = bitcoin_client.get_block_height().await?;
-    let _timeout_height = current_block_height + order.bitcoin_timeout_blocks as u32;
+    let timeout_height = current_block_height + order.bitcoin_timeout_blocks as u32;
     
     // Create HTLC
-    let payment_hash = hex::decode(&order.preimage_hash)
+    let mut payment_hash = [0u8; 32];
+    hex::decode_to_slice(&order.preimage_hash, &mut payment_hash)
         .map_err(|_| ApiError::BadRequest {
             code: "INVALID_PREIMAGE_HASH".to_string(),
             message: "Invalid preimage hash".to_string(),
             details: None,
         })?;
-    let mut payment_hash_array = [0u8; 32];
-    payment_hash_array.copy_from_slice(&payment_hash);
     
     let params = HtlcParams {
         sender_pubkey: resolver_pubkey,
         recipient_pubkey: bitcoin_pubkey,
-        payment_hash: payment_hash_array,
-        timeout: order.bitcoin_timeout_blocks as u32,
+        payment_hash,
+        timeout: timeout_height,
+        output_type: HtlcOutputType::default(),
+        network,
     };
     
     let htlc_script = build_htlc_script(&params)?;
+    let mut htlc = HtlcRecord::new(params, &htlc_script);
+    htlc.order_id = Some(order_id);
+    htlc.min_confirmations = order.bitcoin_confirmations_required as u32;
     
-    // Update order with HTLC details
+    // Store the HTLC and attach it to the order in one go
     let redeem_script_hex = hex::encode(&htlc_script.redeem_script);
-    let _updated_at = Utc::now();
-    
-    /*
-    sqlx::query!(
+    let mut tx = pool.begin().await?;
+    transition_order(&mut tx, order_id, OrderStatus::BitcoinHtlcCreated, "fusion proof accepted").await?;
+    HtlcRepository::insert_with(&mut *tx, &htlc).await?;
+    let watch = WatchedScript::new(htlc_script.script_pubkey.clone(), htlc.min_confirmations, Some(htlc.id));
+    WatchlistRepository::insert_with(&mut *tx, &watch).await?;
+    sqlx::query(
         r#"
         UPDATE orders SET
-            status = ?,
-            bitcoin_htlc_address = ?,
-            bitcoin_htlc_redeem_script = ?,
-            ethereum_fusion_order_id = ?,
-            ethereum_fusion_order_hash = ?,
-            updated_at = ?
+            htlc_id = ?,
+            htlc_address = ?,
+            htlc_redeem_script = ?,
+            fusion_order_id = ?,
+            fusion_order_hash = ?
         WHERE id = ?
         "#,
-        "bitcoin_htlc_created",
-        htlc_script.p2sh_address,
-        redeem_script_hex,
-        proof.fusion_order_id,
-        proof.fusion_order_hash,
-        updated_at,
-        order_id
     )
-    .execute(pool)
+    .bind(htlc.id)
+    .bind(&htlc.address)
+    .bind(&redeem_script_hex)
+    .bind(&proof.fusion_order_id)
+    .bind(&proof.fusion_order_hash)
+    .bind(order_id)
+    .execute(&mut *tx)
     .await?;
-    */
+    tx.commit().await?;
     
     Ok(FusionProofResponse {
         accepted: true,
         next_step: "Send Bitcoin to HTLC address".to_string(),
         bitcoin_htlc: Some(BitcoinHtlcInfo {
-            htlc_id: Uuid::new_v4(),
-            address: htlc_script.p2sh_address,
+            htlc_id: htlc.id,
+            address: htlc.address,
             redeem_script: redeem_script_hex,
             funding_amount: order.bitcoin_amount.unwrap_or(100000) as u64,
         }),
     })
-    }
 }
 
-use chrono;
-
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::services::order::{create_order, get_order};
+    use crate::services::bitcoin::MockBackend;
+    use crate::test_support::test_pool;
 
     #[test]
     fn test_payment_hash_decoding() {
@@ -172,4 +126,85 @@ mod tests {
         let timeout_height = current_height + timeout_blocks;
         assert_eq!(timeout_height, 750144);
     }
-}
\ No newline at end of file
+
+    async fn pool_with_order() -> (SqlitePool, Uuid) {
+        let pool = test_pool().await;
+
+        let request: CreateOrderRequest = serde_json::from_value(serde_json::json!({
+            "direction": "ETH_TO_BTC",
+            "amount": "1000000",
+            "fromToken": { "symbol": "USDC", "address": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48" },
+            "bitcoinAddress": "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+            "bitcoinPublicKey": "02789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd",
+            "preimageHash": "1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef",
+            "resolverPublicKey": "03789ed0bb717d88f7d321a368d905e7430207ebbd82bd342cf11ae157a7ace5fd"
+        }))
+        .unwrap();
+        let order_id = create_order(&pool, None, request).await.unwrap().order_id;
+        (pool, order_id)
+    }
+
+    fn proof() -> FusionProofRequest {
+        FusionProofRequest {
+            fusion_order_id: "fusion-1".to_string(),
+            fusion_order_hash: format!("0x{}", "ab".repeat(32)),
+            fusion_order_signature: format!("0x{}", "a".repeat(130)),
+            fusion_order_data: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submit_fusion_proof_creates_order_htlc() {
+        let (pool, order_id) = pool_with_order().await;
+        let bitcoin_client = BitcoinClient::from_backend(MockBackend::new(2_500_000));
+
+        let response = submit_fusion_proof(&pool, &bitcoin_client, Network::Testnet, order_id, proof())
+            .await
+            .unwrap();
+        let htlc_info = response.bitcoin_htlc.unwrap();
+
+        let htlc = HtlcRepository::new(pool.clone()).find(htlc_info.htlc_id).await.unwrap();
+        assert_eq!(htlc.order_id, Some(order_id));
+        assert_eq!(htlc.params.timeout, 2_500_144);
+        assert_eq!(htlc.min_confirmations, 3);
+        assert_eq!(htlc.address, htlc_info.address);
+
+        // The HTLC address is watched until it is funded and spent
+        let watches = WatchlistRepository::new(pool.clone()).find_active(0).await.unwrap();
+        assert_eq!(watches.len(), 1);
+        assert_eq!((watches[0].htlc_id, watches[0].min_confirmations), (Some(htlc.id), 3));
+
+        let order = get_order(&pool, order_id).await.unwrap();
+        assert!(matches!(order.status, OrderStatus::BitcoinHtlcCreated));
+        assert_eq!(order.htlc_details.unwrap().htlc_id, htlc.id);
+        assert_eq!(order.fusion_order.unwrap().order_id, "fusion-1");
+
+        let events = crate::services::order::get_order_events(&pool, order_id).await.unwrap();
+        let statuses: Vec<_> = events.iter().map(|event| (event.from_status, event.to_status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (None, OrderStatus::Created),
+                (Some(OrderStatus::Created), OrderStatus::BitcoinHtlcCreated),
+            ]
+        );
+
+        // The order has moved on, a second proof is rejected
+        let result = submit_fusion_proof(&pool, &bitcoin_client, Network::Testnet, order_id, proof()).await;
+        assert!(matches!(result, Err(ApiError::Conflict { ref code, .. }) if code == "ILLEGAL_ORDER_TRANSITION"));
+    }
+
+    #[tokio::test]
+    async fn test_submit_fusion_proof_unknown_order() {
+        let (pool, _) = pool_with_order().await;
+        let result = submit_fusion_proof(
+            &pool,
+            &BitcoinClient::from_backend(MockBackend::default()),
+            Network::Testnet,
+            Uuid::new_v4(),
+            proof(),
+        )
+        .await;
+        assert!(matches!(result, Err(ApiError::NotFound { ref code, .. }) if code == "ORDER_NOT_FOUND"));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::BitcoinClient;
+use crate::services::order::claim_htlc::{
+    ensure_unspent, expected_funding_amount, record_claim_broadcast, select_funding_utxo,
+};
+use crate::services::order::refund_htlc::{ensure_expired, ensure_refundable, record_refund_broadcast};
+use crate::services::order::transition_order::ensure_transition;
+use crate::services::transaction::finalize_htlc_psbt::spent_output;
+use crate::services::transaction::{decode_psbt, finalize_htlc_psbt};
+use bitcoin::{consensus::encode::serialize_hex, Address, Network};
+use serde_json::json;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// Finalize a signed claim or refund PSBT and broadcast it
+///
+/// The branch follows from whose signature the PSBT carries. The spend is recorded
+/// like one built by the service: claims store the preimage and move the order to
+/// claimed, refunds are only accepted once the HTLC has expired.
+pub async fn submit_htlc_psbt(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    network: Network,
+    htlc_id: Uuid,
+    request: SubmitPsbtRequest,
+) -> Result<SubmitPsbtResponse, ApiError> {
+    let htlc = HtlcRepository::new(pool.clone()).find(htlc_id).await?;
+    ensure_unspent(&htlc)?;
+
+    let psbt = decode_psbt(&request.psbt)?;
+    let preimage = match request.preimage {
+        Some(ref preimage) => Some(hex::decode(preimage).map_err(|_| ApiError::BadRequest {
+            code: "INVALID_PREIMAGE".to_string(),
+            message: "Preimage must be hex encoded".to_string(),
+            details: None,
+        })?),
+        None => htlc.preimage.clone(),
+    };
+
+    // The PSBT must spend the recorded funding output at its actual amount; legacy
+    // P2SH signatures do not commit to the amount
+    let tip = bitcoin_client.get_block_height().await?;
+    let utxos = bitcoin_client.get_utxos(&htlc.address).await?;
+    let expected_amount = expected_funding_amount(pool, &htlc).await?;
+    let (outpoint, htlc_amount) = select_funding_utxo(&utxos, htlc.funding_outpoint, expected_amount, tip, 1)?;
+    let spent_amount = spent_output(&psbt).map(|output| output.value);
+    let spent_outpoint = psbt.unsigned_tx.input.first().map(|input| input.previous_output);
+    if spent_outpoint != Some(outpoint) || spent_amount != Some(htlc_amount) {
+        return Err(ApiError::BadRequest {
+            code: "INVALID_PSBT".to_string(),
+            message: format!("PSBT does not spend the HTLC funding output {}", outpoint),
+            details: Some(json!({
+                "outpoint": outpoint.to_string(),
+                "amount": htlc_amount.to_sat(),
+            })),
+        });
+    }
+
+    let spend = finalize_htlc_psbt(psbt, &htlc.params, preimage.as_deref())?;
+    match spend.path {
+        HtlcSpendPath::Claim => {
+            select_funding_utxo(&utxos, Some(outpoint), expected_amount, tip, htlc.min_confirmations)?;
+            if let Some(order_id) = htlc.order_id {
+                ensure_transition(pool, order_id, OrderStatus::BitcoinHtlcClaimed).await?;
+            }
+        }
+        HtlcSpendPath::Refund => {
+            ensure_refundable(&htlc)?;
+            ensure_expired(&htlc, tip)?;
+            if let Some(order_id) = htlc.order_id {
+                ensure_transition(pool, order_id, OrderStatus::Expired).await?;
+            }
+        }
+    }
+
+    let transaction = spend.transaction;
+    let txid = bitcoin_client.broadcast_transaction(&serialize_hex(&transaction)).await?;
+    match spend.preimage {
+        Some(ref preimage) => record_claim_broadcast(pool, &htlc, &txid, preimage).await?,
+        None => record_refund_broadcast(pool, &htlc, &txid).await?,
+    }
+
+    let destination_address = transaction
+        .output
+        .first()
+        .and_then(|output| Address::from_script(&output.script_pubkey, network).ok())
+        .map(|address| address.to_string())
+        .unwrap_or_default();
+
+    Ok(SubmitPsbtResponse {
+        transaction_id: txid,
+        status: "broadcast".to_string(),
+        spend_path: spend.path,
+        destination_address,
+        amount: transaction.output.iter().map(|output| output.value.to_sat()).sum(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::order::export_htlc_psbt;
+    use crate::services::transaction::encode_psbt;
+    use crate::test_support::{funded_htlc, key, PREIMAGE};
+    use bitcoin::{key::Secp256k1, PrivateKey};
+    use std::collections::BTreeMap;
+
+    const DESTINATION: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+
+    async fn signed_psbt(
+        pool: &SqlitePool,
+        client: &BitcoinClient,
+        htlc: &HtlcRecord,
+        path: HtlcSpendPath,
+        signer: &PrivateKey,
+    ) -> String {
+        let request = SpendPsbtRequest {
+            destination_address: DESTINATION.to_string(),
+            preimage: None,
+            fee_rate: Some(2),
+            key_origin: None,
+        };
+        let exported = export_htlc_psbt(pool, client, Network::Testnet, htlc.id, path, request).await.unwrap();
+
+        let secp = Secp256k1::new();
+        let mut psbt = decode_psbt(&exported.psbt).unwrap();
+        psbt.sign(&BTreeMap::from([(signer.public_key(&secp), *signer)]), &secp).unwrap();
+        encode_psbt(&psbt)
+    }
+
+    #[tokio::test]
+    async fn test_submits_signed_claim_psbt() {
+        let (pool, chain, client, htlc) = funded_htlc(2_500_010).await;
+        let psbt = signed_psbt(&pool, &client, &htlc, HtlcSpendPath::Claim, &key(1)).await;
+
+        let request = SubmitPsbtRequest { psbt: psbt.clone(), preimage: None };
+        let result = submit_htlc_psbt(&pool, &client, Network::Testnet, htlc.id, request).await;
+        assert!(matches!(result, Err(ApiError::BadRequest { ref code, .. }) if code == "PREIMAGE_REQUIRED"));
+
+        let request = SubmitPsbtRequest { psbt, preimage: Some(hex::encode(PREIMAGE)) };
+        let response = submit_htlc_psbt(&pool, &client, Network::Testnet, htlc.id, request).await.unwrap();
+        assert_eq!(response.spend_path, HtlcSpendPath::Claim);
+        assert_eq!(response.destination_address, DESTINATION);
+
+        // The simulated chain accepted it and the claim is recorded with its preimage
+        chain.mine(1);
+        assert!(client.get_utxos(&htlc.address).await.unwrap().is_empty());
+        let stored = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        assert_eq!(stored.claim_txid.as_deref(), Some(response.transaction_id.as_str()));
+        assert_eq!(stored.preimage.as_deref(), Some(&PREIMAGE[..]));
+    }
+
+    #[tokio::test]
+    async fn test_refund_psbt_waits_for_timeout() {
+        let (pool, chain, client, htlc) = funded_htlc(2_500_010).await;
+        let psbt = signed_psbt(&pool, &client, &htlc, HtlcSpendPath::Refund, &key(2)).await;
+
+        let request = SubmitPsbtRequest { psbt, preimage: None };
+        let result = submit_htlc_psbt(&pool, &client, Network::Testnet, htlc.id, request.clone()).await;
+        assert!(matches!(result, Err(ApiError::Conflict { ref code, .. }) if code == "HTLC_NOT_EXPIRED"));
+
+        chain.mine(10);
+        let response = submit_htlc_psbt(&pool, &client, Network::Testnet, htlc.id, request).await.unwrap();
+        assert_eq!(response.spend_path, HtlcSpendPath::Refund);
+        let stored = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        assert_eq!(stored.refund_txid.as_deref(), Some(response.transaction_id.as_str()));
+    }
+}
@@ -0,0 +1,316 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::BitcoinClient;
+use crate::services::order::roll_back_order;
+use log::{info, warn};
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Confirmations after which a spend is treated as final and no longer tracked
+pub const FINALITY_DEPTH: u32 = 6;
+
+/// What the tracker noticed about one of an HTLC's transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfirmationChange {
+    /// Seen in a block for the first time
+    Confirmed(TxConfirmation),
+    /// The block it was seen in left the active chain; `reconfirmed` is the block it
+    /// is in now, `None` while it is back in the mempool or gone
+    Reorged {
+        lost: TxConfirmation,
+        reconfirmed: Option<TxConfirmation>,
+    },
+}
+
+/// Follow the blocks an HTLC's funding, claim and refund confirm in
+///
+/// The block hash each transaction was seen in is recorded, and on later calls it
+/// must still be the block at that height on the active chain. When it is not, the
+/// transaction was reorged out: where it confirmed since is recorded instead, and
+/// whatever rested on the lost confirmation is undone. A funding left with fewer
+/// confirmations than the HTLC needs takes a `bitcoin_htlc_confirmed` order back to
+/// `bitcoin_htlc_funded`; a spend is watched for fee bumps again.
+pub async fn track_confirmations(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    htlc_id: Uuid,
+) -> Result<Vec<ConfirmationChange>, ApiError> {
+    let repository = HtlcRepository::new(pool.clone());
+    let htlc = repository.find(htlc_id).await?;
+    let recorded = repository.find_confirmations(htlc.id).await?;
+    let tip = bitcoin_client.get_block_height().await?;
+
+    let watched = [
+        (HtlcTransactionKind::Funding, htlc.funding_outpoint.map(|outpoint| outpoint.txid.to_string())),
+        (HtlcTransactionKind::Claim, htlc.claim_txid.clone()),
+        (HtlcTransactionKind::Refund, htlc.refund_txid.clone()),
+    ];
+    let mut changes = Vec::new();
+    for (kind, txid) in watched {
+        let Some(txid) = txid else { continue };
+        let previous = recorded.iter().find(|confirmation| confirmation.txid == txid);
+        if let Some(previous) = previous {
+            if in_active_chain(bitcoin_client, tip, previous).await? {
+                continue;
+            }
+        }
+
+        let current = find_confirmation(bitcoin_client, &htlc, kind, &txid).await?;
+        match (previous, current) {
+            (None, None) => {}
+            (None, Some(current)) => {
+                repository.record_confirmation(&current).await?;
+                changes.push(ConfirmationChange::Confirmed(current));
+            }
+            (Some(previous), current) => {
+                match current {
+                    Some(ref current) => repository.record_confirmation(current).await?,
+                    None => repository.remove_confirmation(&txid).await?,
+                }
+                warn!(
+                    "{} {} of HTLC {} left block {} at height {}",
+                    kind.as_str(),
+                    txid,
+                    htlc.id,
+                    previous.block_hash,
+                    previous.block_height
+                );
+                changes.push(ConfirmationChange::Reorged {
+                    lost: previous.clone(),
+                    reconfirmed: current,
+                });
+            }
+        }
+    }
+
+    for change in &changes {
+        if let ConfirmationChange::Reorged { lost, reconfirmed } = change {
+            undo_confirmation(pool, &htlc, lost, reconfirmed.as_ref(), tip).await?;
+        }
+    }
+    Ok(changes)
+}
+
+/// Whether the recorded block is still the one at its height
+async fn in_active_chain(
+    bitcoin_client: &BitcoinClient,
+    tip: u32,
+    confirmation: &TxConfirmation,
+) -> Result<bool, ApiError> {
+    if confirmation.block_height > tip {
+        return Ok(false);
+    }
+    match bitcoin_client.get_block_hash(confirmation.block_height).await {
+        Ok(hash) => Ok(hash == confirmation.block_hash),
+        Err(ApiError::NotFound { .. }) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Block the transaction is confirmed in on the active chain, if any
+async fn find_confirmation(
+    bitcoin_client: &BitcoinClient,
+    htlc: &HtlcRecord,
+    kind: HtlcTransactionKind,
+    txid: &str,
+) -> Result<Option<TxConfirmation>, ApiError> {
+    let status = match bitcoin_client.get_transaction(txid).await {
+        Ok(info) => info.status,
+        // Dropped along with the block it was in
+        Err(ApiError::NotFound { .. }) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let Some(block_height) = status.block_height.filter(|_| status.confirmed) else {
+        return Ok(None);
+    };
+    let block_hash = match status.block_hash {
+        Some(hash) => hash,
+        None => bitcoin_client.get_block_hash(block_height).await?,
+    };
+
+    Ok(Some(TxConfirmation {
+        txid: txid.to_string(),
+        htlc_id: htlc.id,
+        kind,
+        block_height,
+        block_hash,
+    }))
+}
+
+async fn undo_confirmation(
+    pool: &SqlitePool,
+    htlc: &HtlcRecord,
+    lost: &TxConfirmation,
+    reconfirmed: Option<&TxConfirmation>,
+    tip: u32,
+) -> Result<(), ApiError> {
+    let repository = HtlcRepository::new(pool.clone());
+    match lost.kind {
+        HtlcTransactionKind::Funding => {
+            let confirmations = reconfirmed.map_or(0, |confirmation| tip.saturating_sub(confirmation.block_height) + 1);
+            if confirmations >= htlc.min_confirmations.max(1) {
+                return Ok(());
+            }
+            let Some(order_id) = htlc.order_id else {
+                return Ok(());
+            };
+
+            let mut conn = pool.acquire().await?;
+            let status: String = sqlx::query_scalar("SELECT status FROM orders WHERE id = ?")
+                .bind(order_id)
+                .fetch_one(&mut *conn)
+                .await?;
+            let status = OrderStatus::from_str(&status).map_err(|message| ApiError::InternalError {
+                code: "ORDER_CORRUPT".to_string(),
+                message: format!("Order {}: {}", order_id, message),
+                details: None,
+            })?;
+            if status.can_roll_back_to(OrderStatus::BitcoinHtlcFunded) {
+                let cause = format!(
+                    "reorg: funding {} left block {} at height {}",
+                    lost.txid, lost.block_hash, lost.block_height
+                );
+                roll_back_order(&mut conn, order_id, OrderStatus::BitcoinHtlcFunded, &cause).await?;
+                info!("Order {} moved back to {}: {}", order_id, OrderStatus::BitcoinHtlcFunded.as_str(), cause);
+            } else if !status.is_terminal() {
+                warn!(
+                    "Order {} is {} but its funding {} has {} confirmations after a reorg",
+                    order_id,
+                    status.as_str(),
+                    lost.txid,
+                    confirmations
+                );
+            }
+        }
+        HtlcTransactionKind::Claim | HtlcTransactionKind::Refund => match reconfirmed {
+            Some(confirmation) => repository.record_spend_confirmed(htlc.id, confirmation.block_height).await?,
+            None => repository.reset_spend_confirmed(htlc.id).await?,
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::bitcoin::SimulatedChain;
+    use crate::services::order::{get_order_events, record_htlc_funding, transition_order};
+    use crate::services::transaction::create_claim_transaction;
+    use crate::services::build_htlc_script;
+    use crate::test_support::{htlc_params, insert_order, key, test_pool, PREIMAGE};
+    use bitcoin::{consensus::encode::serialize_hex, Address, Amount, Network};
+    use std::sync::Arc;
+
+    async fn order_status(pool: &SqlitePool, order_id: Uuid) -> OrderStatus {
+        let status: String = sqlx::query_scalar("SELECT status FROM orders WHERE id = ?")
+            .bind(order_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        OrderStatus::from_str(&status).unwrap()
+    }
+
+    /// Order with a confirmed HTLC funding on a fresh chain
+    async fn setup() -> (SqlitePool, Arc<SimulatedChain>, HtlcRecord, Uuid) {
+        let pool = test_pool().await;
+
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::BitcoinHtlcCreated).await;
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let mut htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        htlc.order_id = Some(order_id);
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+        let chain = Arc::new(SimulatedChain::new(Network::Testnet, 2_500_000));
+        let outpoint = chain.fund(&Address::from_str(&htlc.address).unwrap().assume_checked(), Amount::from_sat(100_000));
+        chain.mine(1);
+        record_htlc_funding(&pool, &htlc, outpoint, 100_000).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        transition_order(&mut conn, order_id, OrderStatus::BitcoinHtlcConfirmed, "funding confirmed")
+            .await
+            .unwrap();
+        drop(conn);
+
+        let htlc = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        (pool, chain, htlc, order_id)
+    }
+
+    #[tokio::test]
+    async fn test_reorged_funding_rolls_order_back() {
+        let (pool, chain, htlc, order_id) = setup().await;
+        let client = BitcoinClient::from_arc(chain.clone());
+        let funding_txid = htlc.funding_outpoint.unwrap().txid.to_string();
+
+        let changes = track_confirmations(&pool, &client, htlc.id).await.unwrap();
+        let confirmed = TxConfirmation {
+            txid: funding_txid.clone(),
+            htlc_id: htlc.id,
+            kind: HtlcTransactionKind::Funding,
+            block_height: 2_500_001,
+            block_hash: chain.block_hash(2_500_001).unwrap().to_string(),
+        };
+        assert_eq!(changes, vec![ConfirmationChange::Confirmed(confirmed.clone())]);
+        assert!(track_confirmations(&pool, &client, htlc.id).await.unwrap().is_empty());
+
+        // The funding block is replaced and the funding goes back to the mempool
+        chain.reorg(1);
+        let changes = track_confirmations(&pool, &client, htlc.id).await.unwrap();
+        assert_eq!(
+            changes,
+            vec![ConfirmationChange::Reorged {
+                lost: confirmed,
+                reconfirmed: None,
+            }]
+        );
+        assert_eq!(order_status(&pool, order_id).await, OrderStatus::BitcoinHtlcFunded);
+        let events = get_order_events(&pool, order_id).await.unwrap();
+        assert_eq!(events.last().unwrap().from_status, Some(OrderStatus::BitcoinHtlcConfirmed));
+        assert!(events.last().unwrap().cause.starts_with("reorg: funding"));
+
+        // Mined again in a later block, a fresh confirmation
+        chain.mine(1);
+        let changes = track_confirmations(&pool, &client, htlc.id).await.unwrap();
+        assert!(matches!(
+            changes.as_slice(),
+            [ConfirmationChange::Confirmed(TxConfirmation { block_height: 2_500_002, .. })]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reorged_spend_is_watched_again_without_touching_live_orders() {
+        let (pool, chain, htlc, order_id) = setup().await;
+        let client = BitcoinClient::from_arc(chain.clone());
+        let mut conn = pool.acquire().await.unwrap();
+        transition_order(&mut conn, order_id, OrderStatus::FusionOrderFillable, "fusion order live")
+            .await
+            .unwrap();
+        drop(conn);
+
+        let claim = create_claim_transaction(
+            htlc.funding_outpoint.unwrap(),
+            Amount::from_sat(100_000),
+            &build_htlc_script(&htlc.params).unwrap(),
+            &PREIMAGE,
+            &key(1).inner,
+            &Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap().assume_checked(),
+            Amount::from_sat(1_000),
+        )
+        .unwrap();
+        let claim_txid = client.broadcast_transaction(&serialize_hex(&claim)).await.unwrap();
+        let repository = HtlcRepository::new(pool.clone());
+        repository.record_claim(htlc.id, &claim_txid, &PREIMAGE).await.unwrap();
+        chain.mine(1);
+        repository.record_spend_confirmed(htlc.id, 2_500_002).await.unwrap();
+        assert_eq!(track_confirmations(&pool, &client, htlc.id).await.unwrap().len(), 2);
+
+        // Both blocks go; the funding is left unconfirmed too
+        chain.reorg(2);
+        let changes = track_confirmations(&pool, &client, htlc.id).await.unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes
+            .iter()
+            .all(|change| matches!(change, ConfirmationChange::Reorged { reconfirmed: None, .. })));
+        assert_eq!(repository.find_unconfirmed_spends().await.unwrap().len(), 1);
+        assert_eq!(order_status(&pool, order_id).await, OrderStatus::FusionOrderFillable);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::models::{ApiError, OrderStatus, WebhookEvent};
+use crate::repository::WebhookRepository;
+use chrono::Utc;
+use serde_json::json;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Move an order to `to` if the transition table allows it and log the transition
+///
+/// Takes a connection so callers can make the transition part of a larger transaction.
+/// Returns the status the order was in.
+pub async fn transition_order(
+    conn: &mut SqliteConnection,
+    order_id: Uuid,
+    to: OrderStatus,
+    cause: &str,
+) -> Result<OrderStatus, ApiError> {
+    let from = current_status(conn, order_id).await?;
+    if !from.can_transition_to(to) {
+        return Err(illegal_transition(order_id, from, to));
+    }
+
+    // Guard on the status we read so a concurrent transition cannot be overwritten
+    let now = Utc::now();
+    let updated = sqlx::query("UPDATE orders SET status = ?, updated_at = ? WHERE id = ? AND status = ?")
+        .bind(to.as_str())
+        .bind(now)
+        .bind(order_id)
+        .bind(from.as_str())
+        .execute(&mut *conn)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Err(illegal_transition(order_id, from, to));
+    }
+
+    record_order_event(conn, order_id, Some(from), to, cause).await?;
+    Ok(from)
+}
+
+/// Return an order to `to` after a reorg undid the chain event that moved it on
+///
+/// Only the steps [`OrderStatus::can_roll_back_to`] allows; logged like any transition.
+pub async fn roll_back_order(
+    conn: &mut SqliteConnection,
+    order_id: Uuid,
+    to: OrderStatus,
+    cause: &str,
+) -> Result<OrderStatus, ApiError> {
+    let from = current_status(conn, order_id).await?;
+    if !from.can_roll_back_to(to) {
+        return Err(illegal_transition(order_id, from, to));
+    }
+
+    let updated = sqlx::query("UPDATE orders SET status = ?, updated_at = ? WHERE id = ? AND status = ?")
+        .bind(to.as_str())
+        .bind(Utc::now())
+        .bind(order_id)
+        .bind(from.as_str())
+        .execute(&mut *conn)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Err(illegal_transition(order_id, from, to));
+    }
+
+    record_order_event(conn, order_id, Some(from), to, cause).await?;
+    Ok(from)
+}
+
+/// Fail early if `to` is not reachable from the order's current status
+///
+/// For checks before side effects that cannot be rolled back, such as a broadcast;
+/// the transition itself is still checked again by [`transition_order`].
+pub(crate) async fn ensure_transition(
+    pool: &SqlitePool,
+    order_id: Uuid,
+    to: OrderStatus,
+) -> Result<(), ApiError> {
+    let mut conn = pool.acquire().await?;
+    let from = current_status(&mut conn, order_id).await?;
+    if !from.can_transition_to(to) {
+        return Err(illegal_transition(order_id, from, to));
+    }
+    Ok(())
+}
+
+pub(crate) async fn current_status(conn: &mut SqliteConnection, order_id: Uuid) -> Result<OrderStatus, ApiError> {
+    let status: String = sqlx::query("SELECT status FROM orders WHERE id = ?")
+        .bind(order_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::NotFound {
+            code: "ORDER_NOT_FOUND".to_string(),
+            message: format!("Order {} not found", order_id),
+            details: None,
+        })?
+        .try_get("status")?;
+    OrderStatus::from_str(&status).map_err(|message| ApiError::InternalError {
+        code: "ORDER_CORRUPT".to_string(),
+        message: format!("Order {}: {}", order_id, message),
+        details: None,
+    })
+}
+
+/// Append a row to the order's audit log and queue the webhooks announcing it
+pub(crate) async fn record_order_event(
+    conn: &mut SqliteConnection,
+    order_id: Uuid,
+    from: Option<OrderStatus>,
+    to: OrderStatus,
+    cause: &str,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        "INSERT INTO order_events (order_id, from_status, to_status, cause, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(order_id)
+    .bind(from.map(|status| status.as_str()))
+    .bind(to.as_str())
+    .bind(cause)
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await?;
+
+    if let Some(event) = WebhookEvent::for_order_status(to) {
+        let data = json!({
+            "orderId": order_id,
+            "fromStatus": from.map(|status| status.as_str()),
+            "status": to.as_str(),
+            "cause": cause,
+        });
+        WebhookRepository::enqueue_with(conn, event, data).await?;
+    }
+    Ok(())
+}
+
+fn illegal_transition(order_id: Uuid, from: OrderStatus, to: OrderStatus) -> ApiError {
+    ApiError::Conflict {
+        code: "ILLEGAL_ORDER_TRANSITION".to_string(),
+        message: format!("Order {} cannot move from {} to {}", order_id, from.as_str(), to.as_str()),
+        details: Some(json!({
+            "order_id": order_id,
+            "from": from,
+            "to": to,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SwapDirection;
+    use crate::services::order::get_order_events;
+    use crate::test_support::{insert_order, test_pool};
+
+    #[tokio::test]
+    async fn test_transition_updates_status_and_logs_event() {
+        let pool = test_pool().await;
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::BitcoinHtlcCreated).await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let from = transition_order(&mut conn, order_id, OrderStatus::BitcoinHtlcFunded, "funding verified")
+            .await
+            .unwrap();
+        assert_eq!(from, OrderStatus::BitcoinHtlcCreated);
+        assert_eq!(current_status(&mut conn, order_id).await.unwrap(), OrderStatus::BitcoinHtlcFunded);
+        drop(conn);
+
+        let events = get_order_events(&pool, order_id).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].from_status, Some(OrderStatus::BitcoinHtlcCreated));
+        assert_eq!(events[0].to_status, OrderStatus::BitcoinHtlcFunded);
+        assert_eq!(events[0].cause, "funding verified");
+    }
+
+    #[tokio::test]
+    async fn test_transition_notifies_subscribed_webhooks() {
+        let pool = test_pool().await;
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::BitcoinHtlcCreated).await;
+        let repository = WebhookRepository::new(pool.clone());
+        repository
+            .insert(Uuid::new_v4(), "https://a.example/hook", &"a".repeat(32), &[WebhookEvent::OrderBitcoinHtlcFunded])
+            .await
+            .unwrap();
+
+        // Rolled back with the transition, nothing is announced
+        let mut tx = pool.begin().await.unwrap();
+        transition_order(&mut tx, order_id, OrderStatus::BitcoinHtlcFunded, "funding verified")
+            .await
+            .unwrap();
+        tx.rollback().await.unwrap();
+        assert!(repository.find_due(1, 10).await.unwrap().is_empty());
+
+        let mut conn = pool.acquire().await.unwrap();
+        transition_order(&mut conn, order_id, OrderStatus::BitcoinHtlcFunded, "funding verified")
+            .await
+            .unwrap();
+        drop(conn);
+
+        let due = repository.find_due(1, 10).await.unwrap();
+        assert_eq!(due.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&due[0].payload).unwrap();
+        assert_eq!(payload["event"], "order.bitcoin_htlc_funded");
+        assert_eq!(payload["data"]["orderId"], order_id.to_string());
+        assert_eq!(payload["data"]["fromStatus"], "bitcoin_htlc_created");
+    }
+
+    #[tokio::test]
+    async fn test_illegal_transition_is_a_conflict() {
+        let pool = test_pool().await;
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::Completed).await;
+
+        let error = ensure_transition(&pool, order_id, OrderStatus::Expired).await.unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "ILLEGAL_ORDER_TRANSITION"));
+
+        let mut conn = pool.acquire().await.unwrap();
+        let error = transition_order(&mut conn, order_id, OrderStatus::Expired, "refund")
+            .await
+            .unwrap_err();
+        match error {
+            ApiError::Conflict { details, .. } => {
+                let details = details.unwrap();
+                assert_eq!(details["from"], "completed");
+                assert_eq!(details["to"], "expired");
+            }
+            other => panic!("Expected Conflict, got {:?}", other),
+        }
+        assert_eq!(current_status(&mut conn, order_id).await.unwrap(), OrderStatus::Completed);
+        drop(conn);
+
+        assert!(get_order_events(&pool, order_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_roll_back_only_undoes_confirmations() {
+        let pool = test_pool().await;
+        let order_id = insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::BitcoinHtlcConfirmed).await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        roll_back_order(&mut conn, order_id, OrderStatus::BitcoinHtlcFunded, "reorg")
+            .await
+            .unwrap();
+        assert_eq!(current_status(&mut conn, order_id).await.unwrap(), OrderStatus::BitcoinHtlcFunded);
+        let error = roll_back_order(&mut conn, order_id, OrderStatus::BitcoinHtlcCreated, "reorg")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ApiError::Conflict { ref code, .. } if code == "ILLEGAL_ORDER_TRANSITION"));
+        drop(conn);
+
+        let events = get_order_events(&pool, order_id).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].to_status, OrderStatus::BitcoinHtlcFunded);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_order_is_not_found() {
+        let pool = test_pool().await;
+        insert_order(&pool, SwapDirection::EthToBtc, OrderStatus::Created).await;
+        let error = ensure_transition(&pool, Uuid::new_v4(), OrderStatus::Failed).await.unwrap_err();
+        assert!(matches!(error, ApiError::NotFound { ref code, .. } if code == "ORDER_NOT_FOUND"));
+    }
+}
@@ -0,0 +1,276 @@
+use crate::models::*;
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::BitcoinClient;
+use crate::services::htlc::{parse_htlc_spend, HtlcSpend};
+use crate::services::order::refund_htlc::announce_refund;
+use crate::services::order::transition_order::current_status;
+use crate::services::order::transition_order;
+use bitcoin::{ScriptBuf, Witness};
+use log::{info, warn};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// Detect a spend of a funded HTLC made outside the service and learn from it
+///
+/// In the BTC_TO_ETH direction the user claims the Bitcoin HTLC, and that claim is
+/// how the resolver learns the secret it needs on Ethereum. The spending input is
+/// classified from its witness or scriptSig: a claim stores the preimage and moves the
+/// order to `PreimageRevealed`, or straight to `BitcoinHtlcClaimed` if the funding had
+/// not confirmed yet; a refund is recorded and moves it to `Expired`. The
+/// order is left alone when its status does not allow the move, the HTLC is recorded
+/// either way.
+///
+/// Returns the spend, or `None` while the funding output is unspent or a spend has
+/// already been recorded.
+pub async fn watch_htlc_spend(
+    pool: &SqlitePool,
+    bitcoin_client: &BitcoinClient,
+    htlc_id: Uuid,
+) -> Result<Option<HtlcSpend>, ApiError> {
+    let repository = HtlcRepository::new(pool.clone());
+    let htlc = repository.find(htlc_id).await?;
+    if htlc.claim_txid.is_some() || htlc.refund_txid.is_some() {
+        return Ok(None);
+    }
+    let Some(outpoint) = htlc.funding_outpoint else {
+        return Ok(None);
+    };
+    let funding_txid = outpoint.txid.to_string();
+    let Some(spending_txid) = bitcoin_client.get_outspend(&funding_txid, outpoint.vout).await? else {
+        return Ok(None);
+    };
+
+    let spending = bitcoin_client.get_transaction(&spending_txid).await?;
+    let input = spending
+        .vin
+        .iter()
+        .find(|input| input.txid == funding_txid && input.vout == outpoint.vout)
+        .ok_or_else(|| ApiError::InternalError {
+            code: "INVALID_TRANSACTION".to_string(),
+            message: format!("Transaction {} does not spend HTLC output {}", spending_txid, outpoint),
+            details: None,
+        })?;
+    let script_sig = ScriptBuf::from_hex(&input.scriptsig).map_err(|_| invalid_input(&spending_txid))?;
+    let witness = input
+        .witness
+        .iter()
+        .flatten()
+        .map(hex::decode)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid_input(&spending_txid))?;
+    let spend = parse_htlc_spend(&htlc.params, &script_sig, &Witness::from_slice(&witness))?;
+
+    match spend {
+        HtlcSpend::Claim { ref preimage } => {
+            repository.record_claim(htlc.id, &spending_txid, preimage).await?;
+            info!("HTLC {} claimed in {}, preimage {}", htlc.id, spending_txid, hex::encode(preimage));
+            if let Some(order_id) = htlc.order_id {
+                record_order_spend(
+                    pool,
+                    &htlc,
+                    order_id,
+                    HtlcSpendPath::Claim,
+                    &spending_txid,
+                    &format!("preimage revealed by claim {}", spending_txid),
+                )
+                .await?;
+            }
+        }
+        HtlcSpend::Refund => {
+            repository.record_refund(htlc.id, &spending_txid).await?;
+            info!("HTLC {} refunded in {}", htlc.id, spending_txid);
+            if let Some(order_id) = htlc.order_id {
+                record_order_spend(
+                    pool,
+                    &htlc,
+                    order_id,
+                    HtlcSpendPath::Refund,
+                    &spending_txid,
+                    &format!("refund {} seen on chain", spending_txid),
+                )
+                .await?;
+            }
+        }
+        HtlcSpend::Cooperative => {
+            info!("HTLC {} closed cooperatively in {}", htlc.id, spending_txid);
+        }
+    }
+    Ok(Some(spend))
+}
+
+/// Point the order at the spend and move it on, to `PreimageRevealed` for a claim and
+/// `Expired` for a refund, if its status allows
+async fn record_order_spend(
+    pool: &SqlitePool,
+    htlc: &HtlcRecord,
+    order_id: Uuid,
+    path: HtlcSpendPath,
+    txid: &str,
+    cause: &str,
+) -> Result<(), ApiError> {
+    let (to, column) = match path {
+        HtlcSpendPath::Claim => (OrderStatus::PreimageRevealed, "htlc_claim_tx"),
+        HtlcSpendPath::Refund => (OrderStatus::Expired, "htlc_refund_tx"),
+    };
+    let mut tx = pool.begin().await?;
+    sqlx::query(&format!("UPDATE orders SET {} = ? WHERE id = ?", column))
+        .bind(txid)
+        .bind(order_id)
+        .execute(&mut *tx)
+        .await?;
+    // Claimed before the funding confirmed, the order skips straight to claimed
+    let from = current_status(&mut tx, order_id).await?;
+    let to = match path {
+        HtlcSpendPath::Claim if !from.can_transition_to(to) => OrderStatus::BitcoinHtlcClaimed,
+        _ => to,
+    };
+    match transition_order(&mut tx, order_id, to, cause).await {
+        Ok(_) => {}
+        Err(ApiError::Conflict { code, message, .. }) if code == "ILLEGAL_ORDER_TRANSITION" => {
+            warn!("{}: {}", cause, message);
+        }
+        Err(e) => return Err(e),
+    }
+    if path == HtlcSpendPath::Refund {
+        announce_refund(&mut tx, order_id, htlc.id, txid).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+fn invalid_input(txid: &str) -> ApiError {
+    ApiError::InternalError {
+        code: "INVALID_TRANSACTION".to_string(),
+        message: format!("Transaction {} has an undecodable HTLC input", txid),
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::bitcoin::SimulatedChain;
+    use crate::services::order::{get_order_events, record_htlc_funding};
+    use crate::services::transaction::{create_claim_transaction, create_refund_transaction};
+    use crate::services::build_htlc_script;
+    use crate::test_support::{htlc_params, insert_order, key, test_pool, PREIMAGE};
+    use bitcoin::{consensus::encode::serialize_hex, Address, Amount, Network};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    async fn setup(status: OrderStatus) -> (SqlitePool, Arc<SimulatedChain>, HtlcRecord, Uuid) {
+        let pool = test_pool().await;
+
+        let order_id = insert_order(&pool, SwapDirection::BtcToEth, status).await;
+        let params = HtlcParams { timeout: 2_500_010, ..htlc_params(HtlcOutputType::P2wsh) };
+        let mut htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+        htlc.order_id = Some(order_id);
+        HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+        let chain = Arc::new(SimulatedChain::new(Network::Testnet, 2_500_000));
+        let outpoint = chain.fund(&Address::from_str(&htlc.address).unwrap().assume_checked(), Amount::from_sat(100_000));
+        chain.mine(1);
+        record_htlc_funding(&pool, &htlc, outpoint, 100_000).await.unwrap();
+        let htlc = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        (pool, chain, htlc, order_id)
+    }
+
+    fn destination() -> Address {
+        Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+            .unwrap()
+            .require_network(Network::Testnet)
+            .unwrap()
+    }
+
+    async fn order_status(pool: &SqlitePool, order_id: Uuid) -> (String, Option<String>, Option<String>) {
+        sqlx::query_as("SELECT status, htlc_claim_tx, htlc_refund_tx FROM orders WHERE id = ?")
+            .bind(order_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_extracts_preimage_from_user_claim() {
+        let (pool, chain, htlc, order_id) = setup(OrderStatus::BitcoinHtlcConfirmed).await;
+        let client = BitcoinClient::from_arc(chain.clone());
+        assert!(watch_htlc_spend(&pool, &client, htlc.id).await.unwrap().is_none());
+
+        // The user claims with their own key, the service never saw the preimage
+        let claim = create_claim_transaction(
+            htlc.funding_outpoint.unwrap(),
+            Amount::from_sat(100_000),
+            &build_htlc_script(&htlc.params).unwrap(),
+            &PREIMAGE,
+            &key(1).inner,
+            &destination(),
+            Amount::from_sat(1_000),
+        )
+        .unwrap();
+        let claim_txid = client.broadcast_transaction(&serialize_hex(&claim)).await.unwrap();
+
+        let spend = watch_htlc_spend(&pool, &client, htlc.id).await.unwrap();
+        assert_eq!(spend, Some(HtlcSpend::Claim { preimage: PREIMAGE.to_vec() }));
+
+        let htlc = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        assert_eq!(htlc.status, HtlcStatus::Claimed);
+        assert_eq!(htlc.preimage, Some(PREIMAGE.to_vec()));
+        assert_eq!(htlc.claim_txid.as_deref(), Some(claim_txid.as_str()));
+        assert_eq!(
+            order_status(&pool, order_id).await,
+            ("preimage_revealed".to_string(), Some(claim_txid.clone()), None)
+        );
+        let events = get_order_events(&pool, order_id).await.unwrap();
+        assert!(events.last().unwrap().cause.contains(&claim_txid));
+
+        // Recorded once; later ticks leave it alone
+        assert!(watch_htlc_spend(&pool, &client, htlc.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_records_refund_and_keeps_order_that_cannot_move() {
+        let (pool, chain, htlc, order_id) = setup(OrderStatus::BitcoinHtlcConfirmed).await;
+        let client = BitcoinClient::from_arc(chain.clone());
+        chain.mine(10);
+
+        let refund = create_refund_transaction(
+            htlc.funding_outpoint.unwrap(),
+            Amount::from_sat(100_000),
+            &build_htlc_script(&htlc.params).unwrap(),
+            &key(2).inner,
+            &destination(),
+            htlc.params.timeout,
+            Amount::from_sat(1_000),
+        )
+        .unwrap();
+        let refund_txid = client.broadcast_transaction(&serialize_hex(&refund)).await.unwrap();
+        chain.mine(1);
+
+        assert_eq!(watch_htlc_spend(&pool, &client, htlc.id).await.unwrap(), Some(HtlcSpend::Refund));
+        assert_eq!(
+            HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap().refund_txid,
+            Some(refund_txid.clone())
+        );
+        assert_eq!(order_status(&pool, order_id).await, ("expired".to_string(), None, Some(refund_txid)));
+
+        // A claim seen while the order cannot take the preimage still stores it
+        let (pool, chain, htlc, order_id) = setup(OrderStatus::FusionOrderFilling).await;
+        let client = BitcoinClient::from_arc(chain.clone());
+        let claim = create_claim_transaction(
+            htlc.funding_outpoint.unwrap(),
+            Amount::from_sat(100_000),
+            &build_htlc_script(&htlc.params).unwrap(),
+            &PREIMAGE,
+            &key(1).inner,
+            &destination(),
+            Amount::from_sat(1_000),
+        )
+        .unwrap();
+        client.broadcast_transaction(&serialize_hex(&claim)).await.unwrap();
+
+        assert!(watch_htlc_spend(&pool, &client, htlc.id).await.unwrap().is_some());
+        let htlc = HtlcRepository::new(pool.clone()).find(htlc.id).await.unwrap();
+        assert_eq!(htlc.preimage, Some(PREIMAGE.to_vec()));
+        assert_eq!(order_status(&pool, order_id).await.0, "fusion_order_filling");
+    }
+}
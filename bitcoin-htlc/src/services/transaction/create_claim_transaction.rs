@@ -1,23 +1,57 @@
 use bitcoin::{
     absolute::LockTime,
+    ecdsa::Signature,
     key::Secp256k1,
-    secp256k1::{Message, SecretKey},
-    sighash::{EcdsaSighashType, SighashCache},
+    secp256k1::SecretKey,
+    sighash::EcdsaSighashType,
     transaction::Version,
-    Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
-    hashes::Hash,
+    Address, Amount, FeeRate, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
 };
-use crate::models::ApiError;
+use crate::models::{ApiError, HtlcOutputType, HtlcScript};
+use crate::services::transaction::{create_taproot_claim_transaction, fee_for_vsize, htlc_claim_vsize, output_after_fee};
+use crate::services::htlc::{build_claim_spend, htlc_signature_message};
 
 /// Create claim transaction for HTLC
 pub fn create_claim_transaction(
     htlc_outpoint: OutPoint,
     htlc_amount: Amount,
-    redeem_script: &[u8],
+    htlc_script: &HtlcScript,
     preimage: &[u8],
     claim_key: &SecretKey,
     claim_address: &Address,
     fee: Amount,
+) -> Result<Transaction, ApiError> {
+    if htlc_script.output_type == HtlcOutputType::P2tr {
+        return create_taproot_claim_transaction(htlc_outpoint, htlc_amount, htlc_script, preimage, claim_key, claim_address, fee);
+    }
+
+    let mut transaction = unsigned_claim_transaction(htlc_outpoint, htlc_amount, claim_address, fee)?;
+    
+    // Sign the transaction
+    let secp = Secp256k1::new();
+    let message = htlc_signature_message(
+        &transaction,
+        0,
+        htlc_script,
+        htlc_amount,
+        EcdsaSighashType::All,
+    )?;
+    let signature = Signature::sighash_all(secp.sign_ecdsa_low_r(&message, claim_key));
+    
+    // Place signature, preimage and IF selector where the output type expects them
+    let (script_sig, witness) = build_claim_spend(htlc_script, &signature, preimage)?;
+    transaction.input[0].script_sig = script_sig;
+    transaction.input[0].witness = witness;
+    
+    Ok(transaction)
+}
+
+/// Unsigned claim spending the HTLC output to `claim_address`, shared by every output type
+pub(crate) fn unsigned_claim_transaction(
+    htlc_outpoint: OutPoint,
+    htlc_amount: Amount,
+    claim_address: &Address,
+    fee: Amount,
 ) -> Result<Transaction, ApiError> {
     if htlc_amount <= fee {
         return Err(ApiError::InternalError {
@@ -26,11 +60,8 @@ pub fn create_claim_transaction(
             details: None,
         });
     }
-    
-    let claim_amount = htlc_amount - fee;
-    
-    // Create the claim transaction
-    let mut transaction = Transaction {
+
+    Ok(Transaction {
         version: Version::TWO,
         lock_time: LockTime::ZERO,
         input: vec![TxIn {
@@ -40,69 +71,66 @@ pub fn create_claim_transaction(
             witness: Witness::new(),
         }],
         output: vec![TxOut {
-            value: claim_amount,
+            value: htlc_amount - fee,
             script_pubkey: claim_address.script_pubkey(),
         }],
-    };
-    
-    // Sign the transaction
-    let secp = Secp256k1::new();
-    let mut sighash_cache = SighashCache::new(&transaction);
-    let sighash = sighash_cache
-        .p2wsh_signature_hash(
-            0,
-            &ScriptBuf::from(redeem_script.to_vec()),
-            htlc_amount,
-            EcdsaSighashType::All,
-        )
-        .map_err(|e| ApiError::InternalError {
-            code: "BITCOIN_TRANSACTION_ERROR".to_string(),
-            message: format!("Failed to compute sighash: {}", e),
-            details: None,
-        })?;
-    
-    let message = Message::from_digest(sighash.to_byte_array());
-    let signature = secp.sign_ecdsa(&message, claim_key);
-    
-    // Build witness script
-    let mut witness = Witness::new();
-    witness.push(signature.serialize_der());
-    witness.push([EcdsaSighashType::All as u8]);
-    witness.push(preimage);
-    witness.push([1u8]); // OP_TRUE for IF branch
-    witness.push(redeem_script);
-    
-    transaction.input[0].witness = witness;
-    
-    Ok(transaction)
+    })
+}
+
+/// Create claim transaction paying `fee_rate` on its signed vsize
+///
+/// Rates below the minimum relay fee are raised to it, and a claim whose output would
+/// be dust after the fee is rejected.
+pub fn create_claim_transaction_at_fee_rate(
+    htlc_outpoint: OutPoint,
+    htlc_amount: Amount,
+    htlc_script: &HtlcScript,
+    preimage: &[u8],
+    claim_key: &SecretKey,
+    claim_address: &Address,
+    fee_rate: FeeRate,
+) -> Result<Transaction, ApiError> {
+    let destination = claim_address.script_pubkey();
+    let fee = fee_for_vsize(fee_rate, htlc_claim_vsize(htlc_script, preimage.len(), &destination)?)?;
+    output_after_fee(htlc_amount, fee, &destination)?;
+
+    create_claim_transaction(htlc_outpoint, htlc_amount, htlc_script, preimage, claim_key, claim_address, fee)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::{build_htlc_script, verify_spend};
+    use crate::test_support::{htlc_params, key, PREIMAGE};
     use bitcoin::Network;
     use std::str::FromStr;
 
-    #[test]
-    fn test_create_claim_transaction_success() {
-        let _secp = Secp256k1::new();
-        let claim_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
-        let claim_address = Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+    fn claim_address() -> Address {
+        Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
             .unwrap()
             .require_network(Network::Testnet)
-            .unwrap();
-        
-        let htlc_outpoint = OutPoint::default();
-        let redeem_script = vec![0u8; 100]; // Mock script
-        let preimage = [2u8; 32];
+            .unwrap()
+    }
+
+    fn funding_output(htlc: &HtlcScript) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: htlc.script_pubkey.clone(),
+        }
+    }
+
+    #[test]
+    fn test_create_claim_transaction_success() {
+        let claim_key = key(1).inner;
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap();
         
         let transaction = create_claim_transaction(
-            htlc_outpoint,
+            OutPoint::default(),
             Amount::from_sat(100_000),
-            &redeem_script,
-            &preimage,
+            &htlc,
+            &PREIMAGE,
             &claim_key,
-            &claim_address,
+            &claim_address(),
             Amount::from_sat(5_000),
         ).unwrap();
         
@@ -114,22 +142,187 @@ mod tests {
 
     #[test]
     fn test_create_claim_transaction_insufficient_amount() {
-        let claim_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
-        let claim_address = Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
-            .unwrap()
-            .require_network(Network::Testnet)
-            .unwrap();
+        let claim_key = key(1).inner;
         
         let result = create_claim_transaction(
             OutPoint::default(),
             Amount::from_sat(1_000),
-            &[],
+            &build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap(),
             &[],
             &claim_key,
-            &claim_address,
+            &claim_address(),
             Amount::from_sat(5_000),
         );
         
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_claim_spend_verifies_for_every_output_type() {
+        let claim_key = key(1).inner;
+
+        for output_type in [
+            HtlcOutputType::P2sh,
+            HtlcOutputType::P2shP2wsh,
+            HtlcOutputType::P2wsh,
+            HtlcOutputType::P2tr,
+        ] {
+            let htlc = build_htlc_script(&htlc_params(output_type)).unwrap();
+            let transaction = create_claim_transaction(
+                OutPoint::default(),
+                Amount::from_sat(100_000),
+                &htlc,
+                &PREIMAGE,
+                &claim_key,
+                &claim_address(),
+                Amount::from_sat(5_000),
+            ).unwrap();
+
+            let result = verify_spend(&transaction, 0, &[funding_output(&htlc)]);
+            assert!(result.is_ok(), "{:?}: {:?}", output_type, result);
+            assert_eq!(output_type.is_segwit(), !transaction.input[0].witness.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_claim_at_fee_rate_pays_for_its_exact_vsize() {
+        let claim_key = key(1).inner;
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(7);
+
+        for output_type in [
+            HtlcOutputType::P2sh,
+            HtlcOutputType::P2shP2wsh,
+            HtlcOutputType::P2wsh,
+            HtlcOutputType::P2tr,
+        ] {
+            let htlc = build_htlc_script(&htlc_params(output_type)).unwrap();
+            let transaction = create_claim_transaction_at_fee_rate(
+                OutPoint::default(),
+                Amount::from_sat(100_000),
+                &htlc,
+                &PREIMAGE,
+                &claim_key,
+                &claim_address(),
+                fee_rate,
+            ).unwrap();
+
+            let vsize = transaction.vsize() as u64;
+            assert_eq!(htlc_claim_vsize(&htlc, PREIMAGE.len(), &claim_address().script_pubkey()).unwrap(), vsize, "{:?}", output_type);
+            assert_eq!(transaction.output[0].value, Amount::from_sat(100_000 - 7 * vsize));
+            assert!(verify_spend(&transaction, 0, &[funding_output(&htlc)]).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_claim_at_fee_rate_rejects_dust_output() {
+        let claim_key = key(1).inner;
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap();
+        let claim = |htlc_amount: u64| create_claim_transaction_at_fee_rate(
+            OutPoint::default(),
+            Amount::from_sat(htlc_amount),
+            &htlc,
+            &PREIMAGE,
+            &claim_key,
+            &claim_address(),
+            FeeRate::ZERO,
+        );
+
+        // Zero is raised to 1 sat/vB, leaving exactly the 294 sat P2WPKH dust limit
+        let vsize = htlc_claim_vsize(&htlc, PREIMAGE.len(), &claim_address().script_pubkey()).unwrap();
+        assert_eq!(claim(vsize + 294).unwrap().output[0].value, Amount::from_sat(294));
+        assert!(claim(vsize + 293).is_err());
+    }
+
+    #[test]
+    fn test_claim_spend_rejects_wrong_preimage() {
+        let claim_key = key(1).inner;
+
+        for output_type in [HtlcOutputType::P2sh, HtlcOutputType::P2wsh, HtlcOutputType::P2tr] {
+            let htlc = build_htlc_script(&htlc_params(output_type)).unwrap();
+            let transaction = create_claim_transaction(
+                OutPoint::default(),
+                Amount::from_sat(100_000),
+                &htlc,
+                &[9u8; 32],
+                &claim_key,
+                &claim_address(),
+                Amount::from_sat(5_000),
+            ).unwrap();
+
+            assert!(verify_spend(&transaction, 0, &[funding_output(&htlc)]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_claim_spend_rejects_wrong_key() {
+        // The sender key cannot take the claim branch
+        let sender_key = key(2).inner;
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2shP2wsh)).unwrap();
+
+        let transaction = create_claim_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &PREIMAGE,
+            &sender_key,
+            &claim_address(),
+            Amount::from_sat(5_000),
+        ).unwrap();
+
+        assert!(verify_spend(&transaction, 0, &[funding_output(&htlc)]).is_err());
+    }
+
+    #[test]
+    fn test_claim_spend_rejects_wrong_amount() {
+        // Segwit signatures commit to the spent amount
+        let claim_key = key(1).inner;
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap();
+
+        let transaction = create_claim_transaction(
+            OutPoint::default(),
+            Amount::from_sat(90_000),
+            &htlc,
+            &PREIMAGE,
+            &claim_key,
+            &claim_address(),
+            Amount::from_sat(5_000),
+        ).unwrap();
+
+        assert!(verify_spend(&transaction, 0, &[funding_output(&htlc)]).is_err());
+    }
+
+    #[test]
+    fn test_taproot_claim_reveals_only_the_claim_leaf() {
+        let claim_key = key(1).inner;
+        let sender_key = key(2).inner;
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2tr)).unwrap();
+        let taproot = htlc.taproot.as_ref().unwrap();
+
+        let transaction = create_claim_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &PREIMAGE,
+            &claim_key,
+            &claim_address(),
+            Amount::from_sat(5_000),
+        ).unwrap();
+
+        let witness = &transaction.input[0].witness;
+        assert_eq!(witness.len(), 4);
+        assert_eq!(witness.nth(2).unwrap(), taproot.claim_leaf.as_bytes());
+        assert!(transaction.input[0].script_sig.is_empty());
+
+        // A sender signature on the claim leaf is a hard failure under BIP342
+        let transaction = create_claim_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &PREIMAGE,
+            &sender_key,
+            &claim_address(),
+            Amount::from_sat(5_000),
+        ).unwrap();
+        assert!(verify_spend(&transaction, 0, &[funding_output(&htlc)]).is_err());
+    }
+}
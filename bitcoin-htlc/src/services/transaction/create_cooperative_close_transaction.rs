@@ -0,0 +1,173 @@
+use bitcoin::{
+    absolute::LockTime,
+    hashes::Hash,
+    secp256k1::{schnorr, Message},
+    sighash::{Prevouts, SighashCache, TapSighashType},
+    transaction::Version,
+    Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use crate::models::{ApiError, HtlcScript};
+use crate::services::transaction::create_taproot_claim_transaction::taproot_tree;
+
+/// Create the unsigned key path spend of a Taproot HTLC
+///
+/// Returns the transaction together with the BIP341 sighash both parties sign with
+/// MuSig2 (see [`crate::services::htlc::musig2`]). Neither leaf is revealed on chain.
+pub fn create_cooperative_close_transaction(
+    htlc_outpoint: OutPoint,
+    htlc_amount: Amount,
+    htlc_script: &HtlcScript,
+    destination: &Address,
+    fee: Amount,
+) -> Result<(Transaction, Message), ApiError> {
+    taproot_tree(htlc_script)?;
+    if htlc_amount <= fee {
+        return Err(ApiError::InternalError {
+            code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+            message: "HTLC amount must be greater than fee".to_string(),
+            details: None,
+        });
+    }
+
+    let transaction = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: htlc_outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: htlc_amount - fee,
+            script_pubkey: destination.script_pubkey(),
+        }],
+    };
+
+    let prevouts = [TxOut {
+        value: htlc_amount,
+        script_pubkey: htlc_script.script_pubkey.clone(),
+    }];
+    let sighash = SighashCache::new(&transaction)
+        .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+        .map_err(|e| ApiError::InternalError {
+            code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+            message: format!("Failed to compute sighash: {}", e),
+            details: None,
+        })?;
+
+    Ok((transaction, Message::from_digest(sighash.to_byte_array())))
+}
+
+/// Attach the aggregated MuSig2 signature to a cooperative close
+pub fn finalize_cooperative_close(
+    mut transaction: Transaction,
+    signature: &schnorr::Signature,
+) -> Transaction {
+    let mut witness = Witness::new();
+    witness.push(signature.as_ref());
+    transaction.input[0].witness = witness;
+    transaction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::HtlcOutputType;
+    use crate::services::htlc::{cooperative_key_context, musig2};
+    use crate::services::{build_htlc_script, verify_spend};
+    use crate::test_support::{htlc_params, key};
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::Network;
+    use std::str::FromStr;
+
+    fn destination() -> Address {
+        Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+            .unwrap()
+            .require_network(Network::Testnet)
+            .unwrap()
+    }
+
+    fn musig_sign(htlc: &HtlcScript, keys: &[SecretKey], message: &Message) -> schnorr::Signature {
+        let context = cooperative_key_context(&htlc_params(HtlcOutputType::P2tr))
+            .unwrap()
+            .with_taproot_tweak(Some(htlc.taproot.as_ref().unwrap().merkle_root))
+            .unwrap();
+
+        let nonces: Vec<_> = keys
+            .iter()
+            .map(|key| musig2::generate_nonce(key, &context, message).unwrap())
+            .collect();
+        let public_nonces: Vec<_> = nonces.iter().map(|(_, public)| public.clone()).collect();
+        let aggregate_nonce = musig2::aggregate_nonces(&public_nonces).unwrap();
+
+        let partials: Vec<_> = nonces
+            .into_iter()
+            .zip(keys)
+            .map(|((secret, _), key)| {
+                musig2::partial_sign(&context, secret, key, &aggregate_nonce, message).unwrap()
+            })
+            .collect();
+        musig2::aggregate_partial_signatures(&context, &aggregate_nonce, message, &partials).unwrap()
+    }
+
+    #[test]
+    fn test_cooperative_close_verifies_on_key_path() {
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2tr)).unwrap();
+        let (transaction, message) = create_cooperative_close_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &destination(),
+            Amount::from_sat(500),
+        )
+        .unwrap();
+
+        let keys = [key(1).inner, key(2).inner];
+        let signature = musig_sign(&htlc, &keys, &message);
+        let transaction = finalize_cooperative_close(transaction, &signature);
+
+        assert_eq!(transaction.input[0].witness.len(), 1);
+        let prevout = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: htlc.script_pubkey.clone(),
+        };
+        assert!(verify_spend(&transaction, 0, &[prevout]).is_ok());
+    }
+
+    #[test]
+    fn test_cooperative_close_rejects_signature_over_other_amount() {
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2tr)).unwrap();
+        let (transaction, message) = create_cooperative_close_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &destination(),
+            Amount::from_sat(500),
+        )
+        .unwrap();
+
+        let keys = [key(1).inner, key(2).inner];
+        let transaction = finalize_cooperative_close(transaction, &musig_sign(&htlc, &keys, &message));
+
+        let prevout = TxOut {
+            value: Amount::from_sat(90_000),
+            script_pubkey: htlc.script_pubkey.clone(),
+        };
+        assert!(verify_spend(&transaction, 0, &[prevout]).is_err());
+    }
+
+    #[test]
+    fn test_cooperative_close_requires_taproot_output() {
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap();
+
+        let result = create_cooperative_close_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &destination(),
+            Amount::from_sat(500),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use bitcoin::{
+    absolute::LockTime, transaction::Version, Address, Amount, FeeRate, OutPoint, PrivateKey, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Witness,
+};
+use crate::models::ApiError;
+use crate::services::transaction::{fee_for_vsize, funding_vsize, output_after_fee, sign_wallet_inputs};
+
+/// Child spending an unconfirmed wallet output so it and its parent pay `package_fee_rate`
+///
+/// Miners take a low-fee parent together with the child that pays for it. The child
+/// brings the fee of both up to the package rate on their combined vsize, and pays at
+/// least the minimum relay fee on its own. `parent_output` must pay to the P2WPKH or
+/// P2TR address of `wallet_key`; the rest goes to `destination`.
+pub fn create_cpfp_transaction(
+    parent_outpoint: OutPoint,
+    parent_output: &TxOut,
+    parent_vsize: u64,
+    parent_fee: Amount,
+    wallet_key: &PrivateKey,
+    destination: &Address,
+    package_fee_rate: FeeRate,
+) -> Result<Transaction, ApiError> {
+    let destination = destination.script_pubkey();
+    let child_vsize = funding_vsize(
+        1,
+        &parent_output.script_pubkey,
+        &[TxOut {
+            value: Amount::ZERO,
+            script_pubkey: destination.clone(),
+        }],
+    )?;
+
+    let package_fee = fee_for_vsize(package_fee_rate, parent_vsize + child_vsize)?;
+    let fee = package_fee
+        .checked_sub(parent_fee)
+        .unwrap_or(Amount::ZERO)
+        .max(fee_for_vsize(FeeRate::BROADCAST_MIN, child_vsize)?);
+    let value = output_after_fee(parent_output.value, fee, &destination)?;
+
+    let mut transaction = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: parent_outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: destination,
+        }],
+    };
+    sign_wallet_inputs(&mut transaction, std::slice::from_ref(parent_output), wallet_key)?;
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::verify_spend;
+    use bitcoin::{hashes::Hash, key::Secp256k1, secp256k1::SecretKey, Network, Txid};
+
+    fn wallet() -> (PrivateKey, Address) {
+        let key = PrivateKey::new(SecretKey::from_slice(&[4u8; 32]).unwrap(), Network::Testnet);
+        let address = Address::p2wpkh(&key.public_key(&Secp256k1::new()), Network::Testnet).unwrap();
+        (key, address)
+    }
+
+    #[test]
+    fn test_child_pays_for_the_package() {
+        let (key, address) = wallet();
+        let parent_output = TxOut {
+            value: Amount::from_sat(99_000),
+            script_pubkey: address.script_pubkey(),
+        };
+        let outpoint = OutPoint::new(Txid::all_zeros(), 0);
+
+        // A 150 vB parent paying 1 sat/vB, bumped to 20 sat/vB as a package
+        let child = create_cpfp_transaction(
+            outpoint,
+            &parent_output,
+            150,
+            Amount::from_sat(150),
+            &key,
+            &address,
+            FeeRate::from_sat_per_vb_unchecked(20),
+        )
+        .unwrap();
+        verify_spend(&child, 0, std::slice::from_ref(&parent_output)).unwrap();
+
+        let child_fee = parent_output.value - child.output[0].value;
+        let package_vsize = 150 + child.vsize() as u64;
+        assert!(Amount::from_sat(150) + child_fee >= Amount::from_sat(20 * package_vsize));
+        assert!(child.is_explicitly_rbf());
+
+        // A parent already above the package rate still leaves the child the relay minimum
+        let child = create_cpfp_transaction(
+            outpoint,
+            &parent_output,
+            150,
+            Amount::from_sat(50_000),
+            &key,
+            &address,
+            FeeRate::from_sat_per_vb_unchecked(20),
+        )
+        .unwrap();
+        assert!(parent_output.value - child.output[0].value >= Amount::from_sat(child.vsize() as u64));
+    }
+
+    #[test]
+    fn test_rejects_output_of_another_key() {
+        let (key, address) = wallet();
+        let other = PrivateKey::new(SecretKey::from_slice(&[5u8; 32]).unwrap(), Network::Testnet);
+        let parent_output = TxOut {
+            value: Amount::from_sat(99_000),
+            script_pubkey: address.script_pubkey(),
+        };
+
+        let result = create_cpfp_transaction(
+            OutPoint::new(Txid::all_zeros(), 0),
+            &parent_output,
+            150,
+            Amount::from_sat(150),
+            &other,
+            &address,
+            FeeRate::from_sat_per_vb_unchecked(20),
+        );
+        assert!(matches!(result, Err(ApiError::InternalError { ref code, .. }) if code == "BITCOIN_SIGNING_ERROR"));
+        assert!(create_cpfp_transaction(
+            OutPoint::new(Txid::all_zeros(), 0),
+            &parent_output,
+            150,
+            Amount::from_sat(150),
+            &key,
+            &address,
+            FeeRate::from_sat_per_vb_unchecked(1_000),
+        )
+        .is_err());
+    }
+}
@@ -1,9 +1,10 @@
 use bitcoin::{
     absolute::LockTime,
     transaction::Version,
-    Address, Amount, OutPoint, Sequence, Transaction, TxIn, TxOut,
+    Address, Amount, FeeRate, OutPoint, Sequence, Transaction, TxIn, TxOut,
 };
 use crate::models::ApiError;
+use crate::services::transaction::{fee_for_vsize, funding_vsize};
 
 /// Create funding transaction for HTLC
 pub fn create_funding_transaction(
@@ -63,6 +64,59 @@ pub fn create_funding_transaction(
     })
 }
 
+/// Create funding transaction paying `fee_rate` on its signed vsize
+///
+/// The inputs are taken to be the wallet's own coins, of the same type as the change
+/// address. Change below the dust limit is left to the fee instead of creating an
+/// unrelayable output.
+pub fn create_funding_transaction_at_fee_rate(
+    inputs: Vec<(OutPoint, Amount)>,
+    htlc_address: &Address,
+    htlc_amount: Amount,
+    change_address: &Address,
+    fee_rate: FeeRate,
+) -> Result<Transaction, ApiError> {
+    let htlc_script_pubkey = htlc_address.script_pubkey();
+    let change_script_pubkey = change_address.script_pubkey();
+    if htlc_amount < htlc_script_pubkey.dust_value() {
+        return Err(ApiError::InternalError {
+            code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+            message: format!("HTLC amount {} is below the {} dust limit", htlc_amount, htlc_script_pubkey.dust_value()),
+            details: None,
+        });
+    }
+
+    let total_input: Amount = inputs.iter().map(|(_, amount)| *amount).sum();
+    let htlc_output = TxOut {
+        value: htlc_amount,
+        script_pubkey: htlc_script_pubkey,
+    };
+    let change_output = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: change_script_pubkey.clone(),
+    };
+    let vsize_with_change = funding_vsize(inputs.len(), &change_script_pubkey, &[htlc_output.clone(), change_output])?;
+    let fee_with_change = fee_for_vsize(fee_rate, vsize_with_change)?;
+    let fee_without_change = fee_for_vsize(fee_rate, funding_vsize(inputs.len(), &change_script_pubkey, &[htlc_output])?)?;
+
+    let change = total_input.checked_sub(htlc_amount + fee_with_change);
+    let fee = match change {
+        Some(change) if change >= change_script_pubkey.dust_value() => fee_with_change,
+        _ => match total_input.checked_sub(htlc_amount) {
+            Some(remainder) if remainder >= fee_without_change => remainder,
+            _ => {
+                return Err(ApiError::InternalError {
+                    code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+                    message: format!("Insufficient funds: {} < {}", total_input, htlc_amount + fee_without_change),
+                    details: None,
+                });
+            }
+        },
+    };
+
+    create_funding_transaction(inputs, htlc_address, htlc_amount, change_address, fee)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,4 +197,62 @@ mod tests {
         
         assert_eq!(transaction.output.len(), 1); // No change output
     }
+
+    fn p2wsh_htlc_address() -> Address {
+        Address::p2wsh(&bitcoin::ScriptBuf::from(vec![0x51]), Network::Testnet)
+    }
+
+    fn wallet_address() -> Address {
+        Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+            .unwrap()
+            .require_network(Network::Testnet)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_funding_at_fee_rate_pays_for_its_vsize() {
+        let inputs = vec![(OutPoint::default(), Amount::from_sat(150_000)), (OutPoint::default(), Amount::from_sat(60_000))];
+
+        let transaction = create_funding_transaction_at_fee_rate(
+            inputs,
+            &p2wsh_htlc_address(),
+            Amount::from_sat(100_000),
+            &wallet_address(),
+            FeeRate::from_sat_per_vb_unchecked(10),
+        ).unwrap();
+
+        // Two P2WPKH inputs, P2WSH and P2WPKH outputs: 10.5 + 2 * 68 + 43 + 31 vB
+        let fee = Amount::from_sat(210_000) - transaction.output.iter().map(|output| output.value).sum();
+        assert_eq!(fee, Amount::from_sat(2_210));
+        assert_eq!(transaction.output[1].value, Amount::from_sat(107_790));
+    }
+
+    #[test]
+    fn test_funding_at_fee_rate_drops_dust_change() {
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+        let fund = |input: u64| create_funding_transaction_at_fee_rate(
+            vec![(OutPoint::default(), Amount::from_sat(input))],
+            &p2wsh_htlc_address(),
+            Amount::from_sat(100_000),
+            &wallet_address(),
+            fee_rate,
+        );
+
+        // 153 vB with change, 122 without; 294 sat is the P2WPKH dust limit
+        assert_eq!(fund(100_000 + 306 + 294).unwrap().output[1].value, Amount::from_sat(294));
+        let no_change = fund(100_000 + 306 + 293).unwrap();
+        assert_eq!(no_change.output.len(), 1);
+        assert_eq!(no_change.output[0].value, Amount::from_sat(100_000));
+        assert_eq!(fund(100_000 + 244).unwrap().output.len(), 1);
+        assert!(fund(100_000 + 243).is_err());
+
+        let dust_htlc = create_funding_transaction_at_fee_rate(
+            vec![(OutPoint::default(), Amount::from_sat(10_000))],
+            &p2wsh_htlc_address(),
+            Amount::from_sat(329),
+            &wallet_address(),
+            fee_rate,
+        );
+        assert!(dust_htlc.is_err());
+    }
 }
\ No newline at end of file
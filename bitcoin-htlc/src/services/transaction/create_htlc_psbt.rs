@@ -0,0 +1,390 @@
+use base64::{engine::general_purpose, Engine as _};
+use bitcoin::{
+    bip32::{DerivationPath, Fingerprint, KeySource},
+    hashes::{sha256, Hash},
+    psbt::{Input, Output, Psbt, PsbtSighashType},
+    sighash::{EcdsaSighashType, TapSighashType},
+    taproot::{LeafVersion, TapLeafHash},
+    Address, Amount, FeeRate, OutPoint, PublicKey, ScriptBuf, Transaction, TxOut,
+};
+use crate::models::{ApiError, HtlcOutputType, HtlcParams, HtlcScript, HtlcSpendPath};
+use crate::services::htlc::{build_htlc_script, build_htlc_script::nested_witness_program, hash_preimage};
+use crate::services::transaction::create_claim_transaction::unsigned_claim_transaction;
+use crate::services::transaction::create_refund_transaction::unsigned_refund_transaction;
+use crate::services::transaction::create_taproot_claim_transaction::taproot_tree;
+use crate::services::transaction::{
+    create_funding_transaction_at_fee_rate, fee_for_vsize, htlc_claim_vsize, htlc_refund_vsize, output_after_fee,
+};
+
+/// Preimage length a claim is sized for when the PSBT does not carry the preimage
+const DEFAULT_PREIMAGE_LEN: usize = 32;
+
+/// HTLC output spent by a claim or refund PSBT
+#[derive(Debug, Clone, Copy)]
+pub struct HtlcFunding<'a> {
+    pub outpoint: OutPoint,
+    pub amount: Amount,
+    /// Transaction creating the output; legacy P2SH inputs can only describe the
+    /// spent output with it and require it
+    pub transaction: Option<&'a Transaction>,
+}
+
+/// Unsigned claim of the HTLC as a PSBT for an external signer
+///
+/// Same transaction as [`create_claim_transaction_at_fee_rate`](crate::services::create_claim_transaction_at_fee_rate),
+/// with the spent output, redeem and witness scripts or claim leaf, sighash type and
+/// recipient key filled in. The preimage goes into the SHA256 preimages when given.
+pub fn create_claim_psbt(
+    funding: HtlcFunding,
+    params: &HtlcParams,
+    preimage: Option<&[u8]>,
+    claim_address: &Address,
+    fee_rate: FeeRate,
+    key_source: Option<KeySource>,
+) -> Result<Psbt, ApiError> {
+    if preimage.is_some_and(|preimage| hash_preimage(preimage) != params.payment_hash) {
+        return Err(ApiError::BadRequest {
+            code: "INVALID_PREIMAGE".to_string(),
+            message: "Preimage does not hash to the HTLC payment hash".to_string(),
+            details: None,
+        });
+    }
+
+    let htlc_script = build_htlc_script(params)?;
+    let destination = claim_address.script_pubkey();
+    let preimage_len = preimage.map_or(DEFAULT_PREIMAGE_LEN, <[u8]>::len);
+    let fee = fee_for_vsize(fee_rate, htlc_claim_vsize(&htlc_script, preimage_len, &destination)?)?;
+    output_after_fee(funding.amount, fee, &destination)?;
+
+    let transaction = unsigned_claim_transaction(funding.outpoint, funding.amount, claim_address, fee)?;
+    let mut input = htlc_input(&htlc_script, funding, HtlcSpendPath::Claim, &params.recipient_pubkey, key_source)?;
+    if let Some(preimage) = preimage {
+        input
+            .sha256_preimages
+            .insert(sha256::Hash::from_byte_array(params.payment_hash), preimage.to_vec());
+    }
+
+    with_input(transaction, input)
+}
+
+/// Unsigned refund of the HTLC as a PSBT for an external signer
+///
+/// Same transaction as [`create_refund_transaction_at_fee_rate`](crate::services::create_refund_transaction_at_fee_rate),
+/// locked to the HTLC timeout height and prepared for the sender key.
+pub fn create_refund_psbt(
+    funding: HtlcFunding,
+    params: &HtlcParams,
+    refund_address: &Address,
+    fee_rate: FeeRate,
+    key_source: Option<KeySource>,
+) -> Result<Psbt, ApiError> {
+    let htlc_script = build_htlc_script(params)?;
+    let destination = refund_address.script_pubkey();
+    let fee = fee_for_vsize(fee_rate, htlc_refund_vsize(&htlc_script, &destination)?)?;
+    output_after_fee(funding.amount, fee, &destination)?;
+
+    let transaction = unsigned_refund_transaction(funding.outpoint, funding.amount, refund_address, params.timeout, fee)?;
+    let input = htlc_input(&htlc_script, funding, HtlcSpendPath::Refund, &params.sender_pubkey, key_source)?;
+
+    with_input(transaction, input)
+}
+
+/// Unsigned funding of the HTLC from wallet coins as a PSBT
+///
+/// Same transaction as [`create_funding_transaction_at_fee_rate`]. Inputs only carry the
+/// spent wallet outputs; the signing wallet adds its own key origins as updater. The HTLC
+/// output carries its scripts, or its internal key for Taproot, so signers can show what
+/// the funds are locked to.
+pub fn create_funding_psbt(
+    inputs: Vec<(OutPoint, Amount)>,
+    htlc_script: &HtlcScript,
+    htlc_amount: Amount,
+    change_address: &Address,
+    fee_rate: FeeRate,
+) -> Result<Psbt, ApiError> {
+    let htlc_address = Address::from_script(&htlc_script.script_pubkey, *change_address.network())
+        .map_err(|e| ApiError::InternalError {
+            code: "BITCOIN_ADDRESS_ERROR".to_string(),
+            message: format!("Invalid HTLC output: {}", e),
+            details: None,
+        })?;
+    let wallet_outputs: Vec<TxOut> = inputs
+        .iter()
+        .map(|(_, value)| TxOut {
+            value: *value,
+            script_pubkey: change_address.script_pubkey(),
+        })
+        .collect();
+
+    let transaction = create_funding_transaction_at_fee_rate(inputs, &htlc_address, htlc_amount, change_address, fee_rate)?;
+    let mut psbt = Psbt::from_unsigned_tx(transaction).map_err(psbt_error)?;
+    for (input, spent) in psbt.inputs.iter_mut().zip(wallet_outputs) {
+        input.witness_utxo = Some(spent);
+    }
+    psbt.outputs[0] = htlc_output(htlc_script)?;
+
+    Ok(psbt)
+}
+
+/// Standard base64 encoding of a PSBT, as exchanged with signers
+pub fn encode_psbt(psbt: &Psbt) -> String {
+    general_purpose::STANDARD.encode(psbt.serialize())
+}
+
+/// Parse a base64 PSBT received from a caller
+pub fn decode_psbt(encoded: &str) -> Result<Psbt, ApiError> {
+    general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| Psbt::deserialize(&bytes).ok())
+        .ok_or_else(|| ApiError::BadRequest {
+            code: "INVALID_PSBT".to_string(),
+            message: "psbt is not a valid base64 encoded PSBT".to_string(),
+            details: None,
+        })
+}
+
+/// Origin recorded for `signer` when the caller gives none
+///
+/// Service keys are not derived from an HD wallet, so signers match the key itself.
+fn unknown_origin() -> KeySource {
+    (Fingerprint::default(), DerivationPath::master())
+}
+
+/// PSBT input spending the HTLC through `path`, to be signed by `signer`
+///
+/// Segwit and Taproot inputs describe the spent output by itself (`witness_utxo`), a
+/// legacy P2SH input by the whole funding transaction (`non_witness_utxo`, BIP-174).
+/// The funding transaction is added to any input it is given for.
+fn htlc_input(
+    htlc_script: &HtlcScript,
+    funding: HtlcFunding,
+    path: HtlcSpendPath,
+    signer: &PublicKey,
+    key_source: Option<KeySource>,
+) -> Result<Input, ApiError> {
+    let key_source = key_source.unwrap_or_else(unknown_origin);
+    let spent = TxOut {
+        value: funding.amount,
+        script_pubkey: htlc_script.script_pubkey.clone(),
+    };
+    if let Some(transaction) = funding.transaction {
+        let funds_htlc = transaction.txid() == funding.outpoint.txid
+            && transaction.output.get(funding.outpoint.vout as usize) == Some(&spent);
+        if !funds_htlc {
+            return Err(ApiError::BadRequest {
+                code: "FUNDING_TRANSACTION_MISMATCH".to_string(),
+                message: format!("Transaction {} does not create HTLC output {}", transaction.txid(), funding.outpoint),
+                details: None,
+            });
+        }
+    }
+    let mut input = Input {
+        non_witness_utxo: funding.transaction.cloned(),
+        ..Default::default()
+    };
+    if htlc_script.output_type == HtlcOutputType::P2sh {
+        if input.non_witness_utxo.is_none() {
+            return Err(ApiError::InternalError {
+                code: "FUNDING_TRANSACTION_REQUIRED".to_string(),
+                message: format!("P2SH HTLC input {} needs its funding transaction", funding.outpoint),
+                details: None,
+            });
+        }
+    } else {
+        input.witness_utxo = Some(spent);
+    }
+
+    if htlc_script.output_type == HtlcOutputType::P2tr {
+        let taproot = taproot_tree(htlc_script)?;
+        let (leaf, control_block) = match path {
+            HtlcSpendPath::Claim => (&taproot.claim_leaf, &taproot.claim_control_block),
+            HtlcSpendPath::Refund => (&taproot.refund_leaf, &taproot.refund_control_block),
+        };
+        let leaf_hash = TapLeafHash::from_script(leaf, LeafVersion::TapScript);
+        input.tap_internal_key = Some(taproot.internal_key);
+        input.tap_merkle_root = Some(taproot.merkle_root);
+        input.tap_scripts.insert(control_block.clone(), (leaf.clone(), LeafVersion::TapScript));
+        input
+            .tap_key_origins
+            .insert(signer.inner.x_only_public_key().0, (vec![leaf_hash], key_source));
+        input.sighash_type = Some(PsbtSighashType::from(TapSighashType::Default));
+        return Ok(input);
+    }
+
+    let output = htlc_output(htlc_script)?;
+    input.redeem_script = output.redeem_script;
+    input.witness_script = output.witness_script;
+    input.bip32_derivation.insert(signer.inner, key_source);
+    input.sighash_type = Some(PsbtSighashType::from(EcdsaSighashType::All));
+    Ok(input)
+}
+
+/// Scripts committed to by the HTLC output, as PSBT output fields
+fn htlc_output(htlc_script: &HtlcScript) -> Result<Output, ApiError> {
+    let redeem_script = ScriptBuf::from(htlc_script.redeem_script.clone());
+    let mut output = Output::default();
+    match htlc_script.output_type {
+        HtlcOutputType::P2sh => output.redeem_script = Some(redeem_script),
+        HtlcOutputType::P2shP2wsh => {
+            output.redeem_script = Some(nested_witness_program(&htlc_script.redeem_script));
+            output.witness_script = Some(redeem_script);
+        }
+        HtlcOutputType::P2wsh => output.witness_script = Some(redeem_script),
+        HtlcOutputType::P2tr => output.tap_internal_key = Some(taproot_tree(htlc_script)?.internal_key),
+    }
+    Ok(output)
+}
+
+fn with_input(transaction: Transaction, input: Input) -> Result<Psbt, ApiError> {
+    let mut psbt = Psbt::from_unsigned_tx(transaction).map_err(psbt_error)?;
+    psbt.inputs[0] = input;
+    Ok(psbt)
+}
+
+fn psbt_error(error: bitcoin::psbt::Error) -> ApiError {
+    ApiError::InternalError {
+        code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+        message: format!("Failed to create PSBT: {}", error),
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::transaction::{htlc_claim_vsize, select_coins};
+    use crate::test_support::{htlc_params, PREIMAGE};
+    use bitcoin::Network;
+    use std::str::FromStr;
+
+    fn wallet_address() -> Address {
+        Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+            .unwrap()
+            .require_network(Network::Testnet)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_claim_psbt_carries_signing_data() {
+        let params = htlc_params(HtlcOutputType::P2shP2wsh);
+        let htlc_script = build_htlc_script(&params).unwrap();
+        let origin = (Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]), DerivationPath::from_str("m/84'/1'/0'/0/7").unwrap());
+        let psbt = create_claim_psbt(
+            HtlcFunding {
+                outpoint: OutPoint::default(),
+                amount: Amount::from_sat(100_000),
+                transaction: None,
+            },
+            &params,
+            Some(&PREIMAGE),
+            &wallet_address(),
+            FeeRate::from_sat_per_vb_unchecked(4),
+            Some(origin.clone()),
+        )
+        .unwrap();
+
+        let input = &psbt.inputs[0];
+        assert_eq!(input.witness_utxo.as_ref().unwrap().script_pubkey, htlc_script.script_pubkey);
+        assert_eq!(input.witness_script.as_ref().unwrap().as_bytes(), htlc_script.redeem_script.as_slice());
+        assert_eq!(input.redeem_script, Some(nested_witness_program(&htlc_script.redeem_script)));
+        assert_eq!(input.sighash_type, Some(PsbtSighashType::from(EcdsaSighashType::All)));
+        assert_eq!(input.bip32_derivation.get(&params.recipient_pubkey.inner), Some(&origin));
+        assert_eq!(input.sha256_preimages.values().next().unwrap(), &PREIMAGE.to_vec());
+
+        let vsize = htlc_claim_vsize(&htlc_script, PREIMAGE.len(), &wallet_address().script_pubkey()).unwrap();
+        assert_eq!(psbt.fee().unwrap(), Amount::from_sat(4 * vsize));
+        assert_eq!(decode_psbt(&encode_psbt(&psbt)).unwrap(), psbt);
+    }
+
+    #[test]
+    fn test_p2sh_psbt_carries_the_funding_transaction() {
+        let params = htlc_params(HtlcOutputType::P2sh);
+        let htlc_script = build_htlc_script(&params).unwrap();
+        let funding = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: htlc_script.script_pubkey.clone(),
+            }],
+        };
+        let refund = |outpoint: OutPoint, transaction: Option<&Transaction>| {
+            create_refund_psbt(
+                HtlcFunding {
+                    outpoint,
+                    amount: Amount::from_sat(100_000),
+                    transaction,
+                },
+                &params,
+                &wallet_address(),
+                FeeRate::from_sat_per_vb_unchecked(4),
+                None,
+            )
+        };
+
+        let psbt = refund(OutPoint::new(funding.txid(), 0), Some(&funding)).unwrap();
+        let input = &psbt.inputs[0];
+        assert_eq!(input.non_witness_utxo.as_ref(), Some(&funding));
+        assert_eq!(input.witness_utxo, None);
+        assert_eq!(input.redeem_script.as_ref().unwrap().as_bytes(), htlc_script.redeem_script.as_slice());
+        assert_eq!(decode_psbt(&encode_psbt(&psbt)).unwrap(), psbt);
+
+        let error = refund(OutPoint::new(funding.txid(), 0), None).unwrap_err();
+        assert!(matches!(error, ApiError::InternalError { ref code, .. } if code == "FUNDING_TRANSACTION_REQUIRED"));
+        let error = refund(OutPoint::new(funding.txid(), 1), Some(&funding)).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "FUNDING_TRANSACTION_MISMATCH"));
+    }
+
+    #[test]
+    fn test_taproot_refund_psbt_names_the_refund_leaf() {
+        let params = htlc_params(HtlcOutputType::P2tr);
+        let htlc_script = build_htlc_script(&params).unwrap();
+        let taproot = htlc_script.taproot.as_ref().unwrap();
+        let psbt = create_refund_psbt(
+            HtlcFunding {
+                outpoint: OutPoint::default(),
+                amount: Amount::from_sat(100_000),
+                transaction: None,
+            },
+            &params,
+            &wallet_address(),
+            FeeRate::from_sat_per_vb_unchecked(4),
+            None,
+        )
+        .unwrap();
+
+        let input = &psbt.inputs[0];
+        assert_eq!(input.tap_internal_key, Some(taproot.internal_key));
+        assert_eq!(input.tap_merkle_root, Some(taproot.merkle_root));
+        assert_eq!(
+            input.tap_scripts.get(&taproot.refund_control_block),
+            Some(&(taproot.refund_leaf.clone(), LeafVersion::TapScript))
+        );
+        assert_eq!(input.tap_scripts.len(), 1);
+        let (leaf_hashes, _) = &input.tap_key_origins[&params.sender_pubkey.inner.x_only_public_key().0];
+        assert_eq!(leaf_hashes, &vec![TapLeafHash::from_script(&taproot.refund_leaf, LeafVersion::TapScript)]);
+        assert_eq!(psbt.unsigned_tx.lock_time.to_consensus_u32(), params.timeout);
+    }
+
+    #[test]
+    fn test_funding_psbt_describes_inputs_and_htlc_output() {
+        let htlc_script = build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap();
+        let coins = [
+            (OutPoint::new(bitcoin::Txid::all_zeros(), 0), Amount::from_sat(80_000)),
+            (OutPoint::new(bitcoin::Txid::all_zeros(), 1), Amount::from_sat(60_000)),
+        ];
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(3);
+        let wallet_script = wallet_address().script_pubkey();
+        let inputs = select_coins(&coins, Amount::from_sat(100_000), &htlc_script.script_pubkey, &wallet_script, fee_rate).unwrap();
+
+        let psbt = create_funding_psbt(inputs, &htlc_script, Amount::from_sat(100_000), &wallet_address(), fee_rate).unwrap();
+
+        assert_eq!(psbt.inputs.len(), 2);
+        assert!(psbt.inputs.iter().all(|input| input.witness_utxo.as_ref().unwrap().script_pubkey == wallet_script));
+        assert_eq!(psbt.unsigned_tx.output[0].script_pubkey, htlc_script.script_pubkey);
+        assert_eq!(psbt.outputs[0].witness_script.as_ref().unwrap().as_bytes(), htlc_script.redeem_script.as_slice());
+        assert_eq!(psbt.fee().unwrap(), Amount::from_sat(140_000) - psbt.unsigned_tx.output.iter().map(|output| output.value).sum());
+    }
+}
@@ -1,23 +1,58 @@
 use bitcoin::{
     absolute::LockTime,
+    ecdsa::Signature,
     key::Secp256k1,
-    secp256k1::{Message, SecretKey},
-    sighash::{EcdsaSighashType, SighashCache},
+    secp256k1::SecretKey,
+    sighash::EcdsaSighashType,
     transaction::Version,
-    Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
-    hashes::Hash,
+    Address, Amount, FeeRate, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
 };
-use crate::models::ApiError;
+use crate::models::{ApiError, HtlcOutputType, HtlcScript};
+use crate::services::transaction::{create_taproot_refund_transaction, fee_for_vsize, htlc_refund_vsize, output_after_fee};
+use crate::services::htlc::{build_refund_spend, htlc_signature_message};
 
 /// Create refund transaction for HTLC after timeout
 pub fn create_refund_transaction(
     htlc_outpoint: OutPoint,
     htlc_amount: Amount,
-    redeem_script: &[u8],
+    htlc_script: &HtlcScript,
     refund_key: &SecretKey,
     refund_address: &Address,
     timeout: u32,
     fee: Amount,
+) -> Result<Transaction, ApiError> {
+    if htlc_script.output_type == HtlcOutputType::P2tr {
+        return create_taproot_refund_transaction(htlc_outpoint, htlc_amount, htlc_script, refund_key, refund_address, timeout, fee);
+    }
+
+    let mut transaction = unsigned_refund_transaction(htlc_outpoint, htlc_amount, refund_address, timeout, fee)?;
+    
+    // Sign the transaction
+    let secp = Secp256k1::new();
+    let message = htlc_signature_message(
+        &transaction,
+        0,
+        htlc_script,
+        htlc_amount,
+        EcdsaSighashType::All,
+    )?;
+    let signature = Signature::sighash_all(secp.sign_ecdsa_low_r(&message, refund_key));
+    
+    // Refund takes the ELSE branch
+    let (script_sig, witness) = build_refund_spend(htlc_script, &signature)?;
+    transaction.input[0].script_sig = script_sig;
+    transaction.input[0].witness = witness;
+    
+    Ok(transaction)
+}
+
+/// Unsigned refund spending the HTLC output to `refund_address` once `timeout` is reached
+pub(crate) fn unsigned_refund_transaction(
+    htlc_outpoint: OutPoint,
+    htlc_amount: Amount,
+    refund_address: &Address,
+    timeout: u32,
+    fee: Amount,
 ) -> Result<Transaction, ApiError> {
     if htlc_amount <= fee {
         return Err(ApiError::InternalError {
@@ -26,11 +61,10 @@ pub fn create_refund_transaction(
             details: None,
         });
     }
-    
-    let refund_amount = htlc_amount - fee;
-    
-    // Create the refund transaction with locktime
-    let mut transaction = Transaction {
+
+    // The locktime makes the CLTV in the refund branch pass. A non-final sequence keeps
+    // it enforced, and below 0xfffffffe also signals BIP125 so a stuck refund can be replaced
+    Ok(Transaction {
         version: Version::TWO,
         lock_time: LockTime::from_height(timeout)
             .map_err(|e| ApiError::InternalError {
@@ -41,71 +75,68 @@ pub fn create_refund_transaction(
         input: vec![TxIn {
             previous_output: htlc_outpoint,
             script_sig: ScriptBuf::new(),
-            sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
             witness: Witness::new(),
         }],
         output: vec![TxOut {
-            value: refund_amount,
+            value: htlc_amount - fee,
             script_pubkey: refund_address.script_pubkey(),
         }],
-    };
-    
-    // Sign the transaction
-    let secp = Secp256k1::new();
-    let mut sighash_cache = SighashCache::new(&transaction);
-    let sighash = sighash_cache
-        .p2wsh_signature_hash(
-            0,
-            &ScriptBuf::from(redeem_script.to_vec()),
-            htlc_amount,
-            EcdsaSighashType::All,
-        )
-        .map_err(|e| ApiError::InternalError {
-            code: "BITCOIN_TRANSACTION_ERROR".to_string(),
-            message: format!("Failed to compute sighash: {}", e),
-            details: None,
-        })?;
-    
-    let message = Message::from_digest(sighash.to_byte_array());
-    let signature = secp.sign_ecdsa(&message, refund_key);
-    
-    // Build witness script for refund (ELSE branch)
-    let mut witness = Witness::new();
-    witness.push(signature.serialize_der());
-    witness.push([EcdsaSighashType::All as u8]);
-    witness.push([]); // OP_FALSE for ELSE branch
-    witness.push(redeem_script);
-    
-    transaction.input[0].witness = witness;
-    
-    Ok(transaction)
+    })
+}
+
+/// Create refund transaction paying `fee_rate` on its signed vsize
+///
+/// Same minimum relay and dust rules as [`create_claim_transaction_at_fee_rate`](crate::services::create_claim_transaction_at_fee_rate).
+pub fn create_refund_transaction_at_fee_rate(
+    htlc_outpoint: OutPoint,
+    htlc_amount: Amount,
+    htlc_script: &HtlcScript,
+    refund_key: &SecretKey,
+    refund_address: &Address,
+    timeout: u32,
+    fee_rate: FeeRate,
+) -> Result<Transaction, ApiError> {
+    let destination = refund_address.script_pubkey();
+    let fee = fee_for_vsize(fee_rate, htlc_refund_vsize(htlc_script, &destination)?)?;
+    output_after_fee(htlc_amount, fee, &destination)?;
+
+    create_refund_transaction(htlc_outpoint, htlc_amount, htlc_script, refund_key, refund_address, timeout, fee)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::{build_htlc_script, verify_spend};
+    use crate::test_support::{htlc_params, key, HTLC_TIMEOUT};
     use bitcoin::Network;
     use std::str::FromStr;
 
-    #[test]
-    fn test_create_refund_transaction_success() {
-        let refund_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
-        let refund_address = Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+    fn refund_address() -> Address {
+        Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
             .unwrap()
             .require_network(Network::Testnet)
-            .unwrap();
-        
-        let htlc_outpoint = OutPoint::default();
-        let redeem_script = vec![0u8; 100]; // Mock script
-        let timeout = 500_000;
+            .unwrap()
+    }
+
+    fn funding_output(htlc: &HtlcScript) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: htlc.script_pubkey.clone(),
+        }
+    }
+
+    #[test]
+    fn test_create_refund_transaction_success() {
+        let refund_key = key(2).inner;
         
         let transaction = create_refund_transaction(
-            htlc_outpoint,
+            OutPoint::default(),
             Amount::from_sat(100_000),
-            &redeem_script,
+            &build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap(),
             &refund_key,
-            &refund_address,
-            timeout,
+            &refund_address(),
+            HTLC_TIMEOUT,
             Amount::from_sat(5_000),
         ).unwrap();
         
@@ -113,49 +144,165 @@ mod tests {
         assert_eq!(transaction.output.len(), 1);
         assert_eq!(transaction.output[0].value, Amount::from_sat(95_000));
         assert!(!transaction.input[0].witness.is_empty());
-        assert_eq!(transaction.lock_time.to_consensus_u32(), timeout);
+        assert_eq!(transaction.lock_time.to_consensus_u32(), HTLC_TIMEOUT);
     }
 
     #[test]
     fn test_create_refund_transaction_sequence_for_locktime() {
-        let refund_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
-        let refund_address = Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
-            .unwrap()
-            .require_network(Network::Testnet)
-            .unwrap();
+        let refund_key = key(2).inner;
         
         let transaction = create_refund_transaction(
             OutPoint::default(),
             Amount::from_sat(100_000),
-            &[],
+            &build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap(),
             &refund_key,
-            &refund_address,
-            500_000,
+            &refund_address(),
+            HTLC_TIMEOUT,
             Amount::from_sat(5_000),
         ).unwrap();
         
-        // Sequence should enable locktime
-        assert_eq!(transaction.input[0].sequence, Sequence::ENABLE_LOCKTIME_NO_RBF);
+        // Sequence should enable locktime and signal replaceability
+        assert_eq!(transaction.input[0].sequence, Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert!(transaction.is_lock_time_enabled());
+        assert!(transaction.is_explicitly_rbf());
     }
 
     #[test]
     fn test_create_refund_transaction_insufficient_amount() {
-        let refund_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
-        let refund_address = Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
-            .unwrap()
-            .require_network(Network::Testnet)
-            .unwrap();
+        let refund_key = key(2).inner;
         
         let result = create_refund_transaction(
             OutPoint::default(),
             Amount::from_sat(1_000),
-            &[],
+            &build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap(),
             &refund_key,
-            &refund_address,
-            500_000,
+            &refund_address(),
+            HTLC_TIMEOUT,
             Amount::from_sat(5_000),
         );
         
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_refund_at_fee_rate_pays_for_its_exact_vsize() {
+        let refund_key = key(2).inner;
+
+        for output_type in [
+            HtlcOutputType::P2sh,
+            HtlcOutputType::P2shP2wsh,
+            HtlcOutputType::P2wsh,
+            HtlcOutputType::P2tr,
+        ] {
+            let htlc = build_htlc_script(&htlc_params(output_type)).unwrap();
+            let transaction = create_refund_transaction_at_fee_rate(
+                OutPoint::default(),
+                Amount::from_sat(100_000),
+                &htlc,
+                &refund_key,
+                &refund_address(),
+                HTLC_TIMEOUT,
+                FeeRate::from_sat_per_vb_unchecked(3),
+            ).unwrap();
+
+            let vsize = transaction.vsize() as u64;
+            assert_eq!(htlc_refund_vsize(&htlc, &refund_address().script_pubkey()).unwrap(), vsize, "{:?}", output_type);
+            assert_eq!(transaction.output[0].value, Amount::from_sat(100_000 - 3 * vsize));
+            assert!(verify_spend(&transaction, 0, &[funding_output(&htlc)]).is_ok());
+        }
+
+        let dust = create_refund_transaction_at_fee_rate(
+            OutPoint::default(),
+            Amount::from_sat(1_000),
+            &build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap(),
+            &refund_key,
+            &refund_address(),
+            HTLC_TIMEOUT,
+            FeeRate::from_sat_per_vb_unchecked(10),
+        );
+        assert!(dust.is_err());
+    }
+
+    #[test]
+    fn test_refund_spend_verifies_for_every_output_type() {
+        let refund_key = key(2).inner;
+
+        for output_type in [
+            HtlcOutputType::P2sh,
+            HtlcOutputType::P2shP2wsh,
+            HtlcOutputType::P2wsh,
+            HtlcOutputType::P2tr,
+        ] {
+            let htlc = build_htlc_script(&htlc_params(output_type)).unwrap();
+            let transaction = create_refund_transaction(
+                OutPoint::default(),
+                Amount::from_sat(100_000),
+                &htlc,
+                &refund_key,
+                &refund_address(),
+                HTLC_TIMEOUT,
+                Amount::from_sat(5_000),
+            ).unwrap();
+
+            let result = verify_spend(&transaction, 0, &[funding_output(&htlc)]);
+            assert!(result.is_ok(), "{:?}: {:?}", output_type, result);
+        }
+    }
+
+    #[test]
+    fn test_refund_spend_rejects_early_locktime() {
+        let refund_key = key(2).inner;
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2wsh)).unwrap();
+
+        let transaction = create_refund_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &refund_key,
+            &refund_address(),
+            HTLC_TIMEOUT - 1,
+            Amount::from_sat(5_000),
+        ).unwrap();
+
+        let error = verify_spend(&transaction, 0, &[funding_output(&htlc)]).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "SCRIPT_VERIFICATION_FAILED"));
+    }
+
+    #[test]
+    fn test_refund_spend_rejects_wrong_key() {
+        // The recipient key cannot take the refund branch
+        let recipient_key = key(1).inner;
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2sh)).unwrap();
+
+        let transaction = create_refund_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &recipient_key,
+            &refund_address(),
+            HTLC_TIMEOUT,
+            Amount::from_sat(5_000),
+        ).unwrap();
+
+        assert!(verify_spend(&transaction, 0, &[funding_output(&htlc)]).is_err());
+    }
+
+    #[test]
+    fn test_taproot_refund_enforces_locktime() {
+        let refund_key = key(2).inner;
+        let htlc = build_htlc_script(&htlc_params(HtlcOutputType::P2tr)).unwrap();
+
+        let transaction = create_refund_transaction(
+            OutPoint::default(),
+            Amount::from_sat(100_000),
+            &htlc,
+            &refund_key,
+            &refund_address(),
+            HTLC_TIMEOUT - 1,
+            Amount::from_sat(5_000),
+        ).unwrap();
+
+        let error = verify_spend(&transaction, 0, &[funding_output(&htlc)]).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "SCRIPT_VERIFICATION_FAILED"));
+    }
+}
@@ -0,0 +1,73 @@
+use bitcoin::{Address, Amount, FeeRate, OutPoint, PrivateKey, Transaction, TxOut};
+use crate::models::ApiError;
+use crate::services::transaction::{create_funding_transaction_at_fee_rate, select_coins, sign_wallet_inputs};
+
+/// Fund an HTLC from coins of `wallet_key`, signed and ready to broadcast
+///
+/// Coins are chosen with [`select_coins`] and change above dust goes back to
+/// `wallet_address`, the key's P2WPKH or P2TR address that every coin pays to.
+pub fn create_signed_funding_transaction(
+    coins: &[(OutPoint, Amount)],
+    htlc_address: &Address,
+    htlc_amount: Amount,
+    wallet_key: &PrivateKey,
+    wallet_address: &Address,
+    fee_rate: FeeRate,
+) -> Result<Transaction, ApiError> {
+    let wallet_script = wallet_address.script_pubkey();
+    let inputs = select_coins(coins, htlc_amount, &htlc_address.script_pubkey(), &wallet_script, fee_rate)?;
+    let prevouts: Vec<TxOut> = inputs
+        .iter()
+        .map(|(_, value)| TxOut {
+            value: *value,
+            script_pubkey: wallet_script.clone(),
+        })
+        .collect();
+
+    let mut transaction = create_funding_transaction_at_fee_rate(inputs, htlc_address, htlc_amount, wallet_address, fee_rate)?;
+    sign_wallet_inputs(&mut transaction, &prevouts, wallet_key)?;
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::verify_spend;
+    use bitcoin::{hashes::Hash, key::Secp256k1, secp256k1::SecretKey, Network, Txid};
+
+    #[test]
+    fn test_signed_funding_spends_p2tr_wallet_coins() {
+        let secp = Secp256k1::new();
+        let key = PrivateKey::new(SecretKey::from_slice(&[4u8; 32]).unwrap(), Network::Testnet);
+        let wallet_address = Address::p2tr(&secp, key.public_key(&secp).inner.x_only_public_key().0, None, Network::Testnet);
+        let htlc_address = Address::p2wsh(&bitcoin::ScriptBuf::from(vec![0x51]), Network::Testnet);
+        let coins: Vec<(OutPoint, Amount)> = [60_000, 45_000, 20_000]
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| (OutPoint::new(Txid::all_zeros(), vout as u32), Amount::from_sat(*value)))
+            .collect();
+
+        let transaction = create_signed_funding_transaction(
+            &coins,
+            &htlc_address,
+            Amount::from_sat(100_000),
+            &key,
+            &wallet_address,
+            FeeRate::from_sat_per_vb_unchecked(4),
+        )
+        .unwrap();
+
+        assert_eq!(transaction.output[0].value, Amount::from_sat(100_000));
+        let prevouts: Vec<TxOut> = transaction
+            .input
+            .iter()
+            .map(|input| TxOut {
+                value: coins[input.previous_output.vout as usize].1,
+                script_pubkey: wallet_address.script_pubkey(),
+            })
+            .collect();
+        for index in 0..transaction.input.len() {
+            assert!(verify_spend(&transaction, index, &prevouts).is_ok());
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use bitcoin::{
+    hashes::Hash,
+    key::{Keypair, Secp256k1},
+    secp256k1::{Message, SecretKey},
+    sighash::{Prevouts, SighashCache, TapSighashType},
+    taproot::{LeafVersion, TapLeafHash},
+    Address, Amount, OutPoint, ScriptBuf, Transaction, TxOut, Witness,
+};
+use crate::models::{ApiError, HtlcScript, TaprootHtlc};
+use crate::services::transaction::create_claim_transaction::unsigned_claim_transaction;
+
+/// Create claim transaction for a Taproot HTLC through the hashlock leaf
+///
+/// Used when the sender does not co-sign a key path close; the witness reveals the
+/// preimage, the claim leaf and its control block.
+pub fn create_taproot_claim_transaction(
+    htlc_outpoint: OutPoint,
+    htlc_amount: Amount,
+    htlc_script: &HtlcScript,
+    preimage: &[u8],
+    claim_key: &SecretKey,
+    claim_address: &Address,
+    fee: Amount,
+) -> Result<Transaction, ApiError> {
+    let taproot = taproot_tree(htlc_script)?;
+    let mut transaction = unsigned_claim_transaction(htlc_outpoint, htlc_amount, claim_address, fee)?;
+
+    let signature = sign_tap_leaf(&transaction, htlc_amount, htlc_script, &taproot.claim_leaf, claim_key)?;
+
+    let mut witness = Witness::new();
+    witness.push(signature.as_ref());
+    witness.push(preimage);
+    witness.push(taproot.claim_leaf.as_bytes());
+    witness.push(taproot.claim_control_block.serialize());
+    transaction.input[0].witness = witness;
+
+    Ok(transaction)
+}
+
+pub(crate) fn taproot_tree(htlc_script: &HtlcScript) -> Result<&TaprootHtlc, ApiError> {
+    htlc_script.taproot.as_ref().ok_or_else(|| ApiError::InternalError {
+        code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+        message: "HTLC script is not a Taproot output".to_string(),
+        details: None,
+    })
+}
+
+/// BIP341 script path signature (SIGHASH_DEFAULT) over the single HTLC input
+pub(crate) fn sign_tap_leaf(
+    transaction: &Transaction,
+    htlc_amount: Amount,
+    htlc_script: &HtlcScript,
+    leaf: &ScriptBuf,
+    key: &SecretKey,
+) -> Result<bitcoin::secp256k1::schnorr::Signature, ApiError> {
+    let prevouts = [TxOut {
+        value: htlc_amount,
+        script_pubkey: htlc_script.script_pubkey.clone(),
+    }];
+    let leaf_hash = TapLeafHash::from_script(leaf, LeafVersion::TapScript);
+    let sighash = SighashCache::new(transaction)
+        .taproot_script_spend_signature_hash(0, &Prevouts::All(&prevouts), leaf_hash, TapSighashType::Default)
+        .map_err(|e| ApiError::InternalError {
+            code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+            message: format!("Failed to compute sighash: {}", e),
+            details: None,
+        })?;
+
+    let secp = Secp256k1::new();
+    let keypair = Keypair::from_secret_key(&secp, key);
+    Ok(secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &keypair))
+}
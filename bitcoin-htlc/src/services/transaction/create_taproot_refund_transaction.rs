@@ -0,0 +1,28 @@
+use bitcoin::{secp256k1::SecretKey, Address, Amount, OutPoint, Transaction, Witness};
+use crate::models::{ApiError, HtlcScript};
+use crate::services::transaction::create_refund_transaction::unsigned_refund_transaction;
+use crate::services::transaction::create_taproot_claim_transaction::{sign_tap_leaf, taproot_tree};
+
+/// Create refund transaction for a Taproot HTLC through the timelock leaf
+pub fn create_taproot_refund_transaction(
+    htlc_outpoint: OutPoint,
+    htlc_amount: Amount,
+    htlc_script: &HtlcScript,
+    refund_key: &SecretKey,
+    refund_address: &Address,
+    timeout: u32,
+    fee: Amount,
+) -> Result<Transaction, ApiError> {
+    let taproot = taproot_tree(htlc_script)?;
+    let mut transaction = unsigned_refund_transaction(htlc_outpoint, htlc_amount, refund_address, timeout, fee)?;
+
+    let signature = sign_tap_leaf(&transaction, htlc_amount, htlc_script, &taproot.refund_leaf, refund_key)?;
+
+    let mut witness = Witness::new();
+    witness.push(signature.as_ref());
+    witness.push(taproot.refund_leaf.as_bytes());
+    witness.push(taproot.refund_control_block.serialize());
+    transaction.input[0].witness = witness;
+
+    Ok(transaction)
+}
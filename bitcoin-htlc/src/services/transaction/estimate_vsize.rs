@@ -0,0 +1,230 @@
+use bitcoin::{
+    absolute::LockTime,
+    blockdata::script::{Builder, PushBytesBuf},
+    ecdsa::Signature,
+    secp256k1::ecdsa,
+    sighash::EcdsaSighashType,
+    transaction::Version,
+    Amount, FeeRate, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Weight, Witness,
+};
+use crate::models::{ApiError, HtlcOutputType, HtlcScript};
+use crate::services::htlc::{build_claim_spend, build_refund_spend};
+use crate::services::transaction::create_taproot_claim_transaction::taproot_tree;
+
+/// Wallet inputs may be signed without low-R grinding, one byte longer
+const WALLET_SIGNATURE_LEN: usize = 72;
+/// BIP340 signature under SIGHASH_DEFAULT, which adds no sighash byte
+const SCHNORR_SIGNATURE_LEN: usize = 64;
+const COMPRESSED_PUBKEY_LEN: usize = 33;
+
+/// vsize of a signed claim spending the HTLC to `destination`
+pub fn htlc_claim_vsize(
+    htlc_script: &HtlcScript,
+    preimage_len: usize,
+    destination: &Script,
+) -> Result<u64, ApiError> {
+    htlc_spend_vsize(htlc_script, Some(preimage_len), destination)
+}
+
+/// vsize of a signed refund spending the HTLC to `destination`
+pub fn htlc_refund_vsize(htlc_script: &HtlcScript, destination: &Script) -> Result<u64, ApiError> {
+    htlc_spend_vsize(htlc_script, None, destination)
+}
+
+/// vsize of a signed funding transaction whose `input_count` inputs pay to `input_script`
+///
+/// Supports the single-key wallet types: P2WPKH, P2TR key path and P2PKH.
+pub fn funding_vsize(
+    input_count: usize,
+    input_script: &Script,
+    outputs: &[TxOut],
+) -> Result<u64, ApiError> {
+    Ok(funding_weight(input_count, input_script, outputs)?.to_vbytes_ceil())
+}
+
+/// Weight behind [`funding_vsize`]
+pub fn funding_weight(
+    input_count: usize,
+    input_script: &Script,
+    outputs: &[TxOut],
+) -> Result<Weight, ApiError> {
+    let input = wallet_input(input_script)?;
+    Ok(sized_transaction(vec![input; input_count], outputs.to_vec()).weight())
+}
+
+/// Weight a signed wallet input of `input_script` adds to a segwit transaction
+///
+/// Excludes the 2 WU segwit marker, which the transaction pays once.
+pub fn wallet_input_weight(input_script: &Script) -> Result<Weight, ApiError> {
+    Ok(wallet_input(input_script)?.segwit_weight())
+}
+
+fn wallet_input(input_script: &Script) -> Result<TxIn, ApiError> {
+    let (script_sig, witness) = wallet_satisfaction(input_script)?;
+    Ok(TxIn {
+        previous_output: OutPoint::null(),
+        script_sig,
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        witness,
+    })
+}
+
+/// Fee for `vsize` at `fee_rate`, raised to the 1 sat/vB minimum relay fee
+pub fn fee_for_vsize(fee_rate: FeeRate, vsize: u64) -> Result<Amount, ApiError> {
+    fee_rate
+        .max(FeeRate::BROADCAST_MIN)
+        .fee_vb(vsize)
+        .ok_or_else(|| ApiError::InternalError {
+            code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+            message: format!("Fee rate {} overflows for {} vbytes", fee_rate, vsize),
+            details: None,
+        })
+}
+
+/// What `amount` leaves after `fee`, rejected when it would be a dust output
+pub fn output_after_fee(amount: Amount, fee: Amount, script_pubkey: &Script) -> Result<Amount, ApiError> {
+    let dust_limit = script_pubkey.dust_value();
+    amount
+        .checked_sub(fee)
+        .filter(|value| *value >= dust_limit)
+        .ok_or_else(|| ApiError::InternalError {
+            code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+            message: format!(
+                "HTLC amount {} does not cover a {} fee and the {} dust limit",
+                amount, fee, dust_limit
+            ),
+            details: None,
+        })
+}
+
+fn htlc_spend_vsize(
+    htlc_script: &HtlcScript,
+    preimage_len: Option<usize>,
+    destination: &Script,
+) -> Result<u64, ApiError> {
+    let signature = placeholder_signature();
+    let (script_sig, witness) = match (htlc_script.output_type, preimage_len) {
+        (HtlcOutputType::P2tr, preimage_len) => {
+            let taproot = taproot_tree(htlc_script)?;
+            let mut witness = Witness::new();
+            witness.push([0u8; SCHNORR_SIGNATURE_LEN]);
+            match preimage_len {
+                Some(len) => {
+                    witness.push(vec![0u8; len]);
+                    witness.push(taproot.claim_leaf.as_bytes());
+                    witness.push(taproot.claim_control_block.serialize());
+                }
+                None => {
+                    witness.push(taproot.refund_leaf.as_bytes());
+                    witness.push(taproot.refund_control_block.serialize());
+                }
+            }
+            (ScriptBuf::new(), witness)
+        }
+        (_, Some(len)) => build_claim_spend(htlc_script, &signature, &vec![0u8; len])?,
+        (_, None) => build_refund_spend(htlc_script, &signature)?,
+    };
+
+    let input = TxIn {
+        previous_output: OutPoint::null(),
+        script_sig,
+        sequence: Sequence::MAX,
+        witness,
+    };
+    let output = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: destination.to_owned(),
+    };
+
+    Ok(sized_transaction(vec![input], vec![output]).vsize() as u64)
+}
+
+/// Placeholder scriptSig and witness as large as a real signature for the key type
+fn wallet_satisfaction(script_pubkey: &Script) -> Result<(ScriptBuf, Witness), ApiError> {
+    let signature = vec![0u8; WALLET_SIGNATURE_LEN];
+    let pubkey = vec![0u8; COMPRESSED_PUBKEY_LEN];
+
+    if script_pubkey.is_p2wpkh() {
+        Ok((ScriptBuf::new(), Witness::from_slice(&[signature, pubkey])))
+    } else if script_pubkey.is_p2tr() {
+        Ok((ScriptBuf::new(), Witness::from_slice(&[vec![0u8; SCHNORR_SIGNATURE_LEN]])))
+    } else if script_pubkey.is_p2pkh() {
+        let script_sig = Builder::new()
+            .push_slice(PushBytesBuf::try_from(signature).expect("fits a push"))
+            .push_slice(PushBytesBuf::try_from(pubkey).expect("fits a push"))
+            .into_script();
+        Ok((script_sig, Witness::new()))
+    } else {
+        Err(ApiError::InternalError {
+            code: "BITCOIN_TRANSACTION_ERROR".to_string(),
+            message: format!("Cannot size a spend of {}", script_pubkey),
+            details: None,
+        })
+    }
+}
+
+/// Signature as long as the largest low-R one, 70 DER bytes and the sighash byte
+///
+/// The HTLC builders grind for a low R, so nearly every signature they produce has this
+/// length; one in a few hundred comes out a byte shorter.
+fn placeholder_signature() -> Signature {
+    Signature {
+        sig: ecdsa::Signature::from_compact(&[1u8; 64]).expect("valid compact signature"),
+        hash_ty: EcdsaSighashType::All,
+    }
+}
+
+fn sized_transaction(input: Vec<TxIn>, output: Vec<TxOut>) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input,
+        output,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{Address, Network, PublicKey};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_placeholder_signature_has_maximum_length() {
+        assert_eq!(placeholder_signature().to_vec().len(), 71);
+    }
+
+    #[test]
+    fn test_funding_vsize_for_wallet_key_types() {
+        let secp = bitcoin::key::Secp256k1::new();
+        let key = PublicKey::new(bitcoin::secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap().public_key(&secp));
+        let p2wpkh = Address::p2wpkh(&key, Network::Testnet).unwrap().script_pubkey();
+        let p2tr = Address::p2tr(&secp, key.inner.x_only_public_key().0, None, Network::Testnet).script_pubkey();
+        let p2pkh = Address::p2pkh(&key, Network::Testnet).script_pubkey();
+        let outputs = [TxOut { value: Amount::ZERO, script_pubkey: p2wpkh.clone() }];
+
+        // The usual figures: 68 vB per P2WPKH input, 57.5 per key path input, 148 per P2PKH input
+        assert_eq!(funding_vsize(1, &p2wpkh, &outputs).unwrap(), 110);
+        assert_eq!(funding_vsize(2, &p2wpkh, &outputs).unwrap(), 178);
+        assert_eq!(funding_vsize(1, &p2tr, &outputs).unwrap(), 99);
+        assert_eq!(funding_vsize(1, &p2pkh, &outputs).unwrap(), 189);
+
+        let p2sh = Address::from_str("2N8hwP1WmJrFF5QWABn38y63uYLhnJYJYTF").unwrap().assume_checked();
+        assert!(funding_vsize(1, &p2sh.script_pubkey(), &outputs).is_err());
+    }
+
+    #[test]
+    fn test_fee_respects_min_relay_and_dust() {
+        let p2wpkh = ScriptBuf::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+
+        assert_eq!(fee_for_vsize(FeeRate::from_sat_per_vb_unchecked(12), 150).unwrap(), Amount::from_sat(1_800));
+        assert_eq!(fee_for_vsize(FeeRate::ZERO, 150).unwrap(), Amount::from_sat(150));
+        assert_eq!(fee_for_vsize(FeeRate::from_sat_per_kwu(251), 100).unwrap(), Amount::from_sat(101));
+        assert!(fee_for_vsize(FeeRate::MAX, 150).is_err());
+
+        // 294 sat is the P2WPKH dust limit at 3 sat/vB
+        assert_eq!(output_after_fee(Amount::from_sat(1_294), Amount::from_sat(1_000), &p2wpkh).unwrap(), Amount::from_sat(294));
+        assert!(output_after_fee(Amount::from_sat(1_293), Amount::from_sat(1_000), &p2wpkh).is_err());
+        assert!(output_after_fee(Amount::from_sat(900), Amount::from_sat(1_000), &p2wpkh).is_err());
+    }
+}
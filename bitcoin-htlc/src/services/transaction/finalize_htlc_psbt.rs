@@ -0,0 +1,321 @@
+use bitcoin::{
+    hashes::{sha256, Hash},
+    psbt::{Input, Psbt},
+    taproot::{self, LeafVersion, TapLeafHash},
+    PublicKey, ScriptBuf, Transaction, TxOut, Witness,
+};
+use crate::models::{ApiError, HtlcOutputType, HtlcParams, HtlcSpendPath};
+use crate::services::htlc::{build_claim_spend, build_htlc_script, build_refund_spend, hash_preimage};
+use crate::services::transaction::create_taproot_claim_transaction::taproot_tree;
+use crate::services::transaction::verify_spend;
+
+/// Transaction completed from a signed HTLC spend PSBT
+#[derive(Debug, Clone)]
+pub struct FinalizedHtlcSpend {
+    pub transaction: Transaction,
+    pub path: HtlcSpendPath,
+    /// Revealed preimage, for claims
+    pub preimage: Option<Vec<u8>>,
+}
+
+/// Turn a signed claim or refund PSBT into the HTLC witness and extract the transaction
+///
+/// A recipient signature takes the claim branch and needs the preimage, from `preimage`
+/// or the PSBT's SHA256 preimages; a sender signature takes the refund branch. The
+/// finalized input is checked against the spent HTLC output before it is returned.
+pub fn finalize_htlc_psbt(
+    mut psbt: Psbt,
+    params: &HtlcParams,
+    preimage: Option<&[u8]>,
+) -> Result<FinalizedHtlcSpend, ApiError> {
+    let htlc_script = build_htlc_script(params)?;
+    if psbt.inputs.len() != 1 {
+        return Err(invalid_psbt(format!("Expected one HTLC input, got {}", psbt.inputs.len())));
+    }
+    let prevout = spent_output(&psbt)
+        .filter(|output| output.script_pubkey == htlc_script.script_pubkey)
+        .ok_or_else(|| invalid_psbt("PSBT input does not spend the HTLC output".to_string()))?;
+    let input = &psbt.inputs[0];
+
+    let payment_hash = sha256::Hash::from_byte_array(params.payment_hash);
+    let preimage = preimage
+        .map(<[u8]>::to_vec)
+        .or_else(|| input.sha256_preimages.get(&payment_hash).cloned());
+
+    let (path, script_sig, witness) = if htlc_script.output_type == HtlcOutputType::P2tr {
+        let taproot = taproot_tree(&htlc_script)?;
+        if let Some(signature) = leaf_signature(input, &params.recipient_pubkey, &taproot.claim_leaf) {
+            let preimage = checked_preimage(preimage.as_deref(), params)?;
+            let witness = Witness::from_slice(&[
+                signature.to_vec(),
+                preimage.to_vec(),
+                taproot.claim_leaf.to_bytes(),
+                taproot.claim_control_block.serialize(),
+            ]);
+            (HtlcSpendPath::Claim, ScriptBuf::new(), witness)
+        } else if let Some(signature) = leaf_signature(input, &params.sender_pubkey, &taproot.refund_leaf) {
+            let witness = Witness::from_slice(&[
+                signature.to_vec(),
+                taproot.refund_leaf.to_bytes(),
+                taproot.refund_control_block.serialize(),
+            ]);
+            (HtlcSpendPath::Refund, ScriptBuf::new(), witness)
+        } else {
+            return Err(invalid_psbt("PSBT has no signature for the claim or refund leaf".to_string()));
+        }
+    } else if let Some(signature) = input.partial_sigs.get(&params.recipient_pubkey) {
+        let preimage = checked_preimage(preimage.as_deref(), params)?;
+        let (script_sig, witness) = build_claim_spend(&htlc_script, signature, preimage)?;
+        (HtlcSpendPath::Claim, script_sig, witness)
+    } else if let Some(signature) = input.partial_sigs.get(&params.sender_pubkey) {
+        let (script_sig, witness) = build_refund_spend(&htlc_script, signature)?;
+        (HtlcSpendPath::Refund, script_sig, witness)
+    } else {
+        return Err(invalid_psbt("PSBT has no signature from the HTLC recipient or sender".to_string()));
+    };
+
+    // Finalized inputs keep only the spent output and the final scripts (BIP-174)
+    let input = &mut psbt.inputs[0];
+    *input = Input {
+        non_witness_utxo: input.non_witness_utxo.take(),
+        witness_utxo: input.witness_utxo.take(),
+        final_script_sig: (!script_sig.is_empty()).then_some(script_sig),
+        final_script_witness: (!witness.is_empty()).then_some(witness),
+        proprietary: std::mem::take(&mut input.proprietary),
+        unknown: std::mem::take(&mut input.unknown),
+        ..Default::default()
+    };
+
+    let transaction = psbt
+        .extract_tx()
+        .map_err(|e| invalid_psbt(format!("Cannot extract transaction: {}", e)))?;
+    verify_spend(&transaction, 0, &[prevout])?;
+
+    Ok(FinalizedHtlcSpend {
+        transaction,
+        path,
+        preimage: match path {
+            HtlcSpendPath::Claim => preimage,
+            HtlcSpendPath::Refund => None,
+        },
+    })
+}
+
+/// Output spent by the first input: from the previous transaction when the PSBT carries
+/// it, as it must for legacy inputs, from `witness_utxo` otherwise
+pub(crate) fn spent_output(psbt: &Psbt) -> Option<TxOut> {
+    let outpoint = psbt.unsigned_tx.input.first()?.previous_output;
+    let input = psbt.inputs.first()?;
+    match input.non_witness_utxo {
+        Some(ref previous) if previous.txid() == outpoint.txid => previous.output.get(outpoint.vout as usize).cloned(),
+        Some(_) => None,
+        None => input.witness_utxo.clone(),
+    }
+}
+
+fn leaf_signature(input: &Input, key: &PublicKey, leaf: &ScriptBuf) -> Option<taproot::Signature> {
+    let leaf_hash = TapLeafHash::from_script(leaf, LeafVersion::TapScript);
+    input
+        .tap_script_sigs
+        .get(&(key.inner.x_only_public_key().0, leaf_hash))
+        .copied()
+}
+
+fn checked_preimage<'a>(preimage: Option<&'a [u8]>, params: &HtlcParams) -> Result<&'a [u8], ApiError> {
+    let preimage = preimage.ok_or_else(|| ApiError::BadRequest {
+        code: "PREIMAGE_REQUIRED".to_string(),
+        message: "Claim PSBT carries no preimage, submit it with the PSBT".to_string(),
+        details: None,
+    })?;
+    if hash_preimage(preimage) != params.payment_hash {
+        return Err(ApiError::BadRequest {
+            code: "INVALID_PREIMAGE".to_string(),
+            message: "Preimage does not hash to the HTLC payment hash".to_string(),
+            details: None,
+        });
+    }
+    Ok(preimage)
+}
+
+fn invalid_psbt(message: String) -> ApiError {
+    ApiError::BadRequest {
+        code: "INVALID_PSBT".to_string(),
+        message,
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::transaction::{create_claim_psbt, create_refund_psbt, decode_psbt, encode_psbt, HtlcFunding};
+    use crate::test_support::{htlc_params, key, HTLC_TIMEOUT, PREIMAGE};
+    use bitcoin::{
+        key::{Keypair, Secp256k1},
+        secp256k1::Message,
+        sighash::{Prevouts, SighashCache, TapSighashType},
+        Address, Amount, FeeRate, Network, OutPoint, PrivateKey,
+    };
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    fn destination() -> Address {
+        Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+            .unwrap()
+            .require_network(Network::Testnet)
+            .unwrap()
+    }
+
+    /// Funding of the HTLC, and the transaction itself where the PSBT needs it
+    fn funding(params: &HtlcParams) -> (OutPoint, Option<Transaction>) {
+        let funding = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: build_htlc_script(params).unwrap().script_pubkey,
+            }],
+        };
+        let outpoint = OutPoint::new(funding.txid(), 0);
+        (outpoint, (params.output_type == HtlcOutputType::P2sh).then_some(funding))
+    }
+
+    fn claim_psbt(params: &HtlcParams, preimage: Option<&[u8]>) -> Psbt {
+        let (outpoint, funding) = funding(params);
+        create_claim_psbt(
+            HtlcFunding {
+                outpoint,
+                amount: Amount::from_sat(100_000),
+                transaction: funding.as_ref(),
+            },
+            params,
+            preimage,
+            &destination(),
+            FeeRate::from_sat_per_vb_unchecked(5),
+            None,
+        )
+        .unwrap()
+    }
+
+    fn refund_psbt(params: &HtlcParams) -> Psbt {
+        let (outpoint, funding) = funding(params);
+        create_refund_psbt(
+            HtlcFunding {
+                outpoint,
+                amount: Amount::from_sat(100_000),
+                transaction: funding.as_ref(),
+            },
+            params,
+            &destination(),
+            FeeRate::from_sat_per_vb_unchecked(5),
+            None,
+        )
+        .unwrap()
+    }
+
+    /// What an external signer does: sign every input it holds a key for
+    fn sign(psbt: Psbt, signer: &PrivateKey) -> Psbt {
+        // Signers receive and return the PSBT in its base64 form
+        let mut psbt = decode_psbt(&encode_psbt(&psbt)).unwrap();
+        let secp = Secp256k1::new();
+
+        if psbt.inputs[0].tap_scripts.is_empty() {
+            let keys = BTreeMap::from([(signer.public_key(&secp), *signer)]);
+            psbt.sign(&keys, &secp).unwrap();
+        } else {
+            let (_, (leaf, version)) = psbt.inputs[0].tap_scripts.iter().next().unwrap();
+            let leaf_hash = TapLeafHash::from_script(leaf, *version);
+            let prevouts = [psbt.inputs[0].witness_utxo.clone().unwrap()];
+            let sighash = SighashCache::new(&psbt.unsigned_tx)
+                .taproot_script_spend_signature_hash(0, &Prevouts::All(&prevouts), leaf_hash, TapSighashType::Default)
+                .unwrap();
+            let keypair = Keypair::from_secret_key(&secp, &signer.inner);
+            let signature = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &keypair);
+            psbt.inputs[0].tap_script_sigs.insert(
+                (keypair.x_only_public_key().0, leaf_hash),
+                taproot::Signature { sig: signature, hash_ty: TapSighashType::Default },
+            );
+        }
+
+        decode_psbt(&encode_psbt(&psbt)).unwrap()
+    }
+
+    #[test]
+    fn test_finalizes_signed_claim_and_refund_for_every_output_type() {
+        for output_type in [
+            HtlcOutputType::P2sh,
+            HtlcOutputType::P2shP2wsh,
+            HtlcOutputType::P2wsh,
+            HtlcOutputType::P2tr,
+        ] {
+            let params = htlc_params(output_type);
+
+            let claim = finalize_htlc_psbt(sign(claim_psbt(&params, Some(&PREIMAGE)), &key(1)), &params, None).unwrap();
+            assert_eq!(claim.path, HtlcSpendPath::Claim, "{:?}", output_type);
+            assert_eq!(claim.preimage.as_deref(), Some(&PREIMAGE[..]));
+
+            let refund = finalize_htlc_psbt(sign(refund_psbt(&params), &key(2)), &params, None).unwrap();
+            assert_eq!(refund.path, HtlcSpendPath::Refund, "{:?}", output_type);
+            assert_eq!(refund.transaction.lock_time.to_consensus_u32(), params.timeout);
+            assert_eq!(refund.preimage, None);
+        }
+    }
+
+    #[test]
+    fn test_claim_takes_preimage_from_caller_when_psbt_has_none() {
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let signed = sign(claim_psbt(&params, None), &key(1));
+
+        let error = finalize_htlc_psbt(signed.clone(), &params, None).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "PREIMAGE_REQUIRED"));
+        let error = finalize_htlc_psbt(signed.clone(), &params, Some(&[9u8; 32])).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "INVALID_PREIMAGE"));
+
+        let claim = finalize_htlc_psbt(signed, &params, Some(&PREIMAGE)).unwrap();
+        assert!(claim.transaction.input[0].witness.iter().any(|item| item == PREIMAGE));
+    }
+
+    #[test]
+    fn test_rejects_unsigned_and_foreign_psbts() {
+        let params = htlc_params(HtlcOutputType::P2wsh);
+
+        let error = finalize_htlc_psbt(claim_psbt(&params, Some(&PREIMAGE)), &params, None).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "INVALID_PSBT"));
+
+        // Signed by a key the HTLC does not name
+        let error = finalize_htlc_psbt(sign(refund_psbt(&params), &key(5)), &params, None).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "INVALID_PSBT"));
+
+        // A valid signature over another HTLC's output
+        let other = HtlcParams { timeout: HTLC_TIMEOUT + 1, ..params.clone() };
+        let error = finalize_htlc_psbt(sign(refund_psbt(&other), &key(2)), &params, None).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "INVALID_PSBT"));
+    }
+
+    #[test]
+    fn test_rejects_signature_over_altered_transaction() {
+        let params = htlc_params(HtlcOutputType::P2wsh);
+        let mut signed = sign(refund_psbt(&params), &key(2));
+        signed.unsigned_tx.output[0].value = Amount::from_sat(99_999);
+
+        let error = finalize_htlc_psbt(signed, &params, None).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "SCRIPT_VERIFICATION_FAILED"));
+    }
+
+    #[test]
+    fn test_p2sh_spent_output_comes_from_the_funding_transaction() {
+        let params = htlc_params(HtlcOutputType::P2sh);
+        let signed = sign(refund_psbt(&params), &key(2));
+        assert_eq!(spent_output(&signed).unwrap().value, Amount::from_sat(100_000));
+
+        // Another transaction than the one the input spends
+        let mut foreign = signed.clone();
+        foreign.inputs[0].non_witness_utxo.as_mut().unwrap().lock_time = bitcoin::absolute::LockTime::from_consensus(1);
+        assert_eq!(spent_output(&foreign), None);
+        let error = finalize_htlc_psbt(foreign, &params, None).unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest { ref code, .. } if code == "INVALID_PSBT"));
+
+        assert_eq!(finalize_htlc_psbt(signed, &params, None).unwrap().path, HtlcSpendPath::Refund);
+    }
+}
@@ -1,8 +1,34 @@
 pub mod create_funding_transaction;
 pub mod create_claim_transaction;
 pub mod create_refund_transaction;
+pub mod create_taproot_claim_transaction;
+pub mod create_taproot_refund_transaction;
+pub mod create_cooperative_close_transaction;
+pub mod verify_spend;
+pub mod estimate_vsize;
+pub mod select_coins;
+pub mod sign_wallet_inputs;
+pub mod create_signed_funding_transaction;
+pub mod create_htlc_psbt;
+pub mod finalize_htlc_psbt;
+pub mod create_cpfp_transaction;
 
 // Re-export functions for easy access
-pub use create_funding_transaction::create_funding_transaction;
-pub use create_claim_transaction::create_claim_transaction;
-pub use create_refund_transaction::create_refund_transaction;
\ No newline at end of file
+pub use create_funding_transaction::{create_funding_transaction, create_funding_transaction_at_fee_rate};
+pub use create_claim_transaction::{create_claim_transaction, create_claim_transaction_at_fee_rate};
+pub use create_refund_transaction::{create_refund_transaction, create_refund_transaction_at_fee_rate};
+pub use create_taproot_claim_transaction::create_taproot_claim_transaction;
+pub use create_taproot_refund_transaction::create_taproot_refund_transaction;
+pub use create_cooperative_close_transaction::{create_cooperative_close_transaction, finalize_cooperative_close};
+pub use verify_spend::verify_spend;
+pub use select_coins::select_coins;
+pub use sign_wallet_inputs::sign_wallet_inputs;
+pub use create_signed_funding_transaction::create_signed_funding_transaction;
+pub use create_htlc_psbt::{
+    create_claim_psbt, create_funding_psbt, create_refund_psbt, decode_psbt, encode_psbt, HtlcFunding,
+};
+pub use finalize_htlc_psbt::{finalize_htlc_psbt, FinalizedHtlcSpend};
+pub use create_cpfp_transaction::create_cpfp_transaction;
+pub use estimate_vsize::{
+    fee_for_vsize, funding_vsize, funding_weight, htlc_claim_vsize, htlc_refund_vsize, output_after_fee, wallet_input_weight,
+};
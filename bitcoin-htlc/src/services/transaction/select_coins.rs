@@ -0,0 +1,234 @@
+use bitcoin::{Amount, FeeRate, OutPoint, Script, TxOut, Weight};
+use crate::models::ApiError;
+use crate::services::transaction::{funding_weight, wallet_input_weight};
+
+/// Branch and bound steps before giving up on a changeless set, as in Bitcoin Core
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// Pick wallet coins to fund `htlc_amount` to `htlc_script` at `fee_rate`
+///
+/// Branch and bound first looks for coins that pay the amount and fee with less excess
+/// than a change output would cost, so the excess goes to the fee and no change is made.
+/// Without such a set, coins are taken largest first until they also leave change above
+/// dust. Coins worth less than the fee to spend them are never picked.
+pub fn select_coins(
+    coins: &[(OutPoint, Amount)],
+    htlc_amount: Amount,
+    htlc_script: &Script,
+    wallet_script: &Script,
+    fee_rate: FeeRate,
+) -> Result<Vec<(OutPoint, Amount)>, ApiError> {
+    let fee_rate = fee_rate.max(FeeRate::BROADCAST_MIN);
+    // Each part is rounded up to whole vbytes, so the parts never add up to less than the
+    // fee for the vsize of the whole transaction
+    let fee = |weight: Weight| fee_rate.fee_vb(weight.to_vbytes_ceil()).unwrap_or(Amount::MAX_MONEY).to_sat();
+
+    let input_weight = wallet_input_weight(wallet_script)?;
+    let htlc_output = TxOut { value: Amount::ZERO, script_pubkey: htlc_script.to_owned() };
+    let change_output = TxOut { value: Amount::ZERO, script_pubkey: wallet_script.to_owned() };
+    let base_weight = |outputs: &[TxOut]| {
+        funding_weight(1, wallet_script, outputs).map(|weight| weight - input_weight)
+    };
+    let base_fee = fee(base_weight(std::slice::from_ref(&htlc_output))?);
+    let change_fee = fee(base_weight(&[htlc_output, change_output])?) - base_fee;
+
+    let input_fee = fee(input_weight);
+    let mut candidates: Vec<(OutPoint, Amount, u64)> = coins
+        .iter()
+        .filter(|(_, value)| value.to_sat() > input_fee)
+        .map(|(outpoint, value)| (*outpoint, *value, value.to_sat() - input_fee))
+        .collect();
+    candidates.sort_by_key(|(_, _, effective)| std::cmp::Reverse(*effective));
+    let effective_values: Vec<u64> = candidates.iter().map(|(_, _, effective)| *effective).collect();
+
+    let target = htlc_amount.to_sat() + base_fee;
+    let cost_of_change = change_fee + wallet_script.dust_value().to_sat();
+
+    let selected = branch_and_bound(&effective_values, target, cost_of_change)
+        .or_else(|| largest_first(&effective_values, target, target + cost_of_change))
+        .ok_or_else(|| ApiError::InternalError {
+            code: "INSUFFICIENT_FUNDS".to_string(),
+            message: format!(
+                "Insufficient funds: {} sat spendable at {} < {}",
+                effective_values.iter().sum::<u64>(),
+                fee_rate,
+                Amount::from_sat(target)
+            ),
+            details: None,
+        })?;
+
+    Ok(selected
+        .into_iter()
+        .map(|index| (candidates[index].0, candidates[index].1))
+        .collect())
+}
+
+/// Depth-first search over `values`, sorted largest first, for the subset in
+/// `[target, target + cost_of_change]` with the least excess
+fn branch_and_bound(values: &[u64], target: u64, cost_of_change: u64) -> Option<Vec<usize>> {
+    let mut available: u64 = values.iter().sum();
+    if available < target {
+        return None;
+    }
+
+    let mut selection: Vec<usize> = Vec::new();
+    let mut value = 0u64;
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    let mut index = 0;
+
+    for _ in 0..BNB_MAX_TRIES {
+        let mut backtrack = false;
+        if value + available < target || value > target + cost_of_change {
+            backtrack = true;
+        } else if value >= target {
+            let excess = value - target;
+            if best.as_ref().is_none_or(|(best_excess, _)| excess < *best_excess) {
+                best = Some((excess, selection.clone()));
+            }
+            if excess == 0 {
+                break;
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let Some(&last) = selection.last() else {
+                // Every branch was explored
+                break;
+            };
+            // Coins skipped after the last included one become available again
+            index -= 1;
+            while index > last {
+                available += values[index];
+                index -= 1;
+            }
+            // Then take the branch without the last included coin
+            value -= values[last];
+            selection.pop();
+        } else {
+            available -= values[index];
+            // Including a coin equal to one just left out repeats a branch already searched
+            let repeats_omitted = index > 0
+                && selection.last() != Some(&(index - 1))
+                && values[index] == values[index - 1];
+            if !repeats_omitted {
+                selection.push(index);
+                value += values[index];
+            }
+        }
+        index += 1;
+    }
+
+    best.map(|(_, selection)| selection)
+}
+
+/// Largest coins until `with_change` is covered, or all of them if they only reach `target`
+fn largest_first(values: &[u64], target: u64, with_change: u64) -> Option<Vec<usize>> {
+    let mut total = 0;
+    for (index, value) in values.iter().enumerate() {
+        total += value;
+        if total >= with_change {
+            return Some((0..=index).collect());
+        }
+    }
+    (total >= target).then(|| (0..values.len()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{hashes::Hash, Address, Network, PublicKey, Txid};
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use crate::services::create_funding_transaction_at_fee_rate;
+
+    fn wallet_address() -> Address {
+        let secp = Secp256k1::new();
+        let key = PublicKey::new(SecretKey::from_slice(&[4u8; 32]).unwrap().public_key(&secp));
+        Address::p2wpkh(&key, Network::Testnet).unwrap()
+    }
+
+    fn htlc_address() -> Address {
+        Address::p2wsh(&bitcoin::ScriptBuf::from(vec![0x51]), Network::Testnet)
+    }
+
+    fn coins(values: &[u64]) -> Vec<(OutPoint, Amount)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| (OutPoint::new(Txid::all_zeros(), vout as u32), Amount::from_sat(*value)))
+            .collect()
+    }
+
+    fn select(values: &[u64], htlc_amount: u64, sat_per_vb: u64) -> Result<Vec<(OutPoint, Amount)>, ApiError> {
+        select_coins(
+            &coins(values),
+            Amount::from_sat(htlc_amount),
+            &htlc_address().script_pubkey(),
+            &wallet_address().script_pubkey(),
+            FeeRate::from_sat_per_vb_unchecked(sat_per_vb),
+        )
+    }
+
+    fn values(selection: &[(OutPoint, Amount)]) -> Vec<u64> {
+        selection.iter().map(|(_, value)| value.to_sat()).collect()
+    }
+
+    #[test]
+    fn test_branch_and_bound_finds_changeless_set() {
+        assert_eq!(branch_and_bound(&[10, 7, 5, 3], 8, 0), Some(vec![2, 3]));
+        assert_eq!(branch_and_bound(&[10, 7, 5, 3], 15, 0), Some(vec![0, 2]));
+        // Within the cost of change the smallest excess wins
+        assert_eq!(branch_and_bound(&[10, 7, 5], 11, 2), Some(vec![1, 2]));
+        assert_eq!(branch_and_bound(&[10, 7, 5], 9, 1), Some(vec![0]));
+        assert_eq!(branch_and_bound(&[10, 7, 5], 4, 0), None);
+        assert_eq!(branch_and_bound(&[10, 7, 5], 23, 100), None);
+    }
+
+    #[test]
+    fn test_exact_match_pays_no_change() {
+        // One P2WPKH input: 68 vB at 2 sat/vB; 43 vB HTLC output plus 10.5 vB overhead
+        let input_fee = 136;
+        let base_fee = 2 * 54;
+        let exact = 100_000 + base_fee + input_fee;
+
+        let selection = select(&[500_000, exact, 30_000], 100_000, 2).unwrap();
+        assert_eq!(values(&selection), vec![exact]);
+
+        let transaction = create_funding_transaction_at_fee_rate(
+            selection,
+            &htlc_address(),
+            Amount::from_sat(100_000),
+            &wallet_address(),
+            FeeRate::from_sat_per_vb_unchecked(2),
+        ).unwrap();
+        assert_eq!(transaction.output.len(), 1);
+        // The coin pays exactly the fee for the signed 122 vB
+        assert_eq!(exact - 100_000, 2 * 122);
+    }
+
+    #[test]
+    fn test_falls_back_to_largest_first_with_change() {
+        let selection = select(&[40_000, 90_000, 25_000, 70_000], 100_000, 5).unwrap();
+        assert_eq!(values(&selection), vec![90_000, 70_000]);
+
+        let transaction = create_funding_transaction_at_fee_rate(
+            selection,
+            &htlc_address(),
+            Amount::from_sat(100_000),
+            &wallet_address(),
+            FeeRate::from_sat_per_vb_unchecked(5),
+        ).unwrap();
+        assert_eq!(transaction.output.len(), 2);
+        assert!(transaction.output[1].value >= wallet_address().script_pubkey().dust_value());
+    }
+
+    #[test]
+    fn test_skips_uneconomical_coins_and_reports_shortfall() {
+        // At 10 sat/vB a 600 sat coin costs more than its value to spend
+        let selection = select(&[600, 600, 150_000], 100_000, 10).unwrap();
+        assert_eq!(values(&selection), vec![150_000]);
+
+        let error = select(&[600, 600, 50_000], 100_000, 10).unwrap_err();
+        assert!(matches!(error, ApiError::InternalError { ref code, .. } if code == "INSUFFICIENT_FUNDS"));
+    }
+}
@@ -0,0 +1,149 @@
+use bitcoin::{
+    ecdsa,
+    hashes::Hash,
+    key::{Keypair, Secp256k1, TapTweak},
+    secp256k1::Message,
+    sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType},
+    PrivateKey, ScriptBuf, Transaction, TxOut, Witness,
+};
+use crate::models::ApiError;
+
+/// Sign every input of `transaction` spending `prevouts` held by `key`
+///
+/// P2WPKH inputs get a low-R ECDSA signature, P2TR inputs a BIP86 key path signature of
+/// the untweaked key. `prevouts` lists the spent outputs in input order, which Taproot
+/// sighashes commit to as a whole.
+pub fn sign_wallet_inputs(
+    transaction: &mut Transaction,
+    prevouts: &[TxOut],
+    key: &PrivateKey,
+) -> Result<(), ApiError> {
+    if prevouts.len() != transaction.input.len() {
+        return Err(signing_error(format!(
+            "{} prevouts for {} inputs",
+            prevouts.len(),
+            transaction.input.len()
+        )));
+    }
+
+    let secp = Secp256k1::new();
+    let public_key = key.public_key(&secp);
+    let keypair = Keypair::from_secret_key(&secp, &key.inner);
+    let p2wpkh_script = public_key.wpubkey_hash().map(|hash| ScriptBuf::new_p2wpkh(&hash));
+    let p2tr_script = ScriptBuf::new_p2tr(&secp, keypair.x_only_public_key().0, None);
+
+    let mut sighash_cache = SighashCache::new(&*transaction);
+    let mut witnesses = Vec::with_capacity(prevouts.len());
+    for (index, prevout) in prevouts.iter().enumerate() {
+        let witness = if Some(&prevout.script_pubkey) == p2wpkh_script.as_ref() {
+            let sighash = sighash_cache
+                .p2wpkh_signature_hash(index, &prevout.script_pubkey, prevout.value, EcdsaSighashType::All)
+                .map_err(|e| signing_error(format!("Failed to compute sighash: {}", e)))?;
+            let signature = secp.sign_ecdsa_low_r(&Message::from_digest(sighash.to_byte_array()), &key.inner);
+            Witness::p2wpkh(&ecdsa::Signature::sighash_all(signature), &public_key.inner)
+        } else if prevout.script_pubkey == p2tr_script {
+            let sighash = sighash_cache
+                .taproot_key_spend_signature_hash(index, &Prevouts::All(prevouts), TapSighashType::Default)
+                .map_err(|e| signing_error(format!("Failed to compute sighash: {}", e)))?;
+            let tweaked = keypair.tap_tweak(&secp, None).to_inner();
+            let signature = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &tweaked);
+            // SIGHASH_DEFAULT signatures carry no sighash byte
+            Witness::from_slice(&[signature.as_ref()])
+        } else {
+            return Err(signing_error(format!(
+                "Input {} pays to {}, not a P2WPKH or P2TR output of the wallet key",
+                index, prevout.script_pubkey
+            )));
+        };
+        witnesses.push(witness);
+    }
+
+    for (input, witness) in transaction.input.iter_mut().zip(witnesses) {
+        input.witness = witness;
+    }
+    Ok(())
+}
+
+fn signing_error(message: String) -> ApiError {
+    ApiError::InternalError {
+        code: "BITCOIN_SIGNING_ERROR".to_string(),
+        message,
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::verify_spend;
+    use bitcoin::{
+        absolute::LockTime, secp256k1::SecretKey, transaction::Version, Address, Amount, Network, OutPoint,
+        Sequence, TxIn,
+    };
+
+    fn key() -> PrivateKey {
+        PrivateKey::new(SecretKey::from_slice(&[4u8; 32]).unwrap(), Network::Testnet)
+    }
+
+    fn spending(prevouts: &[TxOut]) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: (0..prevouts.len())
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(bitcoin::Txid::all_zeros(), vout as u32),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: ScriptBuf::new_op_return([]),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_signs_p2wpkh_and_p2tr_inputs() {
+        let secp = Secp256k1::new();
+        let key = key();
+        let public_key = key.public_key(&secp);
+        let prevouts = vec![
+            TxOut {
+                value: Amount::from_sat(30_000),
+                script_pubkey: Address::p2wpkh(&public_key, Network::Testnet).unwrap().script_pubkey(),
+            },
+            TxOut {
+                value: Amount::from_sat(40_000),
+                script_pubkey: Address::p2tr(&secp, public_key.inner.x_only_public_key().0, None, Network::Testnet)
+                    .script_pubkey(),
+            },
+        ];
+
+        let mut transaction = spending(&prevouts);
+        sign_wallet_inputs(&mut transaction, &prevouts, &key).unwrap();
+
+        for index in 0..prevouts.len() {
+            let result = verify_spend(&transaction, index, &prevouts);
+            assert!(result.is_ok(), "input {}: {:?}", index, result);
+        }
+        assert_eq!(transaction.input[1].witness.len(), 1);
+    }
+
+    #[test]
+    fn test_rejects_foreign_inputs() {
+        let other = PrivateKey::new(SecretKey::from_slice(&[5u8; 32]).unwrap(), Network::Testnet);
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(30_000),
+            script_pubkey: Address::p2wpkh(&other.public_key(&Secp256k1::new()), Network::Testnet)
+                .unwrap()
+                .script_pubkey(),
+        }];
+
+        let mut transaction = spending(&prevouts);
+        let error = sign_wallet_inputs(&mut transaction, &prevouts, &key()).unwrap_err();
+        assert!(matches!(error, ApiError::InternalError { ref code, .. } if code == "BITCOIN_SIGNING_ERROR"));
+        assert!(sign_wallet_inputs(&mut transaction, &[], &key()).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use bitcoin::{consensus::encode::serialize, Transaction, TxOut};
+use bitcoinconsensus::{Utxo, VERIFY_ALL_PRE_TAPROOT, VERIFY_TAPROOT};
+use crate::models::ApiError;
+
+/// Verify that input `input_index` of `transaction` validly spends its prevout
+///
+/// `prevouts` holds the outputs spent by every input, in input order, since taproot
+/// sighashes commit to all of them. Script evaluation is done by Bitcoin Core's
+/// libbitcoinconsensus with every soft fork up to taproot enforced, so a spend that
+/// passes here passes on a node.
+pub fn verify_spend(
+    transaction: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+) -> Result<(), ApiError> {
+    if input_index >= transaction.input.len() {
+        return Err(script_error(format!("Transaction has no input {}", input_index)));
+    }
+    if prevouts.len() != transaction.input.len() {
+        return Err(script_error(format!(
+            "Expected {} prevouts, got {}",
+            transaction.input.len(),
+            prevouts.len()
+        )));
+    }
+
+    // The C structs borrow the scripts, which `prevouts` keeps alive for the call
+    let spent_outputs: Vec<Utxo> = prevouts
+        .iter()
+        .map(|output| Utxo {
+            script_pubkey: output.script_pubkey.as_bytes().as_ptr(),
+            script_pubkey_len: output.script_pubkey.len() as u32,
+            value: output.value.to_sat() as i64,
+        })
+        .collect();
+    let prevout = &prevouts[input_index];
+
+    bitcoinconsensus::verify_with_flags(
+        prevout.script_pubkey.as_bytes(),
+        prevout.value.to_sat(),
+        &serialize(transaction),
+        Some(&spent_outputs),
+        input_index,
+        VERIFY_ALL_PRE_TAPROOT | VERIFY_TAPROOT,
+    )
+    .map_err(|e| match e {
+        bitcoinconsensus::Error::ERR_SCRIPT => script_error(format!(
+            "Input {} does not satisfy {}",
+            input_index,
+            prevout.script_pubkey.to_asm_string()
+        )),
+        other => script_error(format!("Script verification failed: {}", other)),
+    })
+}
+
+fn script_error(message: impl Into<String>) -> ApiError {
+    ApiError::BadRequest {
+        code: "SCRIPT_VERIFICATION_FAILED".to_string(),
+        message: message.into(),
+        details: None,
+    }
+}
@@ -0,0 +1,12 @@
+use crate::models::{ApiError, WatchEvent};
+use crate::repository::WatchlistRepository;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// Fundings, confirmations, spends and reorgs of a watched script, oldest first
+pub async fn get_watch_events(pool: &SqlitePool, watch_id: Uuid) -> Result<Vec<WatchEvent>, ApiError> {
+    let repository = WatchlistRepository::new(pool.clone());
+    // Distinguish an unknown watch from one nothing happened to yet
+    repository.find(watch_id).await?;
+    repository.find_events(watch_id).await
+}
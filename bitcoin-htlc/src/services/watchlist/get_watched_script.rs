@@ -0,0 +1,9 @@
+use crate::models::{ApiError, WatchedScript};
+use crate::repository::WatchlistRepository;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// A watched script with its funding and spend as scanned so far
+pub async fn get_watched_script(pool: &SqlitePool, watch_id: Uuid) -> Result<WatchedScript, ApiError> {
+    WatchlistRepository::new(pool.clone()).find(watch_id).await
+}
@@ -0,0 +1,10 @@
+pub mod get_watch_events;
+pub mod get_watched_script;
+pub mod scan_watchlist;
+pub mod watch_script;
+
+// Re-export functions for easy access
+pub use get_watch_events::get_watch_events;
+pub use get_watched_script::get_watched_script;
+pub use scan_watchlist::scan_watchlist;
+pub use watch_script::watch_script;
@@ -0,0 +1,280 @@
+use crate::models::{ApiError, WatchEvent, WatchEventKind, WatchStatus, WatchedScript};
+use crate::repository::WatchlistRepository;
+use crate::services::bitcoin::BitcoinClient;
+use crate::services::order::track_confirmations::FINALITY_DEPTH;
+use bitcoin::{Block, OutPoint};
+use chrono::Utc;
+use log::{info, warn};
+use sqlx::SqlitePool;
+
+/// Blocks scanned per call, so catching up after a long downtime doesn't stall a tick
+pub const MAX_BLOCKS_PER_SCAN: u32 = 144;
+
+/// Scan the blocks after the watchlist cursor for fundings and spends of watched scripts
+///
+/// The cursor is the last scanned block and lives in the database, so after a restart
+/// scanning resumes with the blocks mined meanwhile. When the cursor's block is no
+/// longer on the active chain, the cursor steps back until it is, and fundings and
+/// spends seen in the dropped blocks are undone with a `reorged` event; the new blocks
+/// are then scanned like any other. Without a cursor, or once nothing is left to
+/// watch, scanning (re)starts at the tip. Returns the events recorded by this call,
+/// at most [`MAX_BLOCKS_PER_SCAN`] blocks' worth.
+pub async fn scan_watchlist(pool: &SqlitePool, bitcoin_client: &BitcoinClient) -> Result<Vec<WatchEvent>, ApiError> {
+    let repository = WatchlistRepository::new(pool.clone());
+    let tip = bitcoin_client.get_block_height().await?;
+    let mut watches = repository.find_active(tip.saturating_sub(FINALITY_DEPTH)).await?;
+    if watches.is_empty() {
+        repository.clear_scanned_blocks().await?;
+        return Ok(Vec::new());
+    }
+
+    let mut events = Vec::new();
+    let mut cursor = repository.cursor().await?;
+    while let Some((height, hash)) = &cursor {
+        let height = *height;
+        match bitcoin_client.get_block_hash(height).await {
+            Ok(active) if active == *hash => break,
+            Ok(_) | Err(ApiError::NotFound { .. }) => {}
+            Err(e) => return Err(e),
+        }
+
+        warn!("Scanned block {} at height {} left the active chain", hash, height);
+        for watch in &mut watches {
+            let reorged = unwind(watch, height);
+            record(&repository, watch, reorged, &mut events).await?;
+        }
+        repository.remove_scanned_block(height).await?;
+        cursor = repository.cursor().await?;
+    }
+
+    let start = cursor.map_or(tip, |(height, _)| height + 1);
+    let end = tip.min(start.saturating_add(MAX_BLOCKS_PER_SCAN - 1));
+    for height in start..=end {
+        let hash = bitcoin_client.get_block_hash(height).await?;
+        let block = bitcoin_client.get_block(&hash).await?;
+        for watch in &mut watches {
+            let seen = scan_block(watch, &block, height);
+            record(&repository, watch, seen, &mut events).await?;
+        }
+        repository.record_scanned_block(height, &hash).await?;
+    }
+
+    Ok(events)
+}
+
+/// Apply one block to a watch: the first output paying its script funds it, an input
+/// spending that output spends it, and it is confirmed once the funding is deep enough
+fn scan_block(watch: &mut WatchedScript, block: &Block, height: u32) -> Vec<WatchEvent> {
+    let mut events = Vec::new();
+    for tx in &block.txdata {
+        match watch.status {
+            WatchStatus::Watching => {
+                let funding = tx
+                    .output
+                    .iter()
+                    .enumerate()
+                    .find(|(_, output)| output.script_pubkey == watch.script_pubkey);
+                if let Some((vout, output)) = funding {
+                    watch.status = WatchStatus::Funded;
+                    watch.funding_outpoint = Some(OutPoint::new(tx.txid(), vout as u32));
+                    watch.funding_amount = Some(output.value.to_sat());
+                    watch.funding_height = Some(height);
+                    events.push(event(watch, WatchEventKind::Funded, tx.txid().to_string(), height));
+                }
+            }
+            WatchStatus::Funded | WatchStatus::Confirmed => {
+                let spends = tx
+                    .input
+                    .iter()
+                    .any(|input| Some(input.previous_output) == watch.funding_outpoint);
+                if spends {
+                    watch.status = WatchStatus::Spent;
+                    watch.spend_txid = Some(tx.txid().to_string());
+                    watch.spend_height = Some(height);
+                    events.push(event(watch, WatchEventKind::Spent, tx.txid().to_string(), height));
+                }
+            }
+            WatchStatus::Spent => {}
+        }
+    }
+
+    if watch.status == WatchStatus::Funded && watch.is_confirmed_at(height) {
+        watch.status = WatchStatus::Confirmed;
+        let funding_txid = watch.funding_outpoint.map(|outpoint| outpoint.txid.to_string()).unwrap_or_default();
+        events.push(event(watch, WatchEventKind::Confirmed, funding_txid, height));
+    }
+    events
+}
+
+/// Undo what a watch saw at `height`, a block that left the active chain
+///
+/// A lost spend leaves the watch funded or confirmed, as it was before the spend; a
+/// lost funding takes it back to watching.
+fn unwind(watch: &mut WatchedScript, height: u32) -> Vec<WatchEvent> {
+    let mut events = Vec::new();
+    if let (Some(spend_txid), Some(spend_height)) = (watch.spend_txid.take(), watch.spend_height) {
+        if spend_height >= height {
+            watch.status = if watch.is_confirmed_at(spend_height - 1) {
+                WatchStatus::Confirmed
+            } else {
+                WatchStatus::Funded
+            };
+            watch.spend_height = None;
+            events.push(event(watch, WatchEventKind::Reorged, spend_txid, spend_height));
+        } else {
+            watch.spend_txid = Some(spend_txid);
+        }
+    }
+
+    if let (Some(outpoint), Some(funding_height)) = (watch.funding_outpoint, watch.funding_height) {
+        if funding_height >= height {
+            watch.status = WatchStatus::Watching;
+            watch.funding_outpoint = None;
+            watch.funding_amount = None;
+            watch.funding_height = None;
+            events.push(event(watch, WatchEventKind::Reorged, outpoint.txid.to_string(), funding_height));
+        }
+    }
+    events
+}
+
+fn event(watch: &WatchedScript, kind: WatchEventKind, txid: String, block_height: u32) -> WatchEvent {
+    WatchEvent {
+        watch_id: watch.id,
+        htlc_id: watch.htlc_id,
+        kind,
+        txid,
+        block_height,
+        created_at: Utc::now(),
+    }
+}
+
+/// Persist a watch that changed together with its new events
+async fn record(
+    repository: &WatchlistRepository,
+    watch: &mut WatchedScript,
+    new_events: Vec<WatchEvent>,
+    events: &mut Vec<WatchEvent>,
+) -> Result<(), ApiError> {
+    if new_events.is_empty() {
+        return Ok(());
+    }
+    watch.updated_at = Utc::now();
+    repository.record_events(watch, &new_events).await?;
+    for event in &new_events {
+        info!("Watched script {} {} by {} at height {}", watch.id, event.kind.as_str(), event.txid, event.block_height);
+    }
+    events.extend(new_events);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::bitcoin::MockBackend;
+    use crate::test_support::test_pool;
+    use bitcoin::{
+        absolute::LockTime,
+        block::{Header, Version as BlockVersion},
+        hashes::Hash,
+        Amount, BlockHash, CompactTarget, ScriptBuf, Sequence, Transaction, TxIn, TxMerkleNode, TxOut, Witness,
+    };
+    use std::sync::Arc;
+
+    const TIP: u32 = 2_500_000;
+
+    fn transaction(spending: Option<OutPoint>, pays: &ScriptBuf, nonce: u8) -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: spending.unwrap_or(OutPoint::new(bitcoin::Txid::from_byte_array([nonce; 32]), 0)),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: pays.clone(),
+            }],
+        }
+    }
+
+    /// Put a block at `height` and make it the tip; `fork` tells competing blocks apart
+    fn mine(backend: &MockBackend, height: u32, fork: u8, txdata: Vec<Transaction>) {
+        let hash = format!("{:02x}{:062x}", fork, height);
+        let header = Header {
+            version: BlockVersion::TWO,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 0,
+            bits: CompactTarget::from_consensus(0x207f_ffff),
+            nonce: 0,
+        };
+        backend.set_block_hash(height, &hash);
+        backend.set_block(&hash, Block { header, txdata });
+        backend.set_block_height(height);
+    }
+
+    #[tokio::test]
+    async fn test_scan_follows_fundings_spends_and_reorgs() {
+        let pool = test_pool().await;
+        let backend = Arc::new(MockBackend::new(TIP));
+        let bitcoin_client = BitcoinClient::from_arc(backend.clone());
+        let repository = WatchlistRepository::new(pool.clone());
+
+        let script = ScriptBuf::new_op_return([1u8; 4]);
+        let other = ScriptBuf::new_op_return([2u8; 4]);
+        let watch = WatchedScript::new(script.clone(), 2, None);
+        repository.insert(&watch).await.unwrap();
+
+        // The first scan starts at the tip
+        mine(&backend, TIP, 0, vec![transaction(None, &other, 1)]);
+        assert!(scan_watchlist(&pool, &bitcoin_client).await.unwrap().is_empty());
+        assert_eq!(repository.cursor().await.unwrap().unwrap().0, TIP);
+
+        // Blocks mined while the scanner was down are all scanned on its next call
+        let funding = transaction(None, &script, 2);
+        let funding_outpoint = OutPoint::new(funding.txid(), 0);
+        mine(&backend, TIP + 1, 0, vec![funding.clone()]);
+        mine(&backend, TIP + 2, 0, Vec::new());
+        let events = scan_watchlist(&pool, &bitcoin_client).await.unwrap();
+        let kinds: Vec<_> = events.iter().map(|event| (event.kind, event.block_height)).collect();
+        assert_eq!(kinds, vec![(WatchEventKind::Funded, TIP + 1), (WatchEventKind::Confirmed, TIP + 2)]);
+        let stored = repository.find(watch.id).await.unwrap();
+        assert_eq!(stored.status, WatchStatus::Confirmed);
+        assert_eq!(stored.funding_outpoint, Some(funding_outpoint));
+        assert_eq!(stored.funding_amount, Some(100_000));
+
+        let spend = transaction(Some(funding_outpoint), &other, 3);
+        mine(&backend, TIP + 3, 0, vec![spend.clone()]);
+        let events = scan_watchlist(&pool, &bitcoin_client).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].kind, events[0].txid.clone()), (WatchEventKind::Spent, spend.txid().to_string()));
+
+        // A reorg replacing the last two blocks drops the spend until it is mined again
+        mine(&backend, TIP + 2, 1, Vec::new());
+        mine(&backend, TIP + 3, 1, Vec::new());
+        mine(&backend, TIP + 4, 1, vec![spend.clone()]);
+        let events = scan_watchlist(&pool, &bitcoin_client).await.unwrap();
+        let kinds: Vec<_> = events.iter().map(|event| (event.kind, event.block_height)).collect();
+        assert_eq!(
+            kinds,
+            vec![(WatchEventKind::Reorged, TIP + 3), (WatchEventKind::Spent, TIP + 4)]
+        );
+        assert_eq!(repository.cursor().await.unwrap(), Some((TIP + 4, format!("01{:062x}", TIP + 4))));
+
+        // Losing the funding block too takes the watch back to the start
+        mine(&backend, TIP + 1, 2, Vec::new());
+        for height in TIP + 2..=TIP + 4 {
+            mine(&backend, height, 2, Vec::new());
+        }
+        let events = scan_watchlist(&pool, &bitcoin_client).await.unwrap();
+        let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(kinds, vec![WatchEventKind::Reorged, WatchEventKind::Reorged]);
+        let stored = repository.find(watch.id).await.unwrap();
+        assert_eq!(stored.status, WatchStatus::Watching);
+        assert!(stored.funding_outpoint.is_none() && stored.spend_txid.is_none());
+        assert_eq!(repository.find_events(watch.id).await.unwrap().len(), 7);
+    }
+}
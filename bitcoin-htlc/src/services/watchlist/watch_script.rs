@@ -0,0 +1,77 @@
+use crate::models::{ApiError, WatchScriptRequest, WatchedScript};
+use crate::repository::WatchlistRepository;
+use crate::services::order::export_htlc_psbt::parse_address;
+use bitcoin::{Network, ScriptBuf};
+use sqlx::SqlitePool;
+
+/// Start watching an address or raw scriptPubKey for its funding and spend
+///
+/// Scanning starts at the watchlist cursor, so only fundings in blocks from then on
+/// are picked up.
+pub async fn watch_script(
+    pool: &SqlitePool,
+    network: Network,
+    request: WatchScriptRequest,
+) -> Result<WatchedScript, ApiError> {
+    let script_pubkey = match (&request.address, &request.script_pubkey) {
+        (Some(address), None) => parse_address(address, network, "address")?.script_pubkey(),
+        (None, Some(script_hex)) => ScriptBuf::from_hex(script_hex)
+            .ok()
+            .filter(|script| !script.is_empty())
+            .ok_or_else(|| ApiError::BadRequest {
+                code: "INVALID_SCRIPT".to_string(),
+                message: "script_pubkey is not a valid hex script".to_string(),
+                details: None,
+            })?,
+        _ => {
+            return Err(ApiError::BadRequest {
+                code: "INVALID_WATCH".to_string(),
+                message: "Give exactly one of address and script_pubkey".to_string(),
+                details: None,
+            })
+        }
+    };
+
+    let watch = WatchedScript::new(script_pubkey, request.min_confirmations.unwrap_or(1), None);
+    WatchlistRepository::new(pool.clone()).insert(&watch).await?;
+    Ok(watch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::WatchStatus;
+    use crate::test_support::test_pool;
+
+    #[tokio::test]
+    async fn test_watch_script_takes_an_address_or_a_script() {
+        let pool = test_pool().await;
+        let address = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+
+        let request = WatchScriptRequest { address: Some(address.to_string()), script_pubkey: None, min_confirmations: Some(3) };
+        let watch = watch_script(&pool, Network::Testnet, request).await.unwrap();
+        assert_eq!(watch.status, WatchStatus::Watching);
+        assert_eq!(watch.min_confirmations, 3);
+        let stored = WatchlistRepository::new(pool.clone()).find(watch.id).await.unwrap();
+        assert_eq!(stored.script_pubkey, parse_address(address, Network::Testnet, "address").unwrap().script_pubkey());
+
+        let request = WatchScriptRequest {
+            address: None,
+            script_pubkey: Some(stored.script_pubkey.to_hex_string()),
+            min_confirmations: None,
+        };
+        assert_eq!(watch_script(&pool, Network::Testnet, request).await.unwrap().min_confirmations, 1);
+
+        for request in [
+            WatchScriptRequest { address: None, script_pubkey: None, min_confirmations: None },
+            WatchScriptRequest { address: Some(address.to_string()), script_pubkey: Some("00".to_string()), min_confirmations: None },
+            WatchScriptRequest { address: None, script_pubkey: Some("zz".to_string()), min_confirmations: None },
+        ] {
+            let result = watch_script(&pool, Network::Testnet, request).await;
+            assert!(matches!(result, Err(ApiError::BadRequest { .. })));
+        }
+        let request = WatchScriptRequest { address: Some(address.to_string()), script_pubkey: None, min_confirmations: None };
+        let result = watch_script(&pool, Network::Bitcoin, request).await;
+        assert!(matches!(result, Err(ApiError::BadRequest { ref code, .. }) if code == "INVALID_ADDRESS"));
+    }
+}
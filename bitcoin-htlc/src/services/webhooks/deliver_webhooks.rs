@@ -0,0 +1,157 @@
+use crate::models::{ApiError, WebhookDelivery};
+use crate::repository::WebhookRepository;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::warn;
+use reqwest::header::CONTENT_TYPE;
+use sha2::Sha256;
+use sqlx::SqlitePool;
+
+/// Notifications posted per call, so a backlog doesn't stall a tick
+pub const MAX_DELIVERIES_PER_CALL: u32 = 100;
+
+/// Posts of one notification before it is given up on
+pub const MAX_DELIVERY_ATTEMPTS: u32 = 8;
+
+/// Post the due notifications of the webhook outbox
+///
+/// Bodies are signed with HMAC-SHA256 under the webhook's secret and the signature
+/// is sent as `X-Webhook-Signature: sha256=<hex>`; `X-Webhook-Event` names the event
+/// and `X-Webhook-Delivery` the notification, for receivers to drop repeats. A 2xx
+/// answer delivers it, anything else is retried after 30 seconds, doubling each
+/// time, until [`MAX_DELIVERY_ATTEMPTS`] posts failed. Delivery is at least once: a
+/// crash between post and bookkeeping posts again. Returns how many were delivered.
+pub async fn deliver_webhooks(pool: &SqlitePool, http: &reqwest::Client) -> Result<usize, ApiError> {
+    let repository = WebhookRepository::new(pool.clone());
+    let mut delivered = 0;
+    for mut delivery in repository.find_due(MAX_DELIVERY_ATTEMPTS, MAX_DELIVERIES_PER_CALL).await? {
+        let retry_at = Utc::now().timestamp() + (30 << delivery.attempts.min(10));
+        if !repository.claim(&mut delivery, retry_at).await? {
+            continue;
+        }
+
+        match post(http, &delivery).await {
+            Ok(()) => {
+                repository.mark_delivered(delivery.id).await?;
+                delivered += 1;
+            }
+            Err(reason) => warn!(
+                "Webhook {} could not be notified of {} (attempt {} of {}): {}",
+                delivery.webhook_id, delivery.event, delivery.attempts, MAX_DELIVERY_ATTEMPTS, reason
+            ),
+        }
+    }
+    Ok(delivered)
+}
+
+/// `X-Webhook-Signature` value of `payload` under `secret`
+pub fn sign_payload(secret: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(payload.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn post(http: &reqwest::Client, delivery: &WebhookDelivery) -> Result<(), String> {
+    let response = http
+        .post(&delivery.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header("X-Webhook-Event", &delivery.event)
+        .header("X-Webhook-Delivery", delivery.id.to_string())
+        .header("X-Webhook-Signature", sign_payload(&delivery.secret, &delivery.payload))
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("answered {}", response.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::WebhookEvent;
+    use crate::test_support::test_pool;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+
+    /// HTTP endpoint answering every request with `status`, passing on the raw requests
+    async fn endpoint(status: u16) -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (requests, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                // Read the head, then as much body as it announces
+                loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some(head_end) = text.find("\r\n\r\n") {
+                        let length = text[..head_end]
+                            .lines()
+                            .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                            .and_then(|length| length.parse::<usize>().ok())
+                            .unwrap_or(0);
+                        if request.len() >= head_end + 4 + length || read == 0 {
+                            break;
+                        }
+                    }
+                }
+                let answer = format!("HTTP/1.1 {} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                stream.write_all(answer.as_bytes()).await.unwrap();
+                requests.send(String::from_utf8(request).unwrap()).unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_delivers_signed_notifications_once() {
+        let pool = test_pool().await;
+        let (url, mut received) = endpoint(204).await;
+        let secret = "s".repeat(32);
+        let repository = WebhookRepository::new(pool.clone());
+        repository.insert(Uuid::new_v4(), &url, &secret, &[WebhookEvent::WatchFunded]).await.unwrap();
+        repository.enqueue(WebhookEvent::WatchFunded, serde_json::json!({ "txid": "aa" })).await.unwrap();
+        let payload = repository.find_due(MAX_DELIVERY_ATTEMPTS, 10).await.unwrap()[0].payload.clone();
+
+        let http = reqwest::Client::new();
+        assert_eq!(deliver_webhooks(&pool, &http).await.unwrap(), 1);
+        let request = received.recv().await.unwrap();
+        let lowercase = request.to_lowercase();
+        assert!(request.starts_with("POST /hook "));
+        assert!(lowercase.contains("x-webhook-event: watch.funded\r\n"));
+        assert!(lowercase.contains(&format!("x-webhook-signature: {}\r\n", sign_payload(&secret, &payload))));
+        assert!(request.ends_with(&format!("\r\n\r\n{}", payload)));
+
+        assert_eq!(deliver_webhooks(&pool, &http).await.unwrap(), 0);
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_notifications_wait_for_a_retry() {
+        let pool = test_pool().await;
+        let (url, mut received) = endpoint(500).await;
+        let repository = WebhookRepository::new(pool.clone());
+        repository.insert(Uuid::new_v4(), &url, &"s".repeat(32), &[WebhookEvent::OrderCompleted]).await.unwrap();
+        repository.enqueue(WebhookEvent::OrderCompleted, serde_json::json!({})).await.unwrap();
+
+        let http = reqwest::Client::new();
+        assert_eq!(deliver_webhooks(&pool, &http).await.unwrap(), 0);
+        received.recv().await.unwrap();
+        // The retry is not due yet
+        assert_eq!(deliver_webhooks(&pool, &http).await.unwrap(), 0);
+        assert!(received.try_recv().is_err());
+
+        let attempts: i64 = sqlx::query_scalar("SELECT attempts FROM webhook_deliveries").fetch_one(&pool).await.unwrap();
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod deliver_webhooks;
+pub mod register_webhook;
+
+// Re-export functions for easy access
+pub use deliver_webhooks::deliver_webhooks;
+pub use register_webhook::register_webhook;
@@ -0,0 +1,32 @@
+use crate::models::{ApiError, WebhookRegistration, WebhookResponse};
+use crate::repository::WebhookRepository;
+use rand::Rng;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// Store a webhook so the events it subscribes to are posted to its URL
+///
+/// Without a secret of its own the webhook gets a random one, returned once here;
+/// every delivery is signed with it.
+pub async fn register_webhook(
+    pool: &SqlitePool,
+    registration: WebhookRegistration,
+) -> Result<WebhookResponse, ApiError> {
+    let webhook_id = Uuid::new_v4();
+    let secret = registration.secret.unwrap_or_else(|| {
+        let mut rng = rand::thread_rng();
+        let bytes: Vec<u8> = (0..32).map(|_| rng.gen::<u8>()).collect();
+        hex::encode(bytes)
+    });
+
+    WebhookRepository::new(pool.clone())
+        .insert(webhook_id, &registration.url, &secret, &registration.events)
+        .await?;
+
+    Ok(WebhookResponse {
+        webhook_id,
+        url: registration.url,
+        events: registration.events.iter().map(|event| event.as_str().to_string()).collect(),
+        secret: Some(secret),
+    })
+}
@@ -0,0 +1,97 @@
+//! Fixtures shared by the unit tests
+
+use crate::models::{HtlcOutputType, HtlcParams, HtlcRecord, OrderStatus, SwapDirection};
+use crate::repository::HtlcRepository;
+use crate::services::bitcoin::{BitcoinClient, SimulatedChain};
+use crate::services::{build_htlc_script, hash_preimage};
+use bitcoin::{key::Secp256k1, secp256k1::SecretKey, Address, Amount, Network, PrivateKey};
+use chrono::{Duration, Utc};
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Preimage of the HTLCs built by [`htlc_params`] and the orders of [`insert_order`]
+pub const PREIMAGE: [u8; 32] = [7u8; 32];
+
+/// Timeout of the HTLCs built by [`htlc_params`]
+pub const HTLC_TIMEOUT: u32 = 2_500_144;
+
+/// Bitcoin amount of the orders of [`insert_order`], in sat
+pub const ORDER_AMOUNT: u64 = 100_000;
+
+/// Empty in-memory database with every migration applied
+pub async fn test_pool() -> SqlitePool {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect(":memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    pool
+}
+
+/// Testnet key whose secret is `seed` repeated, so tests can name parties by number
+pub fn key(seed: u8) -> PrivateKey {
+    PrivateKey::new(SecretKey::from_slice(&[seed; 32]).unwrap(), Network::Testnet)
+}
+
+/// Testnet HTLC of `output_type` from `key(2)` to `key(1)`, locked to [`PREIMAGE`]
+/// until [`HTLC_TIMEOUT`]
+pub fn htlc_params(output_type: HtlcOutputType) -> HtlcParams {
+    let secp = Secp256k1::new();
+    HtlcParams {
+        recipient_pubkey: key(1).public_key(&secp),
+        sender_pubkey: key(2).public_key(&secp),
+        payment_hash: hash_preimage(&PREIMAGE),
+        timeout: HTLC_TIMEOUT,
+        output_type,
+        network: Network::Testnet,
+    }
+}
+
+/// Order in `status` for [`ORDER_AMOUNT`] sat, locked to [`PREIMAGE`] with `key(2)` as
+/// resolver and expiring in an hour
+pub async fn insert_order(pool: &SqlitePool, direction: SwapDirection, status: OrderStatus) -> Uuid {
+    let order_id = Uuid::new_v4();
+    let now = Utc::now();
+    sqlx::query(
+        r#"
+        INSERT INTO orders (
+            id, direction, status, preimage_hash, bitcoin_amount, resolver_public_key,
+            bitcoin_timeout_blocks, ethereum_timeout_blocks,
+            bitcoin_confirmations_required, ethereum_confirmations_required,
+            created_at, updated_at, expires_at
+        ) VALUES (?, ?, ?, ?, ?, ?, 144, 300, 1, 12, ?, ?, ?)
+        "#,
+    )
+    .bind(order_id)
+    .bind(direction.as_str())
+    .bind(status.as_str())
+    .bind(hex::encode(hash_preimage(&PREIMAGE)))
+    .bind(ORDER_AMOUNT as i64)
+    .bind(key(2).public_key(&Secp256k1::new()).to_string())
+    .bind(now)
+    .bind(now)
+    .bind(now + Duration::hours(1))
+    .execute(pool)
+    .await
+    .unwrap();
+    order_id
+}
+
+/// P2WSH HTLC of [`htlc_params`] timing out at `timeout`, funded with 100 000 sat and
+/// confirmed once on a fresh chain at 2 500 000
+pub async fn funded_htlc(timeout: u32) -> (SqlitePool, Arc<SimulatedChain>, BitcoinClient, HtlcRecord) {
+    let pool = test_pool().await;
+
+    let params = HtlcParams { timeout, ..htlc_params(HtlcOutputType::P2wsh) };
+    let htlc = HtlcRecord::new(params.clone(), &build_htlc_script(&params).unwrap());
+    HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+    let chain = Arc::new(SimulatedChain::new(Network::Testnet, 2_500_000));
+    chain.fund(&Address::from_str(&htlc.address).unwrap().assume_checked(), Amount::from_sat(100_000));
+    chain.mine(1);
+    let client = BitcoinClient::from_arc(chain.clone());
+    (pool, chain, client, htlc)
+}
@@ -1,6 +1,10 @@
 use actix_web::{test, web, App};
 use sqlx::sqlite::SqlitePoolOptions;
-use thunder_portal::{routes::configure_routes, AppState};
+use thunder_portal::{
+    routes::configure_routes,
+    services::bitcoin::{BitcoinClient, MockBackend},
+    AppState,
+};
 use serde_json::json;
 
 #[actix_rt::test]
@@ -19,7 +23,8 @@ async fn test_create_htlc_endpoint() {
         .expect("Failed to run migrations");
 
     // Create app state
-    let app_state = AppState::new(pool);
+    let bitcoin_client = BitcoinClient::from_backend(MockBackend::new(2_500_000));
+    let app_state = AppState::with_bitcoin_client(pool, bitcoin_client);
 
     let app = test::init_service(
         App::new()
@@ -44,6 +49,7 @@ async fn test_create_htlc_endpoint() {
     assert!(resp.status().is_success());
 
     let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["htlc_id"].is_string());
     assert!(body["htlc_address"].is_string());
     assert!(body["htlc_script"].is_string());
     assert!(body["script_hash"].is_string());
@@ -97,7 +103,8 @@ async fn test_create_htlc_deterministic() {
         .expect("Failed to run migrations");
 
     // Create app state
-    let app_state = AppState::new(pool);
+    let bitcoin_client = BitcoinClient::from_backend(MockBackend::new(2_500_000));
+    let app_state = AppState::with_bitcoin_client(pool, bitcoin_client);
 
     let app = test::init_service(
         App::new()
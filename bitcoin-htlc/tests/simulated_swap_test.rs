@@ -0,0 +1,151 @@
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{Address, Amount, Network, OutPoint, PrivateKey, ScriptBuf, Transaction, Witness};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use thunder_portal::models::{ApiError, ClaimRequest, HtlcOutputType, HtlcParams, HtlcRecord, HtlcStatus};
+use thunder_portal::repository::HtlcRepository;
+use thunder_portal::services::bitcoin::{BitcoinClient, SimulatedChain};
+use thunder_portal::services::order::{claim_htlc, refund_htlc};
+use thunder_portal::services::{build_htlc_script, create_funding_transaction, create_refund_transaction, hash_preimage};
+
+const PREIMAGE: [u8; 32] = [7u8; 32];
+
+struct Swap {
+    pool: SqlitePool,
+    chain: Arc<SimulatedChain>,
+    client: BitcoinClient,
+    sender: PrivateKey,
+    recipient: PrivateKey,
+    htlc: HtlcRecord,
+}
+
+fn key(seed: u8) -> PrivateKey {
+    PrivateKey::new(SecretKey::from_slice(&[seed; 32]).unwrap(), Network::Testnet)
+}
+
+/// HTLC timing out `timeout_blocks` after the tip, funded and confirmed on a fresh chain
+async fn funded_swap(timeout_blocks: u32) -> Swap {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect(":memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+    let chain = Arc::new(SimulatedChain::new(Network::Testnet, 2_500_000));
+    let client = BitcoinClient::from_arc(chain.clone());
+    let secp = Secp256k1::new();
+    let (sender, recipient, wallet) = (key(1), key(2), key(3));
+
+    let params = HtlcParams {
+        recipient_pubkey: recipient.public_key(&secp),
+        sender_pubkey: sender.public_key(&secp),
+        payment_hash: hash_preimage(&PREIMAGE),
+        timeout: 2_500_000 + timeout_blocks,
+        output_type: HtlcOutputType::P2wsh,
+        network: Network::Testnet,
+    };
+    let htlc_script = build_htlc_script(&params).unwrap();
+    let htlc = HtlcRecord::new(params, &htlc_script);
+    HtlcRepository::new(pool.clone()).insert(&htlc).await.unwrap();
+
+    // The sender's wallet funds the HTLC from a faucet coin
+    let wallet_address = Address::p2wpkh(&wallet.public_key(&secp), Network::Testnet).unwrap();
+    let coin = chain.fund(&wallet_address, Amount::from_sat(200_000));
+    let htlc_address = htlc.address.parse::<Address<_>>().unwrap().assume_checked();
+    let mut funding = create_funding_transaction(
+        vec![(coin, Amount::from_sat(200_000))],
+        &htlc_address,
+        Amount::from_sat(100_000),
+        &wallet_address,
+        Amount::from_sat(1_000),
+    )
+    .unwrap();
+    sign_p2wpkh(&mut funding, Amount::from_sat(200_000), &wallet);
+    client.broadcast_transaction(&serialize_hex(&funding)).await.unwrap();
+    chain.mine(1);
+
+    Swap { pool, chain, client, sender, recipient, htlc }
+}
+
+fn sign_p2wpkh(transaction: &mut Transaction, value: Amount, key: &PrivateKey) {
+    let secp = Secp256k1::new();
+    let public_key = key.public_key(&secp);
+    let script_pubkey = ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash().unwrap());
+    let sighash = SighashCache::new(&*transaction)
+        .p2wpkh_signature_hash(0, &script_pubkey, value, EcdsaSighashType::All)
+        .unwrap();
+    let signature = secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &key.inner);
+    transaction.input[0].witness = Witness::p2wpkh(&bitcoin::ecdsa::Signature::sighash_all(signature), &public_key.inner);
+}
+
+#[actix_rt::test]
+async fn test_claim_with_preimage_on_simulated_chain() {
+    let swap = funded_swap(144).await;
+
+    let response = claim_htlc(
+        &swap.pool,
+        &swap.client,
+        Network::Testnet,
+        Some(&swap.recipient),
+        swap.htlc.id,
+        ClaimRequest { preimage: hex::encode(PREIMAGE), bitcoin_tx_hex: None },
+    )
+    .await
+    .unwrap();
+    swap.chain.mine(1);
+
+    let claim = swap.client.get_transaction(&response.transaction_id).await.unwrap();
+    assert_eq!(claim.status.block_height, Some(2_500_002));
+    assert!(swap.client.get_utxos(&swap.htlc.address).await.unwrap().is_empty());
+    let payout = swap.client.get_utxos(&response.claim_address).await.unwrap();
+    assert_eq!(payout[0].value, response.claimed_amount);
+
+    let stored = HtlcRepository::new(swap.pool.clone()).find(swap.htlc.id).await.unwrap();
+    assert_eq!(stored.status, HtlcStatus::Claimed);
+}
+
+#[actix_rt::test]
+async fn test_refund_after_timeout_on_simulated_chain() {
+    let swap = funded_swap(6).await;
+    let timeout = swap.htlc.params.timeout;
+    let utxo = swap.client.get_utxos(&swap.htlc.address).await.unwrap().remove(0);
+    let outpoint = OutPoint::new(utxo.txid.parse().unwrap(), utxo.vout);
+
+    // The chain enforces the CLTV even when the service's own checks are bypassed
+    swap.chain.mine(timeout - 2_500_001 - 1);
+    let refund_address = Address::p2wpkh(&swap.sender.public_key(&Secp256k1::new()), Network::Testnet).unwrap();
+    let early = create_refund_transaction(
+        outpoint,
+        Amount::from_sat(utxo.value),
+        &build_htlc_script(&swap.htlc.params).unwrap(),
+        &swap.sender.inner,
+        &refund_address,
+        timeout,
+        Amount::from_sat(1_000),
+    )
+    .unwrap();
+    let rejected = swap.client.broadcast_transaction(&serialize_hex(&early)).await;
+    assert!(matches!(rejected, Err(ApiError::InternalError { ref message, .. }) if message.contains("non-final")));
+
+    let not_expired = refund_htlc(&swap.pool, &swap.client, Network::Testnet, Some(&swap.sender), swap.htlc.id).await;
+    assert!(matches!(not_expired, Err(ApiError::Conflict { ref code, .. }) if code == "HTLC_NOT_EXPIRED"));
+
+    swap.chain.mine(1);
+    let response = refund_htlc(&swap.pool, &swap.client, Network::Testnet, Some(&swap.sender), swap.htlc.id)
+        .await
+        .unwrap();
+    swap.chain.mine(1);
+
+    let refund = swap.client.get_transaction(&response.transaction_id).await.unwrap();
+    assert_eq!(refund.status.block_height, Some(timeout + 1));
+    assert_eq!(response.refund_address, refund_address.to_string());
+    assert_eq!(swap.client.get_utxos(&response.refund_address).await.unwrap()[0].value, response.refunded_amount);
+
+    let stored = HtlcRepository::new(swap.pool.clone()).find(swap.htlc.id).await.unwrap();
+    assert_eq!(stored.status, HtlcStatus::Refunded);
+}